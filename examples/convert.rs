@@ -135,6 +135,7 @@ fn main() {
             }
             let options = msl::Options {
                 binding_map: &binding_map,
+                clip_space: Default::default(),
             };
             let msl = msl::write_string(&module, options).unwrap();
             fs::write(&args[2], msl).unwrap();
@@ -151,7 +152,9 @@ fn main() {
                 }
             });
 
-            let spv = spv::Writer::new(&module.header, debug_flag).write(&module);
+            let spv = spv::Writer::new(&module.header, debug_flag)
+                .write(&module)
+                .unwrap();
 
             let bytes = spv
                 .iter()
@@ -173,7 +176,7 @@ fn main() {
                 .open(&args[2])
                 .unwrap();
 
-            glsl::write(&module, &mut file).unwrap();
+            glsl::write(&module, &mut file, Default::default()).unwrap();
         }
         #[cfg(feature = "serialize")]
         "ron" => {
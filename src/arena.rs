@@ -8,6 +8,51 @@ use std::{cmp::Ordering, fmt, hash, marker::PhantomData, num::NonZeroU32};
 /// `Index` is an implementation detail to `Handle`.
 type Index = NonZeroU32;
 
+/// Identity of an arena, carried by the handles it produces.
+///
+/// It's only tracked in debug builds, to catch handles used with the wrong
+/// arena. Handles that are not produced by an arena have an unknown identity,
+/// and are not checked.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct ArenaTag {
+    #[cfg(debug_assertions)]
+    id: u32,
+}
+
+impl ArenaTag {
+    const UNKNOWN: Self = ArenaTag {
+        #[cfg(debug_assertions)]
+        id: 0,
+    };
+
+    fn new() -> Self {
+        #[cfg(debug_assertions)]
+        {
+            use std::sync::atomic::{AtomicU32, Ordering};
+            static LAST_ID: AtomicU32 = AtomicU32::new(0);
+            ArenaTag {
+                id: LAST_ID.fetch_add(1, Ordering::Relaxed) + 1,
+            }
+        }
+        #[cfg(not(debug_assertions))]
+        ArenaTag {}
+    }
+
+    fn is_compatible(self, other: Self) -> bool {
+        self == Self::UNKNOWN || other == Self::UNKNOWN || self == other
+    }
+}
+
+/// Error returned when a handle doesn't refer to a value of an arena.
+#[derive(Clone, Copy, Debug, PartialEq, thiserror::Error)]
+#[error("handle {index} of {kind} doesn't belong to the arena")]
+pub struct BadHandle {
+    /// Name of the type of the values.
+    pub kind: &'static str,
+    /// Index of the handle.
+    pub index: usize,
+}
+
 /// A strongly typed reference to a SPIR-V element.
 #[cfg_attr(feature = "serialize", derive(crate::Serialize))]
 #[cfg_attr(feature = "deserialize", derive(crate::Deserialize))]
@@ -18,6 +63,8 @@ type Index = NonZeroU32;
 pub struct Handle<T> {
     index: Index,
     #[cfg_attr(any(feature = "serialize", feature = "deserialize"), serde(skip))]
+    tag: ArenaTag,
+    #[cfg_attr(any(feature = "serialize", feature = "deserialize"), serde(skip))]
     marker: PhantomData<T>,
 }
 
@@ -25,6 +72,7 @@ impl<T> Clone for Handle<T> {
     fn clone(&self) -> Self {
         Handle {
             index: self.index,
+            tag: self.tag,
             marker: self.marker,
         }
     }
@@ -61,16 +109,32 @@ impl<T> Handle<T> {
     #[cfg(test)]
     pub const DUMMY: Self = Handle {
         index: unsafe { NonZeroU32::new_unchecked(!0) },
+        tag: ArenaTag::UNKNOWN,
         marker: PhantomData,
     };
 
     pub(crate) fn new(index: Index) -> Self {
         Handle {
             index,
+            tag: ArenaTag::UNKNOWN,
+            marker: PhantomData,
+        }
+    }
+
+    fn with_tag(index: usize, tag: ArenaTag) -> Self {
+        Handle {
+            index: unsafe { Index::new_unchecked(index as u32 + 1) },
+            tag,
             marker: PhantomData,
         }
     }
 
+    /// Creates a handle from its zero-based index.
+    pub(crate) fn from_usize(index: usize) -> Self {
+        let index = Index::new(index as u32 + 1).unwrap();
+        Handle::new(index)
+    }
+
     /// Returns the zero-based index of this handle.
     pub fn index(self) -> usize {
         let index = self.index.get() - 1;
@@ -84,7 +148,7 @@ impl<T> Handle<T> {
 /// Adding new items to the arena produces a strongly-typed [`Handle`].
 /// The arena can be indexed using the given handle to obtain
 /// a reference to the stored item.
-#[derive(Debug)]
+#[derive(Clone)]
 #[cfg_attr(feature = "serialize", derive(crate::Serialize))]
 #[cfg_attr(feature = "deserialize", derive(crate::Deserialize))]
 #[cfg_attr(
@@ -94,6 +158,99 @@ impl<T> Handle<T> {
 pub struct Arena<T> {
     /// Values of this arena.
     data: Vec<T>,
+    /// Identity of this arena, checked when the handles are used.
+    #[cfg_attr(
+        any(feature = "serialize", feature = "deserialize"),
+        serde(skip, default = "ArenaTag::new")
+    )]
+    tag: ArenaTag,
+    /// Hash index of the values, used by `fetch_or_append`.
+    #[cfg_attr(
+        any(feature = "serialize", feature = "deserialize"),
+        serde(skip, default = "Lookup::default")
+    )]
+    lookup: Lookup<T>,
+}
+
+/// Handles of the values of an arena, grouped by the hash of the value.
+///
+/// The hash function is only known after a lookup requiring `T: Hash`, so the
+/// values appended before it are not indexed until the next such lookup. The
+/// values are indexed as they are appended afterwards. A value modified in
+/// place is removed from the index, and stays stale until the next lookup
+/// that can modify the arena.
+struct Lookup<T> {
+    hasher: Option<fn(&T) -> u64>,
+    /// Hashes of the first values, `None` for the stale ones.
+    hashes: Vec<Option<u64>>,
+    /// Positions of the stale values.
+    stale: Vec<usize>,
+    handles: crate::FastHashMap<u64, Vec<Handle<T>>>,
+}
+
+impl<T> Clone for Lookup<T> {
+    fn clone(&self) -> Self {
+        Lookup {
+            hasher: self.hasher,
+            hashes: self.hashes.clone(),
+            stale: self.stale.clone(),
+            handles: self.handles.clone(),
+        }
+    }
+}
+
+impl<T> Default for Lookup<T> {
+    fn default() -> Self {
+        Lookup {
+            hasher: None,
+            hashes: Vec::new(),
+            stale: Vec::new(),
+            handles: crate::FastHashMap::default(),
+        }
+    }
+}
+
+impl<T> Lookup<T> {
+    fn insert(&mut self, hash: u64, handle: Handle<T>) {
+        self.handles.entry(hash).or_default().push(handle);
+    }
+
+    fn remove(&mut self, hash: u64, index: usize) {
+        if let Some(handles) = self.handles.get_mut(&hash) {
+            handles.retain(|handle| handle.index() != index);
+        }
+    }
+
+    /// Indexes the stale values and the ones appended without a hash function.
+    fn update(&mut self, data: &[T], tag: ArenaTag) {
+        let hasher = match self.hasher {
+            Some(hasher) => hasher,
+            None => return,
+        };
+        for index in std::mem::take(&mut self.stale) {
+            let hash = hasher(&data[index]);
+            self.hashes[index] = Some(hash);
+            self.insert(hash, Handle::with_tag(index, tag));
+        }
+        for (index, value) in data.iter().enumerate().skip(self.hashes.len()) {
+            let hash = hasher(value);
+            self.hashes.push(Some(hash));
+            self.insert(hash, Handle::with_tag(index, tag));
+        }
+    }
+}
+
+fn hash_value<T: hash::Hash>(value: &T) -> u64 {
+    use hash::Hasher as _;
+    let mut hasher = fxhash::FxHasher::default();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+impl<T: fmt::Debug> fmt::Debug for Arena<T> {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        self.data.fmt(formatter)
+    }
 }
 
 impl<T> Default for Arena<T> {
@@ -105,7 +262,11 @@ impl<T> Default for Arena<T> {
 impl<T> Arena<T> {
     /// Create a new arena with no initial capacity allocated.
     pub fn new() -> Self {
-        Arena { data: Vec::new() }
+        Arena {
+            data: Vec::new(),
+            tag: ArenaTag::new(),
+            lookup: Lookup::default(),
+        }
     }
 
     /// Returns the current number of items stored in this arena.
@@ -121,49 +282,224 @@ impl<T> Arena<T> {
     /// Returns an iterator over the items stored in this arena, returning both
     /// the item's handle and a reference to it.
     pub fn iter(&self) -> impl Iterator<Item = (Handle<T>, &T)> {
-        self.data.iter().enumerate().map(|(i, v)| {
-            let position = i + 1;
-            let index = unsafe { Index::new_unchecked(position as u32) };
-            (Handle::new(index), v)
-        })
+        let tag = self.tag;
+        self.data
+            .iter()
+            .enumerate()
+            .map(move |(i, v)| (Handle::with_tag(i, tag), v))
     }
 
     /// Adds a new value to the arena, returning a typed handle.
     ///
     /// The value is not linked to any SPIR-V module.
     pub fn append(&mut self, value: T) -> Handle<T> {
-        let position = self.data.len() + 1;
-        let index = unsafe { Index::new_unchecked(position as u32) };
+        let handle = Handle::with_tag(self.data.len(), self.tag);
+        let lookup = &mut self.lookup;
+        if let Some(hasher) = lookup.hasher {
+            if lookup.hashes.len() == self.data.len() {
+                let hash = hasher(&value);
+                lookup.hashes.push(Some(hash));
+                lookup.insert(hash, handle);
+            }
+        }
         self.data.push(value);
-        Handle::new(index)
+        handle
     }
 
     /// Adds a value with a check for uniqueness: returns a handle pointing to
     /// an existing element if its value matches the given one, or adds a new
     /// element otherwise.
+    ///
+    /// The values are looked up by their hash, in constant time.
     pub fn fetch_or_append(&mut self, value: T) -> Handle<T>
     where
-        T: PartialEq,
+        T: hash::Hash + PartialEq,
+    {
+        self.lookup.hasher = Some(hash_value::<T>);
+        self.lookup.update(&self.data, self.tag);
+        match self.fetch(&value) {
+            Some(handle) => handle,
+            None => self.append(value),
+        }
+    }
+
+    /// Returns the handle of a value equal to the given one, if any.
+    ///
+    /// The values that are not indexed yet, see [`Arena::get_mut`], are
+    /// compared one by one.
+    pub fn fetch(&self, value: &T) -> Option<Handle<T>>
+    where
+        T: hash::Hash + PartialEq,
     {
-        if let Some(index) = self.data.iter().position(|d| d == &value) {
-            let index = unsafe { Index::new_unchecked((index + 1) as u32) };
-            Handle::new(index)
-        } else {
-            self.append(value)
+        let lookup = &self.lookup;
+        let indexed = lookup
+            .handles
+            .get(&hash_value(value))
+            .into_iter()
+            .flatten()
+            .map(|handle| handle.index());
+        let unindexed = lookup
+            .stale
+            .iter()
+            .cloned()
+            .chain(lookup.hashes.len()..self.data.len());
+        indexed
+            .chain(unindexed)
+            .filter(|&index| self.data[index] == *value)
+            .min()
+            .map(|index| Handle::with_tag(index, self.tag))
+    }
+
+    /// Removes the values appended after the first `len` ones.
+    ///
+    /// It allows rolling back the values appended by a failed operation.
+    /// Handles to the removed values become invalid.
+    pub fn truncate(&mut self, len: usize) {
+        let lookup = &mut self.lookup;
+        for index in len..lookup.hashes.len() {
+            if let Some(hash) = lookup.hashes[index] {
+                lookup.remove(hash, index);
+            }
         }
+        lookup.hashes.truncate(len);
+        lookup.stale.retain(|&index| index < len);
+        self.data.truncate(len);
+    }
+
+    /// Returns a function converting the handles of another arena to the
+    /// handles of this one with the same indices.
+    ///
+    /// It's used when values are copied along with the handles between them.
+    pub(crate) fn adopter(&self) -> impl Fn(Handle<T>) -> Handle<T> {
+        let tag = self.tag;
+        move |handle| Handle::with_tag(handle.index(), tag)
+    }
+
+    /// Returns the position of the value a handle refers to, in debug builds
+    /// checking that the handle was produced by this arena.
+    fn position(&self, handle: Handle<T>) -> usize {
+        debug_assert!(
+            self.tag.is_compatible(handle.tag),
+            "{:?} of {} is used with a different arena",
+            handle,
+            std::any::type_name::<T>()
+        );
+        handle.index()
+    }
+
+    /// Get a reference to an element in the arena, or an error if the handle
+    /// doesn't belong to this arena.
+    ///
+    /// Handles from other arenas are only detected in debug builds.
+    pub fn try_get(&self, handle: Handle<T>) -> Result<&T, BadHandle> {
+        let error = BadHandle {
+            kind: std::any::type_name::<T>(),
+            index: handle.index(),
+        };
+        if !self.tag.is_compatible(handle.tag) {
+            return Err(error);
+        }
+        self.data.get(handle.index()).ok_or(error)
     }
 
     /// Get a mutable reference to an element in the arena.
+    ///
+    /// The element is removed from the hash index, and indexed again
+    /// by the next [`Arena::fetch_or_append`].
     pub fn get_mut(&mut self, handle: Handle<T>) -> &mut T {
-        self.data.get_mut(handle.index.get() as usize - 1).unwrap()
+        let position = self.position(handle);
+        let lookup = &mut self.lookup;
+        if let Some(hash) = lookup.hashes.get_mut(position).and_then(Option::take) {
+            lookup.remove(hash, position);
+            lookup.stale.push(position);
+        }
+        self.data.get_mut(position).unwrap()
+    }
+
+    /// Keeps only the values marked in `keep`, which has an entry for every value.
+    ///
+    /// The kept values are moved to the front, preserving their order, and the
+    /// returned map gives their new handles. The arena gets a new identity,
+    /// so the old handles are caught in debug builds. The handles stored
+    /// inside the values are not updated.
+    pub fn compact(&mut self, keep: &[bool]) -> HandleMap<T> {
+        debug_assert_eq!(keep.len(), self.data.len());
+        let data = std::mem::take(&mut self.data);
+        self.data = data
+            .into_iter()
+            .zip(keep)
+            .filter_map(|(value, &keep)| if keep { Some(value) } else { None })
+            .collect();
+        self.tag = ArenaTag::new();
+        self.lookup = Lookup {
+            hasher: self.lookup.hasher,
+            ..Lookup::default()
+        };
+        self.lookup.update(&self.data, self.tag);
+        HandleMap::from_keep(keep, self.tag)
+    }
+
+    /// Keeps only the values for which the predicate returns `true`.
+    ///
+    /// See [`Arena::compact`] for the details.
+    pub fn retain(&mut self, mut predicate: impl FnMut(Handle<T>, &T) -> bool) -> HandleMap<T> {
+        let keep = self
+            .iter()
+            .map(|(handle, value)| predicate(handle, value))
+            .collect::<Vec<_>>();
+        self.compact(&keep)
     }
 }
 
 impl<T> std::ops::Index<Handle<T>> for Arena<T> {
     type Output = T;
     fn index(&self, handle: Handle<T>) -> &T {
-        let index = handle.index.get() - 1;
-        &self.data[index as usize]
+        &self.data[self.position(handle)]
+    }
+}
+
+/// New handles of the values of an arena, after some of them were removed.
+#[derive(Debug)]
+pub struct HandleMap<T> {
+    new_handles: Vec<Option<Handle<T>>>,
+}
+
+impl<T> HandleMap<T> {
+    /// Create a map keeping all the handles of an arena with `len` values.
+    pub fn identity(len: usize) -> Self {
+        HandleMap {
+            new_handles: (0..len)
+                .map(|index| Some(Handle::from_usize(index)))
+                .collect(),
+        }
+    }
+
+    fn from_keep(keep: &[bool], tag: ArenaTag) -> Self {
+        let mut count = 0;
+        let new_handles = keep
+            .iter()
+            .map(|&keep| {
+                if keep {
+                    count += 1;
+                    Some(Handle::with_tag(count - 1, tag))
+                } else {
+                    None
+                }
+            })
+            .collect();
+        HandleMap { new_handles }
+    }
+
+    /// Returns the new handle of a value, or `None` if it was removed.
+    pub fn get(&self, handle: Handle<T>) -> Option<Handle<T>> {
+        self.new_handles.get(handle.index()).cloned().flatten()
+    }
+
+    /// Replaces a handle by the new handle of its value.
+    ///
+    /// The value must not have been removed.
+    pub fn adjust(&self, handle: &mut Handle<T>) {
+        *handle = self.get(*handle).unwrap();
     }
 }
 
@@ -206,4 +542,101 @@ mod tests {
         assert!(t1 != t2);
         assert!(arena[t1] != arena[t2]);
     }
+
+    #[test]
+    fn fetch_after_modification() {
+        let mut arena: Arena<u8> = Arena::new();
+        let t1 = arena.fetch_or_append(0);
+        let t2 = arena.fetch_or_append(1);
+        assert_eq!(arena.fetch_or_append(0), t1);
+        assert_eq!(arena.fetch(&1), Some(t2));
+        *arena.get_mut(t2) = 2;
+        assert_eq!(arena.fetch(&1), None);
+        assert_eq!(arena.fetch(&2), Some(t2));
+        assert_eq!(arena.fetch(&0), Some(t1));
+        assert_eq!(arena.fetch_or_append(2), t2);
+        assert_eq!(
+            arena.iter().map(|(_, &value)| value).collect::<Vec<_>>(),
+            [0, 2]
+        );
+    }
+
+    #[test]
+    fn fetch_appended() {
+        let mut arena: Arena<u8> = Arena::new();
+        let t1 = arena.append(0);
+        assert_eq!(arena.fetch(&0), Some(t1));
+        let t2 = arena.fetch_or_append(1);
+        let t3 = arena.append(2);
+        assert_eq!(arena.fetch(&1), Some(t2));
+        assert_eq!(arena.fetch(&2), Some(t3));
+        let map = arena.retain(|handle, _| handle != t2);
+        assert_eq!(arena.fetch(&1), None);
+        assert_eq!(arena.fetch(&2), map.get(t3));
+    }
+
+    #[test]
+    fn truncate() {
+        let mut arena: Arena<u8> = Arena::new();
+        let t1 = arena.fetch_or_append(0);
+        arena.fetch_or_append(1);
+        arena.truncate(1);
+        assert_eq!(arena.len(), 1);
+        assert_eq!(arena.fetch(&1), None);
+        assert_eq!(arena.fetch(&0), Some(t1));
+    }
+
+    #[test]
+    fn retain() {
+        let mut arena: Arena<u8> = Arena::new();
+        let handles = (0..5).map(|value| arena.append(value)).collect::<Vec<_>>();
+        let map = arena.retain(|_, &value| value % 2 == 0);
+        assert_eq!(
+            arena.iter().map(|(_, &value)| value).collect::<Vec<_>>(),
+            [0, 2, 4]
+        );
+        assert_eq!(map.get(handles[1]), None);
+        let mut handle = handles[4];
+        map.adjust(&mut handle);
+        assert_eq!(arena[handle], 4);
+        if cfg!(debug_assertions) {
+            assert!(arena.try_get(handles[0]).is_err());
+        }
+    }
+
+    #[test]
+    fn debug() {
+        let mut arena: Arena<u8> = Arena::new();
+        arena.fetch_or_append(1);
+        arena.append(2);
+        assert_eq!(format!("{:?}", arena), "[1, 2]");
+    }
+
+    #[test]
+    fn try_get() {
+        let mut arena: Arena<u8> = Arena::new();
+        let t1 = arena.append(0);
+        assert_eq!(arena.try_get(t1), Ok(&0));
+        assert!(arena.try_get(Handle::DUMMY).is_err());
+
+        let mut other: Arena<u8> = Arena::new();
+        other.append(0);
+        let t2 = other.append(1);
+        assert!(arena.try_get(t2).is_err());
+        if cfg!(debug_assertions) {
+            let first = other.iter().next().map(|(handle, _)| handle).unwrap();
+            assert!(arena.try_get(first).is_err());
+        }
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic]
+    fn foreign_handle() {
+        let mut arena: Arena<u8> = Arena::new();
+        arena.append(0);
+        let mut other: Arena<u8> = Arena::new();
+        let handle = other.append(0);
+        let _ = arena[handle];
+    }
 }
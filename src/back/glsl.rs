@@ -1,7 +1,9 @@
 use crate::{
-    Arena, ArraySize, BinaryOperator, BuiltIn, Constant, ConstantInner, DerivativeAxis, Expression,
-    FastHashMap, Function, FunctionOrigin, GlobalVariable, Handle, ImageFlags, IntrinsicFunction,
-    LocalVariable, Module, ScalarKind, Statement, StorageClass, Type, TypeInner, UnaryOperator,
+    proc::CallGraph, Arena, ArraySize, BinaryOperator, BuiltIn, Constant, ConstantInner,
+    DerivativeAxis, ExecutionModes, Expression, FastHashMap, Function, FunctionOrigin,
+    GlobalVariable, Handle, ImageFlags, IntrinsicFunction, LocalVariable, Module,
+    PrimitiveTopology, ScalarKind, ShaderStage, Statement, StorageClass, StorageFormat,
+    TessellationSpacing, Type, TypeInner, UnaryOperator, VertexOrder,
 };
 use std::{
     borrow::Cow,
@@ -38,9 +40,35 @@ impl fmt::Display for Error {
     }
 }
 
-pub fn write(module: &Module, out: &mut impl Write) -> Result<(), Error> {
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Options {
+    /// Adjustment of the position written by the vertex shaders.
+    pub clip_space: crate::proc::ClipSpaceAdjustment,
+}
+
+pub fn write(module: &Module, out: &mut impl Write, options: Options) -> Result<(), Error> {
+    let module = &*options.clip_space.apply(module);
     writeln!(out, "#version 450 core")?;
 
+    let mut extensions = Vec::new();
+    for (_, ty) in module.types.iter() {
+        match ty.inner {
+            TypeInner::Scalar { kind, width }
+            | TypeInner::Vector { kind, width, .. }
+            | TypeInner::Matrix { kind, width, .. } => {
+                if let Some(extension) = scalar_extension(kind, width) {
+                    if !extensions.contains(&extension) {
+                        extensions.push(extension);
+                    }
+                }
+            }
+            _ => continue,
+        }
+    }
+    for extension in extensions {
+        writeln!(out, "#extension {} : require", extension)?;
+    }
+
     let mut counter = 0;
     let mut names = FastHashMap::default();
 
@@ -93,6 +121,37 @@ pub fn write(module: &Module, out: &mut impl Write) -> Result<(), Error> {
         }
     }
 
+    let mut constants = FastHashMap::default();
+
+    // Specialization constants are declared up front and referenced by name
+    for (handle, constant) in module.constants.iter() {
+        if constant.specialization.is_some() {
+            constants.insert(handle, namer(constant.name.as_ref()));
+        }
+    }
+
+    for (handle, constant) in module.constants.iter() {
+        let name = match constants.get(&handle) {
+            Some(name) => name,
+            None => continue,
+        };
+        // composites can't be overridden directly, only through their components
+        if !matches!(constant.inner, ConstantInner::Composite(_)) {
+            write!(
+                out,
+                "layout(constant_id={}) ",
+                constant.specialization.unwrap()
+            )?;
+        }
+        writeln!(
+            out,
+            "const {} {} = {};",
+            write_type(constant.ty, &module.types, &structs)?,
+            name,
+            write_constant_value(handle, module, &structs, &constants)?
+        )?;
+    }
+
     let mut globals_lookup = FastHashMap::default();
 
     for (handle, global) in module.global_variables.iter() {
@@ -105,6 +164,11 @@ pub fn write(module: &Module, out: &mut impl Write) -> Result<(), Error> {
                 BuiltIn::ClipDistance => "gl_ClipDistance",
                 BuiltIn::InstanceIndex => "gl_InstanceIndex",
                 BuiltIn::VertexIndex => "gl_VertexIndex",
+                BuiltIn::InvocationId => "gl_InvocationID",
+                BuiltIn::TessCoord => "gl_TessCoord",
+                BuiltIn::TessLevelOuter => "gl_TessLevelOuter",
+                BuiltIn::TessLevelInner => "gl_TessLevelInner",
+                BuiltIn::PrimitiveId => "gl_PrimitiveID",
                 BuiltIn::PointSize => "gl_PointSize",
                 BuiltIn::FragCoord => "gl_FragCoord",
                 BuiltIn::FrontFacing => "gl_FrontFacing",
@@ -119,23 +183,66 @@ pub fn write(module: &Module, out: &mut impl Write) -> Result<(), Error> {
             continue;
         }
 
+        let mut layout = Vec::new();
+        let mut memory_qualifier = "";
+        if let TypeInner::Image { flags, format, .. } = module.types[global.ty].inner {
+            if let Some(format) = format {
+                layout.push(write_storage_format(format).to_string());
+            }
+            if !flags.contains(ImageFlags::SAMPLED) {
+                if !flags.contains(ImageFlags::CAN_STORE) {
+                    memory_qualifier = "readonly ";
+                } else if !flags.contains(ImageFlags::CAN_LOAD) {
+                    memory_qualifier = "writeonly ";
+                }
+            }
+        }
         if let Some(ref binding) = global.binding {
-            write!(out, "layout({}) ", Binding(binding.clone()))?;
+            layout.push(Binding(binding.clone()).to_string());
+        }
+        if !layout.is_empty() {
+            write!(out, "layout({}) ", layout.join(","))?;
         }
 
         let name = namer(global.name.as_ref());
 
-        writeln!(
+        write!(
             out,
-            "{}{} {};",
+            "{}{}{} {}",
             write_storage_class(global.class)?,
+            memory_qualifier,
             write_type(global.ty, &module.types, &structs)?,
             name
         )?;
+        if let Some(init) = global.init {
+            write!(
+                out,
+                " = {}",
+                write_constant(init, module, &structs, &constants)?
+            )?;
+        }
+        writeln!(out, ";")?;
 
         globals_lookup.insert(handle, name);
     }
 
+    // execution modes apply to the whole shader, so only one entry point can declare them
+    let mut execution_modes = Vec::new();
+    for entry_point in module.entry_points.iter() {
+        let mut declarations = Vec::new();
+        write_execution_modes(entry_point.stage, &entry_point.modes, &mut declarations)?;
+        if !declarations.is_empty() {
+            if !execution_modes.is_empty() {
+                return Err(Error::Custom(String::from(
+                    "Several entry points declare execution modes, \
+                    extract them into separate modules first",
+                )));
+            }
+            execution_modes = declarations;
+        }
+    }
+    out.write_all(&execution_modes)?;
+
     let mut functions = FastHashMap::default();
 
     // Do a first pass to collect names
@@ -143,9 +250,19 @@ pub fn write(module: &Module, out: &mut impl Write) -> Result<(), Error> {
         functions.insert(handle, namer(func.name.as_ref()));
     }
 
-    // TODO: glsl is order dependent so we need to build functions in order
-    for (handle, func) in module.functions.iter() {
+    // glsl is order dependent, so callees have to be written before callers
+    let order = CallGraph::new(&module.functions)
+        .reverse_topological_order()
+        .map_err(|e| Error::Custom(e.to_string()))?;
+    for handle in order {
+        let func = &module.functions[handle];
         let name = functions.get(&handle).unwrap();
+        let args: FastHashMap<_, _> = func
+            .arguments
+            .iter()
+            .enumerate()
+            .map(|(pos, arg)| (pos as u32, (namer(arg.name.as_ref()), arg.ty)))
+            .collect();
 
         writeln!(
             out,
@@ -157,10 +274,22 @@ pub fn write(module: &Module, out: &mut impl Write) -> Result<(), Error> {
                     &structs
                 ))?,
             name,
-            func.parameter_types
-                .iter()
-                .map(|ty| write_type(*ty, &module.types, &structs))
-                .collect::<Result<Vec<_>, _>>()?
+            (0..func.arguments.len() as u32)
+                .map(|pos| {
+                    let (ref name, ty) = args[&pos];
+                    // pointers are passed by reference
+                    let qualifier = match module.types[ty].inner {
+                        TypeInner::Pointer { .. } => "inout ",
+                        _ => "",
+                    };
+                    Ok(format!(
+                        "{}{} {}",
+                        qualifier,
+                        write_type(ty, &module.types, &structs)?,
+                        name
+                    ))
+                })
+                .collect::<Result<Vec<_>, Error>>()?
                 .join(","),
         )?;
 
@@ -175,12 +304,8 @@ pub fn write(module: &Module, out: &mut impl Write) -> Result<(), Error> {
             globals: &globals_lookup,
             locals_lookup: &locals,
             structs: &structs,
-            args: &func
-                .parameter_types
-                .iter()
-                .enumerate()
-                .map(|(pos, ty)| (pos as u32, (namer(None), *ty)))
-                .collect(),
+            constants: &constants,
+            args: &args,
             expressions: &func.expressions,
             locals: &func.local_variables,
         };
@@ -211,6 +336,84 @@ pub fn write(module: &Module, out: &mut impl Write) -> Result<(), Error> {
     Ok(())
 }
 
+fn write_execution_modes(
+    stage: ShaderStage,
+    modes: &ExecutionModes,
+    out: &mut impl Write,
+) -> Result<(), Error> {
+    let mut inputs = Vec::new();
+    let mut outputs = Vec::new();
+
+    if let Some(topology) = modes.input_topology {
+        inputs.push(String::from(match topology {
+            PrimitiveTopology::Points => "points",
+            PrimitiveTopology::Lines => "lines",
+            PrimitiveTopology::LinesAdjacency => "lines_adjacency",
+            PrimitiveTopology::Triangles => "triangles",
+            PrimitiveTopology::TrianglesAdjacency => "triangles_adjacency",
+            PrimitiveTopology::Quads => "quads",
+            PrimitiveTopology::Isolines => "isolines",
+            other => {
+                return Err(Error::Custom(format!(
+                    "Cannot use {:?} as input topology",
+                    other
+                )))
+            }
+        }));
+    }
+    if let Some(spacing) = modes.spacing {
+        inputs.push(String::from(match spacing {
+            TessellationSpacing::Equal => "equal_spacing",
+            TessellationSpacing::FractionalEven => "fractional_even_spacing",
+            TessellationSpacing::FractionalOdd => "fractional_odd_spacing",
+        }));
+    }
+    if let Some(order) = modes.vertex_order {
+        inputs.push(String::from(match order {
+            VertexOrder::Cw => "cw",
+            VertexOrder::Ccw => "ccw",
+        }));
+    }
+    if let Some(invocations) = modes.invocations {
+        inputs.push(format!("invocations={}", invocations));
+    }
+    if let Some([x, y, z]) = modes.workgroup_size {
+        inputs.push(format!(
+            "local_size_x={},local_size_y={},local_size_z={}",
+            x, y, z
+        ));
+    }
+
+    if let Some(topology) = modes.output_topology {
+        outputs.push(String::from(match topology {
+            PrimitiveTopology::Points => "points",
+            PrimitiveTopology::LineStrip => "line_strip",
+            PrimitiveTopology::TriangleStrip => "triangle_strip",
+            other => {
+                return Err(Error::Custom(format!(
+                    "Cannot use {:?} as output topology",
+                    other
+                )))
+            }
+        }));
+    }
+    if let Some(vertices) = modes.output_vertices {
+        outputs.push(match stage {
+            ShaderStage::TessellationControl => format!("vertices={}", vertices),
+            _ => format!("max_vertices={}", vertices),
+        });
+    }
+
+    if !inputs.is_empty() {
+        writeln!(out, "layout({}) in;", inputs.join(","))?;
+    }
+    if !outputs.is_empty() {
+        writeln!(out, "layout({}) out;", outputs.join(","))?;
+    }
+
+    Ok(())
+}
+
 struct Binding(crate::Binding);
 impl fmt::Display for Binding {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -229,6 +432,7 @@ struct StatementBuilder<'a> {
     pub globals: &'a FastHashMap<Handle<GlobalVariable>, String>,
     pub locals_lookup: &'a FastHashMap<Handle<LocalVariable>, String>,
     pub structs: &'a FastHashMap<Handle<Type>, String>,
+    pub constants: &'a FastHashMap<Handle<Constant>, String>,
     pub args: &'a FastHashMap<u32, (String, Handle<Type>)>,
     pub expressions: &'a Arena<Expression>,
     pub locals: &'a Arena<LocalVariable>,
@@ -326,11 +530,30 @@ fn write_statement(
             ))?
         ),
         Statement::Kill => String::from("discard;"),
+        Statement::EmitVertex => String::from("EmitVertex();"),
+        Statement::EndPrimitive => String::from("EndPrimitive();"),
         Statement::Store { pointer, value } => format!(
             "{} = {};",
             write_expression(&builder.expressions[*pointer], module, builder)?.0,
             write_expression(&builder.expressions[*value], module, builder)?.0
         ),
+        Statement::Call {
+            function,
+            arguments,
+        } => format!(
+            "{}({});",
+            builder.functions.get(function).unwrap(),
+            arguments
+                .iter()
+                .map::<Result<_, Error>, _>(|arg| Ok(write_expression(
+                    &builder.expressions[*arg],
+                    module,
+                    builder
+                )?
+                .0))
+                .collect::<Result<Vec<_>, _>>()?
+                .join(","),
+        ),
     })
 }
 
@@ -393,7 +616,7 @@ fn write_expression<'a>(
             }
         }
         Expression::Constant(constant) => (
-            write_constant(&module.constants[*constant], module, builder)?,
+            write_constant(*constant, module, builder.structs, builder.constants)?,
             Cow::Borrowed(&module.types[module.constants[*constant].ty].inner),
         ),
         Expression::Compose { ty, components } => {
@@ -471,6 +694,10 @@ fn write_expression<'a>(
         Expression::FunctionParameter(pos) => {
             let (arg, ty) = builder.args.get(&pos).unwrap().clone();
 
+            let ty = match module.types[ty].inner {
+                TypeInner::Pointer { base, .. } => base,
+                _ => ty,
+            };
             (arg, Cow::Borrowed(&module.types[ty].inner))
         }
         Expression::GlobalVariable(handle) => (
@@ -498,7 +725,9 @@ fn write_expression<'a>(
                 write_expression(&builder.expressions[*coordinate], module, builder)?;
 
             let (kind, dim, arrayed, ms, width) = match image_ty.as_ref() {
-                TypeInner::Image { base, dim, flags } => match module.types[*base].inner {
+                TypeInner::Image {
+                    base, dim, flags, ..
+                } => match module.types[*base].inner {
                     TypeInner::Scalar { kind, width } => (
                         kind,
                         *dim,
@@ -743,32 +972,60 @@ fn write_expression<'a>(
 }
 
 fn write_constant(
-    constant: &Constant,
+    handle: Handle<Constant>,
     module: &Module,
-    builder: &StatementBuilder<'_>,
+    structs: &FastHashMap<Handle<Type>, String>,
+    constants: &FastHashMap<Handle<Constant>, String>,
 ) -> Result<String, Error> {
+    match constants.get(&handle) {
+        Some(name) => Ok(name.clone()),
+        None => write_constant_value(handle, module, structs, constants),
+    }
+}
+
+fn write_constant_value(
+    handle: Handle<Constant>,
+    module: &Module,
+    structs: &FastHashMap<Handle<Type>, String>,
+    constants: &FastHashMap<Handle<Constant>, String>,
+) -> Result<String, Error> {
+    let constant = &module.constants[handle];
+    let width = match module.types[constant.ty].inner {
+        TypeInner::Scalar { width, .. } => width,
+        _ => 4,
+    };
     Ok(match constant.inner {
-        ConstantInner::Sint(int) => int.to_string(),
-        ConstantInner::Uint(int) => int.to_string(),
-        ConstantInner::Float(float) => format!("{:?}", float),
+        ConstantInner::Sint(int) => match width {
+            2 => format!("int16_t({})", int),
+            8 => format!("{}l", int),
+            _ => int.to_string(),
+        },
+        ConstantInner::Uint(int) => match width {
+            2 => format!("uint16_t({}u)", int),
+            8 => format!("{}ul", int),
+            _ => format!("{}u", int),
+        },
+        ConstantInner::Float(float) => match width {
+            2 => format!("{:?}hf", float),
+            8 => format!("{:?}lf", float),
+            _ => format!("{:?}", float),
+        },
         ConstantInner::Bool(boolean) => boolean.to_string(),
         ConstantInner::Composite(ref components) => format!(
             "{}({})",
             match module.types[constant.ty].inner {
-                TypeInner::Vector { size, .. } => format!("vec{}", size as u8,),
-                TypeInner::Matrix { columns, rows, .. } =>
-                    format!("mat{}x{}", columns as u8, rows as u8,),
-                TypeInner::Struct { .. } => builder.structs.get(&constant.ty).unwrap().clone(),
-                TypeInner::Array { .. } => write_type(constant.ty, &module.types, builder.structs)?,
+                TypeInner::Vector { .. } | TypeInner::Matrix { .. } | TypeInner::Array { .. } =>
+                    write_type(constant.ty, &module.types, structs)?,
+                TypeInner::Struct { .. } => structs.get(&constant.ty).unwrap().clone(),
                 _ =>
                     return Err(Error::Custom(format!(
                         "Cannot build constant of type {}",
-                        write_type(constant.ty, &module.types, builder.structs)?
+                        write_type(constant.ty, &module.types, structs)?
                     ))),
             },
             components
                 .iter()
-                .map(|component| write_constant(&module.constants[*component], module, builder))
+                .map(|component| write_constant(*component, module, structs, constants))
                 .collect::<Result<Vec<_>, _>>()?
                 .join(","),
         ),
@@ -781,39 +1038,27 @@ fn write_type<'a>(
     structs: &'a FastHashMap<Handle<Type>, String>,
 ) -> Result<String, Error> {
     Ok(match types[ty].inner {
-        TypeInner::Scalar { kind, width } => match kind {
-            ScalarKind::Sint => String::from("int"),
-            ScalarKind::Uint => String::from("uint"),
-            ScalarKind::Float => match width {
-                4 => String::from("float"),
-                8 => String::from("double"),
-                _ => {
-                    return Err(Error::Custom(format!(
-                        "Cannot build float of width {}",
-                        width
-                    )))
-                }
-            },
-            ScalarKind::Bool => String::from("bool"),
-        },
-        TypeInner::Vector { size, kind, width } => format!(
-            "{}vec{}",
-            match kind {
-                ScalarKind::Sint => "i",
-                ScalarKind::Uint => "u",
-                ScalarKind::Float => match width {
-                    4 => "",
-                    8 => "d",
-                    _ =>
-                        return Err(Error::Custom(format!(
-                            "Cannot build float of width {}",
-                            width
-                        ))),
-                },
-                ScalarKind::Bool => "b",
-            },
-            size as u8
-        ),
+        TypeInner::Scalar { kind, width } => String::from(match (kind, width) {
+            (ScalarKind::Sint, 2) => "int16_t",
+            (ScalarKind::Sint, 4) => "int",
+            (ScalarKind::Sint, 8) => "int64_t",
+            (ScalarKind::Uint, 2) => "uint16_t",
+            (ScalarKind::Uint, 4) => "uint",
+            (ScalarKind::Uint, 8) => "uint64_t",
+            (ScalarKind::Float, 2) => "float16_t",
+            (ScalarKind::Float, 4) => "float",
+            (ScalarKind::Float, 8) => "double",
+            (ScalarKind::Bool, _) => "bool",
+            _ => {
+                return Err(Error::Custom(format!(
+                    "Cannot build {:?} of width {}",
+                    kind, width
+                )))
+            }
+        }),
+        TypeInner::Vector { size, kind, width } => {
+            format!("{}vec{}", write_scalar_prefix(kind, width)?, size as u8)
+        }
         TypeInner::Matrix {
             columns,
             rows,
@@ -821,20 +1066,7 @@ fn write_type<'a>(
             width,
         } => format!(
             "{}mat{}x{}",
-            match kind {
-                ScalarKind::Sint => "i",
-                ScalarKind::Uint => "u",
-                ScalarKind::Float => match width {
-                    4 => "",
-                    8 => "d",
-                    _ =>
-                        return Err(Error::Custom(format!(
-                            "Cannot build float of width {}",
-                            width
-                        ))),
-                },
-                ScalarKind::Bool => "b",
-            },
+            write_scalar_prefix(kind, width)?,
             columns as u8,
             rows as u8
         ),
@@ -845,8 +1077,10 @@ fn write_type<'a>(
             write_array_size(size)?
         ),
         TypeInner::Struct { .. } => structs.get(&ty).unwrap().clone(),
-        TypeInner::Image { base, dim, flags } => format!(
-            "{}texture{}{}",
+        TypeInner::Image {
+            base, dim, flags, ..
+        } => format!(
+            "{}{}{}{}",
             match types[base].inner {
                 TypeInner::Scalar { kind, .. } => match kind {
                     ScalarKind::Sint => "i",
@@ -863,6 +1097,11 @@ fn write_type<'a>(
                         write_type(base, types, structs)?
                     ))),
             },
+            if flags.contains(ImageFlags::SAMPLED) {
+                "texture"
+            } else {
+                "image"
+            },
             ImageDimension(dim),
             write_image_flags(flags)?
         ),
@@ -879,6 +1118,39 @@ fn write_type<'a>(
     })
 }
 
+fn write_scalar_prefix(kind: ScalarKind, width: crate::Bytes) -> Result<&'static str, Error> {
+    Ok(match (kind, width) {
+        (ScalarKind::Sint, 2) => "i16",
+        (ScalarKind::Sint, 4) => "i",
+        (ScalarKind::Sint, 8) => "i64",
+        (ScalarKind::Uint, 2) => "u16",
+        (ScalarKind::Uint, 4) => "u",
+        (ScalarKind::Uint, 8) => "u64",
+        (ScalarKind::Float, 2) => "f16",
+        (ScalarKind::Float, 4) => "",
+        (ScalarKind::Float, 8) => "d",
+        (ScalarKind::Bool, _) => "b",
+        _ => {
+            return Err(Error::Custom(format!(
+                "Cannot build {:?} of width {}",
+                kind, width
+            )))
+        }
+    })
+}
+
+/// Returns the extension required to use scalars of the given kind and width, if any.
+fn scalar_extension(kind: ScalarKind, width: crate::Bytes) -> Option<&'static str> {
+    match (kind, width) {
+        (ScalarKind::Float, 2) => Some("GL_EXT_shader_explicit_arithmetic_types_float16"),
+        (ScalarKind::Sint, 2) | (ScalarKind::Uint, 2) => {
+            Some("GL_EXT_shader_explicit_arithmetic_types_int16")
+        }
+        (ScalarKind::Sint, 8) | (ScalarKind::Uint, 8) => Some("GL_ARB_gpu_shader_int64"),
+        _ => None,
+    }
+}
+
 fn write_storage_class(class: StorageClass) -> Result<String, Error> {
     Ok(String::from(match class {
         StorageClass::Constant => "const ",
@@ -899,6 +1171,43 @@ fn write_array_size(size: ArraySize) -> Result<String, Error> {
     })
 }
 
+fn write_storage_format(format: StorageFormat) -> &'static str {
+    match format {
+        StorageFormat::R8Unorm => "r8",
+        StorageFormat::R8Snorm => "r8_snorm",
+        StorageFormat::R8Uint => "r8ui",
+        StorageFormat::R8Sint => "r8i",
+        StorageFormat::R16Uint => "r16ui",
+        StorageFormat::R16Sint => "r16i",
+        StorageFormat::R16Float => "r16f",
+        StorageFormat::Rg8Unorm => "rg8",
+        StorageFormat::Rg8Snorm => "rg8_snorm",
+        StorageFormat::Rg8Uint => "rg8ui",
+        StorageFormat::Rg8Sint => "rg8i",
+        StorageFormat::R32Uint => "r32ui",
+        StorageFormat::R32Sint => "r32i",
+        StorageFormat::R32Float => "r32f",
+        StorageFormat::Rg16Uint => "rg16ui",
+        StorageFormat::Rg16Sint => "rg16i",
+        StorageFormat::Rg16Float => "rg16f",
+        StorageFormat::Rgba8Unorm => "rgba8",
+        StorageFormat::Rgba8Snorm => "rgba8_snorm",
+        StorageFormat::Rgba8Uint => "rgba8ui",
+        StorageFormat::Rgba8Sint => "rgba8i",
+        StorageFormat::Rgb10a2Unorm => "rgb10_a2",
+        StorageFormat::Rg11b10Float => "r11f_g11f_b10f",
+        StorageFormat::Rg32Uint => "rg32ui",
+        StorageFormat::Rg32Sint => "rg32i",
+        StorageFormat::Rg32Float => "rg32f",
+        StorageFormat::Rgba16Uint => "rgba16ui",
+        StorageFormat::Rgba16Sint => "rgba16i",
+        StorageFormat::Rgba16Float => "rgba16f",
+        StorageFormat::Rgba32Uint => "rgba32ui",
+        StorageFormat::Rgba32Sint => "rgba32i",
+        StorageFormat::Rgba32Float => "rgba32f",
+    }
+}
+
 fn write_image_flags(flags: ImageFlags) -> Result<String, Error> {
     let mut out = String::new();
 
@@ -5,3 +5,4 @@ pub mod glsl;
 pub mod msl;
 #[cfg(feature = "spirv")]
 pub mod spv;
+pub mod text;
@@ -61,6 +61,8 @@ pub enum Error {
     Format(FmtError),
     UnexpectedLocation,
     MixedExecutionModels(crate::Handle<crate::Function>),
+    UnsupportedStage(crate::ShaderStage),
+    UnsupportedBuiltIn(crate::BuiltIn),
     MissingBinding(crate::Handle<crate::GlobalVariable>),
     MissingBindTarget(BindSource),
     InvalidImageFlags(crate::ImageFlags),
@@ -69,6 +71,7 @@ pub enum Error {
     UnexpectedGlobalType(crate::Handle<crate::Type>),
     UnimplementedBindTarget(BindTarget),
     UnexpectedIndexing(crate::TypeInner),
+    UnsupportedScalarWidth(crate::ScalarKind, crate::Bytes),
     UnsupportedCompose(crate::Handle<crate::Type>),
     UnexpectedLoadPointer(crate::TypeInner),
     UnsupportedBinaryOp(crate::BinaryOperator),
@@ -108,6 +111,8 @@ fn dim_str(dim: crate::ImageDimension) -> &'static str {
 #[derive(Debug, Clone, Copy)]
 pub struct Options<'a> {
     pub binding_map: &'a BindingMap,
+    /// Adjustment of the position written by the vertex shaders.
+    pub clip_space: crate::proc::ClipSpaceAdjustment,
 }
 
 impl Options<'_> {
@@ -151,6 +156,12 @@ impl Indexed for crate::Handle<crate::Type> {
         self.index()
     }
 }
+impl Indexed for crate::Handle<crate::Constant> {
+    const CLASS: &'static str = "const";
+    fn id(&self) -> usize {
+        self.index()
+    }
+}
 impl Indexed for crate::Handle<crate::GlobalVariable> {
     const CLASS: &'static str = "global";
     fn id(&self) -> usize {
@@ -314,6 +325,12 @@ impl ResolvedBinding {
                     Bi::LocalInvocationId => "thread_position_in_threadgroup",
                     Bi::LocalInvocationIndex => "thread_index_in_threadgroup",
                     Bi::WorkGroupId => "threadgroup_position_in_grid",
+                    // Metal has no geometry or tessellation stages
+                    Bi::InvocationId
+                    | Bi::TessCoord
+                    | Bi::TessLevelOuter
+                    | Bi::TessLevelInner
+                    | Bi::PrimitiveId => return Err(Error::UnsupportedBuiltIn(built_in)),
                 };
                 Ok(formatter.write_str(name)?)
             }
@@ -362,6 +379,57 @@ fn scalar_kind_string(kind: crate::ScalarKind) -> &'static str {
     }
 }
 
+fn scalar_string(kind: crate::ScalarKind, width: crate::Bytes) -> Result<&'static str, Error> {
+    use crate::ScalarKind as Sk;
+    match (kind, width) {
+        (Sk::Float, 2) => Ok("half"),
+        (Sk::Sint, 2) => Ok("short"),
+        (Sk::Uint, 2) => Ok("ushort"),
+        (Sk::Sint, 8) => Ok("long"),
+        (Sk::Uint, 8) => Ok("ulong"),
+        (_, 4) | (Sk::Bool, _) => Ok(scalar_kind_string(kind)),
+        _ => Err(Error::UnsupportedScalarWidth(kind, width)),
+    }
+}
+
+/// Returns the initializer of a global variable that is declared in the
+/// generated code, as opposed to being passed in as an argument.
+fn declared_initializer(var: &crate::GlobalVariable) -> Option<Handle<crate::Constant>> {
+    match var.class {
+        crate::StorageClass::Constant | crate::StorageClass::Private => var.init,
+        _ => None,
+    }
+}
+
+/// Returns the private globals with initializers used by a helper function.
+///
+/// These are declared by the entry points and passed down by reference.
+fn private_initialized_globals<'a>(
+    module: &'a crate::Module,
+    fun: &'a crate::Function,
+) -> impl Iterator<Item = Handle<crate::GlobalVariable>> + 'a {
+    module
+        .global_variables
+        .iter()
+        .zip(&fun.global_usage)
+        .filter(|&((_, var), usage)| {
+            var.class == crate::StorageClass::Private && var.init.is_some() && !usage.is_empty()
+        })
+        .map(|((handle, _), _)| handle)
+}
+
+fn address_space_string(class: crate::StorageClass) -> &'static str {
+    match class {
+        crate::StorageClass::Constant | crate::StorageClass::Uniform => "constant",
+        crate::StorageClass::StorageBuffer => "device",
+        crate::StorageClass::WorkGroup => "threadgroup",
+        crate::StorageClass::Input
+        | crate::StorageClass::Output
+        | crate::StorageClass::Private
+        | crate::StorageClass::Function => "thread",
+    }
+}
+
 fn vector_size_string(size: crate::VectorSize) -> &'static str {
     match size {
         crate::VectorSize::Bi => "2",
@@ -465,11 +533,11 @@ impl<W: Write> Writer<W> {
             crate::Expression::Compose { ty, ref components } => {
                 let inner = &module.types[ty].inner;
                 match *inner {
-                    crate::TypeInner::Vector { size, kind, .. } => {
+                    crate::TypeInner::Vector { size, kind, width } => {
                         write!(
                             self.out,
                             "{}{}(",
-                            scalar_kind_string(kind),
+                            scalar_string(kind, width)?,
                             vector_size_string(size)
                         )?;
                         for (i, &handle) in components.iter().enumerate() {
@@ -480,8 +548,8 @@ impl<W: Write> Writer<W> {
                         }
                         write!(self.out, ")")?;
                     }
-                    crate::TypeInner::Scalar { width: 4, kind } if components.len() == 1 => {
-                        write!(self.out, "{}(", scalar_kind_string(kind))?;
+                    crate::TypeInner::Scalar { width, kind } if components.len() == 1 => {
+                        write!(self.out, "{}(", scalar_string(kind, width)?)?;
                         self.put_expression(components[0], function, module)?;
                         write!(self.out, ")")?;
                     }
@@ -517,11 +585,27 @@ impl<W: Write> Writer<W> {
                 write!(self.out, "{}", name)?;
                 Ok(MaybeOwned::Borrowed(inner))
             }
+            crate::Expression::FunctionParameter(index) => {
+                let argument = &function.arguments[index as usize];
+                let name = argument.name.or_index(ParameterIndex(index as usize));
+                write!(self.out, "{}", name)?;
+                let ty = argument.ty;
+                // pointer parameters are references, so they are used like values
+                Ok(match module.types[ty].inner {
+                    crate::TypeInner::Pointer { base, .. } => module.borrow_type(base),
+                    ref other => MaybeOwned::Borrowed(other),
+                })
+            }
             crate::Expression::Load { pointer } => {
                 //write!(self.out, "*")?;
-                match *self.put_expression(pointer, function, module)?.borrow() {
-                    crate::TypeInner::Pointer { base, .. } => Ok(module.borrow_type(base)),
-                    ref other => Err(Error::UnexpectedLoadPointer(other.clone())),
+                match function.expressions[pointer] {
+                    crate::Expression::FunctionParameter(_) => {
+                        self.put_expression(pointer, function, module)
+                    }
+                    _ => match *self.put_expression(pointer, function, module)?.borrow() {
+                        crate::TypeInner::Pointer { base, .. } => Ok(module.borrow_type(base)),
+                        ref other => Err(Error::UnexpectedLoadPointer(other.clone())),
+                    },
                 }
             }
             crate::Expression::Unary { op, expr } => {
@@ -684,6 +768,17 @@ impl<W: Write> Writer<W> {
 
                 other => Err(Error::UnsupportedCall(other.to_owned())),
             },
+            crate::Expression::Call {
+                origin: crate::FunctionOrigin::Local(handle),
+                ref arguments,
+            } => match module.functions[handle].return_type {
+                Some(ty) => {
+                    self.put_call(handle, arguments, function, module)?;
+                    Ok(module.borrow_type(ty))
+                }
+                // functions returning nothing are called by statements
+                None => Err(Error::UnsupportedExpression(expression.clone())),
+            },
             ref other => Err(Error::UnsupportedExpression(other.clone())),
         }
     }
@@ -692,22 +787,50 @@ impl<W: Write> Writer<W> {
         &mut self,
         handle: Handle<crate::Constant>,
         module: &'a crate::Module,
+    ) -> Result<MaybeOwned<'a, crate::TypeInner>, Error> {
+        let constant = &module.constants[handle];
+        if constant.specialization.is_some() {
+            // declared at the program scope
+            write!(self.out, "{}", constant.name.or_index(handle))?;
+            Ok(module.borrow_type(constant.ty))
+        } else {
+            self.put_constant_value(handle, module)
+        }
+    }
+
+    fn put_constant_value<'a>(
+        &mut self,
+        handle: Handle<crate::Constant>,
+        module: &'a crate::Module,
     ) -> Result<MaybeOwned<'a, crate::TypeInner>, Error> {
         let constant = &module.constants[handle];
         let ty = &module.types[constant.ty];
 
+        let width = match ty.inner {
+            crate::TypeInner::Scalar { width, .. } => width,
+            _ => 4,
+        };
         match constant.inner {
-            crate::ConstantInner::Sint(value) => {
-                write!(self.out, "{}", value)?;
-            }
-            crate::ConstantInner::Uint(value) => {
-                write!(self.out, "{}", value)?;
-            }
+            crate::ConstantInner::Sint(value) => match width {
+                2 => write!(self.out, "short({})", value)?,
+                8 => write!(self.out, "{}l", value)?,
+                _ => write!(self.out, "{}", value)?,
+            },
+            crate::ConstantInner::Uint(value) => match width {
+                2 => write!(self.out, "ushort({}u)", value)?,
+                8 => write!(self.out, "{}ul", value)?,
+                _ => write!(self.out, "{}u", value)?,
+            },
             crate::ConstantInner::Float(value) => {
                 write!(self.out, "{}", value)?;
                 if value.fract() == 0.0 {
                     self.out.write_str(".0")?;
                 }
+                match width {
+                    2 => self.out.write_str("h")?,
+                    8 => return Err(Error::UnsupportedScalarWidth(crate::ScalarKind::Float, 8)),
+                    _ => {}
+                }
             }
             crate::ConstantInner::Bool(value) => {
                 write!(self.out, "{}", value)?;
@@ -728,6 +851,34 @@ impl<W: Write> Writer<W> {
         Ok(MaybeOwned::Borrowed(&ty.inner))
     }
 
+    fn put_call(
+        &mut self,
+        handle: Handle<crate::Function>,
+        arguments: &[Handle<crate::Expression>],
+        function: &crate::Function,
+        module: &crate::Module,
+    ) -> Result<(), Error> {
+        let fun = &module.functions[handle];
+        write!(self.out, "{}(", fun.name.or_index(handle))?;
+        for (index, &argument) in arguments.iter().enumerate() {
+            if index != 0 {
+                write!(self.out, ", ")?;
+            }
+            self.put_expression(argument, function, module)?;
+        }
+        for (index, var_handle) in private_initialized_globals(module, fun).enumerate() {
+            if index != 0 || !arguments.is_empty() {
+                write!(self.out, ", ")?;
+            }
+            let name = module.global_variables[var_handle]
+                .name
+                .or_index(var_handle);
+            write!(self.out, "{}", name)?;
+        }
+        write!(self.out, ")")?;
+        Ok(())
+    }
+
     fn put_statement<'a>(
         &mut self,
         level: Level,
@@ -799,12 +950,21 @@ impl<W: Write> Writer<W> {
                 }
                 writeln!(self.out, ";")?;
             }
+            crate::Statement::Call {
+                function: handle,
+                ref arguments,
+            } => {
+                write!(self.out, "{}", level)?;
+                self.put_call(handle, arguments, function, module)?;
+                writeln!(self.out, ";")?;
+            }
             ref other => return Err(Error::UnsupportedStatement(other.clone())),
         };
         Ok(())
     }
 
     pub fn write(&mut self, module: &crate::Module, options: Options) -> Result<(), Error> {
+        let module = &*options.clip_space.apply(module);
         writeln!(self.out, "#include <metal_stdlib>")?;
         writeln!(self.out, "#include <simd/simd.h>")?;
         writeln!(self.out, "using namespace metal;")?;
@@ -812,6 +972,9 @@ impl<W: Write> Writer<W> {
         writeln!(self.out)?;
         self.write_type_defs(module)?;
 
+        writeln!(self.out)?;
+        self.write_global_constants(module)?;
+
         writeln!(self.out)?;
         self.write_functions(module, options)?;
 
@@ -822,14 +985,14 @@ impl<W: Write> Writer<W> {
         for (handle, ty) in module.types.iter() {
             let name = ty.name.or_index(handle);
             match ty.inner {
-                crate::TypeInner::Scalar { kind, .. } => {
-                    write!(self.out, "typedef {} {}", scalar_kind_string(kind), name)?;
+                crate::TypeInner::Scalar { kind, width } => {
+                    write!(self.out, "typedef {} {}", scalar_string(kind, width)?, name)?;
                 }
-                crate::TypeInner::Vector { size, kind, .. } => {
+                crate::TypeInner::Vector { size, kind, width } => {
                     write!(
                         self.out,
                         "typedef {}{} {}",
-                        scalar_kind_string(kind),
+                        scalar_string(kind, width)?,
                         vector_size_string(size),
                         name
                     )?;
@@ -838,12 +1001,12 @@ impl<W: Write> Writer<W> {
                     columns,
                     rows,
                     kind,
-                    ..
+                    width,
                 } => {
                     write!(
                         self.out,
                         "typedef {}{}x{} {}",
-                        scalar_kind_string(kind),
+                        scalar_string(kind, width)?,
                         vector_size_string(columns),
                         vector_size_string(rows),
                         name
@@ -895,8 +1058,17 @@ impl<W: Write> Writer<W> {
                     }
                     write!(self.out, "}}")?;
                 }
-                crate::TypeInner::Image { base, dim, flags } => {
-                    let base_name = module.types[base].name.or_index(base);
+                crate::TypeInner::Image {
+                    base,
+                    dim,
+                    flags,
+                    format,
+                } => {
+                    // storage textures are accessed with the scalar type of their format
+                    let base_name = match format {
+                        Some(format) => scalar_kind_string(format.scalar_kind()).to_string(),
+                        None => module.types[base].name.or_index(base).to_string(),
+                    };
                     let dim_str = dim_str(dim);
                     let msaa_str = if flags.contains(crate::ImageFlags::MULTISAMPLED) {
                         "_ms"
@@ -948,6 +1120,52 @@ impl<W: Write> Writer<W> {
         Ok(())
     }
 
+    fn write_global_constants(&mut self, module: &crate::Module) -> Result<(), Error> {
+        for (handle, constant) in module.constants.iter() {
+            let id = match constant.specialization {
+                Some(id) => id,
+                None => continue,
+            };
+            let ty_name = module.types[constant.ty].name.or_index(constant.ty);
+            let name = constant.name.or_index(handle);
+            if let crate::ConstantInner::Composite(_) = constant.inner {
+                // composites are only specialized through their components
+                write!(self.out, "constant {} {} = ", ty_name, name)?;
+            } else {
+                writeln!(
+                    self.out,
+                    "constant {} {}_override [[function_constant({})]];",
+                    ty_name, name, id
+                )?;
+                write!(
+                    self.out,
+                    "constant {} {} = is_function_constant_defined({}_override) ? {}_override : ",
+                    ty_name, name, name, name
+                )?;
+            }
+            self.put_constant_value(handle, module)?;
+            writeln!(self.out, ";")?;
+        }
+
+        for (handle, var) in module.global_variables.iter() {
+            if var.class != crate::StorageClass::Constant {
+                continue;
+            }
+            if let Some(init) = var.init {
+                let ty_name = module.types[var.ty].name.or_index(var.ty);
+                write!(
+                    self.out,
+                    "constant {} {} = ",
+                    ty_name,
+                    var.name.or_index(handle)
+                )?;
+                self.put_constant(init, module)?;
+                writeln!(self.out, ";")?;
+            }
+        }
+        Ok(())
+    }
+
     fn write_functions(&mut self, module: &crate::Module, options: Options) -> Result<(), Error> {
         for (fun_handle, fun) in module.functions.iter() {
             let fun_name = fun.name.or_index(fun_handle);
@@ -955,6 +1173,9 @@ impl<W: Write> Writer<W> {
             let mut shader_stage = None;
             let mut last_used_global = None;
             for ((handle, var), &usage) in module.global_variables.iter().zip(&fun.global_usage) {
+                if declared_initializer(var).is_some() {
+                    continue;
+                }
                 match var.class {
                     crate::StorageClass::Input => {
                         if let Some(crate::Binding::Location(_)) = var.binding {
@@ -998,6 +1219,9 @@ impl<W: Write> Writer<W> {
                     crate::ShaderStage::Compute => {
                         ("kernel", LocationMode::Uniform, LocationMode::Uniform)
                     }
+                    crate::ShaderStage::TessellationControl
+                    | crate::ShaderStage::TessellationEvaluation
+                    | crate::ShaderStage::Geometry => return Err(Error::UnsupportedStage(stage)),
                 };
                 let location_input_name = fun.name.or_index(InputStructIndex(fun_handle));
 
@@ -1097,7 +1321,10 @@ impl<W: Write> Writer<W> {
 
                 for ((handle, var), &usage) in module.global_variables.iter().zip(&fun.global_usage)
                 {
-                    if usage.is_empty() || var.class == crate::StorageClass::Output {
+                    if usage.is_empty()
+                        || var.class == crate::StorageClass::Output
+                        || declared_initializer(var).is_some()
+                    {
                         continue;
                     }
                     if var.class == crate::StorageClass::Input {
@@ -1144,13 +1371,47 @@ impl<W: Write> Writer<W> {
                     },
                 };
                 writeln!(self.out, "{} {}(", result_type_name, fun_name)?;
-                for (index, &ty) in fun.parameter_types.iter().enumerate() {
-                    let name = Name::from(ParameterIndex(index));
-                    let member_type_name = module.types[ty].name.or_index(ty);
+                let private_globals = private_initialized_globals(module, fun).collect::<Vec<_>>();
+                for (index, argument) in fun.arguments.iter().enumerate() {
+                    let name = argument.name.or_index(ParameterIndex(index));
                     let separator = separate(
-                        index + 1 == fun.parameter_types.len() && last_used_global.is_none(),
+                        index + 1 == fun.arguments.len()
+                            && private_globals.is_empty()
+                            && last_used_global.is_none(),
                     );
-                    writeln!(self.out, "\t{} {}{}", member_type_name, name, separator)?;
+                    let ty = argument.ty;
+                    match module.types[ty].inner {
+                        // pointers are passed by reference
+                        crate::TypeInner::Pointer { base, class } => {
+                            let base_name = module.types[base].name.or_index(base);
+                            writeln!(
+                                self.out,
+                                "\t{} {} &{}{}",
+                                address_space_string(class),
+                                base_name,
+                                name,
+                                separator
+                            )?;
+                        }
+                        _ => {
+                            let member_type_name = module.types[ty].name.or_index(ty);
+                            writeln!(self.out, "\t{} {}{}", member_type_name, name, separator)?;
+                        }
+                    }
+                }
+                // private globals with initializers are owned by the entry point
+                for (index, &handle) in private_globals.iter().enumerate() {
+                    let var = &module.global_variables[handle];
+                    let ty_name = module.types[var.ty].name.or_index(var.ty);
+                    let separator =
+                        separate(index + 1 == private_globals.len() && last_used_global.is_none());
+                    writeln!(
+                        self.out,
+                        "\tthread {} &{}{}",
+                        ty_name,
+                        var.name.or_index(handle),
+                        separator
+                    )?;
                 }
             }
             writeln!(self.out, ") {{")?;
@@ -1161,8 +1422,23 @@ impl<W: Write> Writer<W> {
                     writeln!(self.out, "\t{} {};", output_name, OUTPUT_STRUCT_NAME)?;
                     true
                 }
-                Some(crate::ShaderStage::Compute) | None => false,
+                _ => false,
             };
+            if shader_stage.is_some() {
+                // private variables with initializers live in the entry point
+                for ((handle, var), &usage) in module.global_variables.iter().zip(&fun.global_usage)
+                {
+                    if usage.is_empty() || var.class != crate::StorageClass::Private {
+                        continue;
+                    }
+                    if let Some(init) = var.init {
+                        let ty_name = module.types[var.ty].name.or_index(var.ty);
+                        write!(self.out, "\t{} {} = ", ty_name, var.name.or_index(handle))?;
+                        self.put_constant(init, module)?;
+                        writeln!(self.out, ";")?;
+                    }
+                }
+            }
             for (local_handle, local) in fun.local_variables.iter() {
                 let ty_name = module.types[local.ty].name.or_index(local.ty);
                 write!(
@@ -12,10 +12,38 @@ pub use writer::Writer;
 
 use spirv::*;
 
+#[derive(Clone, Debug)]
+pub enum Error {
+    UnexpectedLoadPointer(crate::TypeInner),
+    UnsupportedExpression(crate::Expression),
+    UnsupportedStatement(crate::Statement),
+    /// Functions returning nothing can only be called by statements.
+    VoidCallExpression(crate::Handle<crate::Function>),
+}
+
 bitflags::bitflags! {
     pub struct WriterFlags: u32 {
         const NONE = 0x0;
         const DEBUG = 0x1;
+        /// Negate the Y coordinate of the position written by the vertex shaders.
+        const FLIP_Y = 0x2;
+        /// Convert the depth of the position written by the vertex shaders
+        /// to the `[-1, 1]` range of GL.
+        const DEPTH_UNIT_TO_SIGNED = 0x4;
+    }
+}
+
+impl WriterFlags {
+    /// Returns the adjustment of the position requested by the flags.
+    fn clip_space(self) -> crate::proc::ClipSpaceAdjustment {
+        crate::proc::ClipSpaceAdjustment {
+            flip_y: self.contains(WriterFlags::FLIP_Y),
+            depth: if self.contains(WriterFlags::DEPTH_UNIT_TO_SIGNED) {
+                Some(crate::proc::DepthConversion::UnitToSigned)
+            } else {
+                None
+            },
+        }
     }
 }
 
@@ -1,5 +1,5 @@
 /*! Standard Portable Intermediate Representation (SPIR-V) backend !*/
-use super::{helpers, Instruction, LogicalLayout, PhysicalLayout, WriterFlags};
+use super::{helpers, Error, Instruction, LogicalLayout, PhysicalLayout, WriterFlags};
 use crate::{FastHashMap, FastHashSet, ImageFlags, VectorSize};
 use spirv::{Op, Word};
 
@@ -51,6 +51,101 @@ fn map_dim(dim: crate::ImageDimension) -> spirv::Dim {
     }
 }
 
+fn scalar_capabilities(
+    kind: crate::ScalarKind,
+    width: crate::Bytes,
+) -> &'static [spirv::Capability] {
+    match (kind, width) {
+        (crate::ScalarKind::Float, 2) => &[spirv::Capability::Float16],
+        (crate::ScalarKind::Float, 8) => &[spirv::Capability::Float64],
+        (crate::ScalarKind::Sint, 2) | (crate::ScalarKind::Uint, 2) => &[spirv::Capability::Int16],
+        (crate::ScalarKind::Sint, 8) | (crate::ScalarKind::Uint, 8) => &[spirv::Capability::Int64],
+        _ => &[],
+    }
+}
+
+/// Converts a float into the bits of the closest half-precision value.
+fn f64_to_f16_bits(value: f64) -> Word {
+    let bits = (value as f32).to_bits();
+    let sign = (bits >> 16) & 0x8000;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x007f_ffff;
+    if exponent == 0xff {
+        // infinity or NaN
+        return sign | 0x7c00 | if mantissa != 0 { 0x200 } else { 0 };
+    }
+    let half_exponent = exponent - 127 + 15;
+    if half_exponent >= 0x1f {
+        // overflow to infinity
+        sign | 0x7c00
+    } else if half_exponent <= 0 {
+        if half_exponent < -10 {
+            // underflow to zero
+            return sign;
+        }
+        // subnormal
+        let mantissa = mantissa | 0x0080_0000;
+        let shift = (14 - half_exponent) as u32;
+        sign | ((mantissa >> shift) + ((mantissa >> (shift - 1)) & 1))
+    } else {
+        let rounding = (mantissa >> 12) & 1;
+        sign | ((((half_exponent as u32) << 10) | (mantissa >> 13)) + rounding)
+    }
+}
+
+fn map_storage_class(class: crate::StorageClass) -> spirv::StorageClass {
+    match class {
+        crate::StorageClass::Constant => spirv::StorageClass::UniformConstant,
+        crate::StorageClass::Function => spirv::StorageClass::Function,
+        crate::StorageClass::Input => spirv::StorageClass::Input,
+        crate::StorageClass::Output => spirv::StorageClass::Output,
+        crate::StorageClass::Private => spirv::StorageClass::Private,
+        crate::StorageClass::StorageBuffer => spirv::StorageClass::StorageBuffer,
+        crate::StorageClass::Uniform => spirv::StorageClass::Uniform,
+        crate::StorageClass::WorkGroup => spirv::StorageClass::Workgroup,
+    }
+}
+
+fn map_image_format(format: Option<crate::StorageFormat>) -> spirv::ImageFormat {
+    use crate::StorageFormat as Sf;
+    use spirv::ImageFormat as If;
+    match format {
+        None => If::Unknown,
+        Some(Sf::R8Unorm) => If::R8,
+        Some(Sf::R8Snorm) => If::R8Snorm,
+        Some(Sf::R8Uint) => If::R8ui,
+        Some(Sf::R8Sint) => If::R8i,
+        Some(Sf::R16Uint) => If::R16ui,
+        Some(Sf::R16Sint) => If::R16i,
+        Some(Sf::R16Float) => If::R16f,
+        Some(Sf::Rg8Unorm) => If::Rg8,
+        Some(Sf::Rg8Snorm) => If::Rg8Snorm,
+        Some(Sf::Rg8Uint) => If::Rg8ui,
+        Some(Sf::Rg8Sint) => If::Rg8i,
+        Some(Sf::R32Uint) => If::R32ui,
+        Some(Sf::R32Sint) => If::R32i,
+        Some(Sf::R32Float) => If::R32f,
+        Some(Sf::Rg16Uint) => If::Rg16ui,
+        Some(Sf::Rg16Sint) => If::Rg16i,
+        Some(Sf::Rg16Float) => If::Rg16f,
+        Some(Sf::Rgba8Unorm) => If::Rgba8,
+        Some(Sf::Rgba8Snorm) => If::Rgba8Snorm,
+        Some(Sf::Rgba8Uint) => If::Rgba8ui,
+        Some(Sf::Rgba8Sint) => If::Rgba8i,
+        Some(Sf::Rgb10a2Unorm) => If::Rgb10A2,
+        Some(Sf::Rg11b10Float) => If::R11fG11fB10f,
+        Some(Sf::Rg32Uint) => If::Rg32ui,
+        Some(Sf::Rg32Sint) => If::Rg32i,
+        Some(Sf::Rg32Float) => If::Rg32f,
+        Some(Sf::Rgba16Uint) => If::Rgba16ui,
+        Some(Sf::Rgba16Sint) => If::Rgba16i,
+        Some(Sf::Rgba16Float) => If::Rgba16f,
+        Some(Sf::Rgba32Uint) => If::Rgba32ui,
+        Some(Sf::Rgba32Sint) => If::Rgba32i,
+        Some(Sf::Rgba32Float) => If::Rgba32f,
+    }
+}
+
 #[derive(Debug, PartialEq)]
 struct LookupFunctionType {
     parameter_type_ids: Vec<Word>,
@@ -71,6 +166,13 @@ pub struct Writer {
     lookup_function_type: FastHashMap<Word, LookupFunctionType>,
     lookup_constant: FastHashMap<Word, crate::Handle<crate::Constant>>,
     lookup_global_variable: FastHashMap<Word, crate::Handle<crate::GlobalVariable>>,
+    /// Pointer and scalar types that aren't in the type arena.
+    lookup_pointer_type: FastHashMap<(crate::Handle<crate::Type>, spirv::StorageClass), Word>,
+    lookup_scalar_type: FastHashMap<(crate::ScalarKind, crate::Bytes), Word>,
+    /// Ids of the parameters of the function being written.
+    parameter_ids: Vec<Word>,
+    /// Ids of the local variables of the function being written.
+    local_variable_ids: Vec<Word>,
 }
 
 impl Writer {
@@ -89,6 +191,10 @@ impl Writer {
             lookup_function_type: FastHashMap::default(),
             lookup_constant: FastHashMap::default(),
             lookup_global_variable: FastHashMap::default(),
+            lookup_pointer_type: FastHashMap::default(),
+            lookup_scalar_type: FastHashMap::default(),
+            parameter_ids: vec![],
+            local_variable_ids: vec![],
         }
     }
 
@@ -135,15 +241,13 @@ impl Writer {
 
     fn get_global_variable_id(
         &mut self,
-        arena: &crate::Arena<crate::Type>,
-        global_arena: &crate::Arena<crate::GlobalVariable>,
+        ir_module: &crate::Module,
         handle: crate::Handle<crate::GlobalVariable>,
     ) -> Word {
         match self.lookup_global_variable.lookup_id(handle) {
             Some(word) => word,
             None => {
-                let global_variable = &global_arena[handle];
-                let (instruction, id) = self.parse_global_variable(arena, global_variable, handle);
+                let (instruction, id) = self.parse_global_variable(ir_module, handle);
                 instruction.to_words(&mut self.logical_layout.declarations);
                 id
             }
@@ -176,24 +280,49 @@ impl Writer {
         handle: crate::Handle<crate::Type>,
         class: spirv::StorageClass,
     ) -> Word {
-        let ty = &arena[handle];
+        if let crate::TypeInner::Pointer { .. } = arena[handle].inner {
+            return self.get_type_id(arena, handle);
+        }
+        // reuse the pointer types of the arena, which are declared by handle
+        let existing = arena.iter().find(|&(_, ty)| match ty.inner {
+            crate::TypeInner::Pointer {
+                base,
+                class: pointer_class,
+            } => base == handle && map_storage_class(pointer_class) == class,
+            _ => false,
+        });
+        if let Some((pointer_handle, _)) = existing {
+            return self.get_type_id(arena, pointer_handle);
+        }
+        if let Some(&id) = self.lookup_pointer_type.get(&(handle, class)) {
+            return id;
+        }
         let type_id = self.get_type_id(arena, handle);
-        match ty.inner {
-            crate::TypeInner::Pointer { .. } => type_id,
-            _ => {
-                let pointer_id = self.generate_id();
-                let instruction = self.instruction_type_pointer(pointer_id, class, type_id);
-                instruction.to_words(&mut self.logical_layout.declarations);
+        let pointer_id = self.generate_id();
+        let instruction = self.instruction_type_pointer(pointer_id, class, type_id);
+        instruction.to_words(&mut self.logical_layout.declarations);
+        self.lookup_pointer_type.insert((handle, class), pointer_id);
+        pointer_id
+    }
 
-                /* TODO
-                    Not able to lookup Pointer, because there is no Handle in the IR for it.
-                    Idea would be to not have any handles at all in the lookups, so we aren't bound
-                    to the IR. We can then insert, like here runtime values to the lookups
-                */
-                // self.lookup_type.insert(pointer_id, global_variable.ty);
-                pointer_id
-            }
+    fn get_scalar_type_id(
+        &mut self,
+        arena: &crate::Arena<crate::Type>,
+        kind: crate::ScalarKind,
+        width: crate::Bytes,
+    ) -> Word {
+        // reuse the scalar type of the arena, which is declared by handle
+        if let Some(handle) = self.find_scalar_handle(arena, kind, width) {
+            return self.get_type_id(arena, handle);
+        }
+        if let Some(&id) = self.lookup_scalar_type.get(&(kind, width)) {
+            return id;
         }
+        let id = self.generate_id();
+        let instruction = self.instruction_type_scalar(id, kind, width);
+        instruction.to_words(&mut self.logical_layout.declarations);
+        self.lookup_scalar_type.insert((kind, width), id);
+        id
     }
 
     fn find_scalar_handle(
@@ -201,7 +330,7 @@ impl Writer {
         arena: &crate::Arena<crate::Type>,
         kind: crate::ScalarKind,
         width: u8,
-    ) -> crate::Handle<crate::Type> {
+    ) -> Option<crate::Handle<crate::Type>> {
         let mut scalar_handle = None;
         for (handle, ty) in arena.iter() {
             match ty.inner {
@@ -217,7 +346,7 @@ impl Writer {
                 _ => continue,
             }
         }
-        scalar_handle.unwrap()
+        scalar_handle
     }
 
     ///
@@ -300,6 +429,11 @@ impl Writer {
 
         let exec_model = match entry_point.stage {
             crate::ShaderStage::Vertex => spirv::ExecutionModel::Vertex,
+            crate::ShaderStage::TessellationControl => spirv::ExecutionModel::TessellationControl,
+            crate::ShaderStage::TessellationEvaluation => {
+                spirv::ExecutionModel::TessellationEvaluation
+            }
+            crate::ShaderStage::Geometry => spirv::ExecutionModel::Geometry,
             crate::ShaderStage::Fragment => spirv::ExecutionModel::Fragment,
             crate::ShaderStage::Compute => spirv::ExecutionModel::GLCompute,
         };
@@ -315,26 +449,16 @@ impl Writer {
             .zip(&function.global_usage)
         {
             if usage.contains(crate::GlobalUse::STORE) || usage.contains(crate::GlobalUse::LOAD) {
-                let id = self.get_global_variable_id(
-                    &ir_module.types,
-                    &ir_module.global_variables,
-                    handle,
-                );
+                let id = self.get_global_variable_id(ir_module, handle);
                 instruction.add_operand(id);
             }
         }
 
         self.try_add_capabilities(exec_model.required_capabilities());
-        match entry_point.stage {
-            crate::ShaderStage::Vertex => {}
-            crate::ShaderStage::Fragment => {
-                let execution_mode = spirv::ExecutionMode::OriginUpperLeft;
-                self.try_add_capabilities(execution_mode.required_capabilities());
-                self.instruction_execution_mode(function_id, execution_mode)
-                    .to_words(&mut self.logical_layout.execution_modes);
-            }
-            crate::ShaderStage::Compute => {}
+        if entry_point.stage == crate::ShaderStage::Fragment {
+            self.write_execution_mode(function_id, spirv::ExecutionMode::OriginUpperLeft, &[]);
         }
+        self.write_execution_modes(function_id, &entry_point.modes);
 
         if self.writer_flags.contains(WriterFlags::DEBUG) {
             self.debugs
@@ -344,14 +468,92 @@ impl Writer {
         instruction
     }
 
+    fn write_execution_mode(
+        &mut self,
+        function_id: Word,
+        execution_mode: spirv::ExecutionMode,
+        operands: &[Word],
+    ) {
+        self.try_add_capabilities(execution_mode.required_capabilities());
+        self.instruction_execution_mode(function_id, execution_mode, operands)
+            .to_words(&mut self.logical_layout.execution_modes);
+    }
+
+    fn write_execution_modes(&mut self, function_id: Word, modes: &crate::ExecutionModes) {
+        use crate::PrimitiveTopology as Pt;
+
+        if let Some(topology) = modes.input_topology {
+            let execution_mode = match topology {
+                Pt::Points => spirv::ExecutionMode::InputPoints,
+                Pt::Lines | Pt::LineStrip => spirv::ExecutionMode::InputLines,
+                Pt::LinesAdjacency => spirv::ExecutionMode::InputLinesAdjacency,
+                Pt::Triangles | Pt::TriangleStrip => spirv::ExecutionMode::Triangles,
+                Pt::TrianglesAdjacency => spirv::ExecutionMode::InputTrianglesAdjacency,
+                Pt::Quads => spirv::ExecutionMode::Quads,
+                Pt::Isolines => spirv::ExecutionMode::Isolines,
+            };
+            self.write_execution_mode(function_id, execution_mode, &[]);
+        }
+        if let Some(topology) = modes.output_topology {
+            let execution_mode = match topology {
+                Pt::Points => spirv::ExecutionMode::OutputPoints,
+                Pt::Lines | Pt::LinesAdjacency | Pt::LineStrip | Pt::Isolines => {
+                    spirv::ExecutionMode::OutputLineStrip
+                }
+                Pt::Triangles | Pt::TrianglesAdjacency | Pt::TriangleStrip | Pt::Quads => {
+                    spirv::ExecutionMode::OutputTriangleStrip
+                }
+            };
+            self.write_execution_mode(function_id, execution_mode, &[]);
+        }
+        if let Some(vertices) = modes.output_vertices {
+            self.write_execution_mode(
+                function_id,
+                spirv::ExecutionMode::OutputVertices,
+                &[vertices],
+            );
+        }
+        if let Some(invocations) = modes.invocations {
+            self.write_execution_mode(
+                function_id,
+                spirv::ExecutionMode::Invocations,
+                &[invocations],
+            );
+        }
+        if let Some(spacing) = modes.spacing {
+            let execution_mode = match spacing {
+                crate::TessellationSpacing::Equal => spirv::ExecutionMode::SpacingEqual,
+                crate::TessellationSpacing::FractionalEven => {
+                    spirv::ExecutionMode::SpacingFractionalEven
+                }
+                crate::TessellationSpacing::FractionalOdd => {
+                    spirv::ExecutionMode::SpacingFractionalOdd
+                }
+            };
+            self.write_execution_mode(function_id, execution_mode, &[]);
+        }
+        if let Some(order) = modes.vertex_order {
+            let execution_mode = match order {
+                crate::VertexOrder::Cw => spirv::ExecutionMode::VertexOrderCw,
+                crate::VertexOrder::Ccw => spirv::ExecutionMode::VertexOrderCcw,
+            };
+            self.write_execution_mode(function_id, execution_mode, &[]);
+        }
+        if let Some(size) = modes.workgroup_size {
+            self.write_execution_mode(function_id, spirv::ExecutionMode::LocalSize, &size);
+        }
+    }
+
     fn instruction_execution_mode(
         &self,
         function_id: Word,
         execution_mode: spirv::ExecutionMode,
+        operands: &[Word],
     ) -> Instruction {
         let mut instruction = Instruction::new(Op::ExecutionMode);
         instruction.add_operand(function_id);
         instruction.add_operand(execution_mode as u32);
+        instruction.add_operands(Vec::from(operands));
         instruction
     }
 
@@ -392,6 +594,27 @@ impl Writer {
         instruction
     }
 
+    fn instruction_type_scalar(
+        &mut self,
+        id: Word,
+        kind: crate::ScalarKind,
+        width: crate::Bytes,
+    ) -> Instruction {
+        self.try_add_capabilities(scalar_capabilities(kind, width));
+        match kind {
+            crate::ScalarKind::Sint => {
+                self.instruction_type_int(id, (width * BITS_PER_BYTE) as u32, Signedness::Signed)
+            }
+            crate::ScalarKind::Uint => {
+                self.instruction_type_int(id, (width * BITS_PER_BYTE) as u32, Signedness::Unsigned)
+            }
+            crate::ScalarKind::Float => {
+                self.instruction_type_float(id, (width * BITS_PER_BYTE) as u32)
+            }
+            crate::ScalarKind::Bool => self.instruction_type_bool(id),
+        }
+    }
+
     fn instruction_type_vector(
         &self,
         id: Word,
@@ -424,6 +647,7 @@ impl Writer {
         sampled_type_id: Word,
         dim: spirv::Dim,
         flags: ImageFlags,
+        format: spirv::ImageFormat,
         comparison: bool,
     ) -> Instruction {
         let mut instruction = Instruction::new(Op::TypeImage);
@@ -447,12 +671,13 @@ impl Writer {
 
         instruction.add_operand(if flags.contains(crate::ImageFlags::SAMPLED) {
             1
+        } else if flags.intersects(crate::ImageFlags::CAN_LOAD | crate::ImageFlags::CAN_STORE) {
+            2
         } else {
             0
         });
 
-        // TODO Image Format defaults to Unknown, not yet in IR
-        instruction.add_operand(spirv::ImageFormat::Unknown as u32);
+        instruction.add_operand(format as u32);
 
         // Access Qualifier
         instruction.add_operand(
@@ -644,6 +869,28 @@ impl Writer {
         instruction
     }
 
+    fn instruction_function_parameter(&self, result_type_id: Word, id: Word) -> Instruction {
+        let mut instruction = Instruction::new(Op::FunctionParameter);
+        instruction.set_type(result_type_id);
+        instruction.set_result(id);
+        instruction
+    }
+
+    fn instruction_function_call(
+        &self,
+        result_type_id: Word,
+        id: Word,
+        function_id: Word,
+        argument_ids: &[Word],
+    ) -> Instruction {
+        let mut instruction = Instruction::new(Op::FunctionCall);
+        instruction.set_type(result_type_id);
+        instruction.set_result(id);
+        instruction.add_operand(function_id);
+        instruction.add_operands(Vec::from(argument_ids));
+        instruction
+    }
+
     fn instruction_function_end(&self) -> Instruction {
         Instruction::new(Op::FunctionEnd)
     }
@@ -673,6 +920,21 @@ impl Writer {
         instruction
     }
 
+    fn instruction_composite_extract(
+        &self,
+        result_type_id: Word,
+        id: Word,
+        composite_id: Word,
+        indices: &[Word],
+    ) -> Instruction {
+        let mut instruction = Instruction::new(Op::CompositeExtract);
+        instruction.set_type(result_type_id);
+        instruction.set_result(id);
+        instruction.add_operand(composite_id);
+        instruction.add_operands(Vec::from(indices));
+        instruction
+    }
+
     ///
     /// Arithmetic Instructions
     ///
@@ -692,6 +954,36 @@ impl Writer {
         instruction
     }
 
+    fn instruction_unary(
+        &self,
+        op: Op,
+        result_type_id: Word,
+        id: Word,
+        operand_id: Word,
+    ) -> Instruction {
+        let mut instruction = Instruction::new(op);
+        instruction.set_type(result_type_id);
+        instruction.set_result(id);
+        instruction.add_operand(operand_id);
+        instruction
+    }
+
+    fn instruction_binary(
+        &self,
+        op: Op,
+        result_type_id: Word,
+        id: Word,
+        left_id: Word,
+        right_id: Word,
+    ) -> Instruction {
+        let mut instruction = Instruction::new(op);
+        instruction.set_type(result_type_id);
+        instruction.set_result(id);
+        instruction.add_operand(left_id);
+        instruction.add_operand(right_id);
+        instruction
+    }
+
     ///
     /// Bit Instructions
     ///
@@ -744,27 +1036,11 @@ impl Writer {
 
         match ty.inner {
             crate::TypeInner::Scalar { kind, width } => {
-                instruction = match kind {
-                    crate::ScalarKind::Sint => self.instruction_type_int(
-                        id,
-                        (width * BITS_PER_BYTE) as u32,
-                        Signedness::Signed,
-                    ),
-                    crate::ScalarKind::Uint => self.instruction_type_int(
-                        id,
-                        (width * BITS_PER_BYTE) as u32,
-                        Signedness::Unsigned,
-                    ),
-                    crate::ScalarKind::Float => {
-                        self.instruction_type_float(id, (width * BITS_PER_BYTE) as u32)
-                    }
-                    crate::ScalarKind::Bool => self.instruction_type_bool(id),
-                };
+                instruction = self.instruction_type_scalar(id, kind, width);
                 self.lookup_type.insert(id, handle);
             }
             crate::TypeInner::Vector { size, kind, width } => {
-                let scalar_handle = self.find_scalar_handle(arena, kind, width);
-                let scalar_id = self.get_type_id(arena, scalar_handle);
+                let scalar_id = self.get_scalar_type_id(arena, kind, width);
                 instruction = self.instruction_type_vector(id, scalar_id, size);
                 self.lookup_type.insert(id, handle);
             }
@@ -774,18 +1050,24 @@ impl Writer {
                 kind,
                 width,
             } => {
-                let scalar_handle = self.find_scalar_handle(arena, kind, width);
-                let scalar_id = self.get_type_id(arena, scalar_handle);
+                let scalar_id = self.get_scalar_type_id(arena, kind, width);
 
                 instruction = self.instruction_type_matrix(id, scalar_id, columns);
                 self.lookup_type.insert(id, handle);
             }
-            crate::TypeInner::Image { base, dim, flags } => {
+            crate::TypeInner::Image {
+                base,
+                dim,
+                flags,
+                format,
+            } => {
                 let type_id = self.get_type_id(arena, base);
                 let dim = map_dim(dim);
                 self.try_add_capabilities(dim.required_capabilities());
+                let format = map_image_format(format);
+                self.try_add_capabilities(format.required_capabilities());
 
-                instruction = self.instruction_type_image(id, type_id, dim, flags, false);
+                instruction = self.instruction_type_image(id, type_id, dim, flags, format, false);
                 self.lookup_type.insert(id, base);
             }
             crate::TypeInner::DepthImage { dim, arrayed } => {
@@ -798,7 +1080,14 @@ impl Writer {
                 } else {
                     crate::ImageFlags::empty()
                 };
-                instruction = self.instruction_type_image(id, type_id, dim, flags, true);
+                instruction = self.instruction_type_image(
+                    id,
+                    type_id,
+                    dim,
+                    flags,
+                    spirv::ImageFormat::Unknown,
+                    true,
+                );
                 //self.lookup_type.insert(id, base);
             }
             crate::TypeInner::Sampler { comparison: _ } => {
@@ -835,17 +1124,7 @@ impl Writer {
             }
             crate::TypeInner::Pointer { base, class } => {
                 let type_id = self.get_type_id(arena, base);
-                let storage_class = match class {
-                    crate::StorageClass::Constant => spirv::StorageClass::UniformConstant,
-                    crate::StorageClass::Function => spirv::StorageClass::Function,
-                    crate::StorageClass::Input => spirv::StorageClass::Input,
-                    crate::StorageClass::Output => spirv::StorageClass::Output,
-                    crate::StorageClass::Private => spirv::StorageClass::Private,
-                    crate::StorageClass::StorageBuffer => spirv::StorageClass::StorageBuffer,
-                    crate::StorageClass::Uniform => spirv::StorageClass::Uniform,
-                    crate::StorageClass::WorkGroup => spirv::StorageClass::Workgroup,
-                };
-                instruction = self.instruction_type_pointer(id, storage_class, type_id);
+                instruction = self.instruction_type_pointer(id, map_storage_class(class), type_id);
                 self.lookup_type.insert(id, handle);
             }
         };
@@ -863,16 +1142,17 @@ impl Writer {
         let constant = &ir_module.constants[handle];
         let arena = &ir_module.types;
 
-        match constant.inner {
+        let (mut instruction, id) = match constant.inner {
             crate::ConstantInner::Sint(val) => {
                 let ty = &ir_module.types[constant.ty];
                 let type_id = self.get_type_id(arena, constant.ty);
 
                 let instruction = match ty.inner {
                     crate::TypeInner::Scalar { kind: _, width } => match width {
-                        4 => self.instruction_constant(type_id, id, &[val as u32]),
+                        // narrow literals are sign-extended to a full word
+                        2 | 4 => self.instruction_constant(type_id, id, &[val as i32 as u32]),
                         8 => {
-                            let (low, high) = ((val >> 32) as u32, val as u32);
+                            let (low, high) = (val as u32, (val >> 32) as u32);
                             self.instruction_constant(type_id, id, &[low, high])
                         }
                         _ => unreachable!(),
@@ -887,9 +1167,9 @@ impl Writer {
 
                 let instruction = match ty.inner {
                     crate::TypeInner::Scalar { kind: _, width } => match width {
-                        4 => self.instruction_constant(type_id, id, &[val as u32]),
+                        2 | 4 => self.instruction_constant(type_id, id, &[val as u32]),
                         8 => {
-                            let (low, high) = ((val >> 32) as u32, val as u32);
+                            let (low, high) = (val as u32, (val >> 32) as u32);
                             self.instruction_constant(type_id, id, &[low, high])
                         }
                         _ => unreachable!(),
@@ -905,10 +1185,11 @@ impl Writer {
 
                 let instruction = match ty.inner {
                     crate::TypeInner::Scalar { kind: _, width } => match width {
+                        2 => self.instruction_constant(type_id, id, &[f64_to_f16_bits(val)]),
                         4 => self.instruction_constant(type_id, id, &[(val as f32).to_bits()]),
                         8 => {
                             let bits = f64::to_bits(val);
-                            let (low, high) = ((bits >> 32) as u32, bits as u32);
+                            let (low, high) = (bits as u32, (bits >> 32) as u32);
                             self.instruction_constant(type_id, id, &[low, high])
                         }
                         _ => unreachable!(),
@@ -939,31 +1220,44 @@ impl Writer {
                 let instruction = self.instruction_constant_composite(type_id, id, constituent_ids);
                 (instruction, id)
             }
+        };
+
+        if let Some(spec_id) = constant.specialization {
+            instruction.op = match instruction.op {
+                Op::ConstantTrue => Op::SpecConstantTrue,
+                Op::ConstantFalse => Op::SpecConstantFalse,
+                Op::ConstantComposite => Op::SpecConstantComposite,
+                _ => Op::SpecConstant,
+            };
+            // composites are specialized through their constituents only
+            if instruction.op != Op::SpecConstantComposite {
+                self.annotations.push(self.instruction_decorate(
+                    id,
+                    spirv::Decoration::SpecId,
+                    &[spec_id],
+                ));
+            }
         }
+
+        (instruction, id)
     }
 
     fn parse_global_variable(
         &mut self,
-        arena: &crate::Arena<crate::Type>,
-        global_variable: &crate::GlobalVariable,
+        ir_module: &crate::Module,
         handle: crate::Handle<crate::GlobalVariable>,
     ) -> (Instruction, Word) {
+        let global_variable = &ir_module.global_variables[handle];
+        let initializer_id = global_variable
+            .init
+            .map(|constant| self.get_constant_id(constant, ir_module));
         let id = self.generate_id();
 
-        let class = match global_variable.class {
-            crate::StorageClass::Constant => spirv::StorageClass::UniformConstant,
-            crate::StorageClass::Function => spirv::StorageClass::Function,
-            crate::StorageClass::Input => spirv::StorageClass::Input,
-            crate::StorageClass::Output => spirv::StorageClass::Output,
-            crate::StorageClass::Private => spirv::StorageClass::Private,
-            crate::StorageClass::StorageBuffer => spirv::StorageClass::StorageBuffer,
-            crate::StorageClass::Uniform => spirv::StorageClass::Uniform,
-            crate::StorageClass::WorkGroup => spirv::StorageClass::Workgroup,
-        };
+        let class = map_storage_class(global_variable.class);
         self.try_add_capabilities(class.required_capabilities());
 
-        let pointer_id = self.get_pointer_id(arena, global_variable.ty, class);
-        let instruction = self.instruction_variable(pointer_id, id, class, None);
+        let pointer_id = self.get_pointer_id(&ir_module.types, global_variable.ty, class);
+        let instruction = self.instruction_variable(pointer_id, id, class, initializer_id);
 
         if self.writer_flags.contains(WriterFlags::DEBUG) {
             self.debugs
@@ -999,6 +1293,11 @@ impl Writer {
                     crate::BuiltIn::Position => spirv::BuiltIn::Position,
                     crate::BuiltIn::VertexIndex => spirv::BuiltIn::VertexIndex,
                     crate::BuiltIn::PointSize => spirv::BuiltIn::PointSize,
+                    crate::BuiltIn::InvocationId => spirv::BuiltIn::InvocationId,
+                    crate::BuiltIn::TessCoord => spirv::BuiltIn::TessCoord,
+                    crate::BuiltIn::TessLevelOuter => spirv::BuiltIn::TessLevelOuter,
+                    crate::BuiltIn::TessLevelInner => spirv::BuiltIn::TessLevelInner,
+                    crate::BuiltIn::PrimitiveId => spirv::BuiltIn::PrimitiveId,
                     crate::BuiltIn::FragCoord => spirv::BuiltIn::FragCoord,
                     crate::BuiltIn::FrontFacing => spirv::BuiltIn::FrontFacing,
                     crate::BuiltIn::SampleIndex => spirv::BuiltIn::SampleId,
@@ -1017,8 +1316,6 @@ impl Writer {
             }
         }
 
-        // TODO Initializer is optional and not (yet) included in the IR
-
         self.lookup_global_variable.insert(id, handle);
         (instruction, id)
     }
@@ -1053,19 +1350,20 @@ impl Writer {
         handle: crate::Handle<crate::Function>,
         function: &crate::Function,
         arena: &crate::Arena<crate::Type>,
-    ) -> Instruction {
-        let id = self.generate_id();
+        output: &mut Vec<Instruction>,
+    ) {
+        let id = self.lookup_function.lookup_id(handle).unwrap();
 
         let return_type_id = self.get_function_type(function.return_type, arena);
 
-        let mut parameter_type_ids = Vec::with_capacity(function.parameter_types.len());
-        for parameter_type in function.parameter_types.iter() {
-            parameter_type_ids.push(self.get_type_id(arena, *parameter_type))
+        let mut parameter_type_ids = Vec::with_capacity(function.arguments.len());
+        for argument in function.arguments.iter() {
+            parameter_type_ids.push(self.get_type_id(arena, argument.ty))
         }
 
         let lookup_function_type = LookupFunctionType {
             return_type_id,
-            parameter_type_ids,
+            parameter_type_ids: parameter_type_ids.clone(),
         };
 
         let type_function_id = self.parse_function_type(lookup_function_type);
@@ -1076,9 +1374,20 @@ impl Writer {
             spirv::FunctionControl::empty(),
             type_function_id,
         );
+        output.push(instruction);
 
-        self.lookup_function.insert(id, handle);
-        instruction
+        self.parameter_ids.clear();
+        for (argument, parameter_type_id) in function.arguments.iter().zip(parameter_type_ids) {
+            let parameter_id = self.generate_id();
+            output.push(self.instruction_function_parameter(parameter_type_id, parameter_id));
+            self.parameter_ids.push(parameter_id);
+
+            if self.writer_flags.contains(WriterFlags::DEBUG) {
+                if let Some(ref name) = argument.name {
+                    self.debugs.push(self.instruction_name(parameter_id, name));
+                }
+            }
+        }
     }
 
     fn parse_expression<'a>(
@@ -1087,18 +1396,44 @@ impl Writer {
         function: &crate::Function,
         expression: &crate::Expression,
         output: &mut Vec<Instruction>,
-    ) -> (Word, &'a crate::TypeInner) {
-        match expression {
+    ) -> Result<(Word, &'a crate::TypeInner), Error> {
+        Ok(match expression {
             crate::Expression::GlobalVariable(handle) => {
                 let var = &ir_module.global_variables[*handle];
                 let inner = &ir_module.types[var.ty].inner;
-                let id = self.get_global_variable_id(
-                    &ir_module.types,
-                    &ir_module.global_variables,
-                    *handle,
-                );
+                let id = self.get_global_variable_id(ir_module, *handle);
                 (id, inner)
             }
+            crate::Expression::FunctionParameter(index) => {
+                let ty = function.arguments[*index as usize].ty;
+                let inner = &ir_module.types[ty].inner;
+                (self.parameter_ids[*index as usize], inner)
+            }
+            crate::Expression::Load { pointer } => {
+                let pointer_expression = &function.expressions[*pointer];
+                let (pointer_id, pointer_inner) =
+                    self.parse_expression(ir_module, function, pointer_expression, output)?;
+                match *pointer_inner {
+                    crate::TypeInner::Pointer { base, .. } => {
+                        let id = self.generate_id();
+                        let type_id = self.get_type_id(&ir_module.types, base);
+                        output.push(self.instruction_load(type_id, id, pointer_id, None));
+                        (id, &ir_module.types[base].inner)
+                    }
+                    ref other => return Err(Error::UnexpectedLoadPointer(other.clone())),
+                }
+            }
+            crate::Expression::Call {
+                origin: crate::FunctionOrigin::Local(handle),
+                arguments,
+            } => {
+                let return_type = ir_module.functions[*handle]
+                    .return_type
+                    .ok_or(Error::VoidCallExpression(*handle))?;
+                let id =
+                    self.write_function_call(ir_module, function, *handle, arguments, output)?;
+                (id, &ir_module.types[return_type].inner)
+            }
             crate::Expression::Constant(handle) => {
                 let var = &ir_module.constants[*handle];
                 let inner = &ir_module.types[var.ty].inner;
@@ -1112,10 +1447,9 @@ impl Writer {
                 let type_id = self.get_type_id(&ir_module.types, *ty);
 
                 let mut constituent_ids = Vec::with_capacity(components.len());
-                for component in components {
-                    let expression = &function.expressions[*component];
+                for &component in components {
                     let (component_id, _) =
-                        self.parse_expression(ir_module, &function, expression, output);
+                        self.write_value(ir_module, function, component, output)?;
                     constituent_ids.push(component_id);
                 }
 
@@ -1125,116 +1459,242 @@ impl Writer {
 
                 (id, inner)
             }
-            crate::Expression::Binary { op, left, right } => {
-                match op {
-                    crate::BinaryOperator::Multiply => {
-                        // TODO OpVectorTimesScalar is only supported
-                        let id = self.generate_id();
-                        let left_expression = &function.expressions[*left];
-                        let right_expression = &function.expressions[*right];
-                        let (left_id, left_inner) =
-                            self.parse_expression(ir_module, function, left_expression, output);
-                        let (right_id, right_inner) =
-                            self.parse_expression(ir_module, function, right_expression, output);
-
-                        let mut result_type_id = None;
-                        let mut vector_id = None;
-                        let mut scalar_id = None;
-
-                        match left_inner {
-                            crate::TypeInner::Vector { size, kind, width } => {
-                                vector_id = Some(left_id);
-                                for (k, v) in self.lookup_type.iter() {
-                                    let ty = &ir_module.types[*v];
-                                    match ty.inner {
-                                        crate::TypeInner::Vector {
-                                            size: _size,
-                                            kind: _kind,
-                                            width: _width,
-                                        } => {
-                                            if size == &_size && kind == &_kind && width == &_width
-                                            {
-                                                result_type_id = Some(*k);
-                                                break;
-                                            }
-                                        }
-                                        _ => continue,
-                                    }
-                                }
-                            }
-                            _ => scalar_id = Some(left_id),
-                        }
+            crate::Expression::Binary {
+                op: crate::BinaryOperator::Multiply,
+                left,
+                right,
+            } => {
+                // TODO OpVectorTimesScalar is only supported
+                let (left_id, left_inner) = self.write_value(ir_module, function, *left, output)?;
+                let (right_id, right_inner) =
+                    self.write_value(ir_module, function, *right, output)?;
+
+                if left_inner == right_inner {
+                    let result_type = self.find_type_handle(ir_module, left_inner, expression)?;
+                    return self.write_arithmetic(
+                        ir_module,
+                        crate::BinaryOperator::Multiply,
+                        result_type,
+                        (left_id, right_id),
+                        expression,
+                        output,
+                    );
+                }
+                let (vector_id, vector_inner, scalar_id) =
+                    if let crate::TypeInner::Vector { .. } = *left_inner {
+                        (left_id, left_inner, right_id)
+                    } else if let crate::TypeInner::Vector { .. } = *right_inner {
+                        (right_id, right_inner, left_id)
+                    } else {
+                        return Err(Error::UnsupportedExpression(expression.clone()));
+                    };
+                let result_type = ir_module
+                    .types
+                    .iter()
+                    .find(|&(_, ty)| ty.inner == *vector_inner)
+                    .map(|(handle, _)| handle)
+                    .ok_or_else(|| Error::UnsupportedExpression(expression.clone()))?;
+                let result_type_id = self.get_type_id(&ir_module.types, result_type);
 
-                        match right_inner {
-                            crate::TypeInner::Vector { size, kind, width } => {
-                                vector_id = Some(right_id);
-                                for (k, v) in self.lookup_type.iter() {
-                                    let ty = &ir_module.types[*v];
-                                    match ty.inner {
-                                        crate::TypeInner::Vector {
-                                            size: _size,
-                                            kind: _kind,
-                                            width: _width,
-                                        } => {
-                                            if size == &_size && kind == &_kind && width == &_width
-                                            {
-                                                result_type_id = Some(*k);
-                                                break;
-                                            }
-                                        }
-                                        _ => continue,
-                                    }
-                                }
-                            }
-                            _ => scalar_id = Some(right_id),
-                        }
+                let id = self.generate_id();
+                let instruction =
+                    self.instruction_vector_times_scalar(result_type_id, id, vector_id, scalar_id);
+                output.push(instruction);
 
-                        // TODO Quick fix
-                        let load_id = self.generate_id();
-
-                        let load_instruction = self.instruction_load(
-                            result_type_id.unwrap(),
-                            load_id,
-                            vector_id.unwrap(),
-                            None,
-                        );
-                        output.push(load_instruction);
-
-                        let instruction = self.instruction_vector_times_scalar(
-                            result_type_id.unwrap(),
-                            id,
-                            load_id,
-                            scalar_id.unwrap(),
-                        );
-                        output.push(instruction);
-
-                        // TODO Not sure how or what to return
-                        (
-                            id,
-                            &crate::TypeInner::Scalar {
-                                kind: crate::ScalarKind::Float,
-                                width: 10,
-                            },
-                        )
+                (id, vector_inner)
+            }
+            crate::Expression::Binary {
+                op: op @ crate::BinaryOperator::Add,
+                left,
+                right,
+            }
+            | crate::Expression::Binary {
+                op: op @ crate::BinaryOperator::Subtract,
+                left,
+                right,
+            } => {
+                let (left_id, left_inner) = self.write_value(ir_module, function, *left, output)?;
+                let (right_id, right_inner) =
+                    self.write_value(ir_module, function, *right, output)?;
+                if left_inner != right_inner {
+                    return Err(Error::UnsupportedExpression(expression.clone()));
+                }
+                let result_type = self.find_type_handle(ir_module, left_inner, expression)?;
+                self.write_arithmetic(
+                    ir_module,
+                    *op,
+                    result_type,
+                    (left_id, right_id),
+                    expression,
+                    output,
+                )?
+            }
+            crate::Expression::Unary {
+                op: crate::UnaryOperator::Negate,
+                expr,
+            } => {
+                let (operand_id, inner) = self.write_value(ir_module, function, *expr, output)?;
+                let op = match *inner {
+                    crate::TypeInner::Scalar {
+                        kind: crate::ScalarKind::Float,
+                        ..
+                    }
+                    | crate::TypeInner::Vector {
+                        kind: crate::ScalarKind::Float,
+                        ..
+                    } => Op::FNegate,
+                    crate::TypeInner::Scalar {
+                        kind: crate::ScalarKind::Sint,
+                        ..
+                    }
+                    | crate::TypeInner::Vector {
+                        kind: crate::ScalarKind::Sint,
+                        ..
+                    } => Op::SNegate,
+                    _ => return Err(Error::UnsupportedExpression(expression.clone())),
+                };
+                let result_type = self.find_type_handle(ir_module, inner, expression)?;
+                let result_type_id = self.get_type_id(&ir_module.types, result_type);
+                let id = self.generate_id();
+                output.push(self.instruction_unary(op, result_type_id, id, operand_id));
+                (id, inner)
+            }
+            crate::Expression::AccessIndex { base, index } => {
+                // only values are accessed, pointers are not chained
+                let (base_id, base_inner) = self.write_value(ir_module, function, *base, output)?;
+                let result_type = match *base_inner {
+                    crate::TypeInner::Vector { kind, width, .. } => {
+                        self.find_scalar_handle(&ir_module.types, kind, width)
                     }
-                    _ => unimplemented!("{:?}", op),
+                    crate::TypeInner::Array { base, .. } => Some(base),
+                    crate::TypeInner::Struct { ref members } => {
+                        members.get(*index as usize).map(|member| member.ty)
+                    }
+                    _ => None,
                 }
+                .ok_or_else(|| Error::UnsupportedExpression(expression.clone()))?;
+                let result_type_id = self.get_type_id(&ir_module.types, result_type);
+                let id = self.generate_id();
+                output.push(self.instruction_composite_extract(
+                    result_type_id,
+                    id,
+                    base_id,
+                    &[*index],
+                ));
+                (id, &ir_module.types[result_type].inner)
             }
             crate::Expression::LocalVariable(variable) => {
-                let id = self.generate_id();
                 let var = &function.local_variables[*variable];
-                let ty = &ir_module.types[var.ty];
+                let id = self.local_variable_ids[variable.index()];
+                (id, &ir_module.types[var.ty].inner)
+            }
+            _ => return Err(Error::UnsupportedExpression(expression.clone())),
+        })
+    }
 
-                let pointer_id =
-                    self.get_pointer_id(&ir_module.types, var.ty, spirv::StorageClass::Function);
+    /// Find the type of the arena with the given inner type.
+    fn find_type_handle(
+        &self,
+        ir_module: &crate::Module,
+        inner: &crate::TypeInner,
+        expression: &crate::Expression,
+    ) -> Result<crate::Handle<crate::Type>, Error> {
+        ir_module
+            .types
+            .iter()
+            .find(|&(_, ty)| ty.inner == *inner)
+            .map(|(handle, _)| handle)
+            .ok_or_else(|| Error::UnsupportedExpression(expression.clone()))
+    }
 
-                let instruction =
-                    self.instruction_variable(pointer_id, id, spirv::StorageClass::Function, None);
-                output.push(instruction);
-                (id, &ty.inner)
-            }
-            _ => unimplemented!("{:?}", expression),
+    /// Write an arithmetic operation on two scalars or vectors of the same type.
+    fn write_arithmetic<'a>(
+        &mut self,
+        ir_module: &'a crate::Module,
+        op: crate::BinaryOperator,
+        result_type: crate::Handle<crate::Type>,
+        (left_id, right_id): (Word, Word),
+        expression: &crate::Expression,
+        output: &mut Vec<Instruction>,
+    ) -> Result<(Word, &'a crate::TypeInner), Error> {
+        use crate::{BinaryOperator as Bo, ScalarKind as Sk};
+        let inner = &ir_module.types[result_type].inner;
+        let kind = match *inner {
+            crate::TypeInner::Scalar { kind, .. } | crate::TypeInner::Vector { kind, .. } => kind,
+            _ => return Err(Error::UnsupportedExpression(expression.clone())),
+        };
+        let op = match (op, kind) {
+            (_, Sk::Bool) => return Err(Error::UnsupportedExpression(expression.clone())),
+            (Bo::Add, Sk::Float) => Op::FAdd,
+            (Bo::Add, _) => Op::IAdd,
+            (Bo::Subtract, Sk::Float) => Op::FSub,
+            (Bo::Subtract, _) => Op::ISub,
+            (Bo::Multiply, Sk::Float) => Op::FMul,
+            (Bo::Multiply, _) => Op::IMul,
+            _ => return Err(Error::UnsupportedExpression(expression.clone())),
+        };
+        let result_type_id = self.get_type_id(&ir_module.types, result_type);
+        let id = self.generate_id();
+        output.push(self.instruction_binary(op, result_type_id, id, left_id, right_id));
+        Ok((id, inner))
+    }
+
+    /// Write an expression used as a value, loading the variables and
+    /// pointers it refers to.
+    fn write_value<'a>(
+        &mut self,
+        ir_module: &'a crate::Module,
+        function: &crate::Function,
+        handle: crate::Handle<crate::Expression>,
+        output: &mut Vec<Instruction>,
+    ) -> Result<(Word, &'a crate::TypeInner), Error> {
+        let expression = &function.expressions[handle];
+        let (id, inner) = self.parse_expression(ir_module, function, expression, output)?;
+        let value_type = match *expression {
+            crate::Expression::GlobalVariable(var) => ir_module.global_variables[var].ty,
+            crate::Expression::LocalVariable(var) => function.local_variables[var].ty,
+            _ => match *inner {
+                crate::TypeInner::Pointer { base, .. } => base,
+                _ => return Ok((id, inner)),
+            },
+        };
+        let load_id = self.generate_id();
+        let type_id = self.get_type_id(&ir_module.types, value_type);
+        output.push(self.instruction_load(type_id, load_id, id, None));
+        Ok((load_id, &ir_module.types[value_type].inner))
+    }
+
+    /// Write an `OpFunctionCall`, returning the id of its result.
+    fn write_function_call(
+        &mut self,
+        ir_module: &crate::Module,
+        function: &crate::Function,
+        callee: crate::Handle<crate::Function>,
+        arguments: &[crate::Handle<crate::Expression>],
+        output: &mut Vec<Instruction>,
+    ) -> Result<Word, Error> {
+        let function_id = self.lookup_function.lookup_id(callee).unwrap();
+
+        let mut argument_ids = Vec::with_capacity(arguments.len());
+        for (parameter, &argument) in ir_module.functions[callee].arguments.iter().zip(arguments) {
+            // pointer parameters get the variables themselves
+            let argument_id = match ir_module.types[parameter.ty].inner {
+                crate::TypeInner::Pointer { .. } => {
+                    let expression = &function.expressions[argument];
+                    self.parse_expression(ir_module, function, expression, output)?
+                        .0
+                }
+                _ => self.write_value(ir_module, function, argument, output)?.0,
+            };
+            argument_ids.push(argument_id);
         }
+
+        let id = self.generate_id();
+        // functions returning nothing are called with the void type
+        let type_id =
+            self.get_function_type(ir_module.functions[callee].return_type, &ir_module.types);
+        output.push(self.instruction_function_call(type_id, id, function_id, &argument_ids));
+        Ok(id)
     }
 
     fn parse_function_block(
@@ -1243,34 +1703,39 @@ impl Writer {
         function: &crate::Function,
         statement: &crate::Statement,
         output: &mut Vec<Instruction>,
-    ) -> Instruction {
-        match statement {
-            crate::Statement::Return { .. } => match function.return_type {
-                Some(ty) => {
-                    let value_id = self.get_type_id(&ir_module.types, ty);
-                    self.instruction_return_value(value_id)
-                }
-                None => self.instruction_return(),
-            },
+    ) -> Result<Option<Instruction>, Error> {
+        Ok(Some(match statement {
+            crate::Statement::Return { value: Some(value) } => {
+                let (value_id, _) = self.write_value(ir_module, function, *value, output)?;
+                self.instruction_return_value(value_id)
+            }
+            crate::Statement::Return { value: None } => self.instruction_return(),
             crate::Statement::Store { pointer, value } => {
                 let pointer_expression = &function.expressions[*pointer];
-                let value_expression = &function.expressions[*value];
                 let (pointer_id, _) =
-                    self.parse_expression(ir_module, function, pointer_expression, output);
-                let (value_id, _) =
-                    self.parse_expression(ir_module, function, value_expression, output);
+                    self.parse_expression(ir_module, function, pointer_expression, output)?;
+                let (value_id, _) = self.write_value(ir_module, function, *value, output)?;
 
                 self.instruction_store(pointer_id, value_id)
             }
-            _ => unimplemented!(),
-        }
+            crate::Statement::Call {
+                function: callee,
+                arguments,
+            } => {
+                self.write_function_call(ir_module, function, *callee, arguments, output)?;
+                return Ok(None);
+            }
+            crate::Statement::EmitVertex => Instruction::new(Op::EmitVertex),
+            crate::Statement::EndPrimitive => Instruction::new(Op::EndPrimitive),
+            _ => return Err(Error::UnsupportedStatement(statement.clone())),
+        }))
     }
 
     fn write_physical_layout(&mut self) {
         self.physical_layout.bound = self.id_count + 1;
     }
 
-    fn write_logical_layout(&mut self, ir_module: &crate::Module) {
+    fn write_logical_layout(&mut self, ir_module: &crate::Module) -> Result<(), Error> {
         self.instruction_ext_inst_import("GLSL.std.450")
             .to_words(&mut self.logical_layout.ext_inst_imports);
 
@@ -1279,21 +1744,70 @@ impl Writer {
                 .push(self.instruction_source(spirv::SourceLanguage::GLSL, 450));
         }
 
+        // functions can be called before they are defined
+        for (handle, _) in ir_module.functions.iter() {
+            let id = self.generate_id();
+            self.lookup_function.insert(id, handle);
+        }
+
         for (handle, function) in ir_module.functions.iter() {
             let mut function_instructions: Vec<Instruction> = vec![];
-            function_instructions.push(self.parse_function(handle, function, &ir_module.types));
+            self.parse_function(
+                handle,
+                function,
+                &ir_module.types,
+                &mut function_instructions,
+            );
 
             let id = self.generate_id();
             function_instructions.push(self.instruction_label(id));
 
+            // variables have to be declared at the start of the first block
+            self.local_variable_ids.clear();
+            for (_, variable) in function.local_variables.iter() {
+                let id = self.generate_id();
+                let pointer_id = self.get_pointer_id(
+                    &ir_module.types,
+                    variable.ty,
+                    spirv::StorageClass::Function,
+                );
+                function_instructions.push(self.instruction_variable(
+                    pointer_id,
+                    id,
+                    spirv::StorageClass::Function,
+                    None,
+                ));
+                self.local_variable_ids.push(id);
+                if self.writer_flags.contains(WriterFlags::DEBUG) {
+                    if let Some(ref name) = variable.name {
+                        self.debugs.push(self.instruction_name(id, name));
+                    }
+                }
+            }
+            for (handle, variable) in function.local_variables.iter() {
+                if let Some(init) = variable.init {
+                    let mut output = vec![];
+                    let (value_id, _) = self.write_value(ir_module, function, init, &mut output)?;
+                    function_instructions.append(&mut output);
+                    function_instructions.push(
+                        self.instruction_store(self.local_variable_ids[handle.index()], value_id),
+                    );
+                }
+            }
+
             for block in function.body.iter() {
                 let mut output: Vec<Instruction> = vec![];
                 let instruction =
-                    self.parse_function_block(ir_module, function, &block, &mut output);
+                    self.parse_function_block(ir_module, function, &block, &mut output)?;
                 function_instructions.append(&mut output);
-                function_instructions.push(instruction);
+                function_instructions.extend(instruction);
             }
 
+            // the block has to end with a terminator
+            match function.body.last() {
+                Some(crate::Statement::Return { .. }) | Some(crate::Statement::Kill) => {}
+                _ => function_instructions.push(self.instruction_return()),
+            }
             function_instructions.push(self.instruction_function_end());
             for instruction in function_instructions.iter() {
                 instruction.to_words(&mut self.logical_layout.function_definitions);
@@ -1309,7 +1823,7 @@ impl Writer {
         // Doing this because we also want to include not used parts of the module
         // to be included in the output
         for (handle, _) in ir_module.global_variables.iter() {
-            self.get_global_variable_id(&ir_module.types, &ir_module.global_variables, handle);
+            self.get_global_variable_id(ir_module, handle);
         }
 
         for (handle, _) in ir_module.types.iter() {
@@ -1337,17 +1851,19 @@ impl Writer {
                 debug.to_words(&mut self.logical_layout.debugs);
             }
         }
+        Ok(())
     }
 
-    pub fn write(&mut self, ir_module: &crate::Module) -> Vec<Word> {
+    pub fn write(&mut self, ir_module: &crate::Module) -> Result<Vec<Word>, Error> {
+        let ir_module = &*self.writer_flags.clip_space().apply(ir_module);
         let mut words: Vec<Word> = vec![];
 
-        self.write_logical_layout(ir_module);
+        self.write_logical_layout(ir_module)?;
         self.write_physical_layout();
 
         self.physical_layout.in_words(&mut words);
         self.logical_layout.in_words(&mut words);
-        words
+        Ok(words)
     }
 }
 
@@ -1379,6 +1895,17 @@ mod tests {
         assert_eq!(writer.capabilities.len(), 1);
     }
 
+    #[test]
+    fn test_f16_bits() {
+        use super::f64_to_f16_bits;
+        assert_eq!(f64_to_f16_bits(0.0), 0x0000);
+        assert_eq!(f64_to_f16_bits(1.0), 0x3c00);
+        assert_eq!(f64_to_f16_bits(-2.0), 0xc000);
+        assert_eq!(f64_to_f16_bits(65504.0), 0x7bff);
+        assert_eq!(f64_to_f16_bits(1.0e6), 0x7c00);
+        assert_eq!(f64_to_f16_bits(2f64.powi(-24)), 0x0001);
+    }
+
     #[test]
     fn test_instruction_capability() {
         let writer = create_writer();
@@ -1461,7 +1988,7 @@ mod tests {
     #[test]
     fn test_instruction_execution_mode() {
         let writer = create_writer();
-        let instruction = writer.instruction_execution_mode(1, ExecutionMode::OriginUpperLeft);
+        let instruction = writer.instruction_execution_mode(1, ExecutionMode::OriginUpperLeft, &[]);
         let mut output = vec![];
 
         let requirements = SpecRequirements {
@@ -1477,6 +2004,26 @@ mod tests {
         validate_instruction(output.as_slice(), &instruction);
     }
 
+    #[test]
+    fn test_write_geometry_execution_modes() {
+        let mut writer = create_writer();
+        let modes = crate::ExecutionModes {
+            input_topology: Some(crate::PrimitiveTopology::Triangles),
+            output_topology: Some(crate::PrimitiveTopology::TriangleStrip),
+            output_vertices: Some(3),
+            ..Default::default()
+        };
+        writer.write_execution_modes(1, &modes);
+
+        let words = &writer.logical_layout.execution_modes;
+        assert_eq!(words.len(), 3 + 3 + 4);
+        assert_eq!(words[2], ExecutionMode::Triangles as u32);
+        assert_eq!(words[5], ExecutionMode::OutputTriangleStrip as u32);
+        assert_eq!(words[8], ExecutionMode::OutputVertices as u32);
+        assert_eq!(words[9], 3);
+        assert!(writer.capabilities.contains(&Capability::Geometry));
+    }
+
     #[test]
     fn test_instruction_source() {
         let writer = create_writer();
@@ -1562,6 +2109,46 @@ mod tests {
         assert_eq!(writer.physical_layout.bound, 1);
     }
 
+    #[test]
+    fn test_spec_id_on_scalars_only() {
+        let mut module =
+            crate::front::wgsl::parse_str("const v : vec2<f32> = vec2<f32>(1.0, 2.0);").unwrap();
+        let handles = module
+            .constants
+            .iter()
+            .map(|(handle, _)| handle)
+            .collect::<Vec<_>>();
+        for (spec_id, &handle) in handles.iter().enumerate() {
+            module.constants.get_mut(handle).specialization = Some(spec_id as u32);
+        }
+
+        let words = create_writer().write(&module).unwrap();
+        let mut spec_ids = 0;
+        let mut spec_composites = 0;
+        let mut index = 5;
+        while index < words.len() {
+            let word_count = (words[index] >> 16) as usize;
+            let op = words[index] & 0xFFFF;
+            if op == Op::Decorate as u32 && words[index + 2] == Decoration::SpecId as u32 {
+                spec_ids += 1;
+            }
+            if op == Op::SpecConstantComposite as u32 {
+                spec_composites += 1;
+            }
+            index += word_count;
+        }
+        let composites = module
+            .constants
+            .iter()
+            .filter(|(_, constant)| match constant.inner {
+                crate::ConstantInner::Composite(_) => true,
+                _ => false,
+            })
+            .count();
+        assert_eq!(spec_composites, composites);
+        assert_eq!(spec_ids, handles.len() - composites);
+    }
+
     fn create_writer() -> Writer {
         let header = Header {
             generator: 0,
@@ -0,0 +1,754 @@
+//! Textual representation of the IR.
+//!
+//! Every item is printed in the order of its arena, with handles written as `%N`.
+//! Types with a unique name are referred to by that name. The output can be
+//! read back with [`front::text`](crate::front::text).
+//!
+//! ```text
+//! version 1 0 0
+//! generator 0
+//! type %0 = f32
+//! type %1 "Particle" = struct { "pos": vec2<f32> offset 0 }
+//! const %0 _ : f32 = float 1.0
+//! global %0 "particles" storage_buffer Particle descriptor 0 1
+//! fn %0 "main"() {
+//!     uses [load]
+//!     %0 = global %0
+//!     return
+//! }
+//! entry_point compute "main" = %0 { workgroup_size 64 1 1 }
+//! ```
+
+use crate::{arena::Handle, FastHashMap};
+use std::fmt::{Error as FmtError, Write};
+
+const INDENT: &str = "    ";
+
+pub(crate) const IMAGE_FLAGS: &[(crate::ImageFlags, &str)] = {
+    use crate::ImageFlags as If;
+    &[
+        (If::ARRAYED, "arrayed"),
+        (If::MULTISAMPLED, "multisampled"),
+        (If::SAMPLED, "sampled"),
+        (If::CAN_LOAD, "load"),
+        (If::CAN_STORE, "store"),
+    ]
+};
+
+pub(crate) const GLOBAL_USES: &[(crate::GlobalUse, &str)] = &[
+    (crate::GlobalUse::LOAD, "load"),
+    (crate::GlobalUse::STORE, "store"),
+];
+
+/// An enum with a keyword for every value.
+pub(crate) trait Keyword: Copy + 'static {
+    /// All the values, in the order they are looked up by the parser.
+    const ALL: &'static [Self];
+
+    /// Return the keyword spelling this value.
+    fn keyword(self) -> &'static str;
+}
+
+/// Implement `Keyword` for IR enums. The `match` makes sure every variant
+/// is listed, and `ALL` is built from the same list.
+macro_rules! keywords {
+    ($($name:ident { $($variant:ident => $keyword:expr,)* })*) => {
+        $(
+            impl Keyword for crate::$name {
+                const ALL: &'static [Self] = &[$(crate::$name::$variant,)*];
+
+                fn keyword(self) -> &'static str {
+                    match self {
+                        $(crate::$name::$variant => $keyword,)*
+                    }
+                }
+            }
+        )*
+    };
+}
+
+keywords! {
+    StorageClass {
+        Constant => "constant",
+        Function => "function",
+        Input => "input",
+        Output => "output",
+        Private => "private",
+        StorageBuffer => "storage_buffer",
+        Uniform => "uniform",
+        WorkGroup => "work_group",
+    }
+
+    BuiltIn {
+        BaseInstance => "base_instance",
+        BaseVertex => "base_vertex",
+        ClipDistance => "clip_distance",
+        InstanceIndex => "instance_index",
+        Position => "position",
+        VertexIndex => "vertex_index",
+        InvocationId => "invocation_id",
+        TessCoord => "tess_coord",
+        TessLevelOuter => "tess_level_outer",
+        TessLevelInner => "tess_level_inner",
+        PrimitiveId => "primitive_id",
+        PointSize => "point_size",
+        FragCoord => "frag_coord",
+        FrontFacing => "front_facing",
+        SampleIndex => "sample_index",
+        FragDepth => "frag_depth",
+        GlobalInvocationId => "global_invocation_id",
+        LocalInvocationId => "local_invocation_id",
+        LocalInvocationIndex => "local_invocation_index",
+        WorkGroupId => "work_group_id",
+    }
+
+    ImageDimension {
+        D1 => "d1",
+        D2 => "d2",
+        D3 => "d3",
+        Cube => "cube",
+    }
+
+    StorageFormat {
+        R8Unorm => "r8_unorm",
+        R8Snorm => "r8_snorm",
+        R8Uint => "r8_uint",
+        R8Sint => "r8_sint",
+        R16Uint => "r16_uint",
+        R16Sint => "r16_sint",
+        R16Float => "r16_float",
+        Rg8Unorm => "rg8_unorm",
+        Rg8Snorm => "rg8_snorm",
+        Rg8Uint => "rg8_uint",
+        Rg8Sint => "rg8_sint",
+        R32Uint => "r32_uint",
+        R32Sint => "r32_sint",
+        R32Float => "r32_float",
+        Rg16Uint => "rg16_uint",
+        Rg16Sint => "rg16_sint",
+        Rg16Float => "rg16_float",
+        Rgba8Unorm => "rgba8_unorm",
+        Rgba8Snorm => "rgba8_snorm",
+        Rgba8Uint => "rgba8_uint",
+        Rgba8Sint => "rgba8_sint",
+        Rgb10a2Unorm => "rgb10a2_unorm",
+        Rg11b10Float => "rg11b10_float",
+        Rg32Uint => "rg32_uint",
+        Rg32Sint => "rg32_sint",
+        Rg32Float => "rg32_float",
+        Rgba16Uint => "rgba16_uint",
+        Rgba16Sint => "rgba16_sint",
+        Rgba16Float => "rgba16_float",
+        Rgba32Uint => "rgba32_uint",
+        Rgba32Sint => "rgba32_sint",
+        Rgba32Float => "rgba32_float",
+    }
+
+    ShaderStage {
+        Vertex => "vertex",
+        TessellationControl => "tessellation_control",
+        TessellationEvaluation => "tessellation_evaluation",
+        Geometry => "geometry",
+        Fragment => "fragment",
+        Compute => "compute",
+    }
+
+    PrimitiveTopology {
+        Points => "points",
+        Lines => "lines",
+        LinesAdjacency => "lines_adjacency",
+        LineStrip => "line_strip",
+        Triangles => "triangles",
+        TrianglesAdjacency => "triangles_adjacency",
+        TriangleStrip => "triangle_strip",
+        Quads => "quads",
+        Isolines => "isolines",
+    }
+
+    TessellationSpacing {
+        Equal => "equal",
+        FractionalEven => "fractional_even",
+        FractionalOdd => "fractional_odd",
+    }
+
+    VertexOrder {
+        Cw => "cw",
+        Ccw => "ccw",
+    }
+
+    UnaryOperator {
+        Negate => "negate",
+        Not => "not",
+    }
+
+    BinaryOperator {
+        Add => "add",
+        Subtract => "subtract",
+        Multiply => "multiply",
+        Divide => "divide",
+        Modulo => "modulo",
+        Equal => "equal",
+        NotEqual => "not_equal",
+        Less => "less",
+        LessEqual => "less_equal",
+        Greater => "greater",
+        GreaterEqual => "greater_equal",
+        And => "and",
+        ExclusiveOr => "exclusive_or",
+        InclusiveOr => "inclusive_or",
+        LogicalAnd => "logical_and",
+        LogicalOr => "logical_or",
+        ShiftLeftLogical => "shift_left_logical",
+        ShiftRightLogical => "shift_right_logical",
+        ShiftRightArithmetic => "shift_right_arithmetic",
+    }
+
+    IntrinsicFunction {
+        Any => "any",
+        All => "all",
+        IsNan => "is_nan",
+        IsInf => "is_inf",
+        IsFinite => "is_finite",
+        IsNormal => "is_normal",
+    }
+
+    DerivativeAxis {
+        X => "x",
+        Y => "y",
+        Width => "width",
+    }
+}
+
+/// Return the spelling of a scalar type, like `f32`.
+pub(crate) fn scalar_name(kind: crate::ScalarKind, width: crate::Bytes) -> String {
+    match (kind, width) {
+        (crate::ScalarKind::Bool, 1) => "bool".to_string(),
+        (crate::ScalarKind::Bool, _) => format!("bool{}", width as u32 * 8),
+        (crate::ScalarKind::Float, _) => format!("f{}", width as u32 * 8),
+        (crate::ScalarKind::Sint, _) => format!("i{}", width as u32 * 8),
+        (crate::ScalarKind::Uint, _) => format!("u{}", width as u32 * 8),
+    }
+}
+
+/// Parse the spelling of a scalar type.
+pub(crate) fn parse_scalar_name(word: &str) -> Option<(crate::ScalarKind, crate::Bytes)> {
+    if word == "bool" {
+        return Some((crate::ScalarKind::Bool, 1));
+    }
+    let (kind, bits) = if let Some(bits) = word.strip_prefix("bool") {
+        (crate::ScalarKind::Bool, bits)
+    } else if let Some(bits) = word.strip_prefix('f') {
+        (crate::ScalarKind::Float, bits)
+    } else if let Some(bits) = word.strip_prefix('i') {
+        (crate::ScalarKind::Sint, bits)
+    } else if let Some(bits) = word.strip_prefix('u') {
+        (crate::ScalarKind::Uint, bits)
+    } else {
+        return None;
+    };
+    match bits.parse::<u32>() {
+        Ok(bits) if bits % 8 == 0 && bits != 0 && bits <= 255 * 8 => {
+            Some((kind, (bits / 8) as crate::Bytes))
+        }
+        _ => None,
+    }
+}
+
+/// Check if the word can't be used as a type name, because it's a part of the syntax.
+fn is_type_keyword(word: &str) -> bool {
+    match word {
+        "vec2" | "vec3" | "vec4" | "ptr" | "array" | "struct" | "image" | "depth_image"
+        | "sampler" => true,
+        _ => parse_scalar_name(word).is_some() || word.starts_with("mat"),
+    }
+}
+
+fn is_identifier(word: &str) -> bool {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(ch) if ch.is_ascii_alphabetic() || ch == '_' => {}
+        _ => return false,
+    }
+    word != "_" && chars.all(|ch| ch.is_ascii_alphanumeric() || ch == '_')
+}
+
+struct Writer<'a> {
+    module: &'a crate::Module,
+    out: String,
+    type_names: FastHashMap<Handle<crate::Type>, &'a str>,
+}
+
+impl<'a> Writer<'a> {
+    fn name(&mut self, name: &Option<String>) -> Result<(), FmtError> {
+        match *name {
+            Some(ref name) => write!(self.out, "{:?}", name),
+            None => write!(self.out, "_"),
+        }
+    }
+
+    fn ty(&mut self, handle: Handle<crate::Type>) -> Result<(), FmtError> {
+        match self.type_names.get(&handle) {
+            Some(name) => write!(self.out, "{}", name),
+            None => write!(self.out, "%{}", handle.index()),
+        }
+    }
+
+    fn binding(&mut self, binding: &Option<crate::Binding>) -> Result<(), FmtError> {
+        match *binding {
+            Some(crate::Binding::BuiltIn(built_in)) => {
+                write!(self.out, " builtin {}", built_in.keyword())
+            }
+            Some(crate::Binding::Location(location)) => write!(self.out, " location {}", location),
+            Some(crate::Binding::Descriptor { set, binding }) => {
+                write!(self.out, " descriptor {} {}", set, binding)
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn flags<T: Copy + PartialEq>(
+        &mut self,
+        table: &[(T, &str)],
+        contains: impl Fn(T) -> bool,
+    ) -> Result<(), FmtError> {
+        let names = table
+            .iter()
+            .filter(|&&(flag, _)| contains(flag))
+            .map(|&(_, name)| name)
+            .collect::<Vec<_>>();
+        if names.is_empty() {
+            write!(self.out, "-")
+        } else {
+            write!(self.out, "{}", names.join("|"))
+        }
+    }
+
+    fn type_inner(&mut self, inner: &crate::TypeInner) -> Result<(), FmtError> {
+        use crate::TypeInner as Ti;
+        match *inner {
+            Ti::Scalar { kind, width } => write!(self.out, "{}", scalar_name(kind, width)),
+            Ti::Vector { size, kind, width } => {
+                write!(self.out, "vec{}<{}>", size as u8, scalar_name(kind, width))
+            }
+            Ti::Matrix {
+                columns,
+                rows,
+                kind,
+                width,
+            } => write!(
+                self.out,
+                "mat{}x{}<{}>",
+                columns as u8,
+                rows as u8,
+                scalar_name(kind, width)
+            ),
+            Ti::Pointer { base, class } => {
+                write!(self.out, "ptr<{}, ", class.keyword())?;
+                self.ty(base)?;
+                write!(self.out, ">")
+            }
+            Ti::Array { base, size, stride } => {
+                write!(self.out, "array<")?;
+                self.ty(base)?;
+                match size {
+                    crate::ArraySize::Static(count) => write!(self.out, ", {}", count)?,
+                    crate::ArraySize::Dynamic => write!(self.out, ", dynamic")?,
+                }
+                if let Some(stride) = stride {
+                    write!(self.out, ", stride {}", stride)?;
+                }
+                write!(self.out, ">")
+            }
+            Ti::Struct { ref members } => {
+                write!(self.out, "struct {{")?;
+                for (index, member) in members.iter().enumerate() {
+                    if index != 0 {
+                        write!(self.out, ",")?;
+                    }
+                    write!(self.out, " ")?;
+                    self.name(&member.name)?;
+                    write!(self.out, ": ")?;
+                    self.ty(member.ty)?;
+                    match member.origin {
+                        crate::MemberOrigin::BuiltIn(built_in) => {
+                            write!(self.out, " builtin {}", built_in.keyword())?
+                        }
+                        crate::MemberOrigin::Offset(offset) => {
+                            write!(self.out, " offset {}", offset)?
+                        }
+                    }
+                }
+                write!(self.out, " }}")
+            }
+            Ti::Image {
+                base,
+                dim,
+                flags,
+                format,
+            } => {
+                write!(self.out, "image<")?;
+                self.ty(base)?;
+                write!(self.out, ", {}, ", dim.keyword())?;
+                self.flags(IMAGE_FLAGS, |flag| flags.contains(flag))?;
+                if let Some(format) = format {
+                    write!(self.out, ", {}", format.keyword())?;
+                }
+                write!(self.out, ">")
+            }
+            Ti::DepthImage { dim, arrayed } => {
+                write!(self.out, "depth_image<{}", dim.keyword())?;
+                if arrayed {
+                    write!(self.out, ", arrayed")?;
+                }
+                write!(self.out, ">")
+            }
+            Ti::Sampler { comparison } => {
+                write!(self.out, "sampler")?;
+                if comparison {
+                    write!(self.out, " comparison")?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn handles<T>(&mut self, handles: &[Handle<T>]) -> Result<(), FmtError> {
+        write!(self.out, "[")?;
+        for (index, handle) in handles.iter().enumerate() {
+            if index != 0 {
+                write!(self.out, ", ")?;
+            }
+            write!(self.out, "%{}", handle.index())?;
+        }
+        write!(self.out, "]")
+    }
+
+    fn expression(&mut self, expr: &crate::Expression) -> Result<(), FmtError> {
+        use crate::Expression as E;
+        match *expr {
+            E::Access { base, index } => {
+                write!(self.out, "access %{} %{}", base.index(), index.index())
+            }
+            E::AccessIndex { base, index } => {
+                write!(self.out, "access_index %{} {}", base.index(), index)
+            }
+            E::Constant(handle) => write!(self.out, "constant %{}", handle.index()),
+            E::Compose { ty, ref components } => {
+                write!(self.out, "compose ")?;
+                self.ty(ty)?;
+                write!(self.out, " ")?;
+                self.handles(components)
+            }
+            E::FunctionParameter(index) => write!(self.out, "param {}", index),
+            E::GlobalVariable(handle) => write!(self.out, "global %{}", handle.index()),
+            E::LocalVariable(handle) => write!(self.out, "local %{}", handle.index()),
+            E::Load { pointer } => write!(self.out, "load %{}", pointer.index()),
+            E::ImageSample {
+                image,
+                sampler,
+                coordinate,
+                depth_ref,
+            } => {
+                write!(
+                    self.out,
+                    "sample %{} %{} %{}",
+                    image.index(),
+                    sampler.index(),
+                    coordinate.index()
+                )?;
+                if let Some(depth_ref) = depth_ref {
+                    write!(self.out, " depth %{}", depth_ref.index())?;
+                }
+                Ok(())
+            }
+            E::Unary { op, expr } => write!(self.out, "unary {} %{}", op.keyword(), expr.index()),
+            E::Binary { op, left, right } => write!(
+                self.out,
+                "binary {} %{} %{}",
+                op.keyword(),
+                left.index(),
+                right.index()
+            ),
+            E::Intrinsic { fun, argument } => {
+                write!(
+                    self.out,
+                    "intrinsic {} %{}",
+                    fun.keyword(),
+                    argument.index()
+                )
+            }
+            E::DotProduct(left, right) => {
+                write!(self.out, "dot %{} %{}", left.index(), right.index())
+            }
+            E::CrossProduct(left, right) => {
+                write!(self.out, "cross %{} %{}", left.index(), right.index())
+            }
+            E::Derivative { axis, expr } => {
+                write!(self.out, "derivative {} %{}", axis.keyword(), expr.index())
+            }
+            E::Call {
+                ref origin,
+                ref arguments,
+            } => {
+                match *origin {
+                    crate::FunctionOrigin::Local(handle) => {
+                        write!(self.out, "call %{} ", handle.index())?
+                    }
+                    crate::FunctionOrigin::External(ref name) => {
+                        write!(self.out, "call {:?} ", name)?
+                    }
+                }
+                self.handles(arguments)
+            }
+        }
+    }
+
+    fn block(&mut self, block: &[crate::Statement], level: usize) -> Result<(), FmtError> {
+        writeln!(self.out, "{{")?;
+        for statement in block {
+            self.statement(statement, level + 1)?;
+        }
+        write!(self.out, "{}}}", INDENT.repeat(level))
+    }
+
+    fn statement(&mut self, statement: &crate::Statement, level: usize) -> Result<(), FmtError> {
+        use crate::Statement as S;
+        write!(self.out, "{}", INDENT.repeat(level))?;
+        match *statement {
+            S::Empty => write!(self.out, "empty")?,
+            S::Block(ref block) => {
+                write!(self.out, "block ")?;
+                self.block(block, level)?;
+            }
+            S::If {
+                condition,
+                ref accept,
+                ref reject,
+            } => {
+                write!(self.out, "if %{} ", condition.index())?;
+                self.block(accept, level)?;
+                write!(self.out, " else ")?;
+                self.block(reject, level)?;
+            }
+            S::Switch {
+                selector,
+                ref cases,
+                ref default,
+            } => {
+                writeln!(self.out, "switch %{} {{", selector.index())?;
+                let mut values = cases.keys().cloned().collect::<Vec<_>>();
+                values.sort_unstable();
+                for value in values {
+                    let (ref block, ref fall_through) = cases[&value];
+                    write!(self.out, "{}case {} ", INDENT.repeat(level + 1), value)?;
+                    self.block(block, level + 1)?;
+                    if fall_through.is_some() {
+                        write!(self.out, " fallthrough")?;
+                    }
+                    writeln!(self.out)?;
+                }
+                write!(self.out, "{}default ", INDENT.repeat(level + 1))?;
+                self.block(default, level + 1)?;
+                write!(self.out, "\n{}}}", INDENT.repeat(level))?;
+            }
+            S::Loop {
+                ref body,
+                ref continuing,
+            } => {
+                write!(self.out, "loop ")?;
+                self.block(body, level)?;
+                write!(self.out, " continuing ")?;
+                self.block(continuing, level)?;
+            }
+            S::Break => write!(self.out, "break")?,
+            S::Continue => write!(self.out, "continue")?,
+            S::Return { value: Some(value) } => write!(self.out, "return %{}", value.index())?,
+            S::Return { value: None } => write!(self.out, "return")?,
+            S::Kill => write!(self.out, "kill")?,
+            S::Store { pointer, value } => {
+                write!(self.out, "store %{} %{}", pointer.index(), value.index())?
+            }
+            S::Call {
+                function,
+                ref arguments,
+            } => {
+                write!(self.out, "call %{} ", function.index())?;
+                self.handles(arguments)?
+            }
+            S::EmitVertex => write!(self.out, "emit_vertex")?,
+            S::EndPrimitive => write!(self.out, "end_primitive")?,
+        }
+        writeln!(self.out)
+    }
+
+    fn function(
+        &mut self,
+        handle: Handle<crate::Function>,
+        fun: &crate::Function,
+    ) -> Result<(), FmtError> {
+        write!(self.out, "fn %{} ", handle.index())?;
+        self.name(&fun.name)?;
+        write!(self.out, "(")?;
+        for (index, argument) in fun.arguments.iter().enumerate() {
+            if index != 0 {
+                write!(self.out, ", ")?;
+            }
+            self.name(&argument.name)?;
+            write!(self.out, ": ")?;
+            self.ty(argument.ty)?;
+            self.binding(&argument.binding)?;
+        }
+        write!(self.out, ")")?;
+        if let Some(ty) = fun.return_type {
+            write!(self.out, " -> ")?;
+            self.ty(ty)?;
+        }
+        writeln!(self.out, " {{")?;
+
+        write!(self.out, "{}uses [", INDENT)?;
+        for (index, &usage) in fun.global_usage.iter().enumerate() {
+            if index != 0 {
+                write!(self.out, ", ")?;
+            }
+            self.flags(GLOBAL_USES, |flag| usage.contains(flag))?;
+        }
+        writeln!(self.out, "]")?;
+        for (handle, var) in fun.local_variables.iter() {
+            write!(self.out, "{}local %{} ", INDENT, handle.index())?;
+            self.name(&var.name)?;
+            write!(self.out, ": ")?;
+            self.ty(var.ty)?;
+            if let Some(init) = var.init {
+                write!(self.out, " = %{}", init.index())?;
+            }
+            writeln!(self.out)?;
+        }
+        for (handle, expr) in fun.expressions.iter() {
+            write!(self.out, "{}%{} = ", INDENT, handle.index())?;
+            self.expression(expr)?;
+            writeln!(self.out)?;
+        }
+        for statement in fun.body.iter() {
+            self.statement(statement, 1)?;
+        }
+        writeln!(self.out, "}}")
+    }
+
+    fn write(&mut self) -> Result<(), FmtError> {
+        let module = self.module;
+        let (major, minor, patch) = module.header.version;
+        writeln!(self.out, "version {} {} {}", major, minor, patch)?;
+        writeln!(self.out, "generator {}", module.header.generator)?;
+
+        for (handle, ty) in module.types.iter() {
+            write!(self.out, "type %{} ", handle.index())?;
+            self.name(&ty.name)?;
+            write!(self.out, " = ")?;
+            self.type_inner(&ty.inner)?;
+            writeln!(self.out)?;
+        }
+
+        for (handle, constant) in module.constants.iter() {
+            write!(self.out, "const %{} ", handle.index())?;
+            self.name(&constant.name)?;
+            if let Some(id) = constant.specialization {
+                write!(self.out, " spec {}", id)?;
+            }
+            write!(self.out, " : ")?;
+            self.ty(constant.ty)?;
+            write!(self.out, " = ")?;
+            match constant.inner {
+                crate::ConstantInner::Sint(value) => write!(self.out, "sint {}", value)?,
+                crate::ConstantInner::Uint(value) => write!(self.out, "uint {}", value)?,
+                crate::ConstantInner::Float(value) => write!(self.out, "float {:?}", value)?,
+                crate::ConstantInner::Bool(value) => write!(self.out, "bool {}", value)?,
+                crate::ConstantInner::Composite(ref components) => {
+                    write!(self.out, "composite ")?;
+                    self.handles(components)?;
+                }
+            }
+            writeln!(self.out)?;
+        }
+
+        for (handle, var) in module.global_variables.iter() {
+            write!(self.out, "global %{} ", handle.index())?;
+            self.name(&var.name)?;
+            write!(self.out, " {} ", var.class.keyword())?;
+            self.ty(var.ty)?;
+            self.binding(&var.binding)?;
+            if let Some(init) = var.init {
+                write!(self.out, " = %{}", init.index())?;
+            }
+            writeln!(self.out)?;
+        }
+
+        for (handle, fun) in module.functions.iter() {
+            self.function(handle, fun)?;
+        }
+
+        for entry_point in module.entry_points.iter() {
+            write!(
+                self.out,
+                "entry_point {} {:?} = %{} {{",
+                entry_point.stage.keyword(),
+                entry_point.name,
+                entry_point.function.index()
+            )?;
+            let modes = &entry_point.modes;
+            if let Some(topology) = modes.input_topology {
+                write!(self.out, " input_topology {}", topology.keyword())?;
+            }
+            if let Some(topology) = modes.output_topology {
+                write!(self.out, " output_topology {}", topology.keyword())?;
+            }
+            if let Some(vertices) = modes.output_vertices {
+                write!(self.out, " output_vertices {}", vertices)?;
+            }
+            if let Some(invocations) = modes.invocations {
+                write!(self.out, " invocations {}", invocations)?;
+            }
+            if let Some(spacing) = modes.spacing {
+                write!(self.out, " spacing {}", spacing.keyword())?;
+            }
+            if let Some(order) = modes.vertex_order {
+                write!(self.out, " vertex_order {}", order.keyword())?;
+            }
+            if let Some([x, y, z]) = modes.workgroup_size {
+                write!(self.out, " workgroup_size {} {} {}", x, y, z)?;
+            }
+            writeln!(self.out, " }}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Write the textual representation of a module.
+pub fn write_string(module: &crate::Module) -> Result<String, FmtError> {
+    // types are referred to by their name, if it's unique
+    let mut name_counts = FastHashMap::<&str, usize>::default();
+    for (_, ty) in module.types.iter() {
+        if let Some(ref name) = ty.name {
+            *name_counts.entry(name.as_str()).or_insert(0) += 1;
+        }
+    }
+    let type_names = module
+        .types
+        .iter()
+        .filter_map(|(handle, ty)| {
+            let name = ty.name.as_deref()?;
+            if name_counts[name] == 1 && is_identifier(name) && !is_type_keyword(name) {
+                Some((handle, name))
+            } else {
+                None
+            }
+        })
+        .collect();
+
+    let mut writer = Writer {
+        module,
+        out: String::new(),
+        type_names,
+    };
+    writer.write()?;
+    Ok(writer.out)
+}
@@ -0,0 +1,569 @@
+//! Programmatic construction of modules.
+//!
+//! [`ModuleBuilder`] takes care of deduplicating types and constants, and of
+//! computing the [`GlobalUse`](crate::GlobalUse) of the functions, while
+//! [`FunctionBuilder`] appends expressions as they are created, so that they
+//! always come before the statements referencing them.
+
+use crate::{
+    arena::Handle,
+    proc::{ResolveError, Typifier},
+};
+
+use thiserror::Error;
+
+#[derive(Clone, Debug, Error)]
+pub enum BuildError {
+    #[error(transparent)]
+    Resolve(#[from] ResolveError),
+    #[error("Expression {0:?} can't be a vector component")]
+    InvalidVectorComponent(Handle<crate::Expression>),
+    #[error("Vectors can't have {0} components")]
+    InvalidVectorSize(u8),
+}
+
+/// Builder of a [`Module`](crate::Module).
+#[derive(Debug)]
+pub struct ModuleBuilder {
+    module: crate::Module,
+}
+
+impl ModuleBuilder {
+    /// Start building an empty module.
+    pub fn new() -> Self {
+        ModuleBuilder {
+            module: crate::Module {
+                header: crate::Header {
+                    version: (1, 0, 0),
+                    generator: 0,
+                },
+                types: crate::Arena::new(),
+                constants: crate::Arena::new(),
+                global_variables: crate::Arena::new(),
+                functions: crate::Arena::new(),
+                entry_points: Vec::new(),
+            },
+        }
+    }
+
+    /// Return the module built so far.
+    pub fn module(&self) -> &crate::Module {
+        &self.module
+    }
+
+    /// Return a handle to the type, adding it if it's not known yet.
+    pub fn ty(&mut self, inner: crate::TypeInner) -> Handle<crate::Type> {
+        Typifier::deduce_type_handle(inner, &mut self.module.types)
+    }
+
+    /// Add a named type, like a structure.
+    pub fn named_type(&mut self, name: &str, inner: crate::TypeInner) -> Handle<crate::Type> {
+        self.module.types.fetch_or_append(crate::Type {
+            name: Some(name.to_string()),
+            inner,
+        })
+    }
+
+    pub fn scalar(&mut self, kind: crate::ScalarKind, width: crate::Bytes) -> Handle<crate::Type> {
+        self.ty(crate::TypeInner::Scalar { kind, width })
+    }
+
+    pub fn vector(
+        &mut self,
+        size: crate::VectorSize,
+        kind: crate::ScalarKind,
+        width: crate::Bytes,
+    ) -> Handle<crate::Type> {
+        self.ty(crate::TypeInner::Vector { size, kind, width })
+    }
+
+    pub fn matrix(
+        &mut self,
+        columns: crate::VectorSize,
+        rows: crate::VectorSize,
+        kind: crate::ScalarKind,
+        width: crate::Bytes,
+    ) -> Handle<crate::Type> {
+        self.ty(crate::TypeInner::Matrix {
+            columns,
+            rows,
+            kind,
+            width,
+        })
+    }
+
+    pub fn pointer(
+        &mut self,
+        base: Handle<crate::Type>,
+        class: crate::StorageClass,
+    ) -> Handle<crate::Type> {
+        self.ty(crate::TypeInner::Pointer { base, class })
+    }
+
+    /// Return a handle to the constant, adding it if it's not known yet.
+    pub fn constant(
+        &mut self,
+        inner: crate::ConstantInner,
+        ty: Handle<crate::Type>,
+    ) -> Handle<crate::Constant> {
+        self.module.constants.fetch_or_append(crate::Constant {
+            name: None,
+            specialization: None,
+            inner,
+            ty,
+        })
+    }
+
+    /// Add a named constant, optionally overridable by specialization.
+    pub fn named_constant(
+        &mut self,
+        name: &str,
+        specialization: Option<u32>,
+        inner: crate::ConstantInner,
+        ty: Handle<crate::Type>,
+    ) -> Handle<crate::Constant> {
+        self.module.constants.append(crate::Constant {
+            name: Some(name.to_string()),
+            specialization,
+            inner,
+            ty,
+        })
+    }
+
+    /// Return a 32-bit floating-point constant.
+    pub fn float(&mut self, value: f64) -> Handle<crate::Constant> {
+        let ty = self.scalar(crate::ScalarKind::Float, 4);
+        self.constant(crate::ConstantInner::Float(value), ty)
+    }
+
+    /// Return a 32-bit signed integer constant.
+    pub fn sint(&mut self, value: i64) -> Handle<crate::Constant> {
+        let ty = self.scalar(crate::ScalarKind::Sint, 4);
+        self.constant(crate::ConstantInner::Sint(value), ty)
+    }
+
+    /// Return a 32-bit unsigned integer constant.
+    pub fn uint(&mut self, value: u64) -> Handle<crate::Constant> {
+        let ty = self.scalar(crate::ScalarKind::Uint, 4);
+        self.constant(crate::ConstantInner::Uint(value), ty)
+    }
+
+    /// Return a boolean constant.
+    pub fn bool(&mut self, value: bool) -> Handle<crate::Constant> {
+        let ty = self.scalar(crate::ScalarKind::Bool, 1);
+        self.constant(crate::ConstantInner::Bool(value), ty)
+    }
+
+    pub fn global_variable(
+        &mut self,
+        name: &str,
+        class: crate::StorageClass,
+        binding: Option<crate::Binding>,
+        ty: Handle<crate::Type>,
+    ) -> Handle<crate::GlobalVariable> {
+        self.module.global_variables.append(crate::GlobalVariable {
+            name: Some(name.to_string()),
+            class,
+            binding,
+            ty,
+            init: None,
+        })
+    }
+
+    /// Start building a function.
+    ///
+    /// The arguments are given by their names and types.
+    pub fn function(
+        &mut self,
+        name: &str,
+        arguments: &[(&str, Handle<crate::Type>)],
+        return_type: Option<Handle<crate::Type>>,
+    ) -> FunctionBuilder<'_> {
+        FunctionBuilder {
+            module: &mut self.module,
+            function: crate::Function {
+                name: Some(name.to_string()),
+                arguments: arguments
+                    .iter()
+                    .map(|&(name, ty)| crate::FunctionArgument {
+                        name: Some(name.to_string()),
+                        ty,
+                        binding: None,
+                    })
+                    .collect(),
+                return_type,
+                global_usage: Vec::new(),
+                local_variables: crate::Arena::new(),
+                expressions: crate::Arena::new(),
+                body: Vec::new(),
+            },
+            blocks: Vec::new(),
+            typifier: Typifier::new(),
+        }
+    }
+
+    pub fn entry_point(
+        &mut self,
+        stage: crate::ShaderStage,
+        name: &str,
+        function: Handle<crate::Function>,
+        modes: crate::ExecutionModes,
+    ) {
+        self.module.entry_points.push(crate::EntryPoint {
+            stage,
+            name: name.to_string(),
+            function,
+            modes,
+        });
+    }
+
+    /// Finish the module, computing the usage of the global variables.
+    pub fn finish(mut self) -> crate::Module {
+        let handles = self
+            .module
+            .functions
+            .iter()
+            .map(|(handle, _)| handle)
+            .collect::<Vec<_>>();
+        for handle in handles {
+            let fun = &self.module.functions[handle];
+            let global_usage =
+                crate::GlobalUse::scan(&fun.expressions, &fun.body, &self.module.global_variables);
+            self.module.functions.get_mut(handle).global_usage = global_usage;
+        }
+        crate::proc::CallGraph::new(&self.module.functions)
+            .propagate_global_usage(&mut self.module.functions);
+        self.module
+    }
+}
+
+/// Builder of a [`Function`](crate::Function).
+///
+/// Statements are added to the innermost block being built.
+pub struct FunctionBuilder<'a> {
+    module: &'a mut crate::Module,
+    function: crate::Function,
+    /// Blocks of the nested statements being built.
+    blocks: Vec<crate::Block>,
+    typifier: Typifier,
+}
+
+impl FunctionBuilder<'_> {
+    /// Append an expression.
+    pub fn expression(&mut self, expr: crate::Expression) -> Handle<crate::Expression> {
+        self.function.expressions.append(expr)
+    }
+
+    /// Return the type of an expression.
+    pub fn type_of(
+        &mut self,
+        expr: Handle<crate::Expression>,
+    ) -> Result<Handle<crate::Type>, ResolveError> {
+        self.typifier.resolve(
+            expr,
+            &self.function.expressions,
+            &mut self.module.types,
+            &self.module.constants,
+            &self.module.global_variables,
+            &self.function.local_variables,
+            &self.function.arguments,
+            &self.module.functions,
+        )
+    }
+
+    pub fn argument(&mut self, index: u32) -> Handle<crate::Expression> {
+        self.expression(crate::Expression::FunctionParameter(index))
+    }
+
+    pub fn constant(&mut self, constant: Handle<crate::Constant>) -> Handle<crate::Expression> {
+        self.expression(crate::Expression::Constant(constant))
+    }
+
+    pub fn global(&mut self, var: Handle<crate::GlobalVariable>) -> Handle<crate::Expression> {
+        self.expression(crate::Expression::GlobalVariable(var))
+    }
+
+    /// Add a local variable, returning the expression referring to it.
+    pub fn local_variable(
+        &mut self,
+        name: &str,
+        ty: Handle<crate::Type>,
+    ) -> Handle<crate::Expression> {
+        let var = self.function.local_variables.append(crate::LocalVariable {
+            name: Some(name.to_string()),
+            ty,
+            init: None,
+        });
+        self.expression(crate::Expression::LocalVariable(var))
+    }
+
+    /// Add a local variable initialized with the given value,
+    /// returning the expression referring to it.
+    pub fn local_variable_with(
+        &mut self,
+        name: &str,
+        init: Handle<crate::Expression>,
+    ) -> Result<Handle<crate::Expression>, ResolveError> {
+        let ty = self.type_of(init)?;
+        let var = self.function.local_variables.append(crate::LocalVariable {
+            name: Some(name.to_string()),
+            ty,
+            init: Some(init),
+        });
+        Ok(self.expression(crate::Expression::LocalVariable(var)))
+    }
+
+    pub fn load(&mut self, pointer: Handle<crate::Expression>) -> Handle<crate::Expression> {
+        self.expression(crate::Expression::Load { pointer })
+    }
+
+    pub fn access_index(
+        &mut self,
+        base: Handle<crate::Expression>,
+        index: u32,
+    ) -> Handle<crate::Expression> {
+        self.expression(crate::Expression::AccessIndex { base, index })
+    }
+
+    pub fn unary(
+        &mut self,
+        op: crate::UnaryOperator,
+        expr: Handle<crate::Expression>,
+    ) -> Handle<crate::Expression> {
+        self.expression(crate::Expression::Unary { op, expr })
+    }
+
+    pub fn binary(
+        &mut self,
+        op: crate::BinaryOperator,
+        left: Handle<crate::Expression>,
+        right: Handle<crate::Expression>,
+    ) -> Handle<crate::Expression> {
+        self.expression(crate::Expression::Binary { op, left, right })
+    }
+
+    pub fn compose(
+        &mut self,
+        ty: Handle<crate::Type>,
+        components: Vec<Handle<crate::Expression>>,
+    ) -> Handle<crate::Expression> {
+        self.expression(crate::Expression::Compose { ty, components })
+    }
+
+    /// Compose a vector out of scalars and smaller vectors,
+    /// inferring its type from the components.
+    pub fn compose_vector(
+        &mut self,
+        components: Vec<Handle<crate::Expression>>,
+    ) -> Result<Handle<crate::Expression>, BuildError> {
+        let mut count = 0;
+        let mut scalar = None;
+        for &component in components.iter() {
+            let ty = self.type_of(component)?;
+            match self.module.types[ty].inner {
+                crate::TypeInner::Scalar { kind, width } => {
+                    count += 1;
+                    scalar = Some((kind, width));
+                }
+                crate::TypeInner::Vector { size, kind, width } => {
+                    count += size as u8;
+                    scalar = Some((kind, width));
+                }
+                _ => return Err(BuildError::InvalidVectorComponent(component)),
+            }
+        }
+        let size = match count {
+            2 => crate::VectorSize::Bi,
+            3 => crate::VectorSize::Tri,
+            4 => crate::VectorSize::Quad,
+            _ => return Err(BuildError::InvalidVectorSize(count)),
+        };
+        // the count is at least 2, so there is a component
+        let (kind, width) = scalar.unwrap();
+        let ty = Typifier::deduce_type_handle(
+            crate::TypeInner::Vector { size, kind, width },
+            &mut self.module.types,
+        );
+        Ok(self.compose(ty, components))
+    }
+
+    pub fn intrinsic(
+        &mut self,
+        fun: crate::IntrinsicFunction,
+        argument: Handle<crate::Expression>,
+    ) -> Handle<crate::Expression> {
+        self.expression(crate::Expression::Intrinsic { fun, argument })
+    }
+
+    pub fn dot(
+        &mut self,
+        left: Handle<crate::Expression>,
+        right: Handle<crate::Expression>,
+    ) -> Handle<crate::Expression> {
+        self.expression(crate::Expression::DotProduct(left, right))
+    }
+
+    pub fn cross(
+        &mut self,
+        left: Handle<crate::Expression>,
+        right: Handle<crate::Expression>,
+    ) -> Handle<crate::Expression> {
+        self.expression(crate::Expression::CrossProduct(left, right))
+    }
+
+    /// Call a function of the module.
+    pub fn call(
+        &mut self,
+        function: Handle<crate::Function>,
+        arguments: Vec<Handle<crate::Expression>>,
+    ) -> Handle<crate::Expression> {
+        self.expression(crate::Expression::Call {
+            origin: crate::FunctionOrigin::Local(function),
+            arguments,
+        })
+    }
+
+    /// Call a function of the module as a statement, discarding its result.
+    ///
+    /// This is the only way to call the functions returning nothing.
+    pub fn call_statement(
+        &mut self,
+        function: Handle<crate::Function>,
+        arguments: Vec<Handle<crate::Expression>>,
+    ) {
+        self.statement(crate::Statement::Call {
+            function,
+            arguments,
+        });
+    }
+
+    /// Call an external function, like `GLSL.std.450` ones.
+    pub fn call_external(
+        &mut self,
+        name: &str,
+        arguments: Vec<Handle<crate::Expression>>,
+    ) -> Handle<crate::Expression> {
+        self.expression(crate::Expression::Call {
+            origin: crate::FunctionOrigin::External(name.to_string()),
+            arguments,
+        })
+    }
+
+    /// Add a statement to the current block.
+    pub fn statement(&mut self, statement: crate::Statement) {
+        match self.blocks.last_mut() {
+            Some(block) => block.push(statement),
+            None => self.function.body.push(statement),
+        }
+    }
+
+    fn build_block(&mut self, build: impl FnOnce(&mut Self)) -> crate::Block {
+        self.blocks.push(Vec::new());
+        build(self);
+        self.blocks.pop().unwrap_or_default()
+    }
+
+    pub fn store(&mut self, pointer: Handle<crate::Expression>, value: Handle<crate::Expression>) {
+        self.statement(crate::Statement::Store { pointer, value });
+    }
+
+    pub fn ret(&mut self, value: Option<Handle<crate::Expression>>) {
+        self.statement(crate::Statement::Return { value });
+    }
+
+    /// Add an `If` statement, building its branches with the given functions.
+    pub fn if_else(
+        &mut self,
+        condition: Handle<crate::Expression>,
+        accept: impl FnOnce(&mut Self),
+        reject: impl FnOnce(&mut Self),
+    ) {
+        let accept = self.build_block(accept);
+        let reject = self.build_block(reject);
+        self.statement(crate::Statement::If {
+            condition,
+            accept,
+            reject,
+        });
+    }
+
+    /// Add a `Loop` statement, building its body and continuing block
+    /// with the given functions.
+    pub fn loop_(&mut self, body: impl FnOnce(&mut Self), continuing: impl FnOnce(&mut Self)) {
+        let body = self.build_block(body);
+        let continuing = self.build_block(continuing);
+        self.statement(crate::Statement::Loop { body, continuing });
+    }
+
+    /// Finish the function and add it to the module.
+    pub fn finish(self) -> Handle<crate::Function> {
+        self.module.functions.append(self.function)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ModuleBuilder;
+    use crate::{BinaryOperator, ScalarKind, ShaderStage, StorageClass, VectorSize};
+
+    #[test]
+    fn vertex_shader() {
+        let mut builder = ModuleBuilder::new();
+        let float = builder.scalar(ScalarKind::Float, 4);
+        let vec2 = builder.vector(VectorSize::Bi, ScalarKind::Float, 4);
+        let vec4 = builder.vector(VectorSize::Quad, ScalarKind::Float, 4);
+        assert_eq!(builder.scalar(ScalarKind::Float, 4), float);
+        let position = builder.global_variable(
+            "a_position",
+            StorageClass::Input,
+            Some(crate::Binding::Location(0)),
+            vec2,
+        );
+        let output = builder.global_variable(
+            "o_position",
+            StorageClass::Output,
+            Some(crate::Binding::BuiltIn(crate::BuiltIn::Position)),
+            vec4,
+        );
+        let zero = builder.float(0.0);
+        let one = builder.float(1.0);
+        assert_eq!(builder.float(0.0), zero);
+
+        let scale = {
+            let mut fun = builder.function("scale", &[("value", vec2)], Some(vec2));
+            let value = fun.argument(0);
+            let factor = fun.constant(one);
+            let scaled = fun.binary(BinaryOperator::Multiply, value, factor);
+            assert_eq!(fun.type_of(scaled).unwrap(), vec2);
+            fun.ret(Some(scaled));
+            fun.finish()
+        };
+
+        let main = {
+            let mut fun = builder.function("main", &[], None);
+            let position = fun.global(position);
+            let loaded = fun.load(position);
+            let scaled = fun.call(scale, vec![loaded]);
+            let zero = fun.constant(zero);
+            let one = fun.constant(one);
+            let value = fun.compose_vector(vec![scaled, zero, one]).unwrap();
+            assert_eq!(fun.type_of(value).unwrap(), vec4);
+            let output = fun.global(output);
+            let condition = fun.binary(BinaryOperator::Less, zero, one);
+            fun.if_else(condition, |fun| fun.store(output, value), |_| {});
+            fun.ret(None);
+            fun.finish()
+        };
+        builder.entry_point(ShaderStage::Vertex, "main", main, Default::default());
+
+        let module = builder.finish();
+        crate::proc::Validator::new().validate(&module).unwrap();
+        let fun = &module.functions[main];
+        assert!(fun.global_usage[position.index()].contains(crate::GlobalUse::LOAD));
+        assert!(fun.global_usage[output.index()].contains(crate::GlobalUse::STORE));
+        match fun.body[0] {
+            crate::Statement::If { ref accept, .. } => assert_eq!(accept.len(), 1),
+            ref other => panic!("Unexpected statement {:?}", other),
+        }
+    }
+}
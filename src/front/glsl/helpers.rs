@@ -1,4 +1,6 @@
-use crate::{Arena, ImageDimension, ImageFlags, ScalarKind, Type, TypeInner, VectorSize};
+use crate::{
+    Arena, ImageDimension, ImageFlags, ScalarKind, StorageFormat, Type, TypeInner, VectorSize,
+};
 use glsl::syntax::{BinaryOp, TypeSpecifierNonArray, UnaryOp};
 
 pub fn glsl_to_spirv_unary_op(op: UnaryOp) -> crate::UnaryOperator {
@@ -36,6 +38,63 @@ pub fn glsl_to_spirv_binary_op(op: BinaryOp) -> crate::BinaryOperator {
     }
 }
 
+fn storage_image(
+    kind: ScalarKind,
+    dim: ImageDimension,
+    flags: ImageFlags,
+    types: &mut Arena<Type>,
+) -> TypeInner {
+    let base = types.fetch_or_append(Type {
+        name: None,
+        inner: TypeInner::Scalar { kind, width: 4 },
+    });
+
+    TypeInner::Image {
+        base,
+        dim,
+        flags: flags | ImageFlags::CAN_LOAD | ImageFlags::CAN_STORE,
+        format: None,
+    }
+}
+
+pub fn glsl_to_spirv_format(name: &str) -> Option<StorageFormat> {
+    Some(match name {
+        "r8" => StorageFormat::R8Unorm,
+        "r8_snorm" => StorageFormat::R8Snorm,
+        "r8ui" => StorageFormat::R8Uint,
+        "r8i" => StorageFormat::R8Sint,
+        "r16ui" => StorageFormat::R16Uint,
+        "r16i" => StorageFormat::R16Sint,
+        "r16f" => StorageFormat::R16Float,
+        "rg8" => StorageFormat::Rg8Unorm,
+        "rg8_snorm" => StorageFormat::Rg8Snorm,
+        "rg8ui" => StorageFormat::Rg8Uint,
+        "rg8i" => StorageFormat::Rg8Sint,
+        "r32ui" => StorageFormat::R32Uint,
+        "r32i" => StorageFormat::R32Sint,
+        "r32f" => StorageFormat::R32Float,
+        "rg16ui" => StorageFormat::Rg16Uint,
+        "rg16i" => StorageFormat::Rg16Sint,
+        "rg16f" => StorageFormat::Rg16Float,
+        "rgba8" => StorageFormat::Rgba8Unorm,
+        "rgba8_snorm" => StorageFormat::Rgba8Snorm,
+        "rgba8ui" => StorageFormat::Rgba8Uint,
+        "rgba8i" => StorageFormat::Rgba8Sint,
+        "rgb10_a2" => StorageFormat::Rgb10a2Unorm,
+        "r11f_g11f_b10f" => StorageFormat::Rg11b10Float,
+        "rg32ui" => StorageFormat::Rg32Uint,
+        "rg32i" => StorageFormat::Rg32Sint,
+        "rg32f" => StorageFormat::Rg32Float,
+        "rgba16ui" => StorageFormat::Rgba16Uint,
+        "rgba16i" => StorageFormat::Rgba16Sint,
+        "rgba16f" => StorageFormat::Rgba16Float,
+        "rgba32ui" => StorageFormat::Rgba32Uint,
+        "rgba32i" => StorageFormat::Rgba32Sint,
+        "rgba32f" => StorageFormat::Rgba32Float,
+        _ => return None,
+    })
+}
+
 pub fn glsl_to_spirv_type(ty: TypeSpecifierNonArray, types: &mut Arena<Type>) -> Option<TypeInner> {
     use TypeSpecifierNonArray::*;
 
@@ -246,6 +305,168 @@ pub fn glsl_to_spirv_type(ty: TypeSpecifierNonArray, types: &mut Arena<Type>) ->
             kind: ScalarKind::Float,
             width: 8,
         },
+        Image1D => storage_image(
+            ScalarKind::Float,
+            ImageDimension::D1,
+            ImageFlags::empty(),
+            types,
+        ),
+        Image2D => storage_image(
+            ScalarKind::Float,
+            ImageDimension::D2,
+            ImageFlags::empty(),
+            types,
+        ),
+        Image3D => storage_image(
+            ScalarKind::Float,
+            ImageDimension::D3,
+            ImageFlags::empty(),
+            types,
+        ),
+        ImageCube => storage_image(
+            ScalarKind::Float,
+            ImageDimension::Cube,
+            ImageFlags::empty(),
+            types,
+        ),
+        Image1DArray => storage_image(
+            ScalarKind::Float,
+            ImageDimension::D1,
+            ImageFlags::ARRAYED,
+            types,
+        ),
+        Image2DArray => storage_image(
+            ScalarKind::Float,
+            ImageDimension::D2,
+            ImageFlags::ARRAYED,
+            types,
+        ),
+        Image2DMS => storage_image(
+            ScalarKind::Float,
+            ImageDimension::D2,
+            ImageFlags::MULTISAMPLED,
+            types,
+        ),
+        Image2DMSArray => storage_image(
+            ScalarKind::Float,
+            ImageDimension::D2,
+            ImageFlags::ARRAYED | ImageFlags::MULTISAMPLED,
+            types,
+        ),
+        ImageCubeArray => storage_image(
+            ScalarKind::Float,
+            ImageDimension::Cube,
+            ImageFlags::ARRAYED,
+            types,
+        ),
+        IImage1D => storage_image(
+            ScalarKind::Sint,
+            ImageDimension::D1,
+            ImageFlags::empty(),
+            types,
+        ),
+        IImage2D => storage_image(
+            ScalarKind::Sint,
+            ImageDimension::D2,
+            ImageFlags::empty(),
+            types,
+        ),
+        IImage3D => storage_image(
+            ScalarKind::Sint,
+            ImageDimension::D3,
+            ImageFlags::empty(),
+            types,
+        ),
+        IImageCube => storage_image(
+            ScalarKind::Sint,
+            ImageDimension::Cube,
+            ImageFlags::empty(),
+            types,
+        ),
+        IImage1DArray => storage_image(
+            ScalarKind::Sint,
+            ImageDimension::D1,
+            ImageFlags::ARRAYED,
+            types,
+        ),
+        IImage2DArray => storage_image(
+            ScalarKind::Sint,
+            ImageDimension::D2,
+            ImageFlags::ARRAYED,
+            types,
+        ),
+        IImage2DMS => storage_image(
+            ScalarKind::Sint,
+            ImageDimension::D2,
+            ImageFlags::MULTISAMPLED,
+            types,
+        ),
+        IImage2DMSArray => storage_image(
+            ScalarKind::Sint,
+            ImageDimension::D2,
+            ImageFlags::ARRAYED | ImageFlags::MULTISAMPLED,
+            types,
+        ),
+        IImageCubeArray => storage_image(
+            ScalarKind::Sint,
+            ImageDimension::Cube,
+            ImageFlags::ARRAYED,
+            types,
+        ),
+        UImage1D => storage_image(
+            ScalarKind::Uint,
+            ImageDimension::D1,
+            ImageFlags::empty(),
+            types,
+        ),
+        UImage2D => storage_image(
+            ScalarKind::Uint,
+            ImageDimension::D2,
+            ImageFlags::empty(),
+            types,
+        ),
+        UImage3D => storage_image(
+            ScalarKind::Uint,
+            ImageDimension::D3,
+            ImageFlags::empty(),
+            types,
+        ),
+        UImageCube => storage_image(
+            ScalarKind::Uint,
+            ImageDimension::Cube,
+            ImageFlags::empty(),
+            types,
+        ),
+        UImage1DArray => storage_image(
+            ScalarKind::Uint,
+            ImageDimension::D1,
+            ImageFlags::ARRAYED,
+            types,
+        ),
+        UImage2DArray => storage_image(
+            ScalarKind::Uint,
+            ImageDimension::D2,
+            ImageFlags::ARRAYED,
+            types,
+        ),
+        UImage2DMS => storage_image(
+            ScalarKind::Uint,
+            ImageDimension::D2,
+            ImageFlags::MULTISAMPLED,
+            types,
+        ),
+        UImage2DMSArray => storage_image(
+            ScalarKind::Uint,
+            ImageDimension::D2,
+            ImageFlags::ARRAYED | ImageFlags::MULTISAMPLED,
+            types,
+        ),
+        UImageCubeArray => storage_image(
+            ScalarKind::Uint,
+            ImageDimension::Cube,
+            ImageFlags::ARRAYED,
+            types,
+        ),
         TypeName(ty_name) => {
             if let Some(t_pos) = ty_name.0.find("texture") {
                 let scalar_kind = match &ty_name.0[..t_pos] {
@@ -294,7 +515,12 @@ pub fn glsl_to_spirv_type(ty: TypeSpecifierNonArray, types: &mut Arena<Type>) ->
                     _ => panic!(),
                 };
 
-                return Some(TypeInner::Image { base, dim, flags });
+                return Some(TypeInner::Image {
+                    base,
+                    dim,
+                    flags,
+                    format: None,
+                });
             }
 
             match ty_name.0.as_str() {
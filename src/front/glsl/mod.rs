@@ -1,8 +1,9 @@
 #![allow(clippy::panic)]
 use crate::{
-    Arena, ArraySize, BinaryOperator, Binding, BuiltIn, Constant, ConstantInner, EntryPoint,
-    Expression, FastHashMap, Function, GlobalVariable, Handle, Header, LocalVariable, Module,
-    ScalarKind, ShaderStage, StorageClass, StructMember, Type, TypeInner, VectorSize,
+    proc::ConstantSolver, Arena, ArraySize, BinaryOperator, Binding, BuiltIn, Constant,
+    ConstantInner, EntryPoint, ExecutionModes, Expression, FastHashMap, Function, FunctionArgument,
+    GlobalVariable, Handle, Header, ImageFlags, LocalVariable, Module, ScalarKind, ShaderStage,
+    StorageClass, StorageFormat, StructMember, Type, TypeInner, VectorSize,
 };
 use glsl::{
     parser::{Parse, ParseError},
@@ -65,6 +66,15 @@ pub enum ErrorKind {
     EOF,
     #[error("Non constant expression encountered where a constant expression was expected")]
     NonConstantExpr,
+    #[error("Unknown function: {name}")]
+    UnknownFunction { name: String },
+    #[error("Unknown image format: {format}")]
+    UnknownImageFormat { format: String },
+    #[error("Constant expression error: {kind}")]
+    ConstantSolvingError {
+        #[from]
+        kind: crate::proc::ConstantSolvingError,
+    },
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -74,6 +84,26 @@ pub struct Error {
     kind: ErrorKind,
 }
 
+struct TypeQualifiers {
+    class: StorageClass,
+    binding: Option<Binding>,
+    /// Texel format of a storage image.
+    format: Option<StorageFormat>,
+    /// Image access allowed by the memory qualifiers.
+    access: ImageFlags,
+}
+
+impl Default for TypeQualifiers {
+    fn default() -> Self {
+        TypeQualifiers {
+            class: StorageClass::Private,
+            binding: None,
+            format: None,
+            access: ImageFlags::CAN_LOAD | ImageFlags::CAN_STORE,
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone)]
 enum Global {
     Variable(Handle<GlobalVariable>),
@@ -88,7 +118,11 @@ struct Parser<'a> {
     globals_constants: FastHashMap<String, Handle<Constant>>,
     constants: Arena<Constant>,
     functions: Arena<Function>,
+    /// Arguments of the function being parsed.
+    arguments: Vec<FunctionArgument>,
     shader_stage: ShaderStage,
+    /// Declared by `layout(local_size_x = ..) in;` in compute shaders.
+    workgroup_size: Option<[u32; 3]>,
 }
 
 impl<'a> Parser<'a> {
@@ -101,7 +135,9 @@ impl<'a> Parser<'a> {
             globals_constants: FastHashMap::default(),
             constants: Arena::new(),
             functions: Arena::new(),
+            arguments: Vec::new(),
             shader_stage,
+            workgroup_size: None,
         }
     }
 
@@ -139,8 +175,8 @@ impl<'a> Parser<'a> {
                         let mut initializer = None;
                         std::mem::swap(&mut initializer, &mut init.head.initializer);
 
-                        let handle = self.parse_global(init.head)?;
-                        let name = self.globals[handle].name.clone().unwrap();
+                        let handle_global = self.parse_global(init.head)?;
+                        let name = self.globals[handle_global].name.clone().unwrap();
                         if let Some(initializer) = initializer {
                             match initializer {
                                 Initializer::Simple(expr) => {
@@ -152,17 +188,20 @@ impl<'a> Parser<'a> {
                                         &parameter_lookup,
                                     )?;
                                     let handle = expressions.append(expr);
-                                    let val = self.eval_const_expr(handle, &expressions)?;
+                                    let val = self.eval_const_expr(handle, &mut expressions)?;
+                                    self.globals.get_mut(handle_global).init = Some(val);
                                     self.globals_constants.insert(name.clone(), val);
                                 }
                                 _ => todo!(),
                             }
                         }
 
-                        self.globals_lookup.insert(name, Global::Variable(handle));
+                        self.globals_lookup
+                            .insert(name, Global::Variable(handle_global));
                     }
                     Declaration::Block(block) => {
-                        let (class, binding) = Self::parse_type_qualifier(block.qualifier);
+                        let TypeQualifiers { class, binding, .. } =
+                            Self::parse_type_qualifier(block.qualifier)?;
                         let ty_name = block.name.0;
 
                         let name = block.identifier.clone().map(|ident| ident.ident.0);
@@ -232,6 +271,7 @@ impl<'a> Parser<'a> {
                             class,
                             name,
                             ty,
+                            init: None,
                         });
 
                         for (name, index) in reexports {
@@ -239,11 +279,26 @@ impl<'a> Parser<'a> {
                                 .insert(name, Global::StructShorthand(handle, index));
                         }
                     }
+                    Declaration::Global(qualifier, identifiers) if identifiers.is_empty() => {
+                        self.parse_global_qualifier(qualifier)?;
+                    }
                     _ => unimplemented!(),
                 },
             }
         }
 
+        // built-in globals are added while parsing the functions
+        let handles = self
+            .functions
+            .iter()
+            .map(|(handle, _)| handle)
+            .collect::<Vec<_>>();
+        for handle in handles {
+            let fun = self.functions.get_mut(handle);
+            fun.global_usage = crate::GlobalUse::scan(&fun.expressions, &fun.body, &self.globals);
+        }
+        crate::proc::CallGraph::new(&self.functions).propagate_global_usage(&mut self.functions);
+
         Ok(Module {
             header: Header {
                 version: (1, 0, 0),
@@ -257,6 +312,10 @@ impl<'a> Parser<'a> {
                 stage: self.shader_stage,
                 function: entry_point.unwrap(),
                 name: entry,
+                modes: ExecutionModes {
+                    workgroup_size: self.workgroup_size,
+                    ..ExecutionModes::default()
+                },
             }],
         })
     }
@@ -270,7 +329,7 @@ impl<'a> Parser<'a> {
         // Parse return type
         let ty = self.parse_type(function.prototype.ty.ty);
 
-        let mut parameter_types = Vec::with_capacity(function.prototype.parameters.len());
+        self.arguments.clear();
         let mut parameter_lookup = FastHashMap::default();
 
         let mut local_variables = Arena::<LocalVariable>::new();
@@ -278,10 +337,9 @@ impl<'a> Parser<'a> {
         let mut expressions = Arena::<Expression>::new();
         let mut body = Vec::new();
 
-        // TODO: Parse Qualifiers
         for (index, parameter) in function.prototype.parameters.into_iter().enumerate() {
             match parameter {
-                FunctionParameterDeclaration::Named(_ /* TODO */, decl) => {
+                FunctionParameterDeclaration::Named(qualifier, decl) => {
                     let ty = self.parse_type(decl.ty).unwrap();
 
                     let ty = if let Some(array_spec) = decl.ident.array_spec {
@@ -298,14 +356,25 @@ impl<'a> Parser<'a> {
                         ty
                     };
 
-                    parameter_types.push(ty);
+                    let ty = self.parse_parameter_qualifier(qualifier, ty);
+                    self.arguments.push(FunctionArgument {
+                        name: Some(decl.ident.ident.0.clone()),
+                        ty,
+                        binding: None,
+                    });
                     parameter_lookup.insert(
                         decl.ident.ident.0,
                         Expression::FunctionParameter(index as u32),
                     );
                 }
-                FunctionParameterDeclaration::Unnamed(_, ty) => {
-                    parameter_types.push(self.parse_type(ty).unwrap());
+                FunctionParameterDeclaration::Unnamed(qualifier, ty) => {
+                    let ty = self.parse_type(ty).unwrap();
+                    let ty = self.parse_parameter_qualifier(qualifier, ty);
+                    self.arguments.push(FunctionArgument {
+                        name: None,
+                        ty,
+                        binding: None,
+                    });
                 }
             }
         }
@@ -365,7 +434,7 @@ impl<'a> Parser<'a> {
 
         let handle = self.functions.append(Function {
             name: Some(name),
-            parameter_types,
+            arguments: std::mem::take(&mut self.arguments),
             return_type: ty,
             global_usage: vec![],
             local_variables,
@@ -375,6 +444,36 @@ impl<'a> Parser<'a> {
         Ok(handle)
     }
 
+    /// Turns `out` and `inout` parameters into pointers to the declared type.
+    fn parse_parameter_qualifier(
+        &mut self,
+        qualifier: Option<TypeQualifier>,
+        ty: Handle<Type>,
+    ) -> Handle<Type> {
+        let by_reference = qualifier
+            .iter()
+            .flat_map(|qualifier| qualifier.qualifiers.0.iter())
+            .any(|spec| {
+                matches!(
+                    *spec,
+                    TypeQualifierSpec::Storage(StorageQualifier::Out)
+                        | TypeQualifierSpec::Storage(StorageQualifier::InOut)
+                )
+            });
+
+        if by_reference {
+            self.types.fetch_or_append(Type {
+                name: None,
+                inner: TypeInner::Pointer {
+                    base: ty,
+                    class: StorageClass::Function,
+                },
+            })
+        } else {
+            ty
+        }
+    }
+
     fn parse_local_variable(
         &mut self,
         init: InitDeclaratorList,
@@ -538,7 +637,32 @@ impl<'a> Parser<'a> {
                     value: expressions.append(value),
                 })
             }
-            Expr::FunCall(_, _) => unimplemented!(),
+            Expr::FunCall(ident, args) => {
+                let call = self.parse_expression(
+                    Expr::FunCall(ident, args),
+                    expressions,
+                    locals,
+                    locals_map,
+                    parameter_lookup,
+                )?;
+                match call {
+                    // the result, if any, is discarded
+                    Expression::Call {
+                        origin: crate::FunctionOrigin::Local(function),
+                        arguments,
+                    } => Ok(crate::Statement::Call {
+                        function,
+                        arguments,
+                    }),
+                    Expression::Call {
+                        origin: crate::FunctionOrigin::External(name),
+                        ..
+                    } => Err(Error {
+                        kind: ErrorKind::UnknownFunction { name },
+                    }),
+                    _ => unimplemented!(),
+                }
+            }
             Expr::PostInc(_) => unimplemented!(),
             Expr::PostDec(_) => unimplemented!(),
             _ => panic!(),
@@ -570,6 +694,7 @@ impl<'a> Parser<'a> {
                                     width: 4,
                                 },
                             }),
+                            init: None,
                         }),
                     )),
                     "gl_InstanceIndex" => Ok(Expression::GlobalVariable(
@@ -584,6 +709,7 @@ impl<'a> Parser<'a> {
                                     width: 4,
                                 },
                             }),
+                            init: None,
                         }),
                     )),
                     "gl_BaseVertex" => Ok(Expression::GlobalVariable(
@@ -598,6 +724,7 @@ impl<'a> Parser<'a> {
                                     width: 4,
                                 },
                             }),
+                            init: None,
                         }),
                     )),
                     "gl_BaseInstance" => Ok(Expression::GlobalVariable(
@@ -612,6 +739,7 @@ impl<'a> Parser<'a> {
                                     width: 4,
                                 },
                             }),
+                            init: None,
                         }),
                     )),
                     "gl_Position" => Ok(Expression::GlobalVariable(self.globals.fetch_or_append(
@@ -631,6 +759,7 @@ impl<'a> Parser<'a> {
                                     width: 4,
                                 },
                             }),
+                            init: None,
                         },
                     ))),
                     "gl_PointSize" => Ok(Expression::GlobalVariable(self.globals.fetch_or_append(
@@ -645,6 +774,7 @@ impl<'a> Parser<'a> {
                                     width: 4,
                                 },
                             }),
+                            init: None,
                         },
                     ))),
                     "gl_ClipDistance" => Ok(Expression::GlobalVariable(
@@ -659,6 +789,7 @@ impl<'a> Parser<'a> {
                                     width: 4,
                                 },
                             }),
+                            init: None,
                         }),
                     )),
                     other => {
@@ -870,24 +1001,35 @@ impl<'a> Parser<'a> {
                             depth_ref: None, //TODO
                         })
                     }
-                    _ => Ok(Expression::Call {
-                        origin: crate::FunctionOrigin::External(name),
-                        arguments: args
-                            .into_iter()
-                            .map(|arg| {
-                                let expr = self
-                                    .parse_expression(
-                                        arg,
-                                        expressions,
-                                        locals,
-                                        locals_map,
-                                        parameter_lookup,
-                                    )
-                                    .unwrap();
-                                expressions.append(expr)
-                            })
-                            .collect(),
-                    }),
+                    _ => {
+                        // functions have to be declared before they are called
+                        let function = self
+                            .functions
+                            .iter()
+                            .find(|&(_, fun)| fun.name.as_deref() == Some(name.as_str()))
+                            .map(|(handle, _)| handle);
+                        Ok(Expression::Call {
+                            origin: match function {
+                                Some(handle) => crate::FunctionOrigin::Local(handle),
+                                None => crate::FunctionOrigin::External(name),
+                            },
+                            arguments: args
+                                .into_iter()
+                                .map(|arg| {
+                                    let expr = self
+                                        .parse_expression(
+                                            arg,
+                                            expressions,
+                                            locals,
+                                            locals_map,
+                                            parameter_lookup,
+                                        )
+                                        .unwrap();
+                                    expressions.append(expr)
+                                })
+                                .collect(),
+                        })
+                    }
                 }
             }
             Expr::Dot(reg, ident) => {
@@ -912,6 +1054,7 @@ impl<'a> Parser<'a> {
                         &self.constants,
                         &self.globals,
                         locals,
+                        &self.arguments,
                         &self.functions,
                     )
                     .map_err(|e| Error { kind: e.into() })?;
@@ -1039,17 +1182,42 @@ impl<'a> Parser<'a> {
             }
         };
 
-        let (class, binding) = head
+        let TypeQualifiers {
+            class,
+            binding,
+            format,
+            access,
+        } = head
             .ty
             .qualifier
             .map(Self::parse_type_qualifier)
-            .unwrap_or((StorageClass::Private, None));
+            .transpose()?
+            .unwrap_or_default();
+
+        let ty = match self.types[ty].inner {
+            TypeInner::Image {
+                base,
+                dim,
+                flags,
+                format: _,
+            } if !flags.contains(ImageFlags::SAMPLED) => self.types.fetch_or_append(Type {
+                name: None,
+                inner: TypeInner::Image {
+                    base,
+                    dim,
+                    flags: flags & access,
+                    format,
+                },
+            }),
+            _ => ty,
+        };
 
         Ok(self.globals.append(GlobalVariable {
             name,
             class,
             binding,
             ty,
+            init: None,
         }))
     }
 
@@ -1057,72 +1225,25 @@ impl<'a> Parser<'a> {
     pub fn eval_const_expr(
         &mut self,
         expr: Handle<Expression>,
-        expressions: &Arena<Expression>,
+        expressions: &mut Arena<Expression>,
     ) -> Result<Handle<Constant>, Error> {
-        match &expressions[expr] {
-            Expression::Constant(handle) => Ok(*handle),
-            Expression::Call { .. } => todo!(),
-            Expression::GlobalVariable(handle) => {
-                let name = self.globals[*handle].name.as_ref().unwrap();
-                if let Some(handle) = self.globals_constants.get(name) {
-                    Ok(*handle)
-                } else {
-                    todo!("Global const error")
-                }
-            }
-            Expression::Binary { left, right, op } => {
-                let left = self.eval_const_expr(*left, expressions)?;
-                let right = self.eval_const_expr(*right, expressions)?;
-                let inner: ConstantInner;
-                let ty;
-                match op {
-                    BinaryOperator::Add => {
-                        match (&self.constants[left].inner, &self.constants[right].inner) {
-                            (ConstantInner::Sint(left), ConstantInner::Sint(right)) => {
-                                inner = ConstantInner::Sint(left + right);
-                                ty = self.types.fetch_or_append(Type {
-                                    name: None,
-                                    inner: TypeInner::Scalar {
-                                        kind: ScalarKind::Sint,
-                                        width: 4,
-                                    },
-                                })
-                            }
-                            (ConstantInner::Uint(left), ConstantInner::Uint(right)) => {
-                                inner = ConstantInner::Uint(left + right);
-                                ty = self.types.fetch_or_append(Type {
-                                    name: None,
-                                    inner: TypeInner::Scalar {
-                                        kind: ScalarKind::Uint,
-                                        width: 4,
-                                    },
-                                })
-                            }
-                            (ConstantInner::Float(left), ConstantInner::Float(right)) => {
-                                inner = ConstantInner::Float(left + right);
-                                ty = self.types.fetch_or_append(Type {
-                                    name: None,
-                                    inner: TypeInner::Scalar {
-                                        kind: ScalarKind::Float,
-                                        width: 4,
-                                    },
-                                })
-                            }
-                            _ => todo!(),
-                        }
-                    }
-
-                    _ => todo!(),
+        // constant globals are replaced by their values
+        let handles = expressions.iter().map(|(h, _)| h).collect::<Vec<_>>();
+        for handle in handles {
+            if let Expression::GlobalVariable(global) = expressions[handle] {
+                let name = self.globals[global].name.as_ref().unwrap();
+                if let Some(&constant) = self.globals_constants.get(name) {
+                    *expressions.get_mut(handle) = Expression::Constant(constant);
                 }
-                Ok(self.constants.fetch_or_append(Constant {
-                    name: None,
-                    specialization: None,
-                    inner,
-                    ty,
-                }))
             }
-            expr => todo!("Const eval for {:?}", expr),
         }
+
+        let mut solver = ConstantSolver {
+            types: &mut self.types,
+            expressions,
+            constants: &mut self.constants,
+        };
+        solver.solve(expr).map_err(|e| Error { kind: e.into() })
     }
 
     pub fn parse_array_size(&mut self, array_spec: ArraySpecifier) -> Result<ArraySize, Error> {
@@ -1142,7 +1263,7 @@ impl<'a> Parser<'a> {
                 )?;
                 let handle = expressions.append(expr);
 
-                let const_handle = self.eval_const_expr(handle, &expressions)?;
+                let const_handle = self.eval_const_expr(handle, &mut expressions)?;
 
                 match &self.constants[const_handle].inner {
                     ConstantInner::Sint(val) => ArraySize::Static(*val as u32),
@@ -1158,12 +1279,53 @@ impl<'a> Parser<'a> {
         Ok(size)
     }
 
-    fn parse_type_qualifier(qualifier: TypeQualifier) -> (StorageClass, Option<Binding>) {
+    /// Parses a qualifier declared without any variables, like the compute
+    /// shader `layout(local_size_x = 64) in;`.
+    ///
+    /// Only the workgroup size is recorded, the other qualifiers are ignored.
+    fn parse_global_qualifier(&mut self, qualifier: TypeQualifier) -> Result<(), Error> {
+        for qualifier in qualifier.qualifiers {
+            let layout_qualifier = match qualifier {
+                TypeQualifierSpec::Layout(layout_qualifier) => layout_qualifier,
+                _ => continue,
+            };
+            for identifier in layout_qualifier.ids {
+                let (identifier, expr) = match identifier {
+                    LayoutQualifierSpec::Identifier(identifier, Some(expr)) => (identifier, expr),
+                    _ => continue,
+                };
+                let index = match identifier.as_str() {
+                    "local_size_x" => 0,
+                    "local_size_y" => 1,
+                    "local_size_z" => 2,
+                    _ => continue,
+                };
+                let value = match *expr {
+                    Expr::IntConst(value) => value as u32,
+                    Expr::UIntConst(value) => value,
+                    _ => return Err(ErrorKind::NonConstantExpr.into()),
+                };
+                self.workgroup_size.get_or_insert([1; 3])[index] = value;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn parse_type_qualifier(qualifier: TypeQualifier) -> Result<TypeQualifiers, Error> {
         let mut storage = None;
         let mut binding = None;
+        let mut format = None;
+        let mut access = ImageFlags::CAN_LOAD | ImageFlags::CAN_STORE;
 
         for qualifier in qualifier.qualifiers {
             match qualifier {
+                TypeQualifierSpec::Storage(StorageQualifier::ReadOnly) => {
+                    access.remove(ImageFlags::CAN_STORE);
+                }
+                TypeQualifierSpec::Storage(StorageQualifier::WriteOnly) => {
+                    access.remove(ImageFlags::CAN_LOAD);
+                }
                 TypeQualifierSpec::Storage(storage_qualifier) => {
                     assert!(storage.is_none());
 
@@ -1213,6 +1375,16 @@ impl<'a> Parser<'a> {
                                     }
                                 }
                             }
+                            LayoutQualifierSpec::Identifier(identifier, None) => {
+                                assert!(format.is_none());
+
+                                format =
+                                    Some(helpers::glsl_to_spirv_format(&identifier.0).ok_or_else(
+                                        || ErrorKind::UnknownImageFormat {
+                                            format: identifier.0.clone(),
+                                        },
+                                    )?);
+                            }
                             _ => unimplemented!(),
                         }
                     }
@@ -1224,7 +1396,7 @@ impl<'a> Parser<'a> {
                         })
                     } else if let Some(location) = location {
                         binding = Some(Binding::Location(location as u32))
-                    } else {
+                    } else if format.is_none() {
                         panic!()
                     }
                 }
@@ -1232,7 +1404,12 @@ impl<'a> Parser<'a> {
             }
         }
 
-        (storage.unwrap_or(StorageClass::Private), binding)
+        Ok(TypeQualifiers {
+            class: storage.unwrap_or(StorageClass::Private),
+            binding,
+            format,
+            access,
+        })
     }
 }
 
@@ -1248,7 +1425,7 @@ pub fn parse_str(source: &str, entry: String, stage: ShaderStage) -> Result<crat
 
 #[cfg(test)]
 mod tests {
-    use super::parse_str;
+    use super::{parse_str, ErrorKind};
 
     #[test]
     fn test_vertex() {
@@ -1272,6 +1449,37 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_workgroup_size() {
+        let data =
+            "#version 450 core\nlayout(local_size_x = 8, local_size_y = 4) in;\nvoid main() {}\n";
+
+        let module = parse_str(data, String::from("main"), crate::ShaderStage::Compute).unwrap();
+        assert_eq!(module.entry_points[0].modes.workgroup_size, Some([8, 4, 1]));
+    }
+
+    #[test]
+    fn ignored_global_qualifiers() {
+        for &(stage, qualifier) in &[
+            (
+                crate::ShaderStage::Geometry,
+                "layout(triangle_strip, max_vertices = 3) out;",
+            ),
+            (
+                crate::ShaderStage::Compute,
+                "layout(local_size_x_id = 1) in;",
+            ),
+            (
+                crate::ShaderStage::Fragment,
+                "layout(early_fragment_tests) in;",
+            ),
+        ] {
+            let data = format!("#version 450 core\n{}\nvoid main() {{}}\n", qualifier);
+            let module = parse_str(&data, String::from("main"), stage).unwrap();
+            assert_eq!(module.entry_points[0].modes.workgroup_size, None);
+        }
+    }
+
     #[cfg(feature = "glsl_preprocessor")]
     #[test]
     fn test_preprocess() {
@@ -1298,4 +1506,18 @@ mod tests {
             parse_str(data, String::from("main"), crate::ShaderStage::Vertex)
         );
     }
+
+    #[test]
+    fn unknown_image_format() {
+        let data = "
+            #version 450 core
+            layout(set = 0, binding = 0, rgb10) uniform image2D u_image;
+            void main() {}
+        ";
+        let error = parse_str(data, String::from("main"), crate::ShaderStage::Compute).unwrap_err();
+        match error.kind {
+            ErrorKind::UnknownImageFormat { ref format } if format == "rgb10" => {}
+            ref other => panic!("Unexpected error: {:?}", other),
+        }
+    }
 }
@@ -1,4 +1,4 @@
-use crate::{EntryPoint, Module, ShaderStage};
+use crate::{EntryPoint, ExecutionModes, Module, ShaderStage};
 
 mod lex;
 #[cfg(test)]
@@ -39,6 +39,7 @@ pub fn parse_str(source: &str, entry: String, stage: ShaderStage) -> Result<Modu
             stage,
             name: entry,
             function: *entry_handle,
+            modes: ExecutionModes::default(),
         });
     }
 
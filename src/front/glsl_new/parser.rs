@@ -7,7 +7,7 @@ pomelo! {
     %include {
         use super::super::{error::ErrorKind, token::*, ast::*};
         use crate::{Arena, BinaryOperator, Binding, Block, BuiltIn, Constant, ConstantInner, Expression,
-            Function, GlobalVariable, Handle, LocalVariable, ScalarKind,
+            Function, GlobalVariable, Handle, LocalVariable, proc::ConstantSolver, ScalarKind,
             ShaderStage, Statement, StorageClass, Type, TypeInner, VectorSize};
     }
     %token #[derive(Debug)] pub enum Token {};
@@ -148,6 +148,7 @@ pomelo! {
                             width: 4,
                         },
                     }),
+                    init: None,
                 },
             );
             extra.lookup_global_variables.insert(v.1, h);
@@ -440,11 +441,12 @@ pomelo! {
     }
 
     constant_expression ::= conditional_expression(e) {
-        if let Expression::Constant(h) = extra.context.expressions[e] {
-            h
-        } else {
-            return Err(ErrorKind::ExpectedConstant)
-        }
+        let mut solver = ConstantSolver {
+            types: &mut extra.types,
+            expressions: &extra.context.expressions,
+            constants: &mut extra.constants,
+        };
+        solver.solve(e).map_err(|_| ErrorKind::ExpectedConstant)?
     }
 
     // declaration
@@ -623,7 +625,7 @@ pomelo! {
     function_header ::= fully_specified_type(t) Identifier(n) LeftParen {
         Function {
             name: Some(n.1),
-            parameter_types: vec![],
+            arguments: vec![],
             return_type: t.1,
             global_usage: vec![],
             local_variables: Arena::<LocalVariable>::new(),
@@ -707,12 +709,24 @@ pomelo! {
         });
 
         for (id, initializer) in d.ids_initializers {
+            let init = match initializer {
+                Some(init) => {
+                    let mut solver = ConstantSolver {
+                        types: &mut extra.types,
+                        expressions: &extra.context.expressions,
+                        constants: &mut extra.constants,
+                    };
+                    Some(solver.solve(init.expression).map_err(|_| ErrorKind::ExpectedConstant)?)
+                }
+                None => None,
+            };
             let h = extra.global_variables.fetch_or_append(
                 GlobalVariable {
                     name: Some(id.clone()),
                     class,
                     binding: binding.clone(),
                     ty: d.ty,
+                    init,
                 },
             );
             extra.lookup_global_variables.insert(id, h);
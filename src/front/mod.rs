@@ -6,6 +6,7 @@ pub mod glsl;
 pub mod glsl_new;
 #[cfg(feature = "spirv")]
 pub mod spv;
+pub mod text;
 pub mod wgsl;
 
 use crate::arena::Arena;
@@ -18,7 +18,16 @@ use crate::{
 use num_traits::cast::FromPrimitive;
 use std::{convert::TryInto, num::NonZeroU32};
 
-pub const SUPPORTED_CAPABILITIES: &[spirv::Capability] = &[spirv::Capability::Shader];
+pub const SUPPORTED_CAPABILITIES: &[spirv::Capability] = &[
+    spirv::Capability::Shader,
+    spirv::Capability::Geometry,
+    spirv::Capability::Tessellation,
+    spirv::Capability::StorageImageExtendedFormats,
+    spirv::Capability::Float16,
+    spirv::Capability::Float64,
+    spirv::Capability::Int16,
+    spirv::Capability::Int64,
+];
 pub const SUPPORTED_EXTENSIONS: &[&str] = &[];
 pub const SUPPORTED_EXT_SETS: &[&str] = &["GLSL.std.450"];
 
@@ -36,10 +45,14 @@ pub enum Error {
     UnsupportedExtInst(spirv::Word),
     UnsupportedType(Handle<crate::Type>),
     UnsupportedExecutionModel(spirv::Word),
+    UnsupportedExecutionMode(spirv::Word),
     UnsupportedStorageClass(spirv::Word),
     UnsupportedImageDim(spirv::Word),
+    UnsupportedImageFormat(spirv::Word),
     UnsupportedBuiltIn(spirv::Word),
     UnsupportedControlFlow(spirv::Word),
+    UnsupportedSpecConstantOp(spirv::Op),
+    UnsupportedSpecConstantInitializer(spirv::Word),
     InvalidParameter(spirv::Op),
     InvalidOperandCount(spirv::Op, u16),
     InvalidOperand,
@@ -122,6 +135,32 @@ impl<T> LookupHelper for FastHashMap<spirv::Word, T> {
     }
 }
 
+/// Replace the ids of the functions called by statements with their handles.
+///
+/// The callees may be defined after the callers, so the statements
+/// temporarily hold the SPIR-V ids as their handle indices.
+fn resolve_call_statements(
+    block: &mut [crate::Statement],
+    lookup_function: &FastHashMap<spirv::Word, Handle<crate::Function>>,
+) -> Result<(), Error> {
+    for statement in block {
+        if let crate::Statement::Call {
+            ref mut function, ..
+        } = *statement
+        {
+            *function = *lookup_function.lookup(function.index() as spirv::Word)?;
+        }
+        let mut result = Ok(());
+        statement.for_each_block_mut(|block| {
+            if result.is_ok() {
+                result = resolve_call_statements(block, lookup_function);
+            }
+        });
+        result?;
+    }
+    Ok(())
+}
+
 fn map_vector_size(word: spirv::Word) -> Result<crate::VectorSize, Error> {
     match word {
         2 => Ok(crate::VectorSize::Bi),
@@ -156,12 +195,65 @@ fn map_image_dim(word: spirv::Word) -> Result<crate::ImageDimension, Error> {
     }
 }
 
+fn map_image_format(word: spirv::Word) -> Result<Option<crate::StorageFormat>, Error> {
+    use crate::StorageFormat as Sf;
+    use spirv::ImageFormat as If;
+    Ok(Some(match If::from_u32(word) {
+        Some(If::Unknown) => return Ok(None),
+        Some(If::R8) => Sf::R8Unorm,
+        Some(If::R8Snorm) => Sf::R8Snorm,
+        Some(If::R8ui) => Sf::R8Uint,
+        Some(If::R8i) => Sf::R8Sint,
+        Some(If::R16ui) => Sf::R16Uint,
+        Some(If::R16i) => Sf::R16Sint,
+        Some(If::R16f) => Sf::R16Float,
+        Some(If::Rg8) => Sf::Rg8Unorm,
+        Some(If::Rg8Snorm) => Sf::Rg8Snorm,
+        Some(If::Rg8ui) => Sf::Rg8Uint,
+        Some(If::Rg8i) => Sf::Rg8Sint,
+        Some(If::R32ui) => Sf::R32Uint,
+        Some(If::R32i) => Sf::R32Sint,
+        Some(If::R32f) => Sf::R32Float,
+        Some(If::Rg16ui) => Sf::Rg16Uint,
+        Some(If::Rg16i) => Sf::Rg16Sint,
+        Some(If::Rg16f) => Sf::Rg16Float,
+        Some(If::Rgba8) => Sf::Rgba8Unorm,
+        Some(If::Rgba8Snorm) => Sf::Rgba8Snorm,
+        Some(If::Rgba8ui) => Sf::Rgba8Uint,
+        Some(If::Rgba8i) => Sf::Rgba8Sint,
+        Some(If::Rgb10A2) => Sf::Rgb10a2Unorm,
+        Some(If::R11fG11fB10f) => Sf::Rg11b10Float,
+        Some(If::Rg32ui) => Sf::Rg32Uint,
+        Some(If::Rg32i) => Sf::Rg32Sint,
+        Some(If::Rg32f) => Sf::Rg32Float,
+        Some(If::Rgba16ui) => Sf::Rgba16Uint,
+        Some(If::Rgba16i) => Sf::Rgba16Sint,
+        Some(If::Rgba16f) => Sf::Rgba16Float,
+        Some(If::Rgba32ui) => Sf::Rgba32Uint,
+        Some(If::Rgba32i) => Sf::Rgba32Sint,
+        Some(If::Rgba32f) => Sf::Rgba32Float,
+        _ => return Err(Error::UnsupportedImageFormat(word)),
+    }))
+}
+
 fn map_width(word: spirv::Word) -> Result<crate::Bytes, Error> {
     (word >> 3) // bits to bytes
         .try_into()
         .map_err(|_| Error::InvalidTypeWidth(word))
 }
 
+fn f16_to_f64(bits: u16) -> f64 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = i32::from((bits >> 10) & 0x1f);
+    let mantissa = f64::from(bits & 0x3ff);
+    sign * match exponent {
+        0 => mantissa * 2f64.powi(-24),
+        0x1f if mantissa == 0.0 => f64::INFINITY,
+        0x1f => f64::NAN,
+        _ => (1.0 + mantissa / 1024.0) * 2f64.powi(exponent - 15),
+    }
+}
+
 //TODO: this method may need to be gone, depending on whether
 // WGSL allows treating images and samplers as expressions and pass them around.
 fn reach_global_type(
@@ -219,6 +311,7 @@ struct Decoration {
     block: Option<Block>,
     offset: Option<spirv::Word>,
     array_stride: Option<NonZeroU32>,
+    specialization: Option<spirv::Word>,
 }
 
 impl Decoration {
@@ -299,6 +392,7 @@ struct EntryPoint {
     name: String,
     function_id: spirv::Word,
     variable_ids: Vec<spirv::Word>,
+    modes: crate::ExecutionModes,
 }
 
 #[derive(Debug)]
@@ -313,6 +407,152 @@ struct LookupConstant {
     type_id: spirv::Word,
 }
 
+/// Operation of an `OpSpecConstantOp` that depends on specialization
+/// constants, lowered into expressions at the start of every function.
+#[derive(Debug)]
+enum SpecConstantOperation {
+    Unary {
+        op: crate::UnaryOperator,
+        expr_id: spirv::Word,
+    },
+    Binary {
+        op: crate::BinaryOperator,
+        left_id: spirv::Word,
+        right_id: spirv::Word,
+    },
+    /// Signed modulo, taking the sign of the divisor.
+    SignedModulo {
+        left_id: spirv::Word,
+        right_id: spirv::Word,
+    },
+    /// Conversion to the result type, changing the scalar width.
+    ///
+    /// There is no conversion expression, so it's only folded.
+    Convert { expr_id: spirv::Word },
+    /// Selection by a condition known at compile time.
+    Alias { expr_id: spirv::Word },
+    Extract {
+        composite_id: spirv::Word,
+        indices: Vec<u32>,
+    },
+    Shuffle {
+        first_id: spirv::Word,
+        first_size: u32,
+        second_id: spirv::Word,
+        components: Vec<u32>,
+    },
+    /// Composite constant with specialized operations as constituents.
+    Compose { constituent_ids: Vec<spirv::Word> },
+}
+
+/// Provides the expression of an operand, appending it if needed.
+type ResolveOperand<'a> =
+    dyn FnMut(&mut Arena<crate::Expression>, spirv::Word) -> Option<Handle<crate::Expression>> + 'a;
+
+impl SpecConstantOperation {
+    /// Append the expressions computing this operation of type `ty`.
+    ///
+    /// The operands are provided by `resolve`, and `None` is returned
+    /// if any of them can't be resolved.
+    fn build(
+        &self,
+        ty: Handle<crate::Type>,
+        expressions: &mut Arena<crate::Expression>,
+        resolve: &mut ResolveOperand,
+    ) -> Option<Handle<crate::Expression>> {
+        use crate::Expression as E;
+        let expr = match *self {
+            SpecConstantOperation::Unary { op, expr_id } => E::Unary {
+                op,
+                expr: resolve(expressions, expr_id)?,
+            },
+            SpecConstantOperation::Binary {
+                op,
+                left_id,
+                right_id,
+            } => E::Binary {
+                op,
+                left: resolve(expressions, left_id)?,
+                right: resolve(expressions, right_id)?,
+            },
+            SpecConstantOperation::SignedModulo { left_id, right_id } => {
+                // `((a % b) + b) % b` has the sign of `b`, unlike `a % b`
+                let left = resolve(expressions, left_id)?;
+                let right = resolve(expressions, right_id)?;
+                let remainder = expressions.append(E::Binary {
+                    op: crate::BinaryOperator::Modulo,
+                    left,
+                    right,
+                });
+                let sum = expressions.append(E::Binary {
+                    op: crate::BinaryOperator::Add,
+                    left: remainder,
+                    right,
+                });
+                E::Binary {
+                    op: crate::BinaryOperator::Modulo,
+                    left: sum,
+                    right,
+                }
+            }
+            SpecConstantOperation::Convert { expr_id } => E::Compose {
+                ty,
+                components: vec![resolve(expressions, expr_id)?],
+            },
+            SpecConstantOperation::Alias { expr_id } => return resolve(expressions, expr_id),
+            SpecConstantOperation::Extract {
+                composite_id,
+                ref indices,
+            } => {
+                let mut base = resolve(expressions, composite_id)?;
+                for &index in indices.iter() {
+                    base = expressions.append(E::AccessIndex { base, index });
+                }
+                return Some(base);
+            }
+            SpecConstantOperation::Shuffle {
+                first_id,
+                first_size,
+                second_id,
+                ref components,
+            } => {
+                let first = resolve(expressions, first_id)?;
+                let second = resolve(expressions, second_id)?;
+                let components = components
+                    .iter()
+                    .map(|&index| {
+                        // undefined components (0xFFFFFFFF) pick the first one
+                        let (base, index) = if index >= first_size && index != !0 {
+                            (second, index - first_size)
+                        } else {
+                            (first, if index == !0 { 0 } else { index })
+                        };
+                        expressions.append(E::AccessIndex { base, index })
+                    })
+                    .collect();
+                E::Compose { ty, components }
+            }
+            SpecConstantOperation::Compose {
+                ref constituent_ids,
+            } => E::Compose {
+                ty,
+                components: constituent_ids
+                    .iter()
+                    .map(|&id| resolve(expressions, id))
+                    .collect::<Option<_>>()?,
+            },
+        };
+        Some(expressions.append(expr))
+    }
+}
+
+#[derive(Debug)]
+struct DeferredSpecConstantOp {
+    id: spirv::Word,
+    type_id: spirv::Word,
+    operation: SpecConstantOperation,
+}
+
 #[derive(Debug)]
 struct LookupVariable {
     handle: Handle<crate::GlobalVariable>,
@@ -347,13 +587,8 @@ enum Terminator {
     },
 }
 
-struct Assignment {
-    to: Handle<crate::Expression>,
-    value: Handle<crate::Expression>,
-}
-
 struct ControlFlowNode {
-    assignments: Vec<Assignment>,
+    statements: Vec<crate::Statement>,
     terminator: Terminator,
 }
 
@@ -370,6 +605,8 @@ pub struct Parser<I> {
     lookup_void_type: FastHashSet<spirv::Word>,
     // Lookup for samplers and sampled images, storing flags on how they are used.
     lookup_constant: FastHashMap<spirv::Word, LookupConstant>,
+    // Spec constant operations, in the order of declaration.
+    spec_constant_ops: Vec<DeferredSpecConstantOp>,
     lookup_variable: FastHashMap<spirv::Word, LookupVariable>,
     lookup_expression: FastHashMap<spirv::Word, LookupExpression>,
     lookup_sampled_image: FastHashMap<spirv::Word, LookupSampledImage>,
@@ -392,6 +629,7 @@ impl<I: Iterator<Item = u32>> Parser<I> {
             lookup_type: FastHashMap::default(),
             lookup_void_type: FastHashSet::default(),
             lookup_constant: FastHashMap::default(),
+            spec_constant_ops: Vec::new(),
             lookup_variable: FastHashMap::default(),
             lookup_expression: FastHashMap::default(),
             lookup_sampled_image: FastHashMap::default(),
@@ -456,6 +694,13 @@ impl<I: Iterator<Item = u32>> Parser<I> {
                     Some(Bi::InstanceIndex) => crate::BuiltIn::InstanceIndex,
                     Some(Bi::Position) => crate::BuiltIn::Position,
                     Some(Bi::VertexIndex) => crate::BuiltIn::VertexIndex,
+                    // tessellation
+                    Some(Bi::InvocationId) => crate::BuiltIn::InvocationId,
+                    Some(Bi::TessCoord) => crate::BuiltIn::TessCoord,
+                    Some(Bi::TessLevelOuter) => crate::BuiltIn::TessLevelOuter,
+                    Some(Bi::TessLevelInner) => crate::BuiltIn::TessLevelInner,
+                    // geometry
+                    Some(Bi::PrimitiveId) => crate::BuiltIn::PrimitiveId,
                     // fragment
                     Some(Bi::PointSize) => crate::BuiltIn::PointSize,
                     Some(Bi::FragCoord) => crate::BuiltIn::FragCoord,
@@ -496,6 +741,10 @@ impl<I: Iterator<Item = u32>> Parser<I> {
                 inst.expect(base_words + 2)?;
                 dec.array_stride = NonZeroU32::new(self.next()?);
             }
+            spirv::Decoration::SpecId => {
+                inst.expect(base_words + 2)?;
+                dec.specialization = Some(self.next()?);
+            }
             other => {
                 log::warn!("Unknown decoration {:?}", other);
                 for _ in base_words + 1..inst.wc {
@@ -543,7 +792,7 @@ impl<I: Iterator<Item = u32>> Parser<I> {
         global_arena: &Arena<crate::GlobalVariable>,
         local_function_calls: &mut FastHashMap<Handle<crate::Expression>, spirv::Word>,
     ) -> Result<ControlFlowNode, Error> {
-        let mut assignments = Vec::new();
+        let mut statements = Vec::new();
         let terminator = loop {
             use spirv::Op;
             let inst = self.next_inst()?;
@@ -787,11 +1036,19 @@ impl<I: Iterator<Item = u32>> Parser<I> {
                     if base_type.base_id != Some(value_expr.type_id) {
                         return Err(Error::InvalidStoreType(value_expr.type_id));
                     }
-                    assignments.push(Assignment {
-                        to: base_expr.handle,
+                    statements.push(crate::Statement::Store {
+                        pointer: base_expr.handle,
                         value: value_expr.handle,
                     });
                 }
+                Op::EmitVertex => {
+                    inst.expect(1)?;
+                    statements.push(crate::Statement::EmitVertex);
+                }
+                Op::EndPrimitive => {
+                    inst.expect(1)?;
+                    statements.push(crate::Statement::EndPrimitive);
+                }
                 Op::Return => {
                     inst.expect(1)?;
                     break Terminator::Return { value: None };
@@ -804,10 +1061,32 @@ impl<I: Iterator<Item = u32>> Parser<I> {
                         condition: None,
                     };
                 }
+                Op::FAdd => {
+                    inst.expect(5)?;
+                    self.parse_expr_binary_op(expressions, crate::BinaryOperator::Add)?;
+                }
                 Op::FSub => {
                     inst.expect(5)?;
                     self.parse_expr_binary_op(expressions, crate::BinaryOperator::Subtract)?;
                 }
+                Op::FNegate | Op::SNegate => {
+                    inst.expect(4)?;
+                    let result_type_id = self.next()?;
+                    let result_id = self.next()?;
+                    let operand_id = self.next()?;
+                    let operand_lexp = self.lookup_expression.lookup(operand_id)?;
+                    let expr = crate::Expression::Unary {
+                        op: crate::UnaryOperator::Negate,
+                        expr: operand_lexp.handle,
+                    };
+                    self.lookup_expression.insert(
+                        result_id,
+                        LookupExpression {
+                            handle: expressions.append(expr),
+                            type_id: result_type_id,
+                        },
+                    );
+                }
                 Op::FMul => {
                     inst.expect(5)?;
                     self.parse_expr_binary_op(expressions, crate::BinaryOperator::Multiply)?;
@@ -908,6 +1187,7 @@ impl<I: Iterator<Item = u32>> Parser<I> {
                             base: _,
                             dim,
                             flags,
+                            format: _,
                         } if flags
                             & (crate::ImageFlags::MULTISAMPLED | crate::ImageFlags::SAMPLED)
                             == crate::ImageFlags::SAMPLED =>
@@ -1065,20 +1345,28 @@ impl<I: Iterator<Item = u32>> Parser<I> {
                         let arg_id = self.next()?;
                         arguments.push(self.lookup_expression.lookup(arg_id)?.handle);
                     }
-                    let expr = crate::Expression::Call {
-                        // will be replaced by `Local()` after all the functions are parsed
-                        origin: crate::FunctionOrigin::External(String::new()),
-                        arguments,
-                    };
-                    let expr_handle = expressions.append(expr);
-                    local_function_calls.insert(expr_handle, func_id);
-                    self.lookup_expression.insert(
-                        result_id,
-                        LookupExpression {
-                            handle: expr_handle,
-                            type_id: result_type_id,
-                        },
-                    );
+                    if self.lookup_void_type.contains(&result_type_id) {
+                        statements.push(crate::Statement::Call {
+                            // will be resolved after all the functions are parsed
+                            function: Handle::from_usize(func_id as usize),
+                            arguments,
+                        });
+                    } else {
+                        let expr = crate::Expression::Call {
+                            // will be replaced by `Local()` after all the functions are parsed
+                            origin: crate::FunctionOrigin::External(String::new()),
+                            arguments,
+                        };
+                        let expr_handle = expressions.append(expr);
+                        local_function_calls.insert(expr_handle, func_id);
+                        self.lookup_expression.insert(
+                            result_id,
+                            LookupExpression {
+                                handle: expr_handle,
+                                type_id: result_type_id,
+                            },
+                        );
+                    }
                 }
                 Op::ExtInst => {
                     inst.expect_at_least(5)?;
@@ -1122,12 +1410,12 @@ impl<I: Iterator<Item = u32>> Parser<I> {
             }
         };
         Ok(ControlFlowNode {
-            assignments,
+            statements,
             terminator,
         })
     }
 
-    fn make_expression_storage(&mut self) -> Arena<crate::Expression> {
+    fn make_expression_storage(&mut self) -> Result<Arena<crate::Expression>, Error> {
         let mut expressions = Arena::new();
         #[allow(clippy::panic)]
         {
@@ -1153,8 +1441,26 @@ impl<I: Iterator<Item = u32>> Parser<I> {
                 },
             );
         }
+        // register spec constant operations, which may refer to each other
+        for deferred in self.spec_constant_ops.iter() {
+            let ty = self.lookup_type.lookup(deferred.type_id)?.handle;
+            let lookup_expression = &self.lookup_expression;
+            let handle = deferred
+                .operation
+                .build(ty, &mut expressions, &mut |_, id| {
+                    lookup_expression.get(&id).map(|lexp| lexp.handle)
+                })
+                .ok_or(Error::InvalidId(deferred.id))?;
+            self.lookup_expression.insert(
+                deferred.id,
+                LookupExpression {
+                    handle,
+                    type_id: deferred.type_id,
+                },
+            );
+        }
         // done
-        expressions
+        Ok(expressions)
     }
 
     fn switch(&mut self, state: ModuleState, op: spirv::Op) -> Result<(), Error> {
@@ -1191,7 +1497,7 @@ impl<I: Iterator<Item = u32>> Parser<I> {
                 Op::ExtInstImport => self.parse_ext_inst_import(inst),
                 Op::MemoryModel => self.parse_memory_model(inst),
                 Op::EntryPoint => self.parse_entry_point(inst, &mut entry_points),
-                Op::ExecutionMode => self.parse_execution_mode(inst),
+                Op::ExecutionMode => self.parse_execution_mode(inst, &mut entry_points),
                 Op::Source => self.parse_source(inst),
                 Op::SourceExtension => self.parse_source_extension(inst),
                 Op::Name => self.parse_name(inst),
@@ -1213,7 +1519,16 @@ impl<I: Iterator<Item = u32>> Parser<I> {
                 Op::TypeSampledImage => self.parse_type_sampled_image(inst),
                 Op::TypeSampler => self.parse_type_sampler(inst, &mut module),
                 Op::Constant | Op::SpecConstant => self.parse_constant(inst, &mut module),
-                Op::ConstantComposite => self.parse_composite_constant(inst, &mut module),
+                Op::ConstantTrue | Op::SpecConstantTrue => {
+                    self.parse_bool_constant(inst, true, &mut module)
+                }
+                Op::ConstantFalse | Op::SpecConstantFalse => {
+                    self.parse_bool_constant(inst, false, &mut module)
+                }
+                Op::ConstantComposite | Op::SpecConstantComposite => {
+                    self.parse_composite_constant(inst, &mut module)
+                }
+                Op::SpecConstantOp => self.parse_spec_constant_op(inst, &mut module),
                 Op::Variable => self.parse_global_variable(inst, &mut module),
                 Op::Function => self.parse_function(inst, &mut module),
                 _ => Err(Error::UnsupportedInstruction(self.state, inst.op)), //TODO
@@ -1241,6 +1556,7 @@ impl<I: Iterator<Item = u32>> Parser<I> {
                     base: _,
                     dim,
                     flags,
+                    format: _,
                 } => {
                     ty.inner = crate::TypeInner::DepthImage {
                         dim,
@@ -1266,6 +1582,17 @@ impl<I: Iterator<Item = u32>> Parser<I> {
                 _ => unreachable!(),
             }
         }
+        let handles = module
+            .functions
+            .iter()
+            .map(|(handle, _)| handle)
+            .collect::<Vec<_>>();
+        for handle in handles {
+            let body = &mut module.functions.get_mut(handle).body;
+            resolve_call_statements(body, &self.lookup_function)?;
+        }
+        crate::proc::CallGraph::new(&module.functions)
+            .propagate_global_usage(&mut module.functions);
 
         if !self.future_decor.is_empty() {
             log::warn!("Unused item decorations: {:?}", self.future_decor);
@@ -1281,12 +1608,20 @@ impl<I: Iterator<Item = u32>> Parser<I> {
             module.entry_points.push(crate::EntryPoint {
                 stage: match raw.exec_model {
                     spirv::ExecutionModel::Vertex => crate::ShaderStage::Vertex,
+                    spirv::ExecutionModel::TessellationControl => {
+                        crate::ShaderStage::TessellationControl
+                    }
+                    spirv::ExecutionModel::TessellationEvaluation => {
+                        crate::ShaderStage::TessellationEvaluation
+                    }
+                    spirv::ExecutionModel::Geometry => crate::ShaderStage::Geometry,
                     spirv::ExecutionModel::Fragment => crate::ShaderStage::Fragment,
                     spirv::ExecutionModel::GLCompute => crate::ShaderStage::Compute,
                     other => return Err(Error::UnsupportedExecutionModel(other as u32)),
                 },
                 name: raw.name,
                 function: *self.lookup_function.lookup(raw.function_id)?,
+                modes: raw.modes,
             });
         }
 
@@ -1358,18 +1693,60 @@ impl<I: Iterator<Item = u32>> Parser<I> {
             name,
             function_id,
             variable_ids: self.data.by_ref().take(left as usize).collect(),
+            modes: crate::ExecutionModes::default(),
         };
         entry_points.push(ep);
         Ok(())
     }
 
-    fn parse_execution_mode(&mut self, inst: Instruction) -> Result<(), Error> {
+    fn parse_execution_mode(
+        &mut self,
+        inst: Instruction,
+        entry_points: &mut [EntryPoint],
+    ) -> Result<(), Error> {
+        use crate::{PrimitiveTopology as Pt, TessellationSpacing as Ts};
+        use spirv::ExecutionMode as Em;
+
         self.switch(ModuleState::ExecutionMode, inst.op)?;
         inst.expect_at_least(3)?;
-        let _ep_id = self.next()?;
-        let _mode = self.next()?;
+        let ep_id = self.next()?;
+        let mode_raw = self.next()?;
+        let mode = Em::from_u32(mode_raw).ok_or(Error::UnsupportedExecutionMode(mode_raw))?;
+        let mut literals = Vec::with_capacity(inst.wc as usize - 3);
         for _ in 3..inst.wc {
-            let _ = self.next()?; //TODO
+            literals.push(self.next()?);
+        }
+
+        for ep in entry_points.iter_mut().filter(|ep| ep.function_id == ep_id) {
+            let modes = &mut ep.modes;
+            match mode {
+                Em::InputPoints => modes.input_topology = Some(Pt::Points),
+                Em::InputLines => modes.input_topology = Some(Pt::Lines),
+                Em::InputLinesAdjacency => modes.input_topology = Some(Pt::LinesAdjacency),
+                Em::Triangles => modes.input_topology = Some(Pt::Triangles),
+                Em::InputTrianglesAdjacency => modes.input_topology = Some(Pt::TrianglesAdjacency),
+                Em::Quads => modes.input_topology = Some(Pt::Quads),
+                Em::Isolines => modes.input_topology = Some(Pt::Isolines),
+                Em::OutputPoints => modes.output_topology = Some(Pt::Points),
+                Em::OutputLineStrip => modes.output_topology = Some(Pt::LineStrip),
+                Em::OutputTriangleStrip => modes.output_topology = Some(Pt::TriangleStrip),
+                Em::OutputVertices => {
+                    modes.output_vertices = Some(*literals.first().ok_or(Error::InvalidOperand)?)
+                }
+                Em::Invocations => {
+                    modes.invocations = Some(*literals.first().ok_or(Error::InvalidOperand)?)
+                }
+                Em::SpacingEqual => modes.spacing = Some(Ts::Equal),
+                Em::SpacingFractionalEven => modes.spacing = Some(Ts::FractionalEven),
+                Em::SpacingFractionalOdd => modes.spacing = Some(Ts::FractionalOdd),
+                Em::VertexOrderCw => modes.vertex_order = Some(crate::VertexOrder::Cw),
+                Em::VertexOrderCcw => modes.vertex_order = Some(crate::VertexOrder::Ccw),
+                Em::LocalSize => match literals[..] {
+                    [x, y, z] => modes.workgroup_size = Some([x, y, z]),
+                    _ => return Err(Error::InvalidOperand),
+                },
+                _ => log::warn!("Unhandled execution mode {:?}", mode),
+            }
         }
         Ok(())
     }
@@ -1763,10 +2140,10 @@ impl<I: Iterator<Item = u32>> Parser<I> {
             flags |= crate::ImageFlags::MULTISAMPLED;
         }
         let is_sampled = self.next()?;
-        if is_sampled != 0 {
+        if is_sampled == 1 {
             flags |= crate::ImageFlags::SAMPLED;
         }
-        let _format = self.next()?;
+        let format = map_image_format(self.next()?)?;
         if inst.wc > 9 {
             inst.expect(10)?;
             let access = self.next()?;
@@ -1776,7 +2153,10 @@ impl<I: Iterator<Item = u32>> Parser<I> {
             if access == 1 || access == 2 {
                 flags |= crate::ImageFlags::CAN_STORE;
             }
-        };
+        } else if is_sampled == 2 {
+            // storage image without an access qualifier
+            flags |= crate::ImageFlags::CAN_LOAD | crate::ImageFlags::CAN_STORE;
+        }
 
         let decor = self.future_decor.remove(&id).unwrap_or_default();
 
@@ -1784,6 +2164,7 @@ impl<I: Iterator<Item = u32>> Parser<I> {
             base: self.lookup_type.lookup(sample_type_id)?.handle,
             dim: map_image_dim(dim)?,
             flags,
+            format,
         };
         let handle = module.types.append(crate::Type {
             name: decor.name,
@@ -1862,7 +2243,7 @@ impl<I: Iterator<Item = u32>> Parser<I> {
             } => {
                 let low = self.next()?;
                 let high = if width > 4 {
-                    inst.expect(4)?;
+                    inst.expect(5)?;
                     self.next()?
                 } else {
                     0
@@ -1873,17 +2254,18 @@ impl<I: Iterator<Item = u32>> Parser<I> {
                 kind: crate::ScalarKind::Sint,
                 width,
             } => {
-                use std::cmp::Ordering;
                 let low = self.next()?;
-                let high = match width.cmp(&4) {
-                    Ordering::Less => return Err(Error::InvalidTypeWidth(u32::from(width))),
-                    Ordering::Greater => {
-                        inst.expect(4)?;
-                        self.next()?
+                let value = match width {
+                    // narrow literals are sign-extended to a full word
+                    2 | 4 => i64::from(low as i32),
+                    8 => {
+                        inst.expect(5)?;
+                        let high = self.next()?;
+                        ((u64::from(high) << 32) | u64::from(low)) as i64
                     }
-                    Ordering::Equal => !0,
+                    _ => return Err(Error::InvalidTypeWidth(u32::from(width))),
                 };
-                crate::ConstantInner::Sint(((u64::from(high) << 32) | u64::from(low)) as i64)
+                crate::ConstantInner::Sint(value)
             }
             crate::TypeInner::Scalar {
                 kind: crate::ScalarKind::Float,
@@ -1891,9 +2273,10 @@ impl<I: Iterator<Item = u32>> Parser<I> {
             } => {
                 let low = self.next()?;
                 let extended = match width {
+                    2 => f16_to_f64(low as u16),
                     4 => f64::from(f32::from_bits(low)),
                     8 => {
-                        inst.expect(4)?;
+                        inst.expect(5)?;
                         let high = self.next()?;
                         f64::from_bits((u64::from(high) << 32) | u64::from(low))
                     }
@@ -1903,12 +2286,13 @@ impl<I: Iterator<Item = u32>> Parser<I> {
             }
             _ => return Err(Error::UnsupportedType(type_lookup.handle)),
         };
+        let dec = self.future_decor.remove(&id).unwrap_or_default();
         self.lookup_constant.insert(
             id,
             LookupConstant {
                 handle: module.constants.append(crate::Constant {
-                    name: self.future_decor.remove(&id).and_then(|dec| dec.name),
-                    specialization: None, //TODO
+                    name: dec.name,
+                    specialization: dec.specialization,
                     inner,
                     ty,
                 }),
@@ -1932,9 +2316,28 @@ impl<I: Iterator<Item = u32>> Parser<I> {
         let id = self.next()?;
 
         let constituents_count = inst.wc - 3;
-        let mut constituents = Vec::with_capacity(constituents_count as usize);
+        let mut constituent_ids = Vec::with_capacity(constituents_count as usize);
         for _ in 0..constituents_count {
-            let constituent_id = self.next()?;
+            constituent_ids.push(self.next()?);
+        }
+
+        // composites of spec constant operations are expressions as well
+        if constituent_ids.iter().any(|&constituent_id| {
+            self.spec_constant_ops
+                .iter()
+                .any(|deferred| deferred.id == constituent_id)
+        }) {
+            self.future_decor.remove(&id);
+            self.spec_constant_ops.push(DeferredSpecConstantOp {
+                id,
+                type_id,
+                operation: SpecConstantOperation::Compose { constituent_ids },
+            });
+            return Ok(());
+        }
+
+        let mut constituents = Vec::with_capacity(constituent_ids.len());
+        for constituent_id in constituent_ids {
             let constant = self.lookup_constant.lookup(constituent_id)?;
             constituents.push(constant.handle);
         }
@@ -1955,6 +2358,238 @@ impl<I: Iterator<Item = u32>> Parser<I> {
         Ok(())
     }
 
+    fn parse_bool_constant(
+        &mut self,
+        inst: Instruction,
+        value: bool,
+        module: &mut crate::Module,
+    ) -> Result<(), Error> {
+        self.switch(ModuleState::Type, inst.op)?;
+        inst.expect(3)?;
+        let type_id = self.next()?;
+        let id = self.next()?;
+        let type_lookup = self.lookup_type.lookup(type_id)?;
+        let ty = type_lookup.handle;
+        match module.types[ty].inner {
+            crate::TypeInner::Scalar {
+                kind: crate::ScalarKind::Bool,
+                ..
+            } => {}
+            _ => return Err(Error::UnsupportedType(ty)),
+        }
+        let dec = self.future_decor.remove(&id).unwrap_or_default();
+        self.lookup_constant.insert(
+            id,
+            LookupConstant {
+                handle: module.constants.append(crate::Constant {
+                    name: dec.name,
+                    specialization: dec.specialization,
+                    inner: crate::ConstantInner::Bool(value),
+                    ty,
+                }),
+                type_id,
+            },
+        );
+        Ok(())
+    }
+
+    /// Returns the type of a constant or a spec constant operation.
+    fn spec_constant_type_id(&self, id: spirv::Word) -> Result<spirv::Word, Error> {
+        match self
+            .spec_constant_ops
+            .iter()
+            .find(|deferred| deferred.id == id)
+        {
+            Some(deferred) => Ok(deferred.type_id),
+            None => Ok(self.lookup_constant.lookup(id)?.type_id),
+        }
+    }
+
+    /// Evaluate a spec constant operation that doesn't depend on
+    /// specialization constants.
+    fn fold_spec_constant_op(
+        &self,
+        operation: &SpecConstantOperation,
+        ty: Handle<crate::Type>,
+        module: &mut crate::Module,
+    ) -> Option<Handle<crate::Constant>> {
+        let mut expressions = Arena::new();
+        let lookup_constant = &self.lookup_constant;
+        let mut expr = operation.build(ty, &mut expressions, &mut |expressions, id| {
+            lookup_constant
+                .get(&id)
+                .map(|lconst| expressions.append(crate::Expression::Constant(lconst.handle)))
+        })?;
+        // the signedness of the operands may differ from the result
+        match module.types[ty].inner {
+            crate::TypeInner::Scalar { .. } | crate::TypeInner::Vector { .. } => {
+                expr = expressions.append(crate::Expression::Compose {
+                    ty,
+                    components: vec![expr],
+                });
+            }
+            _ => {}
+        }
+        let type_count = module.types.len();
+        let constant_count = module.constants.len();
+        let result = crate::proc::ConstantSolver {
+            types: &mut module.types,
+            expressions: &expressions,
+            constants: &mut module.constants,
+        }
+        .solve(expr);
+        // only keep the intermediate constants if the evaluation succeeds
+        if result.is_err() {
+            module.types.truncate(type_count);
+            module.constants.truncate(constant_count);
+        }
+        result.ok()
+    }
+
+    fn parse_spec_constant_op(
+        &mut self,
+        inst: Instruction,
+        module: &mut crate::Module,
+    ) -> Result<(), Error> {
+        use crate::{BinaryOperator as Bo, UnaryOperator as Uo};
+        use spirv::Op;
+
+        self.switch(ModuleState::Type, inst.op)?;
+        inst.expect_at_least(5)?;
+        let type_id = self.next()?;
+        let id = self.next()?;
+        let opcode = self.next()?;
+        let op = Op::from_u32(opcode).ok_or(Error::UnknownInstruction(opcode as u16))?;
+        let binary_op = match op {
+            Op::IAdd => Some(Bo::Add),
+            Op::ISub => Some(Bo::Subtract),
+            Op::IMul => Some(Bo::Multiply),
+            Op::UDiv | Op::SDiv => Some(Bo::Divide),
+            Op::UMod | Op::SRem => Some(Bo::Modulo),
+            Op::ShiftLeftLogical => Some(Bo::ShiftLeftLogical),
+            Op::ShiftRightLogical => Some(Bo::ShiftRightLogical),
+            Op::ShiftRightArithmetic => Some(Bo::ShiftRightArithmetic),
+            Op::BitwiseAnd => Some(Bo::And),
+            Op::BitwiseOr => Some(Bo::InclusiveOr),
+            Op::BitwiseXor => Some(Bo::ExclusiveOr),
+            Op::LogicalAnd => Some(Bo::LogicalAnd),
+            Op::LogicalOr => Some(Bo::LogicalOr),
+            Op::LogicalEqual | Op::IEqual => Some(Bo::Equal),
+            Op::LogicalNotEqual | Op::INotEqual => Some(Bo::NotEqual),
+            Op::ULessThan | Op::SLessThan => Some(Bo::Less),
+            Op::ULessThanEqual | Op::SLessThanEqual => Some(Bo::LessEqual),
+            Op::UGreaterThan | Op::SGreaterThan => Some(Bo::Greater),
+            Op::UGreaterThanEqual | Op::SGreaterThanEqual => Some(Bo::GreaterEqual),
+            _ => None,
+        };
+        let operation = if let Some(op) = binary_op {
+            inst.expect(6)?;
+            SpecConstantOperation::Binary {
+                op,
+                left_id: self.next()?,
+                right_id: self.next()?,
+            }
+        } else {
+            match op {
+                Op::SMod => {
+                    inst.expect(6)?;
+                    SpecConstantOperation::SignedModulo {
+                        left_id: self.next()?,
+                        right_id: self.next()?,
+                    }
+                }
+                Op::SNegate | Op::Not | Op::LogicalNot => {
+                    inst.expect(5)?;
+                    SpecConstantOperation::Unary {
+                        op: if op == Op::SNegate {
+                            Uo::Negate
+                        } else {
+                            Uo::Not
+                        },
+                        expr_id: self.next()?,
+                    }
+                }
+                Op::SConvert | Op::UConvert | Op::FConvert => {
+                    inst.expect(5)?;
+                    SpecConstantOperation::Convert {
+                        expr_id: self.next()?,
+                    }
+                }
+                Op::Select => {
+                    inst.expect(7)?;
+                    let condition_id = self.next()?;
+                    let accept_id = self.next()?;
+                    let reject_id = self.next()?;
+                    // there is no selection expression, so the condition must be known
+                    let condition = match self.lookup_constant.get(&condition_id) {
+                        Some(lconst) => &module.constants[lconst.handle],
+                        None => return Err(Error::UnsupportedSpecConstantOp(op)),
+                    };
+                    match condition.inner {
+                        crate::ConstantInner::Bool(value) if condition.specialization.is_none() => {
+                            SpecConstantOperation::Alias {
+                                expr_id: if value { accept_id } else { reject_id },
+                            }
+                        }
+                        _ => return Err(Error::UnsupportedSpecConstantOp(op)),
+                    }
+                }
+                Op::CompositeExtract => {
+                    inst.expect_at_least(6)?;
+                    let composite_id = self.next()?;
+                    let mut indices = Vec::with_capacity(inst.wc as usize - 5);
+                    for _ in 5..inst.wc {
+                        indices.push(self.next()?);
+                    }
+                    SpecConstantOperation::Extract {
+                        composite_id,
+                        indices,
+                    }
+                }
+                Op::VectorShuffle => {
+                    inst.expect_at_least(7)?;
+                    let first_id = self.next()?;
+                    let second_id = self.next()?;
+                    let first_type_id = self.spec_constant_type_id(first_id)?;
+                    let first_ty = self.lookup_type.lookup(first_type_id)?.handle;
+                    let first_size = match module.types[first_ty].inner {
+                        crate::TypeInner::Vector { size, .. } => size as u32,
+                        _ => return Err(Error::InvalidInnerType(first_type_id)),
+                    };
+                    let mut components = Vec::with_capacity(inst.wc as usize - 6);
+                    for _ in 6..inst.wc {
+                        components.push(self.next()?);
+                    }
+                    SpecConstantOperation::Shuffle {
+                        first_id,
+                        first_size,
+                        second_id,
+                        components,
+                    }
+                }
+                _ => return Err(Error::UnsupportedSpecConstantOp(op)),
+            }
+        };
+        // names of the operations are dropped, like the ones of other expressions
+        self.future_decor.remove(&id);
+        let ty = self.lookup_type.lookup(type_id)?.handle;
+        match self.fold_spec_constant_op(&operation, ty, module) {
+            Some(handle) => {
+                self.lookup_constant
+                    .insert(id, LookupConstant { handle, type_id });
+            }
+            None if matches!(operation, SpecConstantOperation::Convert { .. }) => {
+                return Err(Error::UnsupportedSpecConstantOp(op));
+            }
+            None => self.spec_constant_ops.push(DeferredSpecConstantOp {
+                id,
+                type_id,
+                operation,
+            }),
+        }
+        Ok(())
+    }
+
     fn parse_global_variable(
         &mut self,
         inst: Instruction,
@@ -1965,10 +2600,22 @@ impl<I: Iterator<Item = u32>> Parser<I> {
         let type_id = self.next()?;
         let id = self.next()?;
         let storage = self.next()?;
-        if inst.wc != 4 {
+        let init = if inst.wc != 4 {
             inst.expect(5)?;
-            let _init = self.next()?; //TODO
-        }
+            let init_id = self.next()?;
+            // spec constant operations only exist as expressions
+            if self
+                .spec_constant_ops
+                .iter()
+                .any(|deferred| deferred.id == init_id)
+            {
+                return Err(Error::UnsupportedSpecConstantInitializer(init_id));
+            }
+            let lconst = self.lookup_constant.lookup(init_id)?;
+            Some(lconst.handle)
+        } else {
+            None
+        };
         let lookup_type = self.lookup_type.lookup(type_id)?;
         let dec = self
             .future_decor
@@ -1990,6 +2637,7 @@ impl<I: Iterator<Item = u32>> Parser<I> {
             class: map_storage_class(storage)?,
             binding,
             ty,
+            init,
         };
         self.lookup_variable.insert(
             id,
@@ -2019,7 +2667,7 @@ impl<I: Iterator<Item = u32>> Parser<I> {
             }
             crate::Function {
                 name: self.future_decor.remove(&fun_id).and_then(|dec| dec.name),
-                parameter_types: Vec::with_capacity(ft.parameter_type_ids.len()),
+                arguments: Vec::with_capacity(ft.parameter_type_ids.len()),
                 return_type: if self.lookup_void_type.contains(&result_type) {
                     None
                 } else {
@@ -2027,19 +2675,19 @@ impl<I: Iterator<Item = u32>> Parser<I> {
                 },
                 global_usage: Vec::new(),
                 local_variables: Arena::new(),
-                expressions: self.make_expression_storage(),
+                expressions: self.make_expression_storage()?,
                 body: Vec::new(),
             }
         };
         // read parameters
-        for i in 0..fun.parameter_types.capacity() {
+        for i in 0..fun.arguments.capacity() {
             match self.next_inst()? {
                 Instruction {
                     op: spirv::Op::FunctionParameter,
                     wc: 3,
                 } => {
                     let type_id = self.next()?;
-                    let _id = self.next()?;
+                    let id = self.next()?;
                     //Note: we redo the lookup in order to work around `self` borrowing
                     if type_id
                         != self
@@ -2050,7 +2698,17 @@ impl<I: Iterator<Item = u32>> Parser<I> {
                         return Err(Error::WrongFunctionParameterType(type_id));
                     }
                     let ty = self.lookup_type.lookup(type_id)?.handle;
-                    fun.parameter_types.push(ty);
+                    fun.arguments.push(crate::FunctionArgument {
+                        name: self.future_decor.remove(&id).and_then(|dec| dec.name),
+                        ty,
+                        binding: None,
+                    });
+                    // pointer parameters are loaded from and stored to like variables
+                    let handle = fun
+                        .expressions
+                        .append(crate::Expression::FunctionParameter(i as u32));
+                    self.lookup_expression
+                        .insert(id, LookupExpression { handle, type_id });
                 }
                 Instruction { op, .. } => return Err(Error::InvalidParameter(op)),
             }
@@ -2074,12 +2732,7 @@ impl<I: Iterator<Item = u32>> Parser<I> {
                         &mut local_function_calls,
                     )?;
                     // temp until the CFG is fully processed
-                    for assign in node.assignments.iter() {
-                        fun.body.push(crate::Statement::Store {
-                            pointer: assign.to,
-                            value: assign.value,
-                        });
-                    }
+                    fun.body.extend(node.statements.iter().cloned());
                     match node.terminator {
                         Terminator::Return { value } => {
                             fun.body.push(crate::Statement::Return { value });
@@ -2145,4 +2798,195 @@ mod test {
         ];
         let _ = super::parse_u8_slice(&bin).unwrap();
     }
+
+    fn inst(op: spirv::Op, operands: &[u32]) -> Vec<u32> {
+        let mut words = vec![((operands.len() as u32 + 1) << 16) | op as u32];
+        words.extend_from_slice(operands);
+        words
+    }
+
+    #[test]
+    fn spec_constant_ops() {
+        use spirv::Op;
+
+        let mut words = vec![spirv::MAGIC_NUMBER, 0x10000, 0, 20, 0];
+        words.extend(inst(Op::MemoryModel, &[0, 1]));
+        words.extend(inst(
+            Op::Decorate,
+            &[3, spirv::Decoration::SpecId as u32, 7],
+        ));
+        words.extend(inst(
+            Op::Decorate,
+            &[5, spirv::Decoration::SpecId as u32, 8],
+        ));
+        words.extend(inst(Op::TypeInt, &[1, 32, 0]));
+        words.extend(inst(Op::TypeBool, &[2]));
+        words.extend(inst(Op::SpecConstant, &[1, 3, 64]));
+        words.extend(inst(Op::Constant, &[1, 4, 1]));
+        words.extend(inst(Op::SpecConstantTrue, &[2, 5]));
+        words.extend(inst(Op::TypeVector, &[6, 1, 3]));
+        words.extend(inst(Op::SpecConstantComposite, &[6, 7, 3, 4, 4]));
+        words.extend(inst(Op::SpecConstantOp, &[1, 8, Op::IMul as u32, 3, 3]));
+        words.extend(inst(Op::TypeVoid, &[9]));
+        words.extend(inst(Op::TypeFunction, &[10, 9]));
+        words.extend(inst(Op::Function, &[9, 11, 0, 10]));
+        words.extend(inst(Op::Label, &[12]));
+        words.extend(inst(Op::Return, &[]));
+        words.extend(inst(Op::FunctionEnd, &[]));
+
+        let module = super::Parser::new(words.into_iter()).parse().unwrap();
+        let specialized = module
+            .constants
+            .iter()
+            .filter_map(|(_, c)| c.specialization.map(|id| (id, c.inner.clone())))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            specialized,
+            vec![
+                (7, crate::ConstantInner::Uint(64)),
+                (8, crate::ConstantInner::Bool(true)),
+            ]
+        );
+        let (_, composite) = module.constants.iter().last().unwrap();
+        assert!(matches!(composite.inner, crate::ConstantInner::Composite(ref c) if c.len() == 3));
+
+        let (_, function) = module.functions.iter().next().unwrap();
+        assert!(function.expressions.iter().any(|(_, expr)| matches!(
+            *expr,
+            crate::Expression::Binary {
+                op: crate::BinaryOperator::Multiply,
+                ..
+            }
+        )));
+    }
+
+    #[test]
+    fn spec_constant_folding() {
+        use spirv::Op;
+
+        let mut words = vec![spirv::MAGIC_NUMBER, 0x10000, 0, 30, 0];
+        words.extend(inst(Op::MemoryModel, &[0, 1]));
+        words.extend(inst(
+            Op::Decorate,
+            &[3, spirv::Decoration::SpecId as u32, 7],
+        ));
+        words.extend(inst(Op::TypeInt, &[1, 32, 1]));
+        words.extend(inst(Op::TypeBool, &[2]));
+        words.extend(inst(Op::SpecConstant, &[1, 3, 64]));
+        words.extend(inst(Op::Constant, &[1, 4, -7i32 as u32]));
+        words.extend(inst(Op::Constant, &[1, 5, 3]));
+        words.extend(inst(Op::ConstantTrue, &[2, 6]));
+        words.extend(inst(Op::TypeVector, &[7, 1, 3]));
+        // -7 smod 3 = 2, -7 srem 3 = -1
+        words.extend(inst(Op::SpecConstantOp, &[1, 8, Op::SMod as u32, 4, 5]));
+        words.extend(inst(Op::SpecConstantOp, &[1, 9, Op::SRem as u32, 4, 5]));
+        words.extend(inst(
+            Op::SpecConstantOp,
+            &[1, 10, Op::Select as u32, 6, 8, 9],
+        ));
+        // the spec constant operation turns the composite into an expression
+        words.extend(inst(Op::SpecConstantOp, &[1, 11, Op::IAdd as u32, 3, 5]));
+        words.extend(inst(Op::SpecConstantComposite, &[7, 12, 10, 11, 9]));
+        words.extend(inst(
+            Op::SpecConstantOp,
+            &[7, 13, Op::VectorShuffle as u32, 12, 12, 2, 4, 0],
+        ));
+        words.extend(inst(Op::TypeVoid, &[20]));
+        words.extend(inst(Op::TypeFunction, &[21, 20]));
+        words.extend(inst(Op::Function, &[20, 22, 0, 21]));
+        words.extend(inst(Op::Label, &[23]));
+        words.extend(inst(Op::Return, &[]));
+        words.extend(inst(Op::FunctionEnd, &[]));
+
+        let module = super::Parser::new(words.into_iter()).parse().unwrap();
+        let values = module
+            .constants
+            .iter()
+            .filter_map(|(_, c)| match c.inner {
+                crate::ConstantInner::Sint(value) => Some(value),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert!(values.contains(&2));
+        assert!(values.contains(&-1));
+        assert!(!module
+            .constants
+            .iter()
+            .any(|(_, c)| matches!(c.inner, crate::ConstantInner::Composite(_))));
+
+        let (_, function) = module.functions.iter().next().unwrap();
+        let composes = function
+            .expressions
+            .iter()
+            .filter_map(|(_, expr)| match *expr {
+                crate::Expression::Compose { ref components, .. } => Some(components.len()),
+                _ => None,
+            })
+            .count();
+        assert_eq!(composes, 2);
+    }
+
+    #[test]
+    fn spec_constant_conversion() {
+        use spirv::Op;
+
+        let mut words = vec![spirv::MAGIC_NUMBER, 0x10000, 0, 10, 0];
+        words.extend(inst(Op::Capability, &[spirv::Capability::Int64 as u32]));
+        words.extend(inst(Op::MemoryModel, &[0, 1]));
+        words.extend(inst(
+            Op::Decorate,
+            &[3, spirv::Decoration::SpecId as u32, 1],
+        ));
+        words.extend(inst(Op::TypeInt, &[1, 32, 1]));
+        words.extend(inst(Op::TypeInt, &[2, 64, 1]));
+        words.extend(inst(Op::SpecConstant, &[1, 3, 7]));
+        words.extend(inst(Op::Constant, &[1, 4, -5i32 as u32]));
+        words.extend(inst(Op::SpecConstantOp, &[2, 5, Op::SConvert as u32, 4]));
+        let module = super::Parser::new(words.clone().into_iter())
+            .parse()
+            .unwrap();
+        assert!(module.constants.iter().any(|(_, c)| {
+            c.inner == crate::ConstantInner::Sint(-5)
+                && module.types[c.ty].inner
+                    == crate::TypeInner::Scalar {
+                        kind: crate::ScalarKind::Sint,
+                        width: 8,
+                    }
+        }));
+
+        // the width of a specialization constant can't be changed
+        words.extend(inst(Op::SpecConstantOp, &[2, 6, Op::SConvert as u32, 3]));
+        match super::Parser::new(words.into_iter()).parse() {
+            Err(super::Error::UnsupportedSpecConstantOp(Op::SConvert)) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn spec_constant_initializer() {
+        use spirv::Op;
+
+        let mut words = vec![spirv::MAGIC_NUMBER, 0x10000, 0, 10, 0];
+        words.extend(inst(Op::MemoryModel, &[0, 1]));
+        words.extend(inst(
+            Op::Decorate,
+            &[2, spirv::Decoration::SpecId as u32, 1],
+        ));
+        words.extend(inst(Op::TypeInt, &[1, 32, 0]));
+        words.extend(inst(Op::SpecConstant, &[1, 2, 64]));
+        words.extend(inst(Op::SpecConstantOp, &[1, 3, Op::IMul as u32, 2, 2]));
+        words.extend(inst(
+            Op::TypePointer,
+            &[4, spirv::StorageClass::Private as u32, 1],
+        ));
+        words.extend(inst(
+            Op::Variable,
+            &[4, 5, spirv::StorageClass::Private as u32, 3],
+        ));
+
+        match super::Parser::new(words.into_iter()).parse() {
+            Err(super::Error::UnsupportedSpecConstantInitializer(3)) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
 }
@@ -0,0 +1,879 @@
+//! Parser for the textual representation of the IR.
+//!
+//! The format is produced by [`back::text`](crate::back::text).
+
+use crate::{
+    arena::{Arena, Handle},
+    back::text::{self as keywords, Keyword},
+    FastHashMap,
+};
+use std::{fmt::Debug, ops::BitOr, str::FromStr};
+use thiserror::Error;
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token<'a> {
+    Word(&'a str),
+    Number(&'a str),
+    Handle(&'a str),
+    String(String),
+    Punct(char),
+    Arrow,
+    End,
+}
+
+#[derive(Clone, Debug, Error)]
+pub enum Error {
+    #[error("unexpected {0}")]
+    Unexpected(String),
+    #[error("unknown keyword `{0}`")]
+    UnknownKeyword(String),
+    #[error("invalid number `{0}`")]
+    BadNumber(String),
+    #[error("unknown type `{0}`")]
+    UnknownType(String),
+    #[error("declared %{found}, expected %{expected}")]
+    OutOfOrder { expected: usize, found: usize },
+    #[error("unterminated string")]
+    UnterminatedString,
+    #[error("unknown character `{0}`")]
+    UnknownCharacter(char),
+}
+
+#[derive(Clone, Debug, Error)]
+#[error("error while parsing the IR text at line {line}: {error}")]
+pub struct ParseError {
+    pub error: Error,
+    pub line: usize,
+}
+
+fn unescape(chars: &mut std::iter::Peekable<std::str::CharIndices>) -> Result<String, Error> {
+    let mut string = String::new();
+    loop {
+        match chars.next() {
+            Some((_, '"')) => return Ok(string),
+            Some((_, '\\')) => {
+                let ch = match chars.next() {
+                    Some((_, 'n')) => '\n',
+                    Some((_, 'r')) => '\r',
+                    Some((_, 't')) => '\t',
+                    Some((_, '0')) => '\0',
+                    Some((_, 'u')) => {
+                        let mut code = String::new();
+                        chars.next();
+                        for (_, ch) in chars.by_ref() {
+                            if ch == '}' {
+                                break;
+                            }
+                            code.push(ch);
+                        }
+                        u32::from_str_radix(&code, 16)
+                            .ok()
+                            .and_then(std::char::from_u32)
+                            .ok_or(Error::UnknownCharacter('u'))?
+                    }
+                    Some((_, ch)) => ch,
+                    None => return Err(Error::UnterminatedString),
+                };
+                string.push(ch);
+            }
+            Some((_, ch)) => string.push(ch),
+            None => return Err(Error::UnterminatedString),
+        }
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<(Token<'_>, usize)>, ParseError> {
+    let mut tokens = Vec::new();
+    let mut line = 1;
+    let mut chars = source.char_indices().peekable();
+    let span_end = |chars: &mut std::iter::Peekable<std::str::CharIndices>, is_number: bool| {
+        let mut previous = ' ';
+        while let Some(&(pos, ch)) = chars.peek() {
+            let continues = match ch {
+                '_' => true,
+                '.' => is_number,
+                '-' | '+' => is_number && previous == 'e',
+                _ => ch.is_ascii_alphanumeric(),
+            };
+            if !continues {
+                return pos;
+            }
+            previous = ch;
+            chars.next();
+        }
+        source.len()
+    };
+
+    while let Some((start, ch)) = chars.next() {
+        let token = match ch {
+            '\n' => {
+                line += 1;
+                continue;
+            }
+            _ if ch.is_whitespace() => continue,
+            '%' => {
+                let end = span_end(&mut chars, false);
+                Token::Handle(&source[start + 1..end])
+            }
+            '"' => Token::String(unescape(&mut chars).map_err(|error| ParseError { error, line })?),
+            '-' => match chars.peek() {
+                Some(&(_, '>')) => {
+                    chars.next();
+                    Token::Arrow
+                }
+                Some(&(_, next)) if next.is_ascii_digit() => {
+                    let end = span_end(&mut chars, true);
+                    Token::Number(&source[start..end])
+                }
+                _ => Token::Punct(ch),
+            },
+            _ if ch.is_ascii_digit() => {
+                let end = span_end(&mut chars, true);
+                Token::Number(&source[start..end])
+            }
+            _ if ch.is_ascii_alphabetic() || ch == '_' => {
+                let end = span_end(&mut chars, false);
+                Token::Word(&source[start..end])
+            }
+            '=' | ':' | ',' | '<' | '>' | '{' | '}' | '[' | ']' | '(' | ')' | '|' => {
+                Token::Punct(ch)
+            }
+            _ => {
+                return Err(ParseError {
+                    error: Error::UnknownCharacter(ch),
+                    line,
+                })
+            }
+        };
+        tokens.push((token, line));
+    }
+    tokens.push((Token::End, line));
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: Vec<(Token<'a>, usize)>,
+    position: usize,
+    lookup_type: FastHashMap<String, Option<Handle<crate::Type>>>,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> &Token<'a> {
+        &self.tokens[self.position].0
+    }
+
+    fn next(&mut self) -> Token<'a> {
+        let token = self.tokens[self.position].0.clone();
+        if token != Token::End {
+            self.position += 1;
+        }
+        token
+    }
+
+    fn unexpected(&self, token: Token<'a>) -> Error {
+        Error::Unexpected(match token {
+            Token::Word(word) => format!("`{}`", word),
+            Token::Number(number) => format!("number {}", number),
+            Token::Handle(handle) => format!("%{}", handle),
+            Token::String(string) => format!("string {:?}", string),
+            Token::Punct(ch) => format!("`{}`", ch),
+            Token::Arrow => "`->`".to_string(),
+            Token::End => "end of input".to_string(),
+        })
+    }
+
+    fn skip(&mut self, token: Token<'a>) -> bool {
+        if *self.peek() == token {
+            self.next();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, expected: Token<'a>) -> Result<(), Error> {
+        match self.next() {
+            ref token if *token == expected => Ok(()),
+            other => Err(self.unexpected(other)),
+        }
+    }
+
+    fn word(&mut self) -> Result<&'a str, Error> {
+        match self.next() {
+            Token::Word(word) => Ok(word),
+            other => Err(self.unexpected(other)),
+        }
+    }
+
+    fn number<T: FromStr>(&mut self) -> Result<T, Error> {
+        match self.next() {
+            Token::Number(number) => number
+                .parse()
+                .map_err(|_| Error::BadNumber(number.to_string())),
+            other => Err(self.unexpected(other)),
+        }
+    }
+
+    fn float(&mut self) -> Result<f64, Error> {
+        let negative = self.skip(Token::Punct('-'));
+        let value = match self.next() {
+            Token::Number(number) => number
+                .parse()
+                .map_err(|_| Error::BadNumber(number.to_string()))?,
+            Token::Word("inf") => f64::INFINITY,
+            Token::Word("NaN") => f64::NAN,
+            other => return Err(self.unexpected(other)),
+        };
+        Ok(if negative { -value } else { value })
+    }
+
+    fn string(&mut self) -> Result<String, Error> {
+        match self.next() {
+            Token::String(string) => Ok(string),
+            other => Err(self.unexpected(other)),
+        }
+    }
+
+    fn name(&mut self) -> Result<Option<String>, Error> {
+        match self.next() {
+            Token::String(string) => Ok(Some(string)),
+            Token::Word("_") => Ok(None),
+            other => Err(self.unexpected(other)),
+        }
+    }
+
+    fn handle<T>(&mut self) -> Result<Handle<T>, Error> {
+        match self.next() {
+            Token::Handle(index) => index
+                .parse()
+                .map(Handle::from_usize)
+                .map_err(|_| Error::BadNumber(index.to_string())),
+            other => Err(self.unexpected(other)),
+        }
+    }
+
+    fn handles<T>(&mut self) -> Result<Vec<Handle<T>>, Error> {
+        self.expect(Token::Punct('['))?;
+        let mut handles = Vec::new();
+        while !self.skip(Token::Punct(']')) {
+            if !handles.is_empty() {
+                self.expect(Token::Punct(','))?;
+            }
+            handles.push(self.handle()?);
+        }
+        Ok(handles)
+    }
+
+    /// Parse the handle of a new item, which has to be the next one in its arena.
+    fn declare<T>(&mut self, arena: &Arena<T>) -> Result<(), Error> {
+        let handle = self.handle::<T>()?;
+        if handle.index() == arena.len() {
+            Ok(())
+        } else {
+            Err(Error::OutOfOrder {
+                expected: arena.len(),
+                found: handle.index(),
+            })
+        }
+    }
+
+    fn keyword<T: Keyword>(&mut self) -> Result<T, Error> {
+        let word = self.word()?;
+        T::ALL
+            .iter()
+            .cloned()
+            .find(|&value| value.keyword() == word)
+            .ok_or_else(|| Error::UnknownKeyword(word.to_string()))
+    }
+
+    fn flags<T: Copy + BitOr<Output = T>>(
+        &mut self,
+        table: &[(T, &str)],
+        empty: T,
+    ) -> Result<T, Error> {
+        if self.skip(Token::Punct('-')) {
+            return Ok(empty);
+        }
+        let mut flags = empty;
+        loop {
+            let word = self.word()?;
+            flags = flags
+                | table
+                    .iter()
+                    .find(|&&(_, name)| name == word)
+                    .map(|&(flag, _)| flag)
+                    .ok_or_else(|| Error::UnknownKeyword(word.to_string()))?;
+            if !self.skip(Token::Punct('|')) {
+                return Ok(flags);
+            }
+        }
+    }
+
+    fn binding(&mut self) -> Result<Option<crate::Binding>, Error> {
+        Ok(match *self.peek() {
+            Token::Word("builtin") => {
+                self.next();
+                Some(crate::Binding::BuiltIn(self.keyword()?))
+            }
+            Token::Word("location") => {
+                self.next();
+                Some(crate::Binding::Location(self.number()?))
+            }
+            Token::Word("descriptor") => {
+                self.next();
+                Some(crate::Binding::Descriptor {
+                    set: self.number()?,
+                    binding: self.number()?,
+                })
+            }
+            _ => None,
+        })
+    }
+
+    fn type_ref(&mut self, types: &Arena<crate::Type>) -> Result<Handle<crate::Type>, Error> {
+        match self.next() {
+            Token::Handle(index) => match index.parse::<usize>() {
+                Ok(index) if index < types.len() => Ok(Handle::from_usize(index)),
+                _ => Err(Error::UnknownType(format!("%{}", index))),
+            },
+            Token::Word(name) => match self.lookup_type.get(name) {
+                Some(&Some(handle)) => Ok(handle),
+                _ => Err(Error::UnknownType(name.to_string())),
+            },
+            other => Err(self.unexpected(other)),
+        }
+    }
+
+    fn scalar(&mut self) -> Result<(crate::ScalarKind, crate::Bytes), Error> {
+        let word = self.word()?;
+        keywords::parse_scalar_name(word).ok_or_else(|| Error::UnknownType(word.to_string()))
+    }
+
+    fn generic_scalar(&mut self) -> Result<(crate::ScalarKind, crate::Bytes), Error> {
+        self.expect(Token::Punct('<'))?;
+        let scalar = self.scalar()?;
+        self.expect(Token::Punct('>'))?;
+        Ok(scalar)
+    }
+
+    fn vector_size(&self, word: &str) -> Result<crate::VectorSize, Error> {
+        match word {
+            "2" => Ok(crate::VectorSize::Bi),
+            "3" => Ok(crate::VectorSize::Tri),
+            "4" => Ok(crate::VectorSize::Quad),
+            _ => Err(Error::BadNumber(word.to_string())),
+        }
+    }
+
+    fn type_inner(&mut self, types: &Arena<crate::Type>) -> Result<crate::TypeInner, Error> {
+        use crate::TypeInner as Ti;
+        let word = self.word()?;
+        Ok(match word {
+            "ptr" => {
+                self.expect(Token::Punct('<'))?;
+                let class = self.keyword()?;
+                self.expect(Token::Punct(','))?;
+                let base = self.type_ref(types)?;
+                self.expect(Token::Punct('>'))?;
+                Ti::Pointer { base, class }
+            }
+            "array" => {
+                self.expect(Token::Punct('<'))?;
+                let base = self.type_ref(types)?;
+                self.expect(Token::Punct(','))?;
+                let size = if self.skip(Token::Word("dynamic")) {
+                    crate::ArraySize::Dynamic
+                } else {
+                    crate::ArraySize::Static(self.number()?)
+                };
+                let stride = if self.skip(Token::Punct(',')) {
+                    self.expect(Token::Word("stride"))?;
+                    Some(self.number()?)
+                } else {
+                    None
+                };
+                self.expect(Token::Punct('>'))?;
+                Ti::Array { base, size, stride }
+            }
+            "struct" => {
+                self.expect(Token::Punct('{'))?;
+                let mut members = Vec::new();
+                while !self.skip(Token::Punct('}')) {
+                    if !members.is_empty() {
+                        self.expect(Token::Punct(','))?;
+                    }
+                    let name = self.name()?;
+                    self.expect(Token::Punct(':'))?;
+                    let ty = self.type_ref(types)?;
+                    let origin = match self.word()? {
+                        "builtin" => crate::MemberOrigin::BuiltIn(self.keyword()?),
+                        "offset" => crate::MemberOrigin::Offset(self.number()?),
+                        other => return Err(Error::UnknownKeyword(other.to_string())),
+                    };
+                    members.push(crate::StructMember { name, origin, ty });
+                }
+                Ti::Struct { members }
+            }
+            "image" => {
+                self.expect(Token::Punct('<'))?;
+                let base = self.type_ref(types)?;
+                self.expect(Token::Punct(','))?;
+                let dim = self.keyword()?;
+                self.expect(Token::Punct(','))?;
+                let flags = self.flags(keywords::IMAGE_FLAGS, crate::ImageFlags::empty())?;
+                let format = if self.skip(Token::Punct(',')) {
+                    Some(self.keyword()?)
+                } else {
+                    None
+                };
+                self.expect(Token::Punct('>'))?;
+                Ti::Image {
+                    base,
+                    dim,
+                    flags,
+                    format,
+                }
+            }
+            "depth_image" => {
+                self.expect(Token::Punct('<'))?;
+                let dim = self.keyword()?;
+                let arrayed = self.skip(Token::Punct(','));
+                if arrayed {
+                    self.expect(Token::Word("arrayed"))?;
+                }
+                self.expect(Token::Punct('>'))?;
+                Ti::DepthImage { dim, arrayed }
+            }
+            "sampler" => Ti::Sampler {
+                comparison: self.skip(Token::Word("comparison")),
+            },
+            _ if word.starts_with("vec") => {
+                let size = self.vector_size(&word[3..])?;
+                let (kind, width) = self.generic_scalar()?;
+                Ti::Vector { size, kind, width }
+            }
+            _ if word.starts_with("mat") => {
+                let mut sizes = word[3..].splitn(2, 'x');
+                let columns = self.vector_size(sizes.next().unwrap_or_default())?;
+                let rows = self.vector_size(sizes.next().unwrap_or_default())?;
+                let (kind, width) = self.generic_scalar()?;
+                Ti::Matrix {
+                    columns,
+                    rows,
+                    kind,
+                    width,
+                }
+            }
+            _ => match keywords::parse_scalar_name(word) {
+                Some((kind, width)) => Ti::Scalar { kind, width },
+                None => return Err(Error::UnknownType(word.to_string())),
+            },
+        })
+    }
+
+    fn expression(&mut self, types: &Arena<crate::Type>) -> Result<crate::Expression, Error> {
+        use crate::Expression as E;
+        Ok(match self.word()? {
+            "access" => E::Access {
+                base: self.handle()?,
+                index: self.handle()?,
+            },
+            "access_index" => E::AccessIndex {
+                base: self.handle()?,
+                index: self.number()?,
+            },
+            "constant" => E::Constant(self.handle()?),
+            "compose" => E::Compose {
+                ty: self.type_ref(types)?,
+                components: self.handles()?,
+            },
+            "param" => E::FunctionParameter(self.number()?),
+            "global" => E::GlobalVariable(self.handle()?),
+            "local" => E::LocalVariable(self.handle()?),
+            "load" => E::Load {
+                pointer: self.handle()?,
+            },
+            "sample" => E::ImageSample {
+                image: self.handle()?,
+                sampler: self.handle()?,
+                coordinate: self.handle()?,
+                depth_ref: if self.skip(Token::Word("depth")) {
+                    Some(self.handle()?)
+                } else {
+                    None
+                },
+            },
+            "unary" => E::Unary {
+                op: self.keyword()?,
+                expr: self.handle()?,
+            },
+            "binary" => E::Binary {
+                op: self.keyword()?,
+                left: self.handle()?,
+                right: self.handle()?,
+            },
+            "intrinsic" => E::Intrinsic {
+                fun: self.keyword()?,
+                argument: self.handle()?,
+            },
+            "dot" => E::DotProduct(self.handle()?, self.handle()?),
+            "cross" => E::CrossProduct(self.handle()?, self.handle()?),
+            "derivative" => E::Derivative {
+                axis: self.keyword()?,
+                expr: self.handle()?,
+            },
+            "call" => E::Call {
+                origin: match self.next() {
+                    Token::String(name) => crate::FunctionOrigin::External(name),
+                    Token::Handle(_) => {
+                        self.position -= 1;
+                        crate::FunctionOrigin::Local(self.handle()?)
+                    }
+                    other => return Err(self.unexpected(other)),
+                },
+                arguments: self.handles()?,
+            },
+            other => return Err(Error::UnknownKeyword(other.to_string())),
+        })
+    }
+
+    fn block(&mut self) -> Result<crate::Block, Error> {
+        self.expect(Token::Punct('{'))?;
+        let mut block = Vec::new();
+        while !self.skip(Token::Punct('}')) {
+            block.push(self.statement()?);
+        }
+        Ok(block)
+    }
+
+    fn statement(&mut self) -> Result<crate::Statement, Error> {
+        use crate::Statement as S;
+        Ok(match self.word()? {
+            "empty" => S::Empty,
+            "block" => S::Block(self.block()?),
+            "if" => {
+                let condition = self.handle()?;
+                let accept = self.block()?;
+                self.expect(Token::Word("else"))?;
+                S::If {
+                    condition,
+                    accept,
+                    reject: self.block()?,
+                }
+            }
+            "switch" => {
+                let selector = self.handle()?;
+                self.expect(Token::Punct('{'))?;
+                let mut cases = FastHashMap::default();
+                while self.skip(Token::Word("case")) {
+                    let value = self.number()?;
+                    let block = self.block()?;
+                    let fall_through = if self.skip(Token::Word("fallthrough")) {
+                        Some(crate::FallThrough)
+                    } else {
+                        None
+                    };
+                    cases.insert(value, (block, fall_through));
+                }
+                self.expect(Token::Word("default"))?;
+                let default = self.block()?;
+                self.expect(Token::Punct('}'))?;
+                S::Switch {
+                    selector,
+                    cases,
+                    default,
+                }
+            }
+            "loop" => {
+                let body = self.block()?;
+                self.expect(Token::Word("continuing"))?;
+                S::Loop {
+                    body,
+                    continuing: self.block()?,
+                }
+            }
+            "break" => S::Break,
+            "continue" => S::Continue,
+            "return" => S::Return {
+                value: match *self.peek() {
+                    Token::Handle(_) => Some(self.handle()?),
+                    _ => None,
+                },
+            },
+            "kill" => S::Kill,
+            "store" => S::Store {
+                pointer: self.handle()?,
+                value: self.handle()?,
+            },
+            "call" => S::Call {
+                function: self.handle()?,
+                arguments: self.handles()?,
+            },
+            "emit_vertex" => S::EmitVertex,
+            "end_primitive" => S::EndPrimitive,
+            other => return Err(Error::UnknownKeyword(other.to_string())),
+        })
+    }
+
+    fn function(&mut self, types: &Arena<crate::Type>) -> Result<crate::Function, Error> {
+        let name = self.name()?;
+        self.expect(Token::Punct('('))?;
+        let mut arguments = Vec::new();
+        while !self.skip(Token::Punct(')')) {
+            if !arguments.is_empty() {
+                self.expect(Token::Punct(','))?;
+            }
+            let name = self.name()?;
+            self.expect(Token::Punct(':'))?;
+            arguments.push(crate::FunctionArgument {
+                name,
+                ty: self.type_ref(types)?,
+                binding: self.binding()?,
+            });
+        }
+        let return_type = if self.skip(Token::Arrow) {
+            Some(self.type_ref(types)?)
+        } else {
+            None
+        };
+        self.expect(Token::Punct('{'))?;
+
+        self.expect(Token::Word("uses"))?;
+        self.expect(Token::Punct('['))?;
+        let mut global_usage = Vec::new();
+        while !self.skip(Token::Punct(']')) {
+            if !global_usage.is_empty() {
+                self.expect(Token::Punct(','))?;
+            }
+            global_usage.push(self.flags(keywords::GLOBAL_USES, crate::GlobalUse::empty())?);
+        }
+
+        let mut local_variables = Arena::new();
+        while self.skip(Token::Word("local")) {
+            self.declare(&local_variables)?;
+            let name = self.name()?;
+            self.expect(Token::Punct(':'))?;
+            let ty = self.type_ref(types)?;
+            let init = if self.skip(Token::Punct('=')) {
+                Some(self.handle()?)
+            } else {
+                None
+            };
+            local_variables.append(crate::LocalVariable { name, ty, init });
+        }
+
+        let mut expressions = Arena::new();
+        while let Token::Handle(_) = *self.peek() {
+            self.declare(&expressions)?;
+            self.expect(Token::Punct('='))?;
+            let expression = self.expression(types)?;
+            expressions.append(expression);
+        }
+
+        let mut body = Vec::new();
+        while !self.skip(Token::Punct('}')) {
+            body.push(self.statement()?);
+        }
+
+        Ok(crate::Function {
+            name,
+            arguments,
+            return_type,
+            global_usage,
+            local_variables,
+            expressions,
+            body,
+        })
+    }
+
+    fn execution_modes(&mut self) -> Result<crate::ExecutionModes, Error> {
+        let mut modes = crate::ExecutionModes::default();
+        self.expect(Token::Punct('{'))?;
+        while !self.skip(Token::Punct('}')) {
+            match self.word()? {
+                "input_topology" => modes.input_topology = Some(self.keyword()?),
+                "output_topology" => modes.output_topology = Some(self.keyword()?),
+                "output_vertices" => modes.output_vertices = Some(self.number()?),
+                "invocations" => modes.invocations = Some(self.number()?),
+                "spacing" => modes.spacing = Some(self.keyword()?),
+                "vertex_order" => modes.vertex_order = Some(self.keyword()?),
+                "workgroup_size" => {
+                    modes.workgroup_size = Some([self.number()?, self.number()?, self.number()?])
+                }
+                other => return Err(Error::UnknownKeyword(other.to_string())),
+            }
+        }
+        Ok(modes)
+    }
+
+    fn parse(&mut self) -> Result<crate::Module, Error> {
+        self.expect(Token::Word("version"))?;
+        let version = (self.number()?, self.number()?, self.number()?);
+        self.expect(Token::Word("generator"))?;
+        let generator = self.number()?;
+        let mut module = crate::Module::from_header(crate::Header { version, generator });
+
+        loop {
+            match self.next() {
+                Token::Word("type") => {
+                    self.declare(&module.types)?;
+                    let name = self.name()?;
+                    self.expect(Token::Punct('='))?;
+                    let inner = self.type_inner(&module.types)?;
+                    if let Some(ref name) = name {
+                        // a name shared by several types can't be used as a reference
+                        let handle = Handle::from_usize(module.types.len());
+                        self.lookup_type
+                            .entry(name.clone())
+                            .and_modify(|lookup| *lookup = None)
+                            .or_insert(Some(handle));
+                    }
+                    module.types.append(crate::Type { name, inner });
+                }
+                Token::Word("const") => {
+                    self.declare(&module.constants)?;
+                    let name = self.name()?;
+                    let specialization = if self.skip(Token::Word("spec")) {
+                        Some(self.number()?)
+                    } else {
+                        None
+                    };
+                    self.expect(Token::Punct(':'))?;
+                    let ty = self.type_ref(&module.types)?;
+                    self.expect(Token::Punct('='))?;
+                    let inner = match self.word()? {
+                        "sint" => crate::ConstantInner::Sint(self.number()?),
+                        "uint" => crate::ConstantInner::Uint(self.number()?),
+                        "float" => crate::ConstantInner::Float(self.float()?),
+                        "bool" => crate::ConstantInner::Bool(match self.word()? {
+                            "true" => true,
+                            "false" => false,
+                            other => return Err(Error::UnknownKeyword(other.to_string())),
+                        }),
+                        "composite" => crate::ConstantInner::Composite(self.handles()?),
+                        other => return Err(Error::UnknownKeyword(other.to_string())),
+                    };
+                    module.constants.append(crate::Constant {
+                        name,
+                        specialization,
+                        inner,
+                        ty,
+                    });
+                }
+                Token::Word("global") => {
+                    self.declare(&module.global_variables)?;
+                    let name = self.name()?;
+                    let class = self.keyword()?;
+                    let ty = self.type_ref(&module.types)?;
+                    let binding = self.binding()?;
+                    let init = if self.skip(Token::Punct('=')) {
+                        Some(self.handle()?)
+                    } else {
+                        None
+                    };
+                    module.global_variables.append(crate::GlobalVariable {
+                        name,
+                        class,
+                        binding,
+                        ty,
+                        init,
+                    });
+                }
+                Token::Word("fn") => {
+                    self.declare(&module.functions)?;
+                    let function = self.function(&module.types)?;
+                    module.functions.append(function);
+                }
+                Token::Word("entry_point") => {
+                    let stage = self.keyword()?;
+                    let name = self.string()?;
+                    self.expect(Token::Punct('='))?;
+                    let function = self.handle()?;
+                    let modes = self.execution_modes()?;
+                    module.entry_points.push(crate::EntryPoint {
+                        stage,
+                        name,
+                        function,
+                        modes,
+                    });
+                }
+                Token::End => return Ok(module),
+                other => return Err(self.unexpected(other)),
+            }
+        }
+    }
+}
+
+/// Parse the textual representation of a module.
+pub fn parse_str(source: &str) -> Result<crate::Module, ParseError> {
+    let mut parser = Parser {
+        tokens: tokenize(source)?,
+        position: 0,
+        lookup_type: FastHashMap::default(),
+    };
+    parser.parse().map_err(|error| ParseError {
+        error,
+        line: parser.tokens[parser.position.saturating_sub(1)].1,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_str;
+    use crate::back::text::write_string;
+
+    #[test]
+    fn round_trip() {
+        let module =
+            crate::front::wgsl::parse_str(include_str!("../../test-data/boids.wgsl")).unwrap();
+        let text = write_string(&module).unwrap();
+        let parsed = parse_str(&text).unwrap();
+        assert_eq!(write_string(&parsed).unwrap(), text);
+        crate::proc::Validator::new().validate(&parsed).unwrap();
+    }
+
+    #[test]
+    fn statements() {
+        let source = r#"
+            version 1 0 0
+            generator 0
+            type %0 "Index" = i32
+            type %1 "Index" = u32
+            type %2 "Value" = f32
+            const %0 _ : %0 = sint -1
+            const %1 "scale" spec 3 : Value = float -2.5e-3
+            global %0 "output" output Value location 0
+            fn %0 "main"("index": %0 builtin vertex_index) {
+                uses [store]
+                local %0 "value": Value = %1
+                %0 = param 0
+                %1 = constant %1
+                %2 = global %0
+                %3 = local %0
+                %4 = load %3
+                switch %0 {
+                    case -1 { store %2 %4 } fallthrough
+                    case 2 { break }
+                    default { }
+                }
+                return
+            }
+            entry_point vertex "main" = %0 { }
+        "#;
+        let module = parse_str(source).unwrap();
+        assert_eq!(
+            module.types[module.constants[crate::arena::Handle::from_usize(1)].ty]
+                .name
+                .as_deref(),
+            Some("Value")
+        );
+        let text = write_string(&module).unwrap();
+        assert!(text.contains("type %1 \"Index\" = u32\n"));
+        assert!(text.contains("const %0 _ : %0 = sint -1\n"));
+        assert!(text.contains("case -1 {"));
+        assert_eq!(write_string(&parse_str(&text).unwrap()).unwrap(), text);
+
+        let error = parse_str("version 1 0 0\ngenerator 0\ntype %1 _ = f32").unwrap_err();
+        assert_eq!(error.line, 3);
+    }
+}
@@ -3,7 +3,7 @@
 //! [wgsl]: https://gpuweb.github.io/gpuweb/wgsl.html
 use crate::{
     arena::{Arena, Handle},
-    proc::{ResolveError, Typifier},
+    proc::{LayoutRules, Layouter, ResolveError, Typifier},
     FastHashMap,
 };
 
@@ -144,10 +144,14 @@ pub enum Error<'a> {
     Unexpected(Token<'a>),
     #[error(transparent)]
     UnexpectedConstantType(#[from] crate::proc::UnexpectedConstantTypeError),
+    #[error(transparent)]
+    ConstantSolving(#[from] crate::proc::ConstantSolvingError),
     #[error("unable to parse `{0}` as integer: {1}")]
     BadInteger(&'a str, std::num::ParseIntError),
     #[error("unable to parse `{1}` as float: {1}")]
     BadFloat(&'a str, std::num::ParseFloatError),
+    #[error("array size {0:?} is not a positive integer constant")]
+    BadArraySize(Handle<crate::Constant>),
     #[error("bad field accessor `{0}`")]
     BadAccessor(&'a str),
     #[error(transparent)]
@@ -156,6 +160,8 @@ pub enum Error<'a> {
     UnknownImport(&'a str),
     #[error("unknown storage class: `{0}`")]
     UnknownStorageClass(&'a str),
+    #[error("unknown storage format: `{0}`")]
+    UnknownStorageFormat(&'a str),
     #[error("unknown decoration: `{0}`")]
     UnknownDecoration(&'a str),
     #[error("unknown builtin: `{0}`")]
@@ -168,8 +174,6 @@ pub enum Error<'a> {
     UnknownType(&'a str),
     #[error("unknown function: `{0}`")]
     UnknownFunction(&'a str),
-    #[error("missing offset for structure member `{0}`")]
-    MissingMemberOffset(&'a str),
     //MutabilityViolation(&'a str),
     // TODO: these could be replaced with more detailed errors
     #[error("other error")]
@@ -269,6 +273,13 @@ impl<'a> StringValueLookup<'a> for FastHashMap<&'a str, Handle<crate::Expression
     }
 }
 
+struct ParsedVariable<'a> {
+    name: &'a str,
+    class: Option<crate::StorageClass>,
+    ty: Handle<crate::Type>,
+    init: Option<Handle<crate::Constant>>,
+}
+
 struct StatementContext<'input, 'temp, 'out> {
     lookup_ident: &'temp mut FastHashMap<&'input str, Handle<crate::Expression>>,
     typifier: &'temp mut Typifier,
@@ -277,6 +288,8 @@ struct StatementContext<'input, 'temp, 'out> {
     types: &'out mut Arena<crate::Type>,
     constants: &'out mut Arena<crate::Constant>,
     global_vars: &'out Arena<crate::GlobalVariable>,
+    arguments: &'out [crate::FunctionArgument],
+    functions: &'out Arena<crate::Function>,
 }
 
 impl<'a> StatementContext<'a, '_, '_> {
@@ -289,6 +302,8 @@ impl<'a> StatementContext<'a, '_, '_> {
             types: self.types,
             constants: self.constants,
             global_vars: self.global_vars,
+            arguments: self.arguments,
+            functions: self.functions,
         }
     }
 
@@ -301,6 +316,8 @@ impl<'a> StatementContext<'a, '_, '_> {
             constants: self.constants,
             global_vars: self.global_vars,
             local_vars: self.variables,
+            arguments: self.arguments,
+            functions: self.functions,
         }
     }
 }
@@ -313,6 +330,8 @@ struct ExpressionContext<'input, 'temp, 'out> {
     constants: &'out mut Arena<crate::Constant>,
     global_vars: &'out Arena<crate::GlobalVariable>,
     local_vars: &'out Arena<crate::LocalVariable>,
+    arguments: &'out [crate::FunctionArgument],
+    functions: &'out Arena<crate::Function>,
 }
 
 impl<'a> ExpressionContext<'a, '_, '_> {
@@ -325,6 +344,8 @@ impl<'a> ExpressionContext<'a, '_, '_> {
             constants: self.constants,
             global_vars: self.global_vars,
             local_vars: self.local_vars,
+            arguments: self.arguments,
+            functions: self.functions,
         }
     }
 
@@ -340,7 +361,8 @@ impl<'a> ExpressionContext<'a, '_, '_> {
                 self.constants,
                 self.global_vars,
                 self.local_vars,
-                &Arena::new(),
+                self.arguments,
+                self.functions,
             )
             .map_err(Error::InvalidResolve)
     }
@@ -383,6 +405,17 @@ pub enum Scope {
     GeneralExpr,
 }
 
+/// Find a previously declared function by name.
+fn find_function(
+    functions: &Arena<crate::Function>,
+    name: &str,
+) -> Option<Handle<crate::Function>> {
+    functions
+        .iter()
+        .find(|(_, fun)| fun.name.as_deref() == Some(name))
+        .map(|(handle, _)| handle)
+}
+
 #[derive(Clone, Debug, Error)]
 #[error("error while parsing WGSL in scopes {scopes:?} at position {pos:?}: {error}")]
 pub struct ParseError<'a> {
@@ -395,6 +428,8 @@ pub struct Parser {
     scopes: Vec<Scope>,
     lookup_type: FastHashMap<String, Handle<crate::Type>>,
     std_namespace: Option<String>,
+    /// Structures with members that have no explicit offset, with a flag per member.
+    implicit_offsets: FastHashMap<Handle<crate::Type>, Vec<bool>>,
 }
 
 impl Parser {
@@ -403,6 +438,7 @@ impl Parser {
             scopes: Vec::new(),
             lookup_type: FastHashMap::default(),
             std_namespace: None,
+            implicit_offsets: FastHashMap::default(),
         }
     }
 
@@ -412,6 +448,9 @@ impl Parser {
             "out" => Ok(crate::StorageClass::Output),
             "uniform" => Ok(crate::StorageClass::Uniform),
             "storage_buffer" => Ok(crate::StorageClass::StorageBuffer),
+            "private" => Ok(crate::StorageClass::Private),
+            "function" => Ok(crate::StorageClass::Function),
+            "workgroup" => Ok(crate::StorageClass::WorkGroup),
             _ => Err(Error::UnknownStorageClass(word)),
         }
     }
@@ -438,6 +477,64 @@ impl Parser {
         }
     }
 
+    fn get_storage_format(word: &str) -> Result<crate::StorageFormat, Error<'_>> {
+        use crate::StorageFormat as Sf;
+        Ok(match word {
+            "r8unorm" => Sf::R8Unorm,
+            "r8snorm" => Sf::R8Snorm,
+            "r8uint" => Sf::R8Uint,
+            "r8sint" => Sf::R8Sint,
+            "r16uint" => Sf::R16Uint,
+            "r16sint" => Sf::R16Sint,
+            "r16float" => Sf::R16Float,
+            "rg8unorm" => Sf::Rg8Unorm,
+            "rg8snorm" => Sf::Rg8Snorm,
+            "rg8uint" => Sf::Rg8Uint,
+            "rg8sint" => Sf::Rg8Sint,
+            "r32uint" => Sf::R32Uint,
+            "r32sint" => Sf::R32Sint,
+            "r32float" => Sf::R32Float,
+            "rg16uint" => Sf::Rg16Uint,
+            "rg16sint" => Sf::Rg16Sint,
+            "rg16float" => Sf::Rg16Float,
+            "rgba8unorm" => Sf::Rgba8Unorm,
+            "rgba8snorm" => Sf::Rgba8Snorm,
+            "rgba8uint" => Sf::Rgba8Uint,
+            "rgba8sint" => Sf::Rgba8Sint,
+            "rgb10a2unorm" => Sf::Rgb10a2Unorm,
+            "rg11b10float" => Sf::Rg11b10Float,
+            "rg32uint" => Sf::Rg32Uint,
+            "rg32sint" => Sf::Rg32Sint,
+            "rg32float" => Sf::Rg32Float,
+            "rgba16uint" => Sf::Rgba16Uint,
+            "rgba16sint" => Sf::Rgba16Sint,
+            "rgba16float" => Sf::Rgba16Float,
+            "rgba32uint" => Sf::Rgba32Uint,
+            "rgba32sint" => Sf::Rgba32Sint,
+            "rgba32float" => Sf::Rgba32Float,
+            _ => return Err(Error::UnknownStorageFormat(word)),
+        })
+    }
+
+    fn get_storage_texture(word: &str) -> Option<(crate::ImageDimension, crate::ImageFlags)> {
+        use crate::{ImageDimension as Id, ImageFlags as If};
+        let (access, dim) = if let Some(dim) = word.strip_prefix("texture_ro_") {
+            (If::CAN_LOAD, dim)
+        } else if let Some(dim) = word.strip_prefix("texture_wo_") {
+            (If::CAN_STORE, dim)
+        } else {
+            return None;
+        };
+        Some(match dim {
+            "1d" => (Id::D1, access),
+            "1d_array" => (Id::D1, access | If::ARRAYED),
+            "2d" => (Id::D2, access),
+            "2d_array" => (Id::D2, access | If::ARRAYED),
+            "3d" => (Id::D3, access),
+            _ => return None,
+        })
+    }
+
     fn get_constant_inner(
         word: &str,
     ) -> Result<(crate::ConstantInner, crate::ScalarKind), Error<'_>> {
@@ -457,33 +554,90 @@ impl Parser {
         lexer: &mut Lexer<'a>,
         type_arena: &mut Arena<crate::Type>,
         const_arena: &mut Arena<crate::Constant>,
-    ) -> Result<crate::ConstantInner, Error<'a>> {
+    ) -> Result<Handle<crate::Constant>, Error<'a>> {
         self.scopes.push(Scope::ConstantExpr);
-        let inner = match lexer.peek() {
+        // named constants can be referenced by other constant expressions
+        if let Token::Word(name) = lexer.peek() {
+            let named = const_arena
+                .iter()
+                .find(|(_, constant)| constant.name.as_deref() == Some(name));
+            if let Some((handle, _)) = named {
+                let _ = lexer.next();
+                self.scopes.pop();
+                return Ok(handle);
+            }
+        }
+        let (inner, kind) = match lexer.peek() {
             Token::Word("true") => {
                 let _ = lexer.next();
-                crate::ConstantInner::Bool(true)
+                (crate::ConstantInner::Bool(true), crate::ScalarKind::Bool)
             }
             Token::Word("false") => {
                 let _ = lexer.next();
-                crate::ConstantInner::Bool(false)
+                (crate::ConstantInner::Bool(false), crate::ScalarKind::Bool)
             }
             Token::Number(word) => {
                 let _ = lexer.next();
-                let (inner, _) = Self::get_constant_inner(word)?;
-                inner
+                Self::get_constant_inner(word)?
             }
             _ => {
-                let _ty = self.parse_type_decl(lexer, type_arena);
+                let ty = self.parse_type_decl(lexer, type_arena, const_arena)?;
                 lexer.expect(Token::Paren('('))?;
+                let mut expressions = Arena::new();
+                let mut components = Vec::new();
                 while !lexer.skip(Token::Paren(')')) {
-                    let _ = self.parse_const_expression(lexer, type_arena, const_arena)?;
+                    if !components.is_empty() {
+                        lexer.expect(Token::Separator(','))?;
+                    }
+                    let component = self.parse_const_expression(lexer, type_arena, const_arena)?;
+                    components.push(expressions.append(crate::Expression::Constant(component)));
                 }
-                unimplemented!()
+                let expr = expressions.append(crate::Expression::Compose { ty, components });
+                let mut solver = crate::proc::ConstantSolver {
+                    types: type_arena,
+                    expressions: &expressions,
+                    constants: const_arena,
+                };
+                let handle = solver.solve(expr)?;
+                self.scopes.pop();
+                return Ok(handle);
             }
         };
+        let ty = Typifier::deduce_type_handle(
+            crate::TypeInner::Scalar {
+                kind,
+                width: if kind == crate::ScalarKind::Bool {
+                    1
+                } else {
+                    4
+                },
+            },
+            type_arena,
+        );
         self.scopes.pop();
-        Ok(inner)
+        Ok(const_arena.fetch_or_append(crate::Constant {
+            name: None,
+            specialization: None,
+            inner,
+            ty,
+        }))
+    }
+
+    fn parse_call_arguments<'a>(
+        &mut self,
+        lexer: &mut Lexer<'a>,
+        mut ctx: ExpressionContext<'a, '_, '_>,
+    ) -> Result<Vec<Handle<crate::Expression>>, Error<'a>> {
+        let mut arguments = Vec::new();
+        lexer.expect(Token::Paren('('))?;
+        while !lexer.skip(Token::Paren(')')) {
+            if !arguments.is_empty() {
+                lexer.expect(Token::Separator(','))?;
+            }
+            let arg = self.parse_general_expression(lexer, ctx.reborrow())?;
+            arguments.push(arg);
+        }
+        Ok(arguments)
     }
 
     fn parse_primary_expression<'a>(
@@ -551,22 +705,20 @@ impl Parser {
                 if self.std_namespace.as_deref() == Some(word) {
                     lexer.expect(Token::DoubleColon)?;
                     let name = lexer.next_ident()?;
-                    let mut arguments = Vec::new();
-                    lexer.expect(Token::Paren('('))?;
-                    while !lexer.skip(Token::Paren(')')) {
-                        if !arguments.is_empty() {
-                            lexer.expect(Token::Separator(','))?;
-                        }
-                        let arg = self.parse_general_expression(lexer, ctx.reborrow())?;
-                        arguments.push(arg);
-                    }
+                    let arguments = self.parse_call_arguments(lexer, ctx.reborrow())?;
                     crate::Expression::Call {
                         origin: crate::FunctionOrigin::External(name.to_owned()),
                         arguments,
                     }
+                } else if let Some(function) = find_function(ctx.functions, word) {
+                    let arguments = self.parse_call_arguments(lexer, ctx.reborrow())?;
+                    crate::Expression::Call {
+                        origin: crate::FunctionOrigin::Local(function),
+                        arguments,
+                    }
                 } else {
                     *lexer = backup;
-                    let ty = self.parse_type_decl(lexer, ctx.types)?;
+                    let ty = self.parse_type_decl(lexer, ctx.types, ctx.constants)?;
                     lexer.expect(Token::Paren('('))?;
                     let mut components = Vec::new();
                     while !lexer.skip(Token::Paren(')')) {
@@ -914,10 +1066,11 @@ impl Parser {
         &mut self,
         lexer: &mut Lexer<'a>,
         type_arena: &mut Arena<crate::Type>,
+        const_arena: &mut Arena<crate::Constant>,
     ) -> Result<(&'a str, Handle<crate::Type>), Error<'a>> {
         let name = lexer.next_ident()?;
         lexer.expect(Token::Separator(':'))?;
-        let ty = self.parse_type_decl(lexer, type_arena)?;
+        let ty = self.parse_type_decl(lexer, type_arena, const_arena)?;
         Ok((name, ty))
     }
 
@@ -926,7 +1079,7 @@ impl Parser {
         lexer: &mut Lexer<'a>,
         type_arena: &mut Arena<crate::Type>,
         const_arena: &mut Arena<crate::Constant>,
-    ) -> Result<(&'a str, Option<crate::StorageClass>, Handle<crate::Type>), Error<'a>> {
+    ) -> Result<ParsedVariable<'a>, Error<'a>> {
         self.scopes.push(Scope::VariableDecl);
         let mut class = None;
         if lexer.skip(Token::Paren('<')) {
@@ -936,25 +1089,45 @@ impl Parser {
         }
         let name = lexer.next_ident()?;
         lexer.expect(Token::Separator(':'))?;
-        let ty = self.parse_type_decl(lexer, type_arena)?;
-        if lexer.skip(Token::Operation('=')) {
-            let _inner = self.parse_const_expression(lexer, type_arena, const_arena)?;
-            //TODO
-        }
+        let ty = self.parse_type_decl(lexer, type_arena, const_arena)?;
+        let init = if lexer.skip(Token::Operation('=')) {
+            let value = self.parse_const_expression(lexer, type_arena, const_arena)?;
+            let inner = const_arena[value].inner.clone();
+            crate::proc::check_constant_types(&inner, &type_arena[ty].inner)?;
+            Some(const_arena.fetch_or_append(crate::Constant {
+                name: None,
+                specialization: None,
+                inner,
+                ty,
+            }))
+        } else {
+            None
+        };
         lexer.expect(Token::Separator(';'))?;
         self.scopes.pop();
-        Ok((name, class, ty))
+        Ok(ParsedVariable {
+            name,
+            class,
+            ty,
+            init,
+        })
     }
 
+    /// Parse the members of a structure, flagging the ones without an offset.
+    ///
+    /// The offsets of those are only known once the module is parsed, since they
+    /// depend on the storage class the structure is used in.
     fn parse_struct_body<'a>(
         &mut self,
         lexer: &mut Lexer<'a>,
         type_arena: &mut Arena<crate::Type>,
-    ) -> Result<Vec<crate::StructMember>, Error<'a>> {
+        const_arena: &mut Arena<crate::Constant>,
+    ) -> Result<(Vec<crate::StructMember>, Vec<bool>), Error<'a>> {
         let mut members = Vec::new();
+        let mut implicit = Vec::new();
         lexer.expect(Token::Paren('{'))?;
         loop {
-            let mut offset = !0;
+            let mut offset = None;
             if lexer.skip(Token::DoubleParen('[')) {
                 self.scopes.push(Scope::Decoration);
                 let mut ready = true;
@@ -967,7 +1140,7 @@ impl Parser {
                             ready = true;
                         }
                         Token::Word("offset") if ready => {
-                            offset = lexer.next_uint_literal()?;
+                            offset = Some(lexer.next_uint_literal()?);
                             ready = false;
                         }
                         other => return Err(Error::Unexpected(other)),
@@ -977,27 +1150,109 @@ impl Parser {
             }
             let name = match lexer.next() {
                 Token::Word(word) => word,
-                Token::Paren('}') => return Ok(members),
+                Token::Paren('}') => return Ok((members, implicit)),
                 other => return Err(Error::Unexpected(other)),
             };
-            if offset == !0 {
-                return Err(Error::MissingMemberOffset(name));
-            }
             lexer.expect(Token::Separator(':'))?;
-            let ty = self.parse_type_decl(lexer, type_arena)?;
+            let ty = self.parse_type_decl(lexer, type_arena, const_arena)?;
             lexer.expect(Token::Separator(';'))?;
+            implicit.push(offset.is_none());
             members.push(crate::StructMember {
                 name: Some(name.to_owned()),
-                origin: crate::MemberOrigin::Offset(offset),
+                origin: crate::MemberOrigin::Offset(offset.unwrap_or(0)),
                 ty,
             });
         }
     }
 
+    /// Fill the implicit member offsets and array strides.
+    ///
+    /// Types used in storage buffers are laid out with the std430 rules, and
+    /// the other structures with std140. Strides are only filled for arrays
+    /// used in buffers.
+    fn apply_layouts(&self, module: &mut crate::Module) {
+        let handles = module
+            .types
+            .iter()
+            .map(|(handle, _)| handle)
+            .collect::<Vec<_>>();
+        let mut rules = vec![None; module.types.len()];
+        for (_, var) in module.global_variables.iter() {
+            match var.class {
+                crate::StorageClass::StorageBuffer => {
+                    rules[var.ty.index()].get_or_insert(LayoutRules::Std430);
+                }
+                crate::StorageClass::Uniform => rules[var.ty.index()] = Some(LayoutRules::Std140),
+                _ => {}
+            }
+        }
+        // types only refer to the types before them
+        for &handle in handles.iter().rev() {
+            let rule = match rules[handle.index()] {
+                Some(rule) => rule,
+                None => continue,
+            };
+            let mut inherit = |base: Handle<crate::Type>| match rules[base.index()] {
+                Some(LayoutRules::Std140) => {}
+                _ => rules[base.index()] = Some(rule),
+            };
+            match module.types[handle].inner {
+                crate::TypeInner::Array { base, .. } => inherit(base),
+                crate::TypeInner::Struct { ref members } => {
+                    for member in members {
+                        inherit(member.ty);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        for handle in handles {
+            let rule = rules[handle.index()].unwrap_or(LayoutRules::Std140);
+            let mut layouter = Layouter::new(rule);
+            match module.types[handle].inner {
+                crate::TypeInner::Array { stride: None, .. } if rules[handle.index()].is_some() => {
+                    layouter.update(&module.types);
+                    if let crate::TypeInner::Array {
+                        base,
+                        ref mut stride,
+                        ..
+                    } = module.types.get_mut(handle).inner
+                    {
+                        *stride = std::num::NonZeroU32::new(layouter.array_stride(base));
+                    }
+                }
+                crate::TypeInner::Struct { .. } => {
+                    let implicit = match self.implicit_offsets.get(&handle) {
+                        Some(implicit) => implicit,
+                        None => continue,
+                    };
+                    layouter.update(&module.types);
+                    if let crate::TypeInner::Struct { ref mut members } =
+                        module.types.get_mut(handle).inner
+                    {
+                        let mut end = 0;
+                        for (member, &implicit) in members.iter_mut().zip(implicit) {
+                            let layout = layouter[member.ty];
+                            if let crate::MemberOrigin::Offset(ref mut offset) = member.origin {
+                                if implicit {
+                                    *offset = layout.align_offset(end);
+                                }
+                                end = end.max(*offset + layout.size);
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
     fn parse_type_decl<'a>(
         &mut self,
         lexer: &mut Lexer<'a>,
         type_arena: &mut Arena<crate::Type>,
+        const_arena: &mut Arena<crate::Constant>,
     ) -> Result<Handle<crate::Type>, Error<'a>> {
         self.scopes.push(Scope::TypeDecl);
         let inner = match lexer.next() {
@@ -1122,18 +1377,30 @@ impl Parser {
                 lexer.expect(Token::Paren('<'))?;
                 let class = Self::get_storage_class(lexer.next_ident()?)?;
                 lexer.expect(Token::Separator(','))?;
-                let base = self.parse_type_decl(lexer, type_arena)?;
+                let base = self.parse_type_decl(lexer, type_arena, const_arena)?;
                 lexer.expect(Token::Paren('>'))?;
                 crate::TypeInner::Pointer { base, class }
             }
             Token::Word("array") => {
                 lexer.expect(Token::Paren('<'))?;
-                let base = self.parse_type_decl(lexer, type_arena)?;
+                let base = self.parse_type_decl(lexer, type_arena, const_arena)?;
                 let size = match lexer.next() {
                     Token::Separator(',') => {
-                        let value = lexer.next_uint_literal()?;
+                        let value = self.parse_const_expression(lexer, type_arena, const_arena)?;
                         lexer.expect(Token::Paren('>'))?;
-                        crate::ArraySize::Static(value)
+                        let constant = &const_arena[value];
+                        let size = match constant.inner {
+                            _ if constant.specialization.is_some() => None,
+                            crate::ConstantInner::Uint(size) => Some(size),
+                            crate::ConstantInner::Sint(size) if size >= 0 => Some(size as u64),
+                            _ => None,
+                        };
+                        match size {
+                            Some(size) if size > 0 && size <= u32::MAX as u64 => {
+                                crate::ArraySize::Static(size as u32)
+                            }
+                            _ => return Err(Error::BadArraySize(value)),
+                        }
                     }
                     Token::Separator('>') => crate::ArraySize::Dynamic,
                     other => return Err(Error::Unexpected(other)),
@@ -1145,17 +1412,51 @@ impl Parser {
                 }
             }
             Token::Word("struct") => {
-                let members = self.parse_struct_body(lexer, type_arena)?;
-                crate::TypeInner::Struct { members }
-            }
-            Token::Word(name) => {
+                let (members, implicit) = self.parse_struct_body(lexer, type_arena, const_arena)?;
                 self.scopes.pop();
-                return self
-                    .lookup_type
-                    .get(name)
-                    .cloned()
-                    .ok_or(Error::UnknownType(name));
+                let handle =
+                    Typifier::deduce_type_handle(crate::TypeInner::Struct { members }, type_arena);
+                match self.implicit_offsets.get_mut(&handle) {
+                    Some(flags) => {
+                        for (flag, implicit) in flags.iter_mut().zip(implicit) {
+                            *flag |= implicit;
+                        }
+                    }
+                    None if implicit.contains(&true) => {
+                        self.implicit_offsets.insert(handle, implicit);
+                    }
+                    None => {}
+                }
+                return Ok(handle);
             }
+            Token::Word(name) => match Self::get_storage_texture(name) {
+                Some((dim, flags)) => {
+                    lexer.expect(Token::Paren('<'))?;
+                    let format = Self::get_storage_format(lexer.next_ident()?)?;
+                    lexer.expect(Token::Paren('>'))?;
+                    let base = Typifier::deduce_type_handle(
+                        crate::TypeInner::Scalar {
+                            kind: format.scalar_kind(),
+                            width: 4,
+                        },
+                        type_arena,
+                    );
+                    crate::TypeInner::Image {
+                        base,
+                        dim,
+                        flags,
+                        format: Some(format),
+                    }
+                }
+                None => {
+                    self.scopes.pop();
+                    return self
+                        .lookup_type
+                        .get(name)
+                        .cloned()
+                        .ok_or(Error::UnknownType(name));
+                }
+            },
             other => return Err(Error::Unexpected(other)),
         };
         self.scopes.pop();
@@ -1179,7 +1480,11 @@ impl Parser {
                             Uniform(Handle<crate::Expression>),
                             Variable(Handle<crate::Expression>),
                         }
-                        let (name, ty) = self.parse_variable_ident_decl(lexer, context.types)?;
+                        let (name, ty) = self.parse_variable_ident_decl(
+                            lexer,
+                            context.types,
+                            context.constants,
+                        )?;
                         let init = if lexer.skip(Token::Operation('=')) {
                             let value =
                                 self.parse_general_expression(lexer, context.as_expression())?;
@@ -1257,6 +1562,16 @@ impl Parser {
                     }
                     "break" => crate::Statement::Break,
                     "continue" => crate::Statement::Continue,
+                    ident if find_function(context.functions, ident).is_some() => {
+                        let function = find_function(context.functions, ident).unwrap();
+                        let arguments =
+                            self.parse_call_arguments(lexer, context.as_expression())?;
+                        lexer.expect(Token::Separator(';'))?;
+                        crate::Statement::Call {
+                            function,
+                            arguments,
+                        }
+                    }
                     ident => {
                         // assignment
                         let var_expr = context.lookup_ident.lookup(ident)?;
@@ -1310,26 +1625,30 @@ impl Parser {
             lookup_ident.insert(name, expr_handle);
         }
         // read parameter list
-        let mut parameter_types = Vec::new();
+        let mut arguments = Vec::new();
         lexer.expect(Token::Paren('('))?;
         while !lexer.skip(Token::Paren(')')) {
-            if !parameter_types.is_empty() {
+            if !arguments.is_empty() {
                 lexer.expect(Token::Separator(','))?;
             }
             let (param_name, param_type) =
-                self.parse_variable_ident_decl(lexer, &mut module.types)?;
-            let param_index = parameter_types.len() as u32;
+                self.parse_variable_ident_decl(lexer, &mut module.types, &mut module.constants)?;
+            let param_index = arguments.len() as u32;
             let expression_token =
                 expressions.append(crate::Expression::FunctionParameter(param_index));
             lookup_ident.insert(param_name, expression_token);
-            parameter_types.push(param_type);
+            arguments.push(crate::FunctionArgument {
+                name: Some(param_name.to_owned()),
+                ty: param_type,
+                binding: None,
+            });
         }
         // read return type
         lexer.expect(Token::Arrow)?;
         let return_type = if lexer.skip(Token::Word("void")) {
             None
         } else {
-            Some(self.parse_type_decl(lexer, &mut module.types)?)
+            Some(self.parse_type_decl(lexer, &mut module.types, &mut module.constants)?)
         };
         // read body
         let mut local_variables = Arena::new();
@@ -1344,6 +1663,8 @@ impl Parser {
                 types: &mut module.types,
                 constants: &mut module.constants,
                 global_vars: &module.global_variables,
+                arguments: &arguments,
+                functions: &module.functions,
             },
         )?;
         // done
@@ -1352,7 +1673,7 @@ impl Parser {
 
         let fun = crate::Function {
             name: Some(fun_name.to_owned()),
-            parameter_types,
+            arguments,
             return_type,
             global_usage,
             local_variables,
@@ -1370,6 +1691,7 @@ impl Parser {
     ) -> Result<bool, Error<'a>> {
         // read decorations
         let mut binding = None;
+        let mut workgroup_size = None;
         if lexer.skip(Token::DoubleParen('[')) {
             let (mut bind_index, mut bind_set) = (None, None);
             self.scopes.push(Scope::Decoration);
@@ -1389,6 +1711,17 @@ impl Parser {
                     "set" => {
                         bind_set = Some(lexer.next_uint_literal()?);
                     }
+                    "workgroup_size" => {
+                        let mut size = [1; 3];
+                        for (i, value) in size.iter_mut().enumerate() {
+                            match lexer.peek() {
+                                Token::Number(_) => *value = lexer.next_uint_literal()?,
+                                _ if i > 0 => break,
+                                other => return Err(Error::Unexpected(other)),
+                            }
+                        }
+                        workgroup_size = Some(size);
+                    }
                     other => return Err(Error::UnknownDecoration(other)),
                 }
                 match lexer.next() {
@@ -1406,7 +1739,7 @@ impl Parser {
                         binding: index,
                     });
                 }
-                _ if binding.is_none() => return Err(Error::Other),
+                _ if binding.is_none() && workgroup_size.is_none() => return Err(Error::Other),
                 _ => {}
             }
             self.scopes.pop();
@@ -1432,28 +1765,46 @@ impl Parser {
             Token::Word("type") => {
                 let name = lexer.next_ident()?;
                 lexer.expect(Token::Operation('='))?;
-                let ty = self.parse_type_decl(lexer, &mut module.types)?;
+                let ty = self.parse_type_decl(lexer, &mut module.types, &mut module.constants)?;
                 self.lookup_type.insert(name.to_owned(), ty);
                 lexer.expect(Token::Separator(';'))?;
             }
             Token::Word("const") => {
-                let (name, ty) = self.parse_variable_ident_decl(lexer, &mut module.types)?;
+                let (name, ty) = self.parse_variable_ident_decl(
+                    lexer,
+                    &mut module.types,
+                    &mut module.constants,
+                )?;
                 lexer.expect(Token::Operation('='))?;
-                let inner =
+                let first_new = module.constants.len();
+                let value =
                     self.parse_const_expression(lexer, &mut module.types, &mut module.constants)?;
                 lexer.expect(Token::Separator(';'))?;
+                let inner = module.constants[value].inner.clone();
                 crate::proc::check_constant_types(&inner, &module.types[ty].inner)?;
-                let const_handle = module.constants.append(crate::Constant {
-                    name: Some(name.to_owned()),
-                    specialization: None,
-                    inner,
-                    ty,
-                });
+                // name the value in place, unless it's shared with earlier declarations
+                let const_handle = if value.index() >= first_new {
+                    let constant = module.constants.get_mut(value);
+                    constant.name = Some(name.to_owned());
+                    constant.ty = ty;
+                    value
+                } else {
+                    module.constants.append(crate::Constant {
+                        name: Some(name.to_owned()),
+                        specialization: None,
+                        inner,
+                        ty,
+                    })
+                };
                 lookup_global_expression.insert(name, crate::Expression::Constant(const_handle));
             }
             Token::Word("var") => {
-                let (name, class, ty) =
-                    self.parse_variable_decl(lexer, &mut module.types, &mut module.constants)?;
+                let ParsedVariable {
+                    name,
+                    class,
+                    ty,
+                    init,
+                } = self.parse_variable_decl(lexer, &mut module.types, &mut module.constants)?;
                 let var_handle = module.global_variables.append(crate::GlobalVariable {
                     name: Some(name.to_owned()),
                     class: match class {
@@ -1469,6 +1820,7 @@ impl Parser {
                     },
                     binding: binding.take(),
                     ty,
+                    init,
                 });
                 lookup_global_expression
                     .insert(name, crate::Expression::GlobalVariable(var_handle));
@@ -1498,15 +1850,19 @@ impl Parser {
                     stage,
                     name: export_name.unwrap_or(fun_ident).to_owned(),
                     function: fun_handle,
+                    modes: crate::ExecutionModes {
+                        workgroup_size: workgroup_size.take(),
+                        ..crate::ExecutionModes::default()
+                    },
                 });
             }
             Token::End => return Ok(false),
             token => return Err(Error::Unexpected(token)),
         }
-        match binding {
-            None => Ok(true),
-            // we had the decoration but no var?
-            Some(_) => Err(Error::Other),
+        match (binding, workgroup_size) {
+            (None, None) => Ok(true),
+            // we had the decoration but no var or entry point?
+            _ => Err(Error::Other),
         }
     }
 
@@ -1514,6 +1870,7 @@ impl Parser {
         self.scopes.clear();
         self.lookup_type.clear();
         self.std_namespace = None;
+        self.implicit_offsets.clear();
 
         let mut module = crate::Module::generate_empty();
         let mut lexer = Lexer::new(source);
@@ -1536,6 +1893,10 @@ impl Parser {
                 Ok(true) => {}
                 Ok(false) => {
                     assert_eq!(self.scopes, Vec::new());
+                    self.apply_layouts(&mut module);
+                    // entry points report the globals used by their callees too
+                    crate::proc::CallGraph::new(&module.functions)
+                        .propagate_global_usage(&mut module.functions);
                     return Ok(module);
                 }
             }
@@ -1563,6 +1924,171 @@ mod tests {
         assert!(super::parse_str(wgsl).is_err());
     }
 
+    #[test]
+    fn implicit_member_offsets() {
+        let wgsl =
+            "type Foo = struct { a : f32; b : vec3<f32>; [[offset 32]] c : f32; d : vec2<f32>; };";
+        let module = super::parse_str(wgsl).unwrap();
+        let (_, ty) = module
+            .types
+            .iter()
+            .find(|(_, ty)| matches!(ty.inner, crate::TypeInner::Struct { .. }))
+            .unwrap();
+        match ty.inner {
+            crate::TypeInner::Struct { ref members } => {
+                let offsets = members
+                    .iter()
+                    .map(|member| member.origin.clone())
+                    .collect::<Vec<_>>();
+                assert_eq!(
+                    offsets,
+                    [0, 16, 32, 40]
+                        .iter()
+                        .map(|&offset| crate::MemberOrigin::Offset(offset))
+                        .collect::<Vec<_>>()
+                );
+            }
+            ref other => panic!("Unexpected type {:?}", other),
+        }
+    }
+
+    #[test]
+    fn buffer_layouts() {
+        let wgsl = "
+            type Inner = struct { a : f32; b : array<f32, 2>; };
+            type Outer = struct { c : array<Inner, 2>; };
+            [[binding 0, set 0]] var<storage_buffer> storage : Outer;
+            type Uniforms = struct { d : f32; e : array<vec2<f32>, 2>; };
+            [[binding 1, set 0]] var<uniform> uniforms : Uniforms;
+        ";
+        let module = super::parse_str(wgsl).unwrap();
+        let mut layouts = Vec::new();
+        for (_, ty) in module.types.iter() {
+            if let crate::TypeInner::Struct { ref members } = ty.inner {
+                for member in members {
+                    let stride = match module.types[member.ty].inner {
+                        crate::TypeInner::Array { stride, .. } => stride.map(|s| s.get()),
+                        _ => None,
+                    };
+                    layouts.push((
+                        member.name.as_deref().unwrap(),
+                        member.origin.clone(),
+                        stride,
+                    ));
+                }
+            }
+        }
+        let offset = crate::MemberOrigin::Offset;
+        assert_eq!(
+            layouts,
+            [
+                ("a", offset(0), None),
+                ("b", offset(4), Some(4)),
+                ("c", offset(0), Some(12)),
+                ("d", offset(0), None),
+                ("e", offset(16), Some(16)),
+            ]
+        );
+    }
+
+    #[test]
+    fn check_constant_type_composite() {
+        let wgsl = "const a : vec2<f32> = vec2<f32>(1.0, f32(2));";
+        let module = super::parse_str(wgsl).unwrap();
+        let (_, constant) = module
+            .constants
+            .iter()
+            .find(|(_, constant)| constant.name.is_some())
+            .unwrap();
+        match constant.inner {
+            crate::ConstantInner::Composite(ref components) => {
+                let values = components
+                    .iter()
+                    .map(|&handle| module.constants[handle].inner.clone())
+                    .collect::<Vec<_>>();
+                assert_eq!(
+                    values,
+                    vec![
+                        crate::ConstantInner::Float(1.0),
+                        crate::ConstantInner::Float(2.0)
+                    ]
+                );
+            }
+            ref other => panic!("Unexpected constant {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_array_size_expression() {
+        let wgsl = "
+            const count : i32 = 4;
+            type Named = array<f32, count>;
+            type Converted = array<f32, u32(2)>;
+        ";
+        let module = super::parse_str(wgsl).unwrap();
+        let sizes = module
+            .types
+            .iter()
+            .filter_map(|(_, ty)| match ty.inner {
+                crate::TypeInner::Array {
+                    size: crate::ArraySize::Static(size),
+                    ..
+                } => Some(size),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(sizes, [4, 2]);
+        assert_eq!(module.constants.len(), 3);
+
+        match super::parse_str("type Empty = array<f32, 0>;") {
+            Err(super::ParseError {
+                error: super::Error::BadArraySize(_),
+                ..
+            }) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_local_calls() {
+        let wgsl = "
+            var<private> value : f32;
+            var<private> result : f32;
+            fn get() -> f32 {
+                return value;
+            }
+            fn set() -> void {
+                result = 1.0;
+            }
+            fn main() -> void {
+                var copy : f32 = get();
+                set();
+                return;
+            }
+            entry_point fragment as \"main\" = main;
+        ";
+        let module = super::parse_str(wgsl).unwrap();
+        crate::proc::Validator::new().validate(&module).unwrap();
+        let main = &module.functions[module.entry_points[0].function];
+        assert!(main.global_usage[0].contains(crate::GlobalUse::LOAD));
+        assert!(main.global_usage[1].contains(crate::GlobalUse::STORE));
+        assert!(matches!(main.body[1], crate::Statement::Call { .. }));
+    }
+
+    #[test]
+    fn parse_storage_texture() {
+        let wgsl = "[[set 0, binding 1]] var<uniform> image : texture_wo_2d<rgba16float>;";
+        let module = super::parse_str(wgsl).unwrap();
+        let (_, var) = module.global_variables.iter().next().unwrap();
+        match module.types[var.ty].inner {
+            crate::TypeInner::Image { flags, format, .. } => {
+                assert_eq!(flags, crate::ImageFlags::CAN_STORE);
+                assert_eq!(format, Some(crate::StorageFormat::Rgba16Float));
+            }
+            ref other => panic!("Unexpected type {:?}", other),
+        }
+    }
+
     #[test]
     fn check_lexer() {
         use Token::{End, Number, String, Unknown, Word};
@@ -9,10 +9,11 @@
 
 mod arena;
 pub mod back;
+pub mod builder;
 pub mod front;
 pub mod proc;
 
-pub use crate::arena::{Arena, Handle};
+pub use crate::arena::{Arena, BadHandle, Handle, HandleMap};
 
 use std::{
     collections::{HashMap, HashSet},
@@ -52,15 +53,79 @@ pub struct Header {
 #[allow(missing_docs)] // The names are self evident
 pub enum ShaderStage {
     Vertex,
+    TessellationControl,
+    TessellationEvaluation,
+    Geometry,
     Fragment,
     Compute,
 }
 
-/// Class of storage for variables.
+/// Kind of primitives consumed or produced by a shader stage.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+#[cfg_attr(feature = "deserialize", derive(Deserialize))]
+#[allow(missing_docs)] // The names are self evident
+pub enum PrimitiveTopology {
+    Points,
+    Lines,
+    LinesAdjacency,
+    LineStrip,
+    Triangles,
+    TrianglesAdjacency,
+    TriangleStrip,
+    Quads,
+    Isolines,
+}
+
+/// Spacing of the segments produced by the tessellator.
 #[derive(Clone, Copy, Debug, PartialEq)]
 #[cfg_attr(feature = "serialize", derive(Serialize))]
 #[cfg_attr(feature = "deserialize", derive(Deserialize))]
 #[allow(missing_docs)] // The names are self evident
+pub enum TessellationSpacing {
+    Equal,
+    FractionalEven,
+    FractionalOdd,
+}
+
+/// Winding order of the triangles produced by the tessellator.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+#[cfg_attr(feature = "deserialize", derive(Deserialize))]
+pub enum VertexOrder {
+    /// Clockwise.
+    Cw,
+    /// Counter-clockwise.
+    Ccw,
+}
+
+/// Execution parameters of the geometry, tessellation and compute stages.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+#[cfg_attr(feature = "deserialize", derive(Deserialize))]
+pub struct ExecutionModes {
+    /// Primitives consumed by a geometry shader or generated by the tessellator.
+    pub input_topology: Option<PrimitiveTopology>,
+    /// Primitives produced by a geometry shader.
+    pub output_topology: Option<PrimitiveTopology>,
+    /// Maximum number of vertices emitted by a geometry shader,
+    /// or the number of vertices in an output patch.
+    pub output_vertices: Option<u32>,
+    /// Number of geometry shader invocations per primitive.
+    pub invocations: Option<u32>,
+    /// Spacing of the tessellated segments.
+    pub spacing: Option<TessellationSpacing>,
+    /// Winding order of the tessellated triangles.
+    pub vertex_order: Option<VertexOrder>,
+    /// Number of invocations in a compute workgroup, along each dimension.
+    pub workgroup_size: Option<[u32; 3]>,
+}
+
+/// Class of storage for variables.
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+#[cfg_attr(feature = "deserialize", derive(Deserialize))]
+#[allow(missing_docs)] // The names are self evident
 pub enum StorageClass {
     Constant,
     Function,
@@ -73,7 +138,7 @@ pub enum StorageClass {
 }
 
 /// Built-in inputs and outputs.
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
 #[cfg_attr(feature = "serialize", derive(Serialize))]
 #[cfg_attr(feature = "deserialize", derive(Deserialize))]
 pub enum BuiltIn {
@@ -84,6 +149,13 @@ pub enum BuiltIn {
     InstanceIndex,
     Position,
     VertexIndex,
+    // tessellation
+    InvocationId,
+    TessCoord,
+    TessLevelOuter,
+    TessLevelInner,
+    // geometry
+    PrimitiveId,
     // fragment
     PointSize,
     FragCoord,
@@ -102,7 +174,7 @@ pub type Bytes = u8;
 
 /// Number of components in a vector.
 #[repr(u8)]
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
 #[cfg_attr(feature = "serialize", derive(Serialize))]
 #[cfg_attr(feature = "deserialize", derive(Deserialize))]
 pub enum VectorSize {
@@ -116,7 +188,7 @@ pub enum VectorSize {
 
 /// Primitive type for a scalar.
 #[repr(u8)]
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
 #[cfg_attr(feature = "serialize", derive(Serialize))]
 #[cfg_attr(feature = "deserialize", derive(Deserialize))]
 pub enum ScalarKind {
@@ -132,7 +204,7 @@ pub enum ScalarKind {
 
 /// Size of an array.
 #[repr(u8)]
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
 #[cfg_attr(feature = "serialize", derive(Serialize))]
 #[cfg_attr(feature = "deserialize", derive(Deserialize))]
 pub enum ArraySize {
@@ -143,7 +215,7 @@ pub enum ArraySize {
 }
 
 /// Describes where a struct member is placed.
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
 #[cfg_attr(feature = "serialize", derive(Serialize))]
 #[cfg_attr(feature = "deserialize", derive(Deserialize))]
 pub enum MemberOrigin {
@@ -155,7 +227,7 @@ pub enum MemberOrigin {
 
 /// Member of a user-defined structure.
 // Clone is used only for error reporting and is not intended for end users
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
 #[cfg_attr(feature = "serialize", derive(Serialize))]
 #[cfg_attr(feature = "deserialize", derive(Deserialize))]
 pub struct StructMember {
@@ -165,7 +237,7 @@ pub struct StructMember {
 }
 
 /// The number of dimensions an image has.
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
 #[cfg_attr(feature = "serialize", derive(Serialize))]
 #[cfg_attr(feature = "deserialize", derive(Deserialize))]
 pub enum ImageDimension {
@@ -197,8 +269,59 @@ bitflags::bitflags! {
     }
 }
 
+/// Texel format of a storage image.
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+#[cfg_attr(feature = "deserialize", derive(Deserialize))]
+#[allow(missing_docs)] // The names are self evident
+pub enum StorageFormat {
+    // 8-bit formats
+    R8Unorm,
+    R8Snorm,
+    R8Uint,
+    R8Sint,
+
+    // 16-bit formats
+    R16Uint,
+    R16Sint,
+    R16Float,
+    Rg8Unorm,
+    Rg8Snorm,
+    Rg8Uint,
+    Rg8Sint,
+
+    // 32-bit formats
+    R32Uint,
+    R32Sint,
+    R32Float,
+    Rg16Uint,
+    Rg16Sint,
+    Rg16Float,
+    Rgba8Unorm,
+    Rgba8Snorm,
+    Rgba8Uint,
+    Rgba8Sint,
+
+    // packed 32-bit formats
+    Rgb10a2Unorm,
+    Rg11b10Float,
+
+    // 64-bit formats
+    Rg32Uint,
+    Rg32Sint,
+    Rg32Float,
+    Rgba16Uint,
+    Rgba16Sint,
+    Rgba16Float,
+
+    // 128-bit formats
+    Rgba32Uint,
+    Rgba32Sint,
+    Rgba32Float,
+}
+
 /// A data type declared in the module.
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
 #[cfg_attr(feature = "serialize", derive(Serialize))]
 #[cfg_attr(feature = "deserialize", derive(Deserialize))]
 pub struct Type {
@@ -210,7 +333,7 @@ pub struct Type {
 
 /// Enum with additional information, depending on the kind of type.
 // Clone is used only for error reporting and is not intended for end users
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
 #[cfg_attr(feature = "serialize", derive(Serialize))]
 #[cfg_attr(feature = "deserialize", derive(Deserialize))]
 pub enum TypeInner {
@@ -247,6 +370,8 @@ pub enum TypeInner {
         base: Handle<Type>,
         dim: ImageDimension,
         flags: ImageFlags,
+        /// Texel format of a storage image, if known.
+        format: Option<StorageFormat>,
     },
     /// Depth-comparison image.
     DepthImage { dim: ImageDimension, arrayed: bool },
@@ -255,7 +380,7 @@ pub enum TypeInner {
 }
 
 /// Constant value.
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, Hash, PartialEq)]
 #[cfg_attr(feature = "serialize", derive(Serialize))]
 #[cfg_attr(feature = "deserialize", derive(Deserialize))]
 pub struct Constant {
@@ -278,8 +403,25 @@ pub enum ConstantInner {
     Composite(Vec<Handle<Constant>>),
 }
 
+impl std::hash::Hash for ConstantInner {
+    fn hash<H: std::hash::Hasher>(&self, hasher: &mut H) {
+        std::mem::discriminant(self).hash(hasher);
+        match *self {
+            ConstantInner::Sint(value) => value.hash(hasher),
+            ConstantInner::Uint(value) => value.hash(hasher),
+            ConstantInner::Float(value) => {
+                // positive and negative zeros are equal, so they need the same hash
+                let value = if value == 0.0 { 0.0 } else { value };
+                value.to_bits().hash(hasher)
+            }
+            ConstantInner::Bool(value) => value.hash(hasher),
+            ConstantInner::Composite(ref components) => components.hash(hasher),
+        }
+    }
+}
+
 /// Describes how an input/output variable is to be bound.
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
 #[cfg_attr(feature = "serialize", derive(Serialize))]
 #[cfg_attr(feature = "deserialize", derive(Deserialize))]
 pub enum Binding {
@@ -304,7 +446,7 @@ bitflags::bitflags! {
 }
 
 /// Variable defined at module level.
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, Hash, PartialEq)]
 #[cfg_attr(feature = "serialize", derive(Serialize))]
 #[cfg_attr(feature = "deserialize", derive(Deserialize))]
 pub struct GlobalVariable {
@@ -316,6 +458,8 @@ pub struct GlobalVariable {
     pub binding: Option<Binding>,
     /// The type of this variable.
     pub ty: Handle<Type>,
+    /// Initial value for this variable.
+    pub init: Option<Handle<Constant>>,
 }
 
 /// Variable defined at function level.
@@ -420,7 +564,7 @@ pub enum Expression {
         ty: Handle<Type>,
         components: Vec<Handle<Expression>>,
     },
-    /// Reference a function parameter, by its index.
+    /// Reference a function argument, by its index.
     FunctionParameter(u32),
     /// Reference a global variable.
     GlobalVariable(Handle<GlobalVariable>),
@@ -516,18 +660,49 @@ pub enum Statement {
         pointer: Handle<Expression>,
         value: Handle<Expression>,
     },
+    /// Calls a function, discarding its result if it has one.
+    ///
+    /// Functions returning nothing can only be called this way.
+    Call {
+        function: Handle<Function>,
+        arguments: Vec<Handle<Expression>>,
+    },
+    /// Emits the current values of the output variables as a vertex.
+    ///
+    /// Only valid in geometry shaders.
+    EmitVertex,
+    /// Finishes the current output primitive.
+    ///
+    /// Only valid in geometry shaders.
+    EndPrimitive,
+}
+
+/// A function argument.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+#[cfg_attr(feature = "deserialize", derive(Deserialize))]
+pub struct FunctionArgument {
+    /// Name of the argument, if any.
+    pub name: Option<String>,
+    /// Type of the argument.
+    ///
+    /// Arguments of [`TypeInner::Pointer`] type are passed by reference,
+    /// allowing the function to store through them.
+    pub ty: Handle<Type>,
+    /// For entry points, the binding the argument is bound to.
+    pub binding: Option<Binding>,
 }
 
 /// A function defined in the module.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 #[cfg_attr(feature = "serialize", derive(Serialize))]
 #[cfg_attr(feature = "deserialize", derive(Deserialize))]
 pub struct Function {
     /// Name of the function, if any.
     pub name: Option<String>,
     //pub control: spirv::FunctionControl,
-    /// The types of the parameters of this function.
-    pub parameter_types: Vec<Handle<Type>>,
+    /// Information about function arguments.
+    pub arguments: Vec<FunctionArgument>,
     /// The return type of this function, if any.
     pub return_type: Option<Handle<Type>>,
     /// Vector of global variable usages.
@@ -543,7 +718,7 @@ pub struct Function {
 }
 
 /// Exported function, to be run at a certain stage in the pipeline.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 #[cfg_attr(feature = "serialize", derive(Serialize))]
 #[cfg_attr(feature = "deserialize", derive(Deserialize))]
 pub struct EntryPoint {
@@ -553,6 +728,8 @@ pub struct EntryPoint {
     pub name: String,
     /// The function to be used.
     pub function: Handle<Function>,
+    /// Stage-specific execution parameters.
+    pub modes: ExecutionModes,
 }
 
 /// Shader module.
@@ -566,7 +743,7 @@ pub struct EntryPoint {
 /// Alternatively, you can load an existing shader using one of the [available front ends][front].
 ///
 /// When finished, you can export modules using one of the [available back ends][back].
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 #[cfg_attr(feature = "serialize", derive(Serialize))]
 #[cfg_attr(feature = "deserialize", derive(Deserialize))]
 pub struct Module {
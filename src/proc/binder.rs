@@ -0,0 +1,268 @@
+use crate::{Binding, FastHashMap, FastHashSet, StorageClass};
+
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+#[derive(Clone, Debug, Error)]
+pub enum BindingError {
+    #[error("Binding {0:?} is used by more than one global variable")]
+    Conflict(Binding),
+}
+
+/// Rules for rewriting the bindings of the global variables.
+#[derive(Clone, Debug, Default)]
+pub struct BindingOptions {
+    /// New bindings, indexed by the storage class and the original binding.
+    pub map: FastHashMap<(StorageClass, Binding), Binding>,
+    /// Move the descriptors that aren't in `map` into set 0, placing each set
+    /// right after the bindings of the previous one.
+    pub flatten_sets: bool,
+    /// Give a binding to the resources and varyings that don't have one,
+    /// picking the first free slots.
+    pub assign_missing: bool,
+}
+
+/// Returns the key of the slot a binding occupies.
+///
+/// Descriptors are shared by all the storage classes, while locations and
+/// built-ins are separate for inputs and outputs.
+fn slot(binding: &Binding, class: StorageClass) -> (Binding, Option<StorageClass>) {
+    match *binding {
+        Binding::Descriptor { .. } => (binding.clone(), None),
+        _ => (binding.clone(), Some(class)),
+    }
+}
+
+/// Returns true if the variable is a resource bound through a descriptor.
+fn is_resource(var: &crate::GlobalVariable) -> bool {
+    match var.class {
+        StorageClass::Uniform | StorageClass::StorageBuffer => true,
+        StorageClass::Constant => var.init.is_none(),
+        _ => false,
+    }
+}
+
+/// Returns, for every entry point, which global variables it uses.
+///
+/// A module without entry points is treated as a single one using all the
+/// global variables, and so are the functions without a known usage.
+fn entry_point_usage(module: &crate::Module) -> Vec<Vec<bool>> {
+    let count = module.global_variables.len();
+    if module.entry_points.is_empty() {
+        return vec![vec![true; count]];
+    }
+    module
+        .entry_points
+        .iter()
+        .map(|entry_point| {
+            let usage = &module.functions[entry_point.function].global_usage;
+            if usage.is_empty() {
+                return vec![true; count];
+            }
+            // the variables declared after the function are not used by it
+            let mut used = usage
+                .iter()
+                .map(|usage| !usage.is_empty())
+                .collect::<Vec<_>>();
+            used.resize(count, false);
+            used
+        })
+        .collect()
+}
+
+/// Rewrite the bindings of the global variables of a module.
+///
+/// The bindings present in `options.map` are replaced first. The remaining
+/// descriptors are then flattened if requested, and the variables without
+/// a binding get one assigned. Bindings only conflict when they are used by
+/// the same entry point, and nothing is modified if they do.
+pub fn remap_bindings(
+    module: &mut crate::Module,
+    options: &BindingOptions,
+) -> Result<(), BindingError> {
+    let mut set_bases = BTreeMap::new();
+    if options.flatten_sets {
+        for (_, var) in module.global_variables.iter() {
+            if let Some(Binding::Descriptor { set, binding }) = var.binding {
+                if !options
+                    .map
+                    .contains_key(&(var.class, var.binding.clone().unwrap()))
+                {
+                    let count = set_bases.entry(set).or_insert(0);
+                    *count = (binding + 1).max(*count);
+                }
+            }
+        }
+        let mut base = 0;
+        for count in set_bases.values_mut() {
+            let size = *count;
+            *count = base;
+            base += size;
+        }
+    }
+
+    let usage = entry_point_usage(module);
+    let mut occupied = vec![FastHashSet::default(); usage.len()];
+    let mut bindings = Vec::with_capacity(module.global_variables.len());
+    for (index, (_, var)) in module.global_variables.iter().enumerate() {
+        let binding = match var.binding {
+            Some(ref binding) => match options.map.get(&(var.class, binding.clone())) {
+                Some(new_binding) => Some(new_binding.clone()),
+                None => match *binding {
+                    Binding::Descriptor { set, binding } if options.flatten_sets => {
+                        Some(Binding::Descriptor {
+                            set: 0,
+                            binding: set_bases[&set] + binding,
+                        })
+                    }
+                    _ => Some(binding.clone()),
+                },
+            },
+            None => None,
+        };
+        if let Some(ref binding) = binding {
+            for (occupied, usage) in occupied.iter_mut().zip(usage.iter()) {
+                if usage[index] && !occupied.insert(slot(binding, var.class)) {
+                    return Err(BindingError::Conflict(binding.clone()));
+                }
+            }
+        }
+        bindings.push(binding);
+    }
+
+    if options.assign_missing {
+        for (index, (binding, (_, var))) in bindings
+            .iter_mut()
+            .zip(module.global_variables.iter())
+            .enumerate()
+        {
+            if binding.is_some() {
+                continue;
+            }
+            let is_varying = matches!(var.class, StorageClass::Input | StorageClass::Output);
+            if !is_varying && !is_resource(var) {
+                continue;
+            }
+            // the slot has to be free in all the entry points using the variable
+            let mut occupied = occupied
+                .iter_mut()
+                .zip(usage.iter())
+                .filter(|(_, usage)| usage[index])
+                .map(|(occupied, _)| occupied)
+                .collect::<Vec<_>>();
+            let new_binding = (0..)
+                .map(|index| {
+                    if is_varying {
+                        Binding::Location(index)
+                    } else {
+                        Binding::Descriptor {
+                            set: 0,
+                            binding: index,
+                        }
+                    }
+                })
+                .find(|candidate| {
+                    let slot = slot(candidate, var.class);
+                    occupied.iter().all(|occupied| !occupied.contains(&slot))
+                })
+                .unwrap();
+            for occupied in occupied.iter_mut() {
+                occupied.insert(slot(&new_binding, var.class));
+            }
+            *binding = Some(new_binding);
+        }
+    }
+
+    let handles = module
+        .global_variables
+        .iter()
+        .map(|(handle, _)| handle)
+        .collect::<Vec<_>>();
+    for (handle, binding) in handles.into_iter().zip(bindings) {
+        module.global_variables.get_mut(handle).binding = binding;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{remap_bindings, BindingError, BindingOptions};
+    use crate::{Binding, StorageClass};
+
+    const SOURCE: &str = "
+        [[location 0]] var<in> a_pos : vec2<f32>;
+        var<in> a_uv : vec2<f32>;
+        [[location 0]] var<out> o_color : vec4<f32>;
+        [[binding 0, set 0]] var<uniform> u_scale : f32;
+        [[binding 1, set 1]] var<uniform> u_offset : f32;
+        var<uniform> u_color : vec4<f32>;
+        fn main() -> void {
+            o_color = vec4<f32>(a_pos + a_uv, u_scale, u_offset) * u_color;
+            return;
+        }
+        entry_point fragment as \"main\" = main;
+    ";
+
+    fn bindings(module: &crate::Module) -> Vec<Option<Binding>> {
+        module
+            .global_variables
+            .iter()
+            .map(|(_, var)| var.binding.clone())
+            .collect()
+    }
+
+    #[test]
+    fn flatten_and_assign() {
+        let mut module = crate::front::wgsl::parse_str(SOURCE).unwrap();
+        let mut options = BindingOptions {
+            flatten_sets: true,
+            assign_missing: true,
+            ..BindingOptions::default()
+        };
+        options.map.insert(
+            (StorageClass::Input, Binding::Location(0)),
+            Binding::Location(3),
+        );
+        remap_bindings(&mut module, &options).unwrap();
+        assert_eq!(
+            bindings(&module),
+            [
+                Some(Binding::Location(3)),
+                Some(Binding::Location(0)),
+                Some(Binding::Location(0)),
+                Some(Binding::Descriptor { set: 0, binding: 0 }),
+                Some(Binding::Descriptor { set: 0, binding: 2 }),
+                Some(Binding::Descriptor { set: 0, binding: 1 }),
+            ]
+        );
+    }
+
+    #[test]
+    fn conflict() {
+        let mut module = crate::front::wgsl::parse_str(SOURCE).unwrap();
+        let mut options = BindingOptions::default();
+        options.map.insert(
+            (
+                StorageClass::Uniform,
+                Binding::Descriptor { set: 1, binding: 1 },
+            ),
+            Binding::Descriptor { set: 0, binding: 0 },
+        );
+        let original = bindings(&module);
+        match remap_bindings(&mut module, &options) {
+            Err(BindingError::Conflict(Binding::Descriptor { set: 0, binding: 0 })) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        assert_eq!(bindings(&module), original);
+    }
+
+    #[test]
+    fn entry_points() {
+        // the vertex inputs and the fragment inputs share the locations
+        let mut module =
+            crate::front::wgsl::parse_str(include_str!("../../test-data/quad.wgsl")).unwrap();
+        let original = bindings(&module);
+        remap_bindings(&mut module, &BindingOptions::default()).unwrap();
+        assert_eq!(bindings(&module), original);
+    }
+}
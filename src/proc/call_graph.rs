@@ -0,0 +1,242 @@
+use crate::arena::{Arena, Handle};
+
+use thiserror::Error;
+
+#[derive(Clone, Debug, Error, PartialEq)]
+pub enum CallGraphError {
+    #[error("Function {0:?} calls itself, directly or indirectly")]
+    Recursion(Handle<crate::Function>),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Visit {
+    Pending,
+    Active,
+    Done,
+}
+
+fn collect_statement_calls(block: &[crate::Statement], callees: &mut Vec<Handle<crate::Function>>) {
+    for statement in block {
+        if let crate::Statement::Call { function, .. } = *statement {
+            if !callees.contains(&function) {
+                callees.push(function);
+            }
+        }
+        statement.for_each_block(|block| collect_statement_calls(block, callees));
+    }
+}
+
+/// Graph of the calls between local functions of a module.
+#[derive(Debug)]
+pub struct CallGraph {
+    callees: Vec<Vec<Handle<crate::Function>>>,
+}
+
+impl CallGraph {
+    /// Collect the local calls of all the functions.
+    pub fn new(functions: &Arena<crate::Function>) -> Self {
+        let callees = functions
+            .iter()
+            .map(|(_, fun)| {
+                let mut callees = Vec::new();
+                for (_, expr) in fun.expressions.iter() {
+                    if let crate::Expression::Call {
+                        origin: crate::FunctionOrigin::Local(callee),
+                        ..
+                    } = *expr
+                    {
+                        if !callees.contains(&callee) {
+                            callees.push(callee);
+                        }
+                    }
+                }
+                collect_statement_calls(&fun.body, &mut callees);
+                callees
+            })
+            .collect();
+        CallGraph { callees }
+    }
+
+    /// Return the local functions called by the given one.
+    pub fn callees(&self, function: Handle<crate::Function>) -> &[Handle<crate::Function>] {
+        &self.callees[function.index()]
+    }
+
+    fn visit(
+        &self,
+        function: Handle<crate::Function>,
+        visits: &mut [Visit],
+        order: &mut Vec<Handle<crate::Function>>,
+    ) -> Result<(), CallGraphError> {
+        match visits[function.index()] {
+            Visit::Pending => {}
+            Visit::Active => return Err(CallGraphError::Recursion(function)),
+            Visit::Done => return Ok(()),
+        }
+        visits[function.index()] = Visit::Active;
+        for &callee in self.callees(function) {
+            self.visit(callee, visits, order)?;
+        }
+        visits[function.index()] = Visit::Done;
+        order.push(function);
+        Ok(())
+    }
+
+    /// Return all the functions ordered so that each one comes after
+    /// all of its callees.
+    ///
+    /// Fails if any function is recursive.
+    pub fn reverse_topological_order(
+        &self,
+    ) -> Result<Vec<Handle<crate::Function>>, CallGraphError> {
+        let mut visits = vec![Visit::Pending; self.callees.len()];
+        let mut order = Vec::with_capacity(self.callees.len());
+        for index in 0..self.callees.len() {
+            self.visit(Handle::from_usize(index), &mut visits, &mut order)?;
+        }
+        Ok(order)
+    }
+
+    /// Extend the `global_usage` of each function with the usage of its
+    /// callees, so that it reflects all the globals touched by a call.
+    pub fn propagate_global_usage(&self, functions: &mut Arena<crate::Function>) {
+        let mut changed = true;
+        // recursive calls may need more than one pass
+        while changed {
+            changed = false;
+            let order = match self.reverse_topological_order() {
+                Ok(order) => order,
+                Err(_) => (0..self.callees.len()).map(Handle::from_usize).collect(),
+            };
+            for function in order {
+                for &callee in self.callees(function) {
+                    if callee == function {
+                        continue;
+                    }
+                    let callee_usage = functions[callee].global_usage.clone();
+                    let usage = &mut functions.get_mut(function).global_usage;
+                    for (usage, callee_usage) in usage.iter_mut().zip(callee_usage) {
+                        if !usage.contains(callee_usage) {
+                            *usage |= callee_usage;
+                            changed = true;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CallGraph, CallGraphError};
+    use crate::{builder::ModuleBuilder, GlobalUse, ScalarKind, StorageClass};
+
+    /// Resets the usage of each function to the globals it accesses directly.
+    fn scan_global_usage(module: &mut crate::Module) {
+        let handles = module
+            .functions
+            .iter()
+            .map(|(handle, _)| handle)
+            .collect::<Vec<_>>();
+        for handle in handles {
+            let fun = &module.functions[handle];
+            let global_usage =
+                GlobalUse::scan(&fun.expressions, &fun.body, &module.global_variables);
+            module.functions.get_mut(handle).global_usage = global_usage;
+        }
+    }
+
+    #[test]
+    fn order_and_usage() {
+        let mut builder = ModuleBuilder::new();
+        let float = builder.scalar(ScalarKind::Float, 4);
+        let one = builder.float(1.0);
+        let input = builder.global_variable("input", StorageClass::Private, None, float);
+        let output = builder.global_variable("output", StorageClass::Private, None, float);
+
+        // fn leaf() { output = 1.0; }
+        let leaf = {
+            let mut fun = builder.function("leaf", &[], None);
+            let pointer = fun.global(output);
+            let value = fun.constant(one);
+            fun.store(pointer, value);
+            fun.finish()
+        };
+        // fn middle() { leaf(); }
+        let middle = {
+            let mut fun = builder.function("middle", &[], None);
+            fun.call_statement(leaf, Vec::new());
+            fun.finish()
+        };
+        // fn main() -> f32 { middle(); leaf(); return input; }
+        let main = {
+            let mut fun = builder.function("main", &[], Some(float));
+            fun.call_statement(middle, Vec::new());
+            fun.call_statement(leaf, Vec::new());
+            let pointer = fun.global(input);
+            let value = fun.load(pointer);
+            fun.ret(Some(value));
+            fun.finish()
+        };
+        let mut module = builder.finish();
+        scan_global_usage(&mut module);
+
+        let graph = CallGraph::new(&module.functions);
+        assert_eq!(graph.callees(main), [middle, leaf]);
+        assert_eq!(
+            graph.reverse_topological_order(),
+            Ok(vec![leaf, middle, main])
+        );
+
+        graph.propagate_global_usage(&mut module.functions);
+        assert_eq!(
+            module.functions[main].global_usage,
+            [GlobalUse::LOAD, GlobalUse::STORE]
+        );
+        assert_eq!(
+            module.functions[middle].global_usage,
+            [GlobalUse::empty(), GlobalUse::STORE]
+        );
+    }
+
+    #[test]
+    fn recursion() {
+        let mut builder = ModuleBuilder::new();
+        let float = builder.scalar(ScalarKind::Float, 4);
+        let value = builder.global_variable("value", StorageClass::Private, None, float);
+        let first = crate::Handle::from_usize(builder.module().functions.len());
+        let second = crate::Handle::from_usize(first.index() + 1);
+
+        // fn first() { var x = value; second(); }
+        {
+            let mut fun = builder.function("first", &[], None);
+            let pointer = fun.global(value);
+            let loaded = fun.load(pointer);
+            fun.local_variable_with("x", loaded).unwrap();
+            fun.call_statement(second, Vec::new());
+            fun.finish();
+        }
+        // fn second() { value = value; first(); }
+        {
+            let mut fun = builder.function("second", &[], None);
+            let pointer = fun.global(value);
+            let loaded = fun.load(pointer);
+            fun.store(pointer, loaded);
+            fun.call_statement(first, Vec::new());
+            fun.finish();
+        }
+        let mut module = builder.finish();
+        scan_global_usage(&mut module);
+
+        let graph = CallGraph::new(&module.functions);
+        assert_eq!(
+            graph.reverse_topological_order(),
+            Err(CallGraphError::Recursion(first))
+        );
+        graph.propagate_global_usage(&mut module.functions);
+        for (_, fun) in module.functions.iter() {
+            assert_eq!(fun.global_usage, [GlobalUse::LOAD | GlobalUse::STORE]);
+        }
+    }
+}
@@ -0,0 +1,270 @@
+use crate::arena::{Arena, Handle};
+
+use std::borrow::Cow;
+
+/// Conversion of the clip-space depth between the conventions of the APIs.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DepthConversion {
+    /// From `[0, 1]` (Vulkan, Metal, D3D) to `[-1, 1]` (GL): `z' = 2z - w`.
+    UnitToSigned,
+    /// From `[-1, 1]` (GL) to `[0, 1]` (Vulkan, Metal, D3D): `z' = (z + w) / 2`.
+    SignedToUnit,
+}
+
+/// Changes applied to the position written by the vertex entry points.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ClipSpaceAdjustment {
+    /// Negate the Y coordinate, switching between Y-up and Y-down.
+    pub flip_y: bool,
+    /// Convert the depth coordinate to another range.
+    pub depth: Option<DepthConversion>,
+}
+
+impl ClipSpaceAdjustment {
+    /// Returns true if this adjustment doesn't change anything.
+    pub fn is_identity(&self) -> bool {
+        !self.flip_y && self.depth.is_none()
+    }
+
+    /// Returns the module with this adjustment applied, only copying it if needed.
+    pub fn apply<'a>(&self, module: &'a crate::Module) -> Cow<'a, crate::Module> {
+        if self.is_identity() {
+            Cow::Borrowed(module)
+        } else {
+            let mut module = module.clone();
+            adjust_clip_space(&mut module, self);
+            Cow::Owned(module)
+        }
+    }
+}
+
+/// Insert the adjusting store before every return of a function.
+fn insert_stores(block: &mut crate::Block, store: &crate::Statement) {
+    let mut index = 0;
+    while index < block.len() {
+        if let crate::Statement::Return { .. } = block[index] {
+            block.insert(index, store.clone());
+            index += 1;
+        } else {
+            block[index].for_each_block_mut(|block| insert_stores(block, store));
+        }
+        index += 1;
+    }
+}
+
+/// Build the expression of the adjusted position, as read from a global variable.
+fn adjusted_position(
+    expressions: &mut Arena<crate::Expression>,
+    position: Handle<crate::Expression>,
+    ty: Handle<crate::Type>,
+    half_or_two: Option<Handle<crate::Constant>>,
+    adjustment: &ClipSpaceAdjustment,
+) -> Handle<crate::Expression> {
+    use crate::{BinaryOperator as Bo, Expression as E};
+    let mut components = (0..4)
+        .map(|index| {
+            expressions.append(E::AccessIndex {
+                base: position,
+                index,
+            })
+        })
+        .collect::<Vec<_>>();
+    if adjustment.flip_y {
+        components[1] = expressions.append(E::Unary {
+            op: crate::UnaryOperator::Negate,
+            expr: components[1],
+        });
+    }
+    if let (Some(conversion), Some(constant)) = (adjustment.depth, half_or_two) {
+        let factor = expressions.append(E::Constant(constant));
+        let (z, w) = (components[2], components[3]);
+        components[2] = match conversion {
+            DepthConversion::UnitToSigned => {
+                let double = expressions.append(E::Binary {
+                    op: Bo::Multiply,
+                    left: z,
+                    right: factor,
+                });
+                expressions.append(E::Binary {
+                    op: Bo::Subtract,
+                    left: double,
+                    right: w,
+                })
+            }
+            DepthConversion::SignedToUnit => {
+                let sum = expressions.append(E::Binary {
+                    op: Bo::Add,
+                    left: z,
+                    right: w,
+                });
+                expressions.append(E::Binary {
+                    op: Bo::Multiply,
+                    left: sum,
+                    right: factor,
+                })
+            }
+        };
+    }
+    expressions.append(E::Compose { ty, components })
+}
+
+/// Adjust the clip-space position written by the vertex entry points of a module.
+///
+/// The position is either an output variable, or a member of an output
+/// block like `gl_PerVertex`. The position output is read back and rewritten with the adjusted
+/// coordinates before every return of the entry point functions, so that
+/// all the partial writes, including the ones done by called functions,
+/// are taken into account.
+pub fn adjust_clip_space(module: &mut crate::Module, adjustment: &ClipSpaceAdjustment) {
+    if adjustment.is_identity() {
+        return;
+    }
+    // the position is either a variable or a member of an output block
+    let mut positions = Vec::new();
+    for (handle, var) in module.global_variables.iter() {
+        if var.class != crate::StorageClass::Output {
+            continue;
+        }
+        if var.binding == Some(crate::Binding::BuiltIn(crate::BuiltIn::Position)) {
+            positions.push((handle, None, var.ty));
+        } else if let crate::TypeInner::Struct { ref members } = module.types[var.ty].inner {
+            for (index, member) in members.iter().enumerate() {
+                if member.origin == crate::MemberOrigin::BuiltIn(crate::BuiltIn::Position) {
+                    positions.push((handle, Some(index as u32), member.ty));
+                }
+            }
+        }
+    }
+    // several entry points can share a function, it's only adjusted once
+    let mut functions = module
+        .entry_points
+        .iter()
+        .filter(|ep| ep.stage == crate::ShaderStage::Vertex)
+        .map(|ep| ep.function)
+        .collect::<Vec<_>>();
+    functions.sort();
+    functions.dedup();
+
+    for (var, member, ty) in positions {
+        // the components are accessed, so their type has to be known by the back ends
+        let scalar_ty = match module.types[ty].inner {
+            crate::TypeInner::Vector { kind, width, .. } => {
+                Some(module.types.fetch_or_append(crate::Type {
+                    name: None,
+                    inner: crate::TypeInner::Scalar { kind, width },
+                }))
+            }
+            _ => None,
+        };
+        let half_or_two = match (adjustment.depth, scalar_ty) {
+            (Some(conversion), Some(scalar_ty)) => {
+                let value = match conversion {
+                    DepthConversion::UnitToSigned => 2.0,
+                    DepthConversion::SignedToUnit => 0.5,
+                };
+                Some(module.constants.fetch_or_append(crate::Constant {
+                    name: None,
+                    specialization: None,
+                    inner: crate::ConstantInner::Float(value),
+                    ty: scalar_ty,
+                }))
+            }
+            _ => None,
+        };
+
+        for &function in functions.iter() {
+            let fun = module.functions.get_mut(function);
+            let mut pointer = fun
+                .expressions
+                .append(crate::Expression::GlobalVariable(var));
+            if let Some(index) = member {
+                pointer = fun.expressions.append(crate::Expression::AccessIndex {
+                    base: pointer,
+                    index,
+                });
+            }
+            let value =
+                adjusted_position(&mut fun.expressions, pointer, ty, half_or_two, adjustment);
+            let store = crate::Statement::Store { pointer, value };
+            insert_stores(&mut fun.body, &store);
+            match fun.body.last() {
+                Some(&crate::Statement::Return { .. }) => {}
+                _ => fun.body.push(store),
+            }
+            if let Some(usage) = fun.global_usage.get_mut(var.index()) {
+                *usage |= crate::GlobalUse::LOAD | crate::GlobalUse::STORE;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ClipSpaceAdjustment, DepthConversion};
+
+    #[test]
+    fn flip_and_convert() {
+        let source = "
+            [[location 0]] var<in> a_pos : vec2<f32>;
+            [[builtin position]] var<out> o_position : vec4<f32>;
+            fn main() -> void {
+                o_position = vec4<f32>(a_pos, 0.0, 1.0);
+                return;
+            }
+            entry_point vertex as \"main\" = main;
+        ";
+        let module = crate::front::wgsl::parse_str(source).unwrap();
+        let adjustment = ClipSpaceAdjustment {
+            flip_y: true,
+            depth: Some(DepthConversion::SignedToUnit),
+        };
+        let adjusted = adjustment.apply(&module);
+        crate::proc::Validator::new().validate(&adjusted).unwrap();
+
+        let fun = &adjusted.functions[adjusted.entry_points[0].function];
+        assert_eq!(fun.body.len(), 3);
+        match fun.body[1] {
+            crate::Statement::Store { value, .. } => match fun.expressions[value] {
+                crate::Expression::Compose { ref components, .. } => {
+                    assert_eq!(components.len(), 4);
+                    match fun.expressions[components[1]] {
+                        crate::Expression::Unary {
+                            op: crate::UnaryOperator::Negate,
+                            ..
+                        } => {}
+                        ref other => panic!("Unexpected Y: {:?}", other),
+                    }
+                }
+                ref other => panic!("Unexpected value: {:?}", other),
+            },
+            ref other => panic!("Unexpected statement: {:?}", other),
+        }
+        // the original module is left intact
+        assert_eq!(
+            module.functions[module.entry_points[0].function].body.len(),
+            2
+        );
+    }
+
+    #[test]
+    fn shared_entry_point_function() {
+        let source = "
+            [[builtin position]] var<out> o_position : vec4<f32>;
+            fn main() -> void {
+                o_position = vec4<f32>(0.0, 0.0, 0.0, 1.0);
+                return;
+            }
+            entry_point vertex as \"first\" = main;
+            entry_point vertex as \"second\" = main;
+        ";
+        let module = crate::front::wgsl::parse_str(source).unwrap();
+        let adjustment = ClipSpaceAdjustment {
+            flip_y: true,
+            depth: None,
+        };
+        let adjusted = adjustment.apply(&module);
+        let fun = &adjusted.functions[adjusted.entry_points[1].function];
+        // a single adjusting store before the return
+        assert_eq!(fun.body.len(), 3);
+    }
+}
@@ -0,0 +1,352 @@
+use super::{FunctionMap, ModuleMap};
+use crate::arena::Handle;
+
+/// Which expressions and local variables of a function are used.
+struct FunctionTrace {
+    expressions: Vec<bool>,
+    locals: Vec<bool>,
+}
+
+struct Tracer<'a> {
+    module: &'a crate::Module,
+    types: Vec<bool>,
+    constants: Vec<bool>,
+    globals: Vec<bool>,
+    functions: Vec<Option<FunctionTrace>>,
+    queue: Vec<Handle<crate::Function>>,
+}
+
+impl<'a> Tracer<'a> {
+    fn new(module: &'a crate::Module) -> Self {
+        Tracer {
+            module,
+            types: vec![false; module.types.len()],
+            constants: vec![false; module.constants.len()],
+            globals: vec![false; module.global_variables.len()],
+            functions: module.functions.iter().map(|_| None).collect(),
+            queue: Vec::new(),
+        }
+    }
+
+    fn trace_type(&mut self, handle: Handle<crate::Type>) {
+        if self.types[handle.index()] {
+            return;
+        }
+        self.types[handle.index()] = true;
+        match self.module.types[handle].inner {
+            crate::TypeInner::Pointer { base, .. }
+            | crate::TypeInner::Array { base, .. }
+            | crate::TypeInner::Image { base, .. } => self.trace_type(base),
+            crate::TypeInner::Struct { ref members } => {
+                for member in members {
+                    self.trace_type(member.ty);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn trace_constant(&mut self, handle: Handle<crate::Constant>) {
+        if self.constants[handle.index()] {
+            return;
+        }
+        self.constants[handle.index()] = true;
+        let constant = &self.module.constants[handle];
+        self.trace_type(constant.ty);
+        if let crate::ConstantInner::Composite(ref components) = constant.inner {
+            for &component in components {
+                self.trace_constant(component);
+            }
+        }
+    }
+
+    fn trace_global(&mut self, handle: Handle<crate::GlobalVariable>) {
+        if self.globals[handle.index()] {
+            return;
+        }
+        self.globals[handle.index()] = true;
+        let var = &self.module.global_variables[handle];
+        self.trace_type(var.ty);
+        if let Some(init) = var.init {
+            self.trace_constant(init);
+        }
+    }
+
+    fn enqueue_function(&mut self, handle: Handle<crate::Function>) {
+        if self.functions[handle.index()].is_none() && !self.queue.contains(&handle) {
+            self.queue.push(handle);
+        }
+    }
+
+    fn trace_function(&mut self, handle: Handle<crate::Function>) {
+        let module = self.module;
+        let fun = &module.functions[handle];
+        for argument in fun.arguments.iter() {
+            self.trace_type(argument.ty);
+        }
+        if let Some(ty) = fun.return_type {
+            self.trace_type(ty);
+        }
+        let mut trace = FunctionTrace {
+            expressions: vec![false; fun.expressions.len()],
+            locals: vec![false; fun.local_variables.len()],
+        };
+        self.trace_block(&fun.body, fun, &mut trace);
+        self.functions[handle.index()] = Some(trace);
+    }
+
+    fn trace_block(
+        &mut self,
+        block: &[crate::Statement],
+        fun: &crate::Function,
+        trace: &mut FunctionTrace,
+    ) {
+        for statement in block {
+            if let crate::Statement::Call { function, .. } = *statement {
+                self.enqueue_function(function);
+            }
+            statement.for_each_expression(|expr| self.trace_expression(expr, fun, trace));
+            statement.for_each_block(|block| self.trace_block(block, fun, trace));
+        }
+    }
+
+    fn trace_expression(
+        &mut self,
+        handle: Handle<crate::Expression>,
+        fun: &crate::Function,
+        trace: &mut FunctionTrace,
+    ) {
+        use crate::Expression as E;
+        if trace.expressions[handle.index()] {
+            return;
+        }
+        trace.expressions[handle.index()] = true;
+        let expression = &fun.expressions[handle];
+        match *expression {
+            E::Constant(constant) => self.trace_constant(constant),
+            E::Compose { ty, .. } => self.trace_type(ty),
+            E::GlobalVariable(var) => self.trace_global(var),
+            E::LocalVariable(var) if !trace.locals[var.index()] => {
+                trace.locals[var.index()] = true;
+                let local = &fun.local_variables[var];
+                self.trace_type(local.ty);
+                if let Some(init) = local.init {
+                    self.trace_expression(init, fun, trace);
+                }
+            }
+            E::Call {
+                origin: crate::FunctionOrigin::Local(function),
+                ..
+            } => self.enqueue_function(function),
+            _ => {}
+        }
+        expression.for_each_operand(|operand| self.trace_expression(operand, fun, trace));
+    }
+}
+
+/// Drops the statements following a `Return`, `Break`, `Continue` or `Kill`,
+/// since they can never be executed.
+fn remove_dead_statements(block: &mut crate::Block) {
+    use crate::Statement as S;
+    if let Some(position) = block.iter().position(|statement| {
+        matches!(
+            *statement,
+            S::Return { .. } | S::Break | S::Continue | S::Kill
+        )
+    }) {
+        block.truncate(position + 1);
+    }
+    for statement in block.iter_mut() {
+        match *statement {
+            S::Block(ref mut block) => remove_dead_statements(block),
+            S::If {
+                ref mut accept,
+                ref mut reject,
+                ..
+            } => {
+                remove_dead_statements(accept);
+                remove_dead_statements(reject);
+            }
+            S::Switch {
+                ref mut cases,
+                ref mut default,
+                ..
+            } => {
+                for (case, _) in cases.values_mut() {
+                    remove_dead_statements(case);
+                }
+                remove_dead_statements(default);
+            }
+            S::Loop {
+                ref mut body,
+                ref mut continuing,
+            } => {
+                remove_dead_statements(body);
+                remove_dead_statements(continuing);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Removes everything that isn't reachable from the entry points of a module.
+///
+/// Functions, global variables, constants and types that are not used by
+/// any entry point are dropped, as well as unused expressions and local
+/// variables of the remaining functions, and statements that can't be
+/// executed. All the handles are updated to point into the new arenas.
+///
+/// To only keep some of the entry points, remove the others from
+/// `Module::entry_points` before calling this.
+pub fn compact(module: &mut crate::Module) {
+    let function_handles = module
+        .functions
+        .iter()
+        .map(|(handle, _)| handle)
+        .collect::<Vec<_>>();
+    for &handle in function_handles.iter() {
+        remove_dead_statements(&mut module.functions.get_mut(handle).body);
+    }
+
+    let mut tracer = Tracer::new(module);
+    for entry_point in module.entry_points.iter() {
+        tracer.enqueue_function(entry_point.function);
+    }
+    while let Some(handle) = tracer.queue.pop() {
+        tracer.trace_function(handle);
+    }
+    let Tracer {
+        types,
+        constants,
+        globals,
+        functions,
+        ..
+    } = tracer;
+
+    let kept_functions = functions.iter().map(Option::is_some).collect::<Vec<_>>();
+    let map = ModuleMap {
+        types: module.types.compact(&types),
+        constants: module.constants.compact(&constants),
+        globals: module.global_variables.compact(&globals),
+        functions: module.functions.compact(&kept_functions),
+    };
+
+    let handles = module
+        .functions
+        .iter()
+        .map(|(handle, _)| handle)
+        .collect::<Vec<_>>();
+    for (handle, trace) in handles.into_iter().zip(functions.into_iter().flatten()) {
+        let fun = module.functions.get_mut(handle);
+        let fun_map = FunctionMap {
+            expressions: fun.expressions.compact(&trace.expressions),
+            locals: fun.local_variables.compact(&trace.locals),
+        };
+        fun_map.apply(fun);
+    }
+    map.apply(module);
+}
+
+impl crate::Module {
+    /// Returns a module containing only a single entry point, and the
+    /// declarations it reaches, or `None` if there is no such entry point.
+    ///
+    /// This is useful for the back ends producing one shader per stage.
+    pub fn extract_entry_point(&self, name: &str, stage: crate::ShaderStage) -> Option<Self> {
+        let entry_point = self
+            .entry_points
+            .iter()
+            .find(|ep| ep.stage == stage && ep.name == name)?;
+        let mut module = self.clone();
+        module.entry_points = vec![entry_point.clone()];
+        compact(&mut module);
+        Some(module)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::compact;
+
+    #[test]
+    fn unused_declarations() {
+        let source = "
+            [[location 0]] var<in> a_pos : vec2<f32>;
+            [[location 1]] var<in> a_unused : vec3<i32>;
+            [[builtin position]] var<out> o_position : vec4<f32>;
+            const c_unused : i32 = 7;
+            fn unused() -> void {
+                return;
+            }
+            fn main() -> void {
+                o_position = vec4<f32>(a_pos, 0.0, 1.0);
+                return;
+                o_position = vec4<f32>(a_pos, 1.0, 1.0);
+            }
+            entry_point vertex as \"main\" = main;
+        ";
+        let mut module = crate::front::wgsl::parse_str(source).unwrap();
+        compact(&mut module);
+        crate::proc::Validator::new().validate(&module).unwrap();
+
+        let names = module
+            .global_variables
+            .iter()
+            .map(|(_, var)| var.name.as_deref().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["a_pos", "o_position"]);
+        assert!(module
+            .constants
+            .iter()
+            .all(|(_, constant)| constant.inner != crate::ConstantInner::Sint(7)));
+        assert!(module.types.iter().all(|(_, ty)| match ty.inner {
+            crate::TypeInner::Vector { kind, .. } => kind == crate::ScalarKind::Float,
+            _ => true,
+        }));
+
+        assert_eq!(module.functions.len(), 1);
+        let fun = &module.functions[module.entry_points[0].function];
+        assert_eq!(fun.name.as_deref(), Some("main"));
+        assert_eq!(fun.body.len(), 2);
+        assert_eq!(fun.global_usage.len(), 2);
+    }
+
+    #[test]
+    fn extract_entry_point() {
+        let source = "
+            [[location 0]] var<in> a_pos : vec2<f32>;
+            [[builtin position]] var<out> o_position : vec4<f32>;
+            fn main_vert() -> void {
+                o_position = vec4<f32>(a_pos, 0.0, 1.0);
+                return;
+            }
+            entry_point vertex as \"main\" = main_vert;
+
+            [[location 0]] var<out> o_color : vec4<f32>;
+            fn main_frag() -> void {
+                o_color = vec4<f32>(1.0, 0.0, 0.0, 1.0);
+                return;
+            }
+            entry_point fragment as \"main\" = main_frag;
+        ";
+        let module = crate::front::wgsl::parse_str(source).unwrap();
+        assert!(module
+            .extract_entry_point("main", crate::ShaderStage::Compute)
+            .is_none());
+
+        let fragment = module
+            .extract_entry_point("main", crate::ShaderStage::Fragment)
+            .unwrap();
+        crate::proc::Validator::new().validate(&fragment).unwrap();
+        assert_eq!(fragment.entry_points.len(), 1);
+        assert_eq!(fragment.functions.len(), 1);
+        let names = fragment
+            .global_variables
+            .iter()
+            .map(|(_, var)| var.name.as_deref().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["o_color"]);
+        // the original module is left intact
+        assert_eq!(module.entry_points.len(), 2);
+    }
+}
@@ -0,0 +1,936 @@
+use super::Typifier;
+use crate::{
+    arena::{Arena, Handle},
+    BinaryOperator as Bo, ConstantInner as Ci, ScalarKind as Sk, UnaryOperator as Uo,
+};
+
+use thiserror::Error;
+
+#[derive(Clone, Debug, Error)]
+pub enum ConstantSolvingError {
+    #[error("Expression {0:?} can't be evaluated at compile time")]
+    NotConstant(Handle<crate::Expression>),
+    #[error("Constant {0:?} is a specialization constant, its final value is unknown")]
+    Specialization(Handle<crate::Constant>),
+    #[error("Operator {0:?} can't be applied to the given operands")]
+    InvalidBinaryOperands(crate::BinaryOperator),
+    #[error("Operator {0:?} can't be applied to the given operand")]
+    InvalidUnaryOperand(crate::UnaryOperator),
+    #[error("Intrinsic {0:?} can't be applied to the given argument")]
+    InvalidIntrinsicArgument(crate::IntrinsicFunction),
+    #[error("Function `{0}` can't be applied to the given arguments")]
+    InvalidFunctionArguments(String),
+    #[error("Division by zero")]
+    DivisionByZero,
+    #[error("Constant {0:?} is not a composite")]
+    InvalidAccessBase(Handle<crate::Constant>),
+    #[error("Constant {0:?} is not a valid index")]
+    InvalidAccessIndex(Handle<crate::Constant>),
+    #[error("Index {0} is out of bounds")]
+    IndexOutOfBounds(u32),
+    #[error("Type {0:?} can't be constructed from the given components")]
+    InvalidCompose(Handle<crate::Type>),
+    #[error("Composite operands have different numbers of components")]
+    ComponentCountMismatch,
+}
+
+/// Computes a scalar from the scalars of the arguments and their width.
+type ScalarFunction<'a> = dyn FnMut(&[&Ci], crate::Bytes) -> Result<Ci, ConstantSolvingError> + 'a;
+
+/// Evaluates expressions into constants at compile time.
+///
+/// New values are appended to the constant arena, reusing existing
+/// constants when possible. Specialization constants are never folded,
+/// since their final values are only known at pipeline creation.
+pub struct ConstantSolver<'a> {
+    pub types: &'a mut Arena<crate::Type>,
+    pub expressions: &'a Arena<crate::Expression>,
+    pub constants: &'a mut Arena<crate::Constant>,
+}
+
+impl ConstantSolver<'_> {
+    pub fn solve(
+        &mut self,
+        expr: Handle<crate::Expression>,
+    ) -> Result<Handle<crate::Constant>, ConstantSolvingError> {
+        use crate::Expression as E;
+        match self.expressions[expr] {
+            E::Constant(handle) => Ok(handle),
+            E::Compose { ty, ref components } => {
+                let components = components
+                    .iter()
+                    .map(|&component| self.solve(component))
+                    .collect::<Result<Vec<_>, _>>()?;
+                self.compose(ty, components)
+            }
+            E::AccessIndex { base, index } => {
+                let base = self.solve(base)?;
+                self.access(base, index)
+            }
+            E::Access { base, index } => {
+                let base = self.solve(base)?;
+                let index_handle = self.solve(index)?;
+                let index = match *self.value(index_handle)? {
+                    Ci::Uint(value) => value as u32,
+                    Ci::Sint(value) if value >= 0 => value as u32,
+                    _ => return Err(ConstantSolvingError::InvalidAccessIndex(index_handle)),
+                };
+                self.access(base, index)
+            }
+            E::Unary { op, expr } => {
+                let value = self.solve(expr)?;
+                self.check_arithmetic(&[value], false)?;
+                self.component_wise(&[value], None, &mut |args, width| {
+                    unary_op(op, args[0], width)
+                })
+            }
+            E::Binary { op, left, right } => {
+                let left = self.solve(left)?;
+                let right = self.solve(right)?;
+                self.binary(op, left, right)
+            }
+            E::Intrinsic { fun, argument } => {
+                let argument = self.solve(argument)?;
+                self.intrinsic(fun, argument)
+            }
+            E::DotProduct(left, right) => {
+                let left = self.solve(left)?;
+                let right = self.solve(right)?;
+                self.dot_product(left, right)
+                    .map_err(|_| ConstantSolvingError::InvalidFunctionArguments("dot".to_string()))
+            }
+            E::CrossProduct(left, right) => {
+                let left = self.solve(left)?;
+                let right = self.solve(right)?;
+                self.cross_product(left, right).map_err(|_| {
+                    ConstantSolvingError::InvalidFunctionArguments("cross".to_string())
+                })
+            }
+            E::Call {
+                origin: crate::FunctionOrigin::External(ref name),
+                ref arguments,
+            } => {
+                let arguments = arguments
+                    .iter()
+                    .map(|&argument| self.solve(argument))
+                    .collect::<Result<Vec<_>, _>>()?;
+                match self.call(name, &arguments)? {
+                    Some(handle) => Ok(handle),
+                    None => Err(ConstantSolvingError::NotConstant(expr)),
+                }
+            }
+            _ => Err(ConstantSolvingError::NotConstant(expr)),
+        }
+    }
+
+    fn append(&mut self, inner: Ci, ty: Handle<crate::Type>) -> Handle<crate::Constant> {
+        self.constants.fetch_or_append(crate::Constant {
+            name: None,
+            specialization: None,
+            inner,
+            ty,
+        })
+    }
+
+    /// Returns the value of a scalar, making sure it's not going to be specialized.
+    fn value(&self, handle: Handle<crate::Constant>) -> Result<&Ci, ConstantSolvingError> {
+        let constant = &self.constants[handle];
+        match constant.specialization {
+            Some(_) => Err(ConstantSolvingError::Specialization(handle)),
+            None => Ok(&constant.inner),
+        }
+    }
+
+    fn scalar_width(&self, handle: Handle<crate::Constant>) -> crate::Bytes {
+        match self.types[self.constants[handle].ty].inner {
+            crate::TypeInner::Scalar { width, .. } | crate::TypeInner::Vector { width, .. } => {
+                width
+            }
+            _ => 4,
+        }
+    }
+
+    /// Returns the type with the same shape as `ty`, but a different scalar kind.
+    fn retype(
+        &mut self,
+        ty: Handle<crate::Type>,
+        kind: Sk,
+    ) -> Result<Handle<crate::Type>, ConstantSolvingError> {
+        let inner = match self.types[ty].inner {
+            crate::TypeInner::Scalar { width, .. } => crate::TypeInner::Scalar {
+                kind,
+                width: if kind == Sk::Bool { 1 } else { width },
+            },
+            crate::TypeInner::Vector { size, width, .. } => crate::TypeInner::Vector {
+                size,
+                kind,
+                width: if kind == Sk::Bool { 1 } else { width },
+            },
+            _ => return Err(ConstantSolvingError::InvalidCompose(ty)),
+        };
+        Ok(Typifier::deduce_type_handle(inner, self.types))
+    }
+
+    fn scalar_type(&mut self, kind: Sk, width: crate::Bytes) -> Handle<crate::Type> {
+        Typifier::deduce_type_handle(crate::TypeInner::Scalar { kind, width }, self.types)
+    }
+
+    /// Makes sure all the operands are numeric or boolean scalars, vectors or matrices.
+    fn check_arithmetic(
+        &self,
+        operands: &[Handle<crate::Constant>],
+        allow_matrix: bool,
+    ) -> Result<bool, ConstantSolvingError> {
+        let mut has_matrix = false;
+        for &operand in operands {
+            match self.types[self.constants[operand].ty].inner {
+                crate::TypeInner::Scalar { .. } | crate::TypeInner::Vector { .. } => {}
+                crate::TypeInner::Matrix { .. } if allow_matrix => has_matrix = true,
+                _ => {
+                    return Err(ConstantSolvingError::InvalidCompose(
+                        self.constants[operand].ty,
+                    ))
+                }
+            }
+        }
+        Ok(has_matrix)
+    }
+
+    /// Applies `fun` to the matching scalars of the arguments,
+    /// broadcasting scalar arguments over composite ones.
+    ///
+    /// The result has the shape of the first composite argument, or the type
+    /// of the first argument if there are no composites. Its scalar kind is
+    /// replaced with `kind` when provided.
+    fn component_wise(
+        &mut self,
+        args: &[Handle<crate::Constant>],
+        kind: Option<Sk>,
+        fun: &mut ScalarFunction,
+    ) -> Result<Handle<crate::Constant>, ConstantSolvingError> {
+        let shape = args
+            .iter()
+            .find_map(|&arg| match self.constants[arg].inner {
+                Ci::Composite(ref components) => Some((arg, components.len())),
+                _ => None,
+            });
+
+        match shape {
+            Some((shape, count)) => {
+                let mut components = Vec::with_capacity(count);
+                for i in 0..count {
+                    let sub_args = args
+                        .iter()
+                        .map(|&arg| match self.constants[arg].inner {
+                            Ci::Composite(ref sub) if sub.len() == count => Ok(sub[i]),
+                            Ci::Composite(_) => Err(ConstantSolvingError::ComponentCountMismatch),
+                            _ => Ok(arg),
+                        })
+                        .collect::<Result<Vec<_>, _>>()?;
+                    components.push(self.component_wise(&sub_args, kind, fun)?);
+                }
+                let shape_ty = self.constants[shape].ty;
+                let ty = match kind {
+                    Some(kind) => self.retype(shape_ty, kind)?,
+                    None => shape_ty,
+                };
+                Ok(self.append(Ci::Composite(components), ty))
+            }
+            None => {
+                let first = args[0];
+                let width = self.scalar_width(first);
+                let inner = {
+                    let values = args
+                        .iter()
+                        .map(|&arg| self.value(arg))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    normalize(fun(&values, width)?, width)
+                };
+                let first_ty = self.constants[first].ty;
+                let ty = match kind {
+                    Some(kind) => self.retype(first_ty, kind)?,
+                    None => first_ty,
+                };
+                Ok(self.append(inner, ty))
+            }
+        }
+    }
+
+    fn access(
+        &mut self,
+        base: Handle<crate::Constant>,
+        index: u32,
+    ) -> Result<Handle<crate::Constant>, ConstantSolvingError> {
+        match self.constants[base].inner {
+            Ci::Composite(ref components) => components
+                .get(index as usize)
+                .cloned()
+                .ok_or(ConstantSolvingError::IndexOutOfBounds(index)),
+            _ => Err(ConstantSolvingError::InvalidAccessBase(base)),
+        }
+    }
+
+    fn cast(
+        &mut self,
+        handle: Handle<crate::Constant>,
+        kind: Sk,
+        width: crate::Bytes,
+    ) -> Result<Handle<crate::Constant>, ConstantSolvingError> {
+        let inner = match *self.value(handle)? {
+            Ci::Sint(value) => match kind {
+                Sk::Sint => Ci::Sint(value),
+                Sk::Uint => Ci::Uint(value as u64),
+                Sk::Float => Ci::Float(value as f64),
+                Sk::Bool => Ci::Bool(value != 0),
+            },
+            Ci::Uint(value) => match kind {
+                Sk::Sint => Ci::Sint(value as i64),
+                Sk::Uint => Ci::Uint(value),
+                Sk::Float => Ci::Float(value as f64),
+                Sk::Bool => Ci::Bool(value != 0),
+            },
+            Ci::Float(value) => match kind {
+                Sk::Sint => Ci::Sint(value as i64),
+                Sk::Uint => Ci::Uint(value as u64),
+                Sk::Float => Ci::Float(value),
+                Sk::Bool => Ci::Bool(value != 0.0),
+            },
+            Ci::Bool(value) => match kind {
+                Sk::Sint => Ci::Sint(value as i64),
+                Sk::Uint => Ci::Uint(value as u64),
+                Sk::Float => Ci::Float(if value { 1.0 } else { 0.0 }),
+                Sk::Bool => Ci::Bool(value),
+            },
+            Ci::Composite(_) => {
+                let ty = self.constants[handle].ty;
+                return Err(ConstantSolvingError::InvalidCompose(ty));
+            }
+        };
+        let ty = self.scalar_type(kind, width);
+        Ok(self.append(normalize(inner, width), ty))
+    }
+
+    fn compose(
+        &mut self,
+        ty: Handle<crate::Type>,
+        components: Vec<Handle<crate::Constant>>,
+    ) -> Result<Handle<crate::Constant>, ConstantSolvingError> {
+        let invalid = ConstantSolvingError::InvalidCompose(ty);
+        let inner = match self.types[ty].inner {
+            crate::TypeInner::Scalar { kind, width } => {
+                // a scalar constructor is a conversion
+                return match components[..] {
+                    [value] => self.cast(value, kind, width),
+                    _ => Err(invalid),
+                };
+            }
+            crate::TypeInner::Vector { size, kind, width } => {
+                let mut scalars = Vec::with_capacity(size as usize);
+                for &component in components.iter() {
+                    match self.constants[component].inner {
+                        Ci::Composite(ref sub) => scalars.extend_from_slice(sub),
+                        _ => scalars.push(component),
+                    }
+                }
+                if let [value] = scalars[..] {
+                    // splat the only value
+                    scalars = vec![value; size as usize];
+                }
+                if scalars.len() != size as usize {
+                    return Err(invalid);
+                }
+                let converted = scalars
+                    .into_iter()
+                    .map(|scalar| self.cast(scalar, kind, width))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ci::Composite(converted)
+            }
+            crate::TypeInner::Matrix { columns, .. } => {
+                if components.len() != columns as usize {
+                    return Err(invalid);
+                }
+                self.check_specialization(&components)?;
+                Ci::Composite(components)
+            }
+            crate::TypeInner::Array { .. } | crate::TypeInner::Struct { .. } => {
+                self.check_specialization(&components)?;
+                Ci::Composite(components)
+            }
+            _ => return Err(invalid),
+        };
+        Ok(self.append(inner, ty))
+    }
+
+    /// Makes sure none of the constants, or their components, is going to be specialized.
+    fn check_specialization(
+        &self,
+        handles: &[Handle<crate::Constant>],
+    ) -> Result<(), ConstantSolvingError> {
+        for &handle in handles {
+            let constant = &self.constants[handle];
+            if constant.specialization.is_some() {
+                return Err(ConstantSolvingError::Specialization(handle));
+            }
+            if let Ci::Composite(ref components) = constant.inner {
+                self.check_specialization(components)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn binary(
+        &mut self,
+        op: crate::BinaryOperator,
+        left: Handle<crate::Constant>,
+        right: Handle<crate::Constant>,
+    ) -> Result<Handle<crate::Constant>, ConstantSolvingError> {
+        let has_matrix = self.check_arithmetic(&[left, right], true)?;
+        // matrix products are not component-wise
+        if has_matrix && op == Bo::Multiply {
+            return Err(ConstantSolvingError::InvalidBinaryOperands(op));
+        }
+        let kind = match op {
+            Bo::Equal
+            | Bo::NotEqual
+            | Bo::Less
+            | Bo::LessEqual
+            | Bo::Greater
+            | Bo::GreaterEqual => Some(Sk::Bool),
+            _ => None,
+        };
+        self.component_wise(&[left, right], kind, &mut |args, width| {
+            binary_op(op, args[0], args[1], width)
+        })
+    }
+
+    fn intrinsic(
+        &mut self,
+        fun: crate::IntrinsicFunction,
+        argument: Handle<crate::Constant>,
+    ) -> Result<Handle<crate::Constant>, ConstantSolvingError> {
+        use crate::IntrinsicFunction as If;
+        let invalid = ConstantSolvingError::InvalidIntrinsicArgument(fun);
+        match fun {
+            If::Any | If::All => {
+                let scalars = match self.constants[argument].inner {
+                    Ci::Composite(ref components) => components.clone(),
+                    _ => vec![argument],
+                };
+                let mut result = fun == If::All;
+                for scalar in scalars {
+                    match *self.value(scalar)? {
+                        Ci::Bool(value) if fun == If::All => result &= value,
+                        Ci::Bool(value) => result |= value,
+                        _ => return Err(invalid),
+                    }
+                }
+                let ty = self.scalar_type(Sk::Bool, 1);
+                Ok(self.append(Ci::Bool(result), ty))
+            }
+            If::IsNan | If::IsInf | If::IsFinite | If::IsNormal => {
+                self.check_arithmetic(&[argument], false)?;
+                self.component_wise(&[argument], Some(Sk::Bool), &mut |args, _| match *args[0] {
+                    Ci::Float(value) => Ok(Ci::Bool(match fun {
+                        If::IsNan => value.is_nan(),
+                        If::IsInf => value.is_infinite(),
+                        If::IsFinite => value.is_finite(),
+                        _ => value.is_normal(),
+                    })),
+                    _ => Err(ConstantSolvingError::InvalidIntrinsicArgument(fun)),
+                })
+            }
+        }
+    }
+
+    fn float_components(&self, handle: Handle<crate::Constant>) -> Result<Vec<f64>, ()> {
+        let components = match self.constants[handle].inner {
+            Ci::Composite(ref components) => components.clone(),
+            _ => vec![handle],
+        };
+        components
+            .into_iter()
+            .map(|component| match self.value(component) {
+                Ok(&Ci::Float(value)) => Ok(value),
+                _ => Err(()),
+            })
+            .collect()
+    }
+
+    fn dot_product(
+        &mut self,
+        left: Handle<crate::Constant>,
+        right: Handle<crate::Constant>,
+    ) -> Result<Handle<crate::Constant>, ()> {
+        let a = self.float_components(left)?;
+        let b = self.float_components(right)?;
+        if a.len() != b.len() {
+            return Err(());
+        }
+        let width = self.scalar_width(left);
+        let value = a.iter().zip(b.iter()).map(|(a, b)| a * b).sum();
+        let ty = self.scalar_type(Sk::Float, width);
+        Ok(self.append(normalize(Ci::Float(value), width), ty))
+    }
+
+    fn cross_product(
+        &mut self,
+        left: Handle<crate::Constant>,
+        right: Handle<crate::Constant>,
+    ) -> Result<Handle<crate::Constant>, ()> {
+        let a = self.float_components(left)?;
+        let b = self.float_components(right)?;
+        if a.len() != 3 || b.len() != 3 {
+            return Err(());
+        }
+        let width = self.scalar_width(left);
+        let scalar_ty = self.scalar_type(Sk::Float, width);
+        let components = [
+            a[1] * b[2] - a[2] * b[1],
+            a[2] * b[0] - a[0] * b[2],
+            a[0] * b[1] - a[1] * b[0],
+        ]
+        .iter()
+        .map(|&value| self.append(normalize(Ci::Float(value), width), scalar_ty))
+        .collect();
+        let ty = self.constants[left].ty;
+        Ok(self.append(Ci::Composite(components), ty))
+    }
+
+    /// Evaluates a call to a standard math function.
+    ///
+    /// Returns `None` if the function is not known to the solver.
+    fn call(
+        &mut self,
+        name: &str,
+        arguments: &[Handle<crate::Constant>],
+    ) -> Result<Option<Handle<crate::Constant>>, ConstantSolvingError> {
+        let invalid = || ConstantSolvingError::InvalidFunctionArguments(name.to_string());
+        if let Some(fun) = float_function(name) {
+            if arguments.len() != 1 {
+                return Err(invalid());
+            }
+            self.check_arithmetic(arguments, false)?;
+            return self
+                .component_wise(arguments, None, &mut |args, _| match *args[0] {
+                    Ci::Float(value) => Ok(Ci::Float(fun(value))),
+                    Ci::Sint(value) if name == "abs" => Ok(Ci::Sint(value.wrapping_abs())),
+                    Ci::Sint(value) if name == "sign" => Ok(Ci::Sint(value.signum())),
+                    Ci::Uint(value) if name == "abs" => Ok(Ci::Uint(value)),
+                    _ => Err(invalid()),
+                })
+                .map(Some);
+        }
+
+        let result = match (name, arguments.len()) {
+            ("min", 2) | ("max", 2) | ("clamp", 3) => {
+                self.check_arithmetic(arguments, false)?;
+                self.component_wise(arguments, None, &mut |args, _| {
+                    let (value, low, high) = match name {
+                        "min" => (args[0], None, Some(args[1])),
+                        "max" => (args[0], Some(args[1]), None),
+                        _ => (args[0], Some(args[1]), Some(args[2])),
+                    };
+                    let mut result = value.clone();
+                    if let Some(low) = low {
+                        if compare(Bo::Less, &result, low).ok_or_else(invalid)? {
+                            result = low.clone();
+                        }
+                    }
+                    if let Some(high) = high {
+                        if compare(Bo::Greater, &result, high).ok_or_else(invalid)? {
+                            result = high.clone();
+                        }
+                    }
+                    Ok(result)
+                })?
+            }
+            ("pow", 2) | ("atan2", 2) | ("atan", 2) | ("step", 2) | ("mix", 3) => {
+                self.check_arithmetic(arguments, false)?;
+                self.component_wise(arguments, None, &mut |args, _| {
+                    let floats = args
+                        .iter()
+                        .map(|arg| match **arg {
+                            Ci::Float(value) => Ok(value),
+                            _ => Err(invalid()),
+                        })
+                        .collect::<Result<Vec<_>, _>>()?;
+                    Ok(Ci::Float(match name {
+                        "pow" => floats[0].powf(floats[1]),
+                        "step" => {
+                            if floats[1] < floats[0] {
+                                0.0
+                            } else {
+                                1.0
+                            }
+                        }
+                        "mix" => floats[0] * (1.0 - floats[2]) + floats[1] * floats[2],
+                        _ => floats[0].atan2(floats[1]),
+                    }))
+                })?
+            }
+            ("length", 1) => {
+                let argument = arguments[0];
+                let squared = self
+                    .dot_product(argument, argument)
+                    .map_err(|_| invalid())?;
+                self.component_wise(&[squared], None, &mut |args, _| match *args[0] {
+                    Ci::Float(value) => Ok(Ci::Float(value.sqrt())),
+                    _ => Err(invalid()),
+                })?
+            }
+            ("dot", 2) => self
+                .dot_product(arguments[0], arguments[1])
+                .map_err(|_| invalid())?,
+            ("cross", 2) => self
+                .cross_product(arguments[0], arguments[1])
+                .map_err(|_| invalid())?,
+            _ => return Ok(None),
+        };
+        Ok(Some(result))
+    }
+}
+
+/// Brings a scalar value into the range of its type.
+fn normalize(value: Ci, width: crate::Bytes) -> Ci {
+    let bits = u32::from(width) * 8;
+    match value {
+        Ci::Sint(value) if bits < 64 => {
+            let shift = 64 - bits;
+            Ci::Sint((value << shift) >> shift)
+        }
+        Ci::Uint(value) if bits < 64 => Ci::Uint(value & ((1 << bits) - 1)),
+        Ci::Float(value) if width == 4 => Ci::Float(f64::from(value as f32)),
+        other => other,
+    }
+}
+
+fn compare(op: crate::BinaryOperator, left: &Ci, right: &Ci) -> Option<bool> {
+    use std::cmp::Ordering as O;
+    let ordering = match (left, right) {
+        (&Ci::Sint(a), &Ci::Sint(b)) => a.partial_cmp(&b),
+        (&Ci::Uint(a), &Ci::Uint(b)) => a.partial_cmp(&b),
+        (&Ci::Float(a), &Ci::Float(b)) => a.partial_cmp(&b),
+        (&Ci::Bool(a), &Ci::Bool(b)) if op == Bo::Equal || op == Bo::NotEqual => a.partial_cmp(&b),
+        _ => return None,
+    };
+    // comparisons involving NaN are false, except for `NotEqual`
+    Some(match op {
+        Bo::Equal => ordering == Some(O::Equal),
+        Bo::NotEqual => ordering != Some(O::Equal),
+        Bo::Less => ordering == Some(O::Less),
+        Bo::LessEqual => ordering == Some(O::Less) || ordering == Some(O::Equal),
+        Bo::Greater => ordering == Some(O::Greater),
+        Bo::GreaterEqual => ordering == Some(O::Greater) || ordering == Some(O::Equal),
+        _ => return None,
+    })
+}
+
+fn unary_op(
+    op: crate::UnaryOperator,
+    value: &Ci,
+    width: crate::Bytes,
+) -> Result<Ci, ConstantSolvingError> {
+    Ok(match (op, value) {
+        (Uo::Negate, &Ci::Sint(value)) => Ci::Sint(value.wrapping_neg()),
+        (Uo::Negate, &Ci::Float(value)) => Ci::Float(-value),
+        (Uo::Not, &Ci::Bool(value)) => Ci::Bool(!value),
+        (Uo::Not, &Ci::Sint(value)) => Ci::Sint(!value),
+        (Uo::Not, &Ci::Uint(value)) => normalize(Ci::Uint(!value), width),
+        _ => return Err(ConstantSolvingError::InvalidUnaryOperand(op)),
+    })
+}
+
+fn binary_op(
+    op: crate::BinaryOperator,
+    left: &Ci,
+    right: &Ci,
+    width: crate::Bytes,
+) -> Result<Ci, ConstantSolvingError> {
+    let invalid = ConstantSolvingError::InvalidBinaryOperands(op);
+    if let Some(result) = compare(op, left, right) {
+        return Ok(Ci::Bool(result));
+    }
+
+    if let Bo::ShiftLeftLogical | Bo::ShiftRightLogical | Bo::ShiftRightArithmetic = op {
+        let amount = match *right {
+            Ci::Uint(value) => value as u32,
+            Ci::Sint(value) => value as u32,
+            _ => return Err(invalid),
+        };
+        let mask = match width {
+            8 => !0,
+            _ => (1u64 << (u32::from(width) * 8)) - 1,
+        };
+        return Ok(match (left, op) {
+            (&Ci::Sint(value), Bo::ShiftLeftLogical) => Ci::Sint(value.wrapping_shl(amount)),
+            (&Ci::Sint(value), Bo::ShiftRightArithmetic) => Ci::Sint(value.wrapping_shr(amount)),
+            (&Ci::Sint(value), _) => Ci::Sint(((value as u64 & mask).wrapping_shr(amount)) as i64),
+            (&Ci::Uint(value), Bo::ShiftLeftLogical) => Ci::Uint(value.wrapping_shl(amount)),
+            (&Ci::Uint(value), _) => Ci::Uint(value.wrapping_shr(amount)),
+            _ => return Err(invalid),
+        });
+    }
+
+    Ok(match (left, right) {
+        (&Ci::Sint(a), &Ci::Sint(b)) => Ci::Sint(match op {
+            Bo::Add => a.wrapping_add(b),
+            Bo::Subtract => a.wrapping_sub(b),
+            Bo::Multiply => a.wrapping_mul(b),
+            Bo::Divide if b == 0 => return Err(ConstantSolvingError::DivisionByZero),
+            Bo::Divide => a.wrapping_div(b),
+            Bo::Modulo if b == 0 => return Err(ConstantSolvingError::DivisionByZero),
+            Bo::Modulo => a.wrapping_rem(b),
+            Bo::And => a & b,
+            Bo::ExclusiveOr => a ^ b,
+            Bo::InclusiveOr => a | b,
+            _ => return Err(invalid),
+        }),
+        (&Ci::Uint(a), &Ci::Uint(b)) => Ci::Uint(match op {
+            Bo::Add => a.wrapping_add(b),
+            Bo::Subtract => a.wrapping_sub(b),
+            Bo::Multiply => a.wrapping_mul(b),
+            Bo::Divide => a
+                .checked_div(b)
+                .ok_or(ConstantSolvingError::DivisionByZero)?,
+            Bo::Modulo => a
+                .checked_rem(b)
+                .ok_or(ConstantSolvingError::DivisionByZero)?,
+            Bo::And => a & b,
+            Bo::ExclusiveOr => a ^ b,
+            Bo::InclusiveOr => a | b,
+            _ => return Err(invalid),
+        }),
+        (&Ci::Float(a), &Ci::Float(b)) => Ci::Float(match op {
+            Bo::Add => a + b,
+            Bo::Subtract => a - b,
+            Bo::Multiply => a * b,
+            Bo::Divide => a / b,
+            Bo::Modulo => a % b,
+            _ => return Err(invalid),
+        }),
+        (&Ci::Bool(a), &Ci::Bool(b)) => Ci::Bool(match op {
+            Bo::LogicalAnd | Bo::And => a && b,
+            Bo::LogicalOr | Bo::InclusiveOr => a || b,
+            Bo::ExclusiveOr => a != b,
+            _ => return Err(invalid),
+        }),
+        _ => return Err(invalid),
+    })
+}
+
+fn float_function(name: &str) -> Option<fn(f64) -> f64> {
+    Some(match name {
+        "abs" => f64::abs,
+        "sign" => |value: f64| {
+            if value > 0.0 {
+                1.0
+            } else if value < 0.0 {
+                -1.0
+            } else {
+                value
+            }
+        },
+        "floor" => f64::floor,
+        "ceil" => f64::ceil,
+        "round" => |value: f64| {
+            // halfway cases are rounded to even
+            if (value - value.trunc()).abs() == 0.5 {
+                2.0 * (value / 2.0).round()
+            } else {
+                value.round()
+            }
+        },
+        "trunc" => f64::trunc,
+        "fract" => |value: f64| value - value.floor(),
+        "sqrt" => f64::sqrt,
+        "inversesqrt" => |value: f64| 1.0 / value.sqrt(),
+        "exp" => f64::exp,
+        "exp2" => f64::exp2,
+        "log" => f64::ln,
+        "log2" => f64::log2,
+        "sin" => f64::sin,
+        "cos" => f64::cos,
+        "tan" => f64::tan,
+        "asin" => f64::asin,
+        "acos" => f64::acos,
+        "atan" => f64::atan,
+        "sinh" => f64::sinh,
+        "cosh" => f64::cosh,
+        "tanh" => f64::tanh,
+        "radians" => f64::to_radians,
+        "degrees" => f64::to_degrees,
+        _ => return None,
+    })
+}
+
+/// Replaces the expressions of all functions that can be evaluated
+/// at compile time with `Expression::Constant`.
+pub fn fold_constants(module: &mut crate::Module) {
+    let functions = module
+        .functions
+        .iter()
+        .map(|(handle, _)| handle)
+        .collect::<Vec<_>>();
+    for function_handle in functions {
+        let function = module.functions.get_mut(function_handle);
+        let expressions = function
+            .expressions
+            .iter()
+            .map(|(handle, _)| handle)
+            .collect::<Vec<_>>();
+        for expr in expressions {
+            if let crate::Expression::Constant(_) = function.expressions[expr] {
+                continue;
+            }
+            let mut solver = ConstantSolver {
+                types: &mut module.types,
+                expressions: &function.expressions,
+                constants: &mut module.constants,
+            };
+            if let Ok(constant) = solver.solve(expr) {
+                *function.expressions.get_mut(expr) = crate::Expression::Constant(constant);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fold_constants, ConstantSolver, ConstantSolvingError};
+    use crate::{builder::ModuleBuilder, ConstantInner, Expression};
+
+    /// Solve an expression of a function of the module.
+    fn solve(
+        module: &mut crate::Module,
+        function: crate::Handle<crate::Function>,
+        expr: crate::Handle<Expression>,
+    ) -> Result<crate::Handle<crate::Constant>, ConstantSolvingError> {
+        ConstantSolver {
+            types: &mut module.types,
+            expressions: &module.functions[function].expressions,
+            constants: &mut module.constants,
+        }
+        .solve(expr)
+    }
+
+    fn components(
+        module: &crate::Module,
+        handle: crate::Handle<crate::Constant>,
+    ) -> Vec<ConstantInner> {
+        match module.constants[handle].inner {
+            ConstantInner::Composite(ref components) => components
+                .iter()
+                .map(|&component| module.constants[component].inner.clone())
+                .collect(),
+            ref other => vec![other.clone()],
+        }
+    }
+
+    #[test]
+    fn vector_scalar_product() {
+        let mut builder = ModuleBuilder::new();
+        let (one, two) = (builder.float(1.0), builder.float(2.0));
+        let mut fun = builder.function("main", &[], None);
+        let one = fun.constant(one);
+        let two = fun.constant(two);
+        let vector = fun.compose_vector(vec![one, two]).unwrap();
+        let product = fun.binary(crate::BinaryOperator::Multiply, vector, two);
+        let less = fun.binary(crate::BinaryOperator::Less, product, vector);
+        let function = fun.finish();
+        let mut module = builder.finish();
+
+        let product = solve(&mut module, function, product).unwrap();
+        let less = solve(&mut module, function, less).unwrap();
+        assert_eq!(
+            components(&module, product),
+            vec![ConstantInner::Float(2.0), ConstantInner::Float(4.0)]
+        );
+        assert_eq!(
+            components(&module, less),
+            vec![ConstantInner::Bool(false), ConstantInner::Bool(false)]
+        );
+        match module.types[module.constants[less].ty].inner {
+            crate::TypeInner::Vector {
+                kind: crate::ScalarKind::Bool,
+                ..
+            } => {}
+            ref other => panic!("Unexpected type {:?}", other),
+        }
+    }
+
+    #[test]
+    fn specialization_is_not_folded() {
+        let mut builder = ModuleBuilder::new();
+        let float = builder.scalar(crate::ScalarKind::Float, 4);
+        let spec = builder.named_constant("spec", Some(0), ConstantInner::Float(1.0), float);
+        let two = builder.float(2.0);
+        let mut fun = builder.function("main", &[], None);
+        let spec = fun.constant(spec);
+        let two = fun.constant(two);
+        let sum = fun.binary(crate::BinaryOperator::Add, spec, two);
+        let function = fun.finish();
+        let mut module = builder.finish();
+
+        match solve(&mut module, function, sum) {
+            Err(ConstantSolvingError::Specialization(_)) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn specialized_composite_is_not_folded() {
+        let mut builder = ModuleBuilder::new();
+        let float = builder.scalar(crate::ScalarKind::Float, 4);
+        let array = builder.ty(crate::TypeInner::Array {
+            base: float,
+            size: crate::ArraySize::Static(2),
+            stride: None,
+        });
+        let spec = builder.named_constant("spec", Some(0), ConstantInner::Float(1.0), float);
+        let one = builder.float(1.0);
+        let mut fun = builder.function("main", &[], None);
+        let spec = fun.constant(spec);
+        let one = fun.constant(one);
+        let composite = fun.compose(array, vec![spec, one]);
+        let function = fun.finish();
+        let mut module = builder.finish();
+
+        match solve(&mut module, function, composite) {
+            Err(ConstantSolvingError::Specialization(_)) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+        fold_constants(&mut module);
+        match module.functions[function].expressions[composite] {
+            Expression::Compose { .. } => {}
+            ref other => panic!("Unexpected expression {:?}", other),
+        }
+    }
+
+    #[test]
+    fn fold_function() {
+        let mut builder = ModuleBuilder::new();
+        let float = builder.scalar(crate::ScalarKind::Float, 4);
+        let four = builder.float(4.0);
+        let mut fun = builder.function("scale", &[("x", float)], Some(float));
+        let four = fun.constant(four);
+        let root = fun.call_external("sqrt", vec![four]);
+        let param = fun.argument(0);
+        let scaled = fun.binary(crate::BinaryOperator::Multiply, param, root);
+        fun.ret(Some(scaled));
+        let function = fun.finish();
+        let mut module = builder.finish();
+        fold_constants(&mut module);
+
+        let expressions = &module.functions[function].expressions;
+        match expressions[root] {
+            Expression::Constant(handle) => {
+                assert_eq!(module.constants[handle].inner, ConstantInner::Float(2.0))
+            }
+            ref other => panic!("Unexpected expression {:?}", other),
+        }
+        match expressions[scaled] {
+            Expression::Binary { .. } => {}
+            ref other => panic!("Unexpected expression {:?}", other),
+        }
+    }
+}
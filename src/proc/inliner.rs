@@ -0,0 +1,788 @@
+use super::{CallGraph, Typifier};
+use crate::{
+    arena::{Arena, Handle},
+    FastHashMap,
+};
+
+use thiserror::Error;
+
+#[derive(Clone, Debug, Error)]
+pub enum InliningError {
+    #[error("Function {0:?} is recursive and can't be inlined")]
+    Recursion(Handle<crate::Function>),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Visit {
+    Pending,
+    Active,
+    Done,
+}
+
+fn remap_expression(
+    expression: &mut crate::Expression,
+    expressions: &[Handle<crate::Expression>],
+    locals: &[Handle<crate::LocalVariable>],
+) {
+    if let crate::Expression::LocalVariable(ref mut var) = *expression {
+        *var = locals[var.index()];
+    }
+    expression.for_each_operand_mut(|handle| *handle = expressions[handle.index()]);
+}
+
+fn remap_block(block: &mut [crate::Statement], expressions: &[Handle<crate::Expression>]) {
+    for statement in block {
+        statement.for_each_expression_mut(|handle| *handle = expressions[handle.index()]);
+        statement.for_each_block_mut(|block| remap_block(block, expressions));
+    }
+}
+
+fn contains_return(block: &[crate::Statement]) -> bool {
+    block.iter().any(|statement| match *statement {
+        crate::Statement::Return { .. } => true,
+        _ => {
+            let mut found = false;
+            statement.for_each_block(|block| found |= contains_return(block));
+            found
+        }
+    })
+}
+
+/// Checks if a `Return` can be reached anywhere but at the very end of the block.
+fn has_early_return(block: &[crate::Statement], tail: bool) -> bool {
+    use crate::Statement as S;
+    let count = block.len();
+    block.iter().enumerate().any(|(i, statement)| {
+        let is_tail = tail && i + 1 == count;
+        match *statement {
+            S::Return { .. } => !is_tail,
+            S::Block(ref block) => has_early_return(block, is_tail),
+            S::If {
+                ref accept,
+                ref reject,
+                ..
+            } => has_early_return(accept, is_tail) || has_early_return(reject, is_tail),
+            S::Switch { .. } | S::Loop { .. } => contains_return(std::slice::from_ref(statement)),
+            _ => false,
+        }
+    })
+}
+
+/// Checks if a `Return` is nested in a construct that `Break` would exit.
+fn has_nested_return(block: &[crate::Statement]) -> bool {
+    use crate::Statement as S;
+    block.iter().any(|statement| match *statement {
+        S::Block(ref block) => has_nested_return(block),
+        S::If {
+            ref accept,
+            ref reject,
+            ..
+        } => has_nested_return(accept) || has_nested_return(reject),
+        S::Switch { .. } | S::Loop { .. } => contains_return(std::slice::from_ref(statement)),
+        _ => false,
+    })
+}
+
+/// Replaces the `Return` statements of an inlined body with stores to the
+/// result variable, followed by breaks out of the loop wrapping the body.
+struct ReturnLowering {
+    result: Option<Handle<crate::Expression>>,
+    /// The variable telling that the function returned from a nested
+    /// construct, and the `true` expression to store into it.
+    flag: Option<(Handle<crate::Expression>, Handle<crate::Expression>)>,
+}
+
+impl ReturnLowering {
+    fn lower_block(&self, block: crate::Block, tail: bool, depth: u32) -> crate::Block {
+        use crate::Statement as S;
+        let count = block.len();
+        let mut lowered = Vec::with_capacity(count);
+        for (i, statement) in block.into_iter().enumerate() {
+            let is_tail = tail && i + 1 == count;
+            match statement {
+                S::Return { value } => {
+                    if let (Some(pointer), Some(value)) = (self.result, value) {
+                        lowered.push(S::Store { pointer, value });
+                    }
+                    if !is_tail {
+                        if depth != 0 {
+                            let (pointer, value) = self.flag.unwrap();
+                            lowered.push(S::Store { pointer, value });
+                        }
+                        lowered.push(S::Break);
+                    }
+                }
+                S::Block(block) => lowered.push(S::Block(self.lower_block(block, is_tail, depth))),
+                S::If {
+                    condition,
+                    accept,
+                    reject,
+                } => lowered.push(S::If {
+                    condition,
+                    accept: self.lower_block(accept, is_tail, depth),
+                    reject: self.lower_block(reject, is_tail, depth),
+                }),
+                S::Switch {
+                    selector,
+                    cases,
+                    default,
+                } => {
+                    let returns = cases.values().any(|(case, _)| contains_return(case))
+                        || contains_return(&default);
+                    lowered.push(S::Switch {
+                        selector,
+                        cases: cases
+                            .into_iter()
+                            .map(|(value, (case, fall_through))| {
+                                (
+                                    value,
+                                    (self.lower_block(case, false, depth + 1), fall_through),
+                                )
+                            })
+                            .collect(),
+                        default: self.lower_block(default, false, depth + 1),
+                    });
+                    if returns {
+                        lowered.push(self.flag_check());
+                    }
+                }
+                S::Loop { body, continuing } => {
+                    let returns = contains_return(&body) || contains_return(&continuing);
+                    lowered.push(S::Loop {
+                        body: self.lower_block(body, false, depth + 1),
+                        continuing: self.lower_block(continuing, false, depth + 1),
+                    });
+                    if returns {
+                        lowered.push(self.flag_check());
+                    }
+                }
+                other => lowered.push(other),
+            }
+        }
+        lowered
+    }
+
+    fn flag_check(&self) -> crate::Statement {
+        let (flag, _) = self.flag.unwrap();
+        crate::Statement::If {
+            condition: flag,
+            accept: vec![crate::Statement::Break],
+            reject: Vec::new(),
+        }
+    }
+}
+
+struct Expander<'a, F> {
+    types: &'a mut Arena<crate::Type>,
+    constants: &'a mut Arena<crate::Constant>,
+    functions: &'a Arena<crate::Function>,
+    expressions: &'a mut Arena<crate::Expression>,
+    local_variables: &'a mut Arena<crate::LocalVariable>,
+    should_inline: &'a F,
+    /// Local variables holding the results of the inlined calls.
+    results: FastHashMap<Handle<crate::Expression>, Handle<crate::LocalVariable>>,
+    /// Calls whose results are already computed by the enclosing blocks.
+    available: Vec<Handle<crate::Expression>>,
+    inlined: Vec<Handle<crate::Function>>,
+}
+
+impl<F: Fn(Handle<crate::Function>) -> bool> Expander<'_, F> {
+    fn inlined_callee(&self, expr: Handle<crate::Expression>) -> Option<Handle<crate::Function>> {
+        match self.expressions[expr] {
+            // functions returning nothing are only called by statements,
+            // the validator rejects the other calls
+            crate::Expression::Call {
+                origin: crate::FunctionOrigin::Local(function),
+                ..
+            } if (self.should_inline)(function)
+                && self.functions[function].return_type.is_some() =>
+            {
+                Some(function)
+            }
+            _ => None,
+        }
+    }
+
+    /// Collects the calls to inline, in the order they are evaluated.
+    fn collect_calls(
+        &self,
+        handle: Handle<crate::Expression>,
+        calls: &mut Vec<Handle<crate::Expression>>,
+    ) {
+        self.expressions[handle].for_each_operand(|operand| self.collect_calls(operand, calls));
+        if self.inlined_callee(handle).is_some() && !calls.contains(&handle) {
+            calls.push(handle);
+        }
+    }
+
+    fn bool_expression(&mut self, value: bool) -> Handle<crate::Expression> {
+        let ty = Typifier::deduce_type_handle(
+            crate::TypeInner::Scalar {
+                kind: crate::ScalarKind::Bool,
+                width: 1,
+            },
+            self.types,
+        );
+        let constant = self.constants.fetch_or_append(crate::Constant {
+            name: None,
+            specialization: None,
+            inner: crate::ConstantInner::Bool(value),
+            ty,
+        });
+        self.expressions
+            .append(crate::Expression::Constant(constant))
+    }
+
+    /// Produces the statements computing the result of a call expression.
+    fn inline_call(&mut self, call: Handle<crate::Expression>) -> crate::Block {
+        let function = self.inlined_callee(call).unwrap();
+        let arguments = match self.expressions[call] {
+            crate::Expression::Call { ref arguments, .. } => arguments.clone(),
+            _ => unreachable!(),
+        };
+        let local_variables = &mut *self.local_variables;
+        let return_type = self.functions[function].return_type;
+        let result = *self.results.entry(call).or_insert_with(|| {
+            local_variables.append(crate::LocalVariable {
+                name: None,
+                ty: return_type.unwrap(),
+                init: None,
+            })
+        });
+        self.inline_body(function, arguments, Some(result))
+    }
+
+    /// Produces the statements executing the body of a function,
+    /// storing its return value into `result`.
+    fn inline_body(
+        &mut self,
+        function: Handle<crate::Function>,
+        arguments: Vec<Handle<crate::Expression>>,
+        result: Option<Handle<crate::LocalVariable>>,
+    ) -> crate::Block {
+        let callee = &self.functions[function];
+        if !self.inlined.contains(&function) {
+            self.inlined.push(function);
+        }
+        let mut statements = Vec::new();
+
+        // arguments passed by value are copied, so that they are evaluated once
+        let mut parameters = Vec::with_capacity(arguments.len());
+        for (argument, value) in callee.arguments.iter().zip(arguments) {
+            if let crate::TypeInner::Pointer { .. } = self.types[argument.ty].inner {
+                parameters.push(value);
+            } else {
+                let local = self.local_variables.append(crate::LocalVariable {
+                    name: argument.name.clone(),
+                    ty: argument.ty,
+                    init: None,
+                });
+                let pointer = self
+                    .expressions
+                    .append(crate::Expression::LocalVariable(local));
+                statements.push(crate::Statement::Store { pointer, value });
+                parameters.push(pointer);
+            }
+        }
+
+        let locals = callee
+            .local_variables
+            .iter()
+            .map(|(_, local)| {
+                self.local_variables.append(crate::LocalVariable {
+                    name: local.name.clone(),
+                    ty: local.ty,
+                    init: None,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut expressions = Vec::with_capacity(callee.expressions.len());
+        let mut copied = Vec::new();
+        for (_, expression) in callee.expressions.iter() {
+            expressions.push(match *expression {
+                crate::Expression::FunctionParameter(index) => parameters[index as usize],
+                ref other => {
+                    let handle = self.expressions.append(other.clone());
+                    copied.push(handle);
+                    handle
+                }
+            });
+        }
+        for handle in copied {
+            remap_expression(self.expressions.get_mut(handle), &expressions, &locals);
+        }
+
+        // local variables are initialized every time the body is executed
+        for ((_, local), &new_local) in callee.local_variables.iter().zip(locals.iter()) {
+            if let Some(init) = local.init {
+                let pointer = self
+                    .expressions
+                    .append(crate::Expression::LocalVariable(new_local));
+                statements.push(crate::Statement::Store {
+                    pointer,
+                    value: expressions[init.index()],
+                });
+            }
+        }
+
+        let mut body = callee.body.clone();
+        remap_block(&mut body, &expressions);
+        let result_pointer = result.map(|result| {
+            self.expressions
+                .append(crate::Expression::LocalVariable(result))
+        });
+
+        if has_early_return(&body, true) {
+            let flag = if has_nested_return(&body) {
+                let flag_local = self.local_variables.append(crate::LocalVariable {
+                    name: None,
+                    ty: Typifier::deduce_type_handle(
+                        crate::TypeInner::Scalar {
+                            kind: crate::ScalarKind::Bool,
+                            width: 1,
+                        },
+                        self.types,
+                    ),
+                    init: None,
+                });
+                let pointer = self
+                    .expressions
+                    .append(crate::Expression::LocalVariable(flag_local));
+                let value = self.bool_expression(false);
+                statements.push(crate::Statement::Store { pointer, value });
+                Some((pointer, self.bool_expression(true)))
+            } else {
+                None
+            };
+            let lowering = ReturnLowering {
+                result: result_pointer,
+                flag,
+            };
+            let mut lowered = lowering.lower_block(body, true, 0);
+            lowered.push(crate::Statement::Break);
+            statements.push(crate::Statement::Loop {
+                body: lowered,
+                continuing: Vec::new(),
+            });
+        } else {
+            let lowering = ReturnLowering {
+                result: result_pointer,
+                flag: None,
+            };
+            statements.extend(lowering.lower_block(body, true, 0));
+        }
+
+        statements
+    }
+
+    /// Produces the statements computing the results of the given calls,
+    /// skipping the ones already computed.
+    fn inline_calls(&mut self, calls: Vec<Handle<crate::Expression>>) -> crate::Block {
+        let mut statements = Vec::new();
+        for call in calls {
+            if !self.available.contains(&call) {
+                statements.extend(self.inline_call(call));
+                self.available.push(call);
+            }
+        }
+        statements
+    }
+
+    /// Inlines the calls made by each statement right before it.
+    ///
+    /// The results computed by a statement are reused by the following ones,
+    /// including the statements of the nested blocks.
+    fn expand_block(&mut self, block: crate::Block) -> crate::Block {
+        use crate::Statement as S;
+        let scope = self.available.len();
+        let mut expanded = Vec::with_capacity(block.len());
+        for statement in block {
+            let mut calls = Vec::new();
+            let statement = match statement {
+                S::Block(block) => S::Block(self.expand_block(block)),
+                S::If {
+                    condition,
+                    accept,
+                    reject,
+                } => {
+                    self.collect_calls(condition, &mut calls);
+                    S::If {
+                        condition,
+                        accept: self.expand_block(accept),
+                        reject: self.expand_block(reject),
+                    }
+                }
+                S::Switch {
+                    selector,
+                    cases,
+                    default,
+                } => {
+                    self.collect_calls(selector, &mut calls);
+                    S::Switch {
+                        selector,
+                        cases: cases
+                            .into_iter()
+                            .map(|(value, (case, fall_through))| {
+                                (value, (self.expand_block(case), fall_through))
+                            })
+                            .collect(),
+                        default: self.expand_block(default),
+                    }
+                }
+                S::Loop { body, continuing } => S::Loop {
+                    body: self.expand_block(body),
+                    continuing: self.expand_block(continuing),
+                },
+                S::Return { value } => {
+                    if let Some(value) = value {
+                        self.collect_calls(value, &mut calls);
+                    }
+                    S::Return { value }
+                }
+                S::Store { pointer, value } => {
+                    self.collect_calls(pointer, &mut calls);
+                    self.collect_calls(value, &mut calls);
+                    S::Store { pointer, value }
+                }
+                S::Call {
+                    function,
+                    arguments,
+                } => {
+                    for &argument in arguments.iter() {
+                        self.collect_calls(argument, &mut calls);
+                    }
+                    if (self.should_inline)(function) {
+                        let statements = self.inline_calls(calls);
+                        expanded.extend(statements);
+                        let statements = self.inline_body(function, arguments, None);
+                        expanded.extend(statements);
+                        continue;
+                    }
+                    S::Call {
+                        function,
+                        arguments,
+                    }
+                }
+                other => other,
+            };
+            let statements = self.inline_calls(calls);
+            expanded.extend(statements);
+            expanded.push(statement);
+        }
+        self.available.truncate(scope);
+        expanded
+    }
+}
+
+fn inline_into<F: Fn(Handle<crate::Function>) -> bool>(
+    module: &mut crate::Module,
+    caller: Handle<crate::Function>,
+    should_inline: &F,
+    call_graph: &CallGraph,
+    visits: &mut [Visit],
+) -> Result<(), InliningError> {
+    match visits[caller.index()] {
+        Visit::Pending => {}
+        Visit::Active => return Err(InliningError::Recursion(caller)),
+        Visit::Done => return Ok(()),
+    }
+    visits[caller.index()] = Visit::Active;
+
+    // the callees have to be free of inlinable calls first
+    for &callee in call_graph.callees(caller) {
+        if should_inline(callee) {
+            inline_into(module, callee, should_inline, call_graph, visits)?;
+        }
+    }
+
+    let fun = module.functions.get_mut(caller);
+    let body = std::mem::take(&mut fun.body);
+    let mut expressions = std::mem::take(&mut fun.expressions);
+    let mut local_variables = std::mem::take(&mut fun.local_variables);
+
+    let mut expander = Expander {
+        types: &mut module.types,
+        constants: &mut module.constants,
+        functions: &module.functions,
+        expressions: &mut expressions,
+        local_variables: &mut local_variables,
+        should_inline,
+        results: FastHashMap::default(),
+        available: Vec::new(),
+        inlined: Vec::new(),
+    };
+    // initializers of local variables are evaluated on entry, the ones
+    // using inlined calls become stores following the inlined bodies
+    let mut prologue = Vec::new();
+    let inits = expander
+        .local_variables
+        .iter()
+        .filter_map(|(handle, local)| local.init.map(|init| (handle, init)))
+        .collect::<Vec<_>>();
+    for (local, init) in inits {
+        let mut calls = Vec::new();
+        expander.collect_calls(init, &mut calls);
+        if calls.is_empty() {
+            continue;
+        }
+        prologue.extend(expander.inline_calls(calls));
+        let pointer = expander
+            .expressions
+            .append(crate::Expression::LocalVariable(local));
+        prologue.push(crate::Statement::Store {
+            pointer,
+            value: init,
+        });
+        expander.local_variables.get_mut(local).init = None;
+    }
+    prologue.extend(expander.expand_block(body));
+
+    let results = std::mem::take(&mut expander.results);
+    let inlined = std::mem::take(&mut expander.inlined);
+    for (call, result) in results {
+        *expressions.get_mut(call) = crate::Expression::LocalVariable(result);
+    }
+
+    let mut global_usage = module.functions[caller].global_usage.clone();
+    for callee in inlined {
+        for (usage, &callee_usage) in global_usage
+            .iter_mut()
+            .zip(module.functions[callee].global_usage.iter())
+        {
+            *usage |= callee_usage;
+        }
+    }
+
+    let fun = module.functions.get_mut(caller);
+    fun.body = prologue;
+    fun.expressions = expressions;
+    fun.local_variables = local_variables;
+    fun.global_usage = global_usage;
+    visits[caller.index()] = Visit::Done;
+    Ok(())
+}
+
+/// Inlines the calls to the selected local functions into their callers.
+///
+/// Each statement using the result of a call gets a copy of the callee body
+/// before it, writing the result into a new local variable, which is reused
+/// by the following statements. Call statements are replaced by the callee
+/// body. The inlined functions stay in the module, and can be removed with
+/// [`compact`](super::compact).
+pub fn inline_functions(
+    module: &mut crate::Module,
+    should_inline: impl Fn(Handle<crate::Function>) -> bool,
+) -> Result<(), InliningError> {
+    let mut visits = vec![Visit::Pending; module.functions.len()];
+    let call_graph = CallGraph::new(&module.functions);
+    let handles = module
+        .functions
+        .iter()
+        .map(|(handle, _)| handle)
+        .collect::<Vec<_>>();
+    for handle in handles {
+        inline_into(module, handle, &should_inline, &call_graph, &mut visits)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::inline_functions;
+    use crate::{
+        builder::ModuleBuilder, BinaryOperator, Expression, ScalarKind, Statement, StorageClass,
+    };
+
+    #[test]
+    fn nested_return() {
+        let mut builder = ModuleBuilder::new();
+        let float = builder.scalar(ScalarKind::Float, 4);
+        let zero = builder.float(0.0);
+        let output = builder.global_variable("output", StorageClass::Private, None, float);
+
+        // fn positive(x: f32) -> f32 {
+        //     loop { if x < 0.0 { return 0.0; } break; }
+        //     return x;
+        // }
+        let positive = {
+            let mut fun = builder.function("positive", &[("x", float)], Some(float));
+            let x = fun.argument(0);
+            let zero = fun.constant(zero);
+            let negative = fun.binary(BinaryOperator::Less, x, zero);
+            fun.loop_(
+                |fun| {
+                    fun.if_else(negative, |fun| fun.ret(Some(zero)), |_| {});
+                    fun.statement(Statement::Break);
+                },
+                |_| {},
+            );
+            fun.ret(Some(x));
+            fun.finish()
+        };
+
+        // fn main() { output = positive(output); }
+        let main = {
+            let mut fun = builder.function("main", &[], None);
+            let pointer = fun.global(output);
+            let call = fun.call(positive, vec![pointer]);
+            fun.store(pointer, call);
+            fun.ret(None);
+            fun.finish()
+        };
+
+        let mut module = builder.finish();
+        inline_functions(&mut module, |_| true).unwrap();
+
+        let fun = &module.functions[main];
+        assert!(fun
+            .expressions
+            .iter()
+            .all(|(_, expr)| !matches!(*expr, Expression::Call { .. })));
+        // the argument copy, the result and the return flag
+        assert_eq!(fun.local_variables.len(), 3);
+        match fun.body[..] {
+            [Statement::Store { .. }, Statement::Store { .. }, Statement::Loop { ref body, .. }, Statement::Store { value, .. }, Statement::Return { .. }] =>
+            {
+                assert!(matches!(
+                    body[..],
+                    [
+                        Statement::Loop { .. },
+                        Statement::If { .. },
+                        Statement::Store { .. },
+                        Statement::Break
+                    ]
+                ));
+                assert!(matches!(
+                    fun.expressions[value],
+                    Expression::LocalVariable(_)
+                ));
+            }
+            ref other => panic!("Unexpected body {:?}", other),
+        }
+    }
+
+    #[test]
+    fn shared_result_and_void_call() {
+        let mut builder = ModuleBuilder::new();
+        let float = builder.scalar(ScalarKind::Float, 4);
+        let one = builder.float(1.0);
+        let output = builder.global_variable("output", StorageClass::Private, None, float);
+
+        let get = {
+            let mut fun = builder.function("get", &[], Some(float));
+            let one = fun.constant(one);
+            fun.ret(Some(one));
+            fun.finish()
+        };
+        let set = {
+            let mut fun = builder.function("set", &[], None);
+            let pointer = fun.global(output);
+            let value = fun.constant(one);
+            fun.store(pointer, value);
+            fun.finish()
+        };
+
+        // fn main() { output = get(); output = get(); set(); }
+        let main = {
+            let mut fun = builder.function("main", &[], None);
+            let pointer = fun.global(output);
+            let call = fun.call(get, Vec::new());
+            fun.store(pointer, call);
+            fun.store(pointer, call);
+            fun.call_statement(set, Vec::new());
+            fun.finish()
+        };
+
+        let mut module = builder.finish();
+        inline_functions(&mut module, |_| true).unwrap();
+
+        let fun = &module.functions[main];
+        assert_eq!(fun.local_variables.len(), 1);
+        // the result of `get`, the two original stores, and the body of `set`
+        assert_eq!(fun.body.len(), 4);
+        assert!(fun
+            .body
+            .iter()
+            .all(|statement| matches!(*statement, Statement::Store { .. })));
+    }
+
+    #[test]
+    fn local_initializer() {
+        let mut builder = ModuleBuilder::new();
+        let float = builder.scalar(ScalarKind::Float, 4);
+        let one = builder.float(1.0);
+        let output = builder.global_variable("output", StorageClass::Private, None, float);
+
+        let get = {
+            let mut fun = builder.function("get", &[], Some(float));
+            let one = fun.constant(one);
+            fun.ret(Some(one));
+            fun.finish()
+        };
+
+        // fn get_twice() -> f32 { var x = get(); return x; }
+        let get_twice = {
+            let mut fun = builder.function("get_twice", &[], Some(float));
+            let call = fun.call(get, Vec::new());
+            let x = fun.local_variable_with("x", call).unwrap();
+            let value = fun.load(x);
+            fun.ret(Some(value));
+            fun.finish()
+        };
+
+        // fn main() { var y = get_twice(); output = y; }
+        let main = {
+            let mut fun = builder.function("main", &[], None);
+            let call = fun.call(get_twice, Vec::new());
+            let y = fun.local_variable_with("y", call).unwrap();
+            let value = fun.load(y);
+            let pointer = fun.global(output);
+            fun.store(pointer, value);
+            fun.finish()
+        };
+
+        let mut module = builder.finish();
+        inline_functions(&mut module, |_| true).unwrap();
+
+        for &handle in [get_twice, main].iter() {
+            let fun = &module.functions[handle];
+            assert!(fun
+                .local_variables
+                .iter()
+                .all(|(_, local)| local.init.is_none()));
+        }
+        let fun = &module.functions[main];
+        let local = |pointer| match fun.expressions[pointer] {
+            Expression::LocalVariable(local) => fun.local_variables[local].name.clone(),
+            ref other => panic!("Unexpected pointer {:?}", other),
+        };
+        // `get` result, `x`, `get_twice` result, `y`, then the original store
+        let stored = fun.body[..4]
+            .iter()
+            .map(|statement| match *statement {
+                Statement::Store { pointer, .. } => local(pointer),
+                ref other => panic!("Unexpected statement {:?}", other),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            stored,
+            vec![None, Some("x".to_string()), None, Some("y".to_string())]
+        );
+        assert!(matches!(fun.body[4..], [Statement::Store { .. }]));
+    }
+
+    #[test]
+    fn recursion() {
+        let mut builder = ModuleBuilder::new();
+        let int = builder.scalar(ScalarKind::Sint, 4);
+        let forever = crate::arena::Handle::from_usize(builder.module().functions.len());
+        let mut fun = builder.function("forever", &[], Some(int));
+        let call = fun.call(forever, Vec::new());
+        fun.ret(Some(call));
+        fun.finish();
+
+        let mut module = builder.finish();
+        assert!(inline_functions(&mut module, |_| true).is_err());
+    }
+}
@@ -0,0 +1,279 @@
+use crate::arena::{Arena, Handle};
+
+/// Rules for placing the data in memory.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LayoutRules {
+    /// Layout of uniform buffers in GLSL.
+    ///
+    /// Arrays and structures are aligned to 16 bytes.
+    Std140,
+    /// Layout of storage buffers in GLSL.
+    Std430,
+    /// Every value is only aligned to the size of its components.
+    ///
+    /// This matches the scalar block layout of Vulkan.
+    Scalar,
+    /// Layout of the Metal Shading Language.
+    ///
+    /// Three-component vectors take the space of four components,
+    /// and arrays and structures are not padded further.
+    Metal,
+}
+
+/// Size and alignment of a type, in bytes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TypeLayout {
+    pub size: u32,
+    pub alignment: u32,
+}
+
+impl TypeLayout {
+    /// Return the stride of this type when placed in an array.
+    pub fn stride(&self) -> u32 {
+        round_up(self.alignment, self.size)
+    }
+
+    /// Return the first properly aligned offset, starting from the given one.
+    pub fn align_offset(&self, offset: u32) -> u32 {
+        round_up(self.alignment, offset)
+    }
+}
+
+fn round_up(alignment: u32, value: u32) -> u32 {
+    match value % alignment {
+        0 => value,
+        rest => value + alignment - rest,
+    }
+}
+
+/// Computes the layout of types.
+///
+/// Explicit member offsets and array strides are respected, the rules only
+/// apply to the values that are not specified.
+#[derive(Debug)]
+pub struct Layouter {
+    rules: LayoutRules,
+    layouts: Vec<TypeLayout>,
+}
+
+impl Layouter {
+    pub fn new(rules: LayoutRules) -> Self {
+        Layouter {
+            rules,
+            layouts: Vec::new(),
+        }
+    }
+
+    /// Compute the layouts of the types added to the arena since the last update.
+    ///
+    /// The types have to only refer to the types before them.
+    pub fn update(&mut self, types: &Arena<crate::Type>) {
+        for (_, ty) in types.iter().skip(self.layouts.len()) {
+            let layout = self.compute(&ty.inner);
+            self.layouts.push(layout);
+        }
+    }
+
+    fn vector_layout(&self, size: crate::VectorSize, width: crate::Bytes) -> TypeLayout {
+        let width = width as u32;
+        let alignment = match (self.rules, size) {
+            (LayoutRules::Scalar, _) => width,
+            (_, crate::VectorSize::Bi) => 2 * width,
+            (_, _) => 4 * width,
+        };
+        let size = match (self.rules, size) {
+            (LayoutRules::Metal, crate::VectorSize::Tri) => 4 * width,
+            (_, _) => size as u32 * width,
+        };
+        TypeLayout { size, alignment }
+    }
+
+    /// Return the alignment of a type used as an array element or a structure.
+    fn aggregate_alignment(&self, alignment: u32) -> u32 {
+        match self.rules {
+            LayoutRules::Std140 => alignment.max(16),
+            LayoutRules::Std430 | LayoutRules::Scalar | LayoutRules::Metal => alignment,
+        }
+    }
+
+    /// Return the stride of an array of the given type, when it's not specified.
+    pub fn array_stride(&self, base: Handle<crate::Type>) -> u32 {
+        let base = self[base];
+        round_up(self.aggregate_alignment(base.alignment), base.size)
+    }
+
+    /// Return the offsets of the structure members.
+    ///
+    /// Built-in members don't have an offset, they are placed right after
+    /// the previous members.
+    pub fn member_offsets(&self, members: &[crate::StructMember]) -> Vec<u32> {
+        let mut end = 0;
+        members
+            .iter()
+            .map(|member| {
+                let layout = self[member.ty];
+                let offset = match member.origin {
+                    crate::MemberOrigin::Offset(offset) => offset,
+                    crate::MemberOrigin::BuiltIn(_) => round_up(layout.alignment, end),
+                };
+                end = end.max(offset + layout.size);
+                offset
+            })
+            .collect()
+    }
+
+    fn compute(&self, inner: &crate::TypeInner) -> TypeLayout {
+        use crate::TypeInner as Ti;
+        match *inner {
+            Ti::Scalar { width, .. } => TypeLayout {
+                size: width as u32,
+                alignment: width as u32,
+            },
+            Ti::Vector { size, width, .. } => self.vector_layout(size, width),
+            Ti::Matrix {
+                columns,
+                rows,
+                width,
+                ..
+            } => {
+                let column = self.vector_layout(rows, width);
+                let alignment = self.aggregate_alignment(column.alignment);
+                TypeLayout {
+                    size: columns as u32 * round_up(alignment, column.size),
+                    alignment,
+                }
+            }
+            Ti::Array { base, size, stride } => {
+                let alignment = self.aggregate_alignment(self[base].alignment);
+                let stride = match stride {
+                    Some(stride) => stride.get(),
+                    None => self.array_stride(base),
+                };
+                let count = match size {
+                    crate::ArraySize::Static(count) => count,
+                    crate::ArraySize::Dynamic => 0,
+                };
+                TypeLayout {
+                    size: stride * count,
+                    alignment,
+                }
+            }
+            Ti::Struct { ref members } => {
+                let mut alignment = 1;
+                let mut end = 0;
+                for (member, offset) in members.iter().zip(self.member_offsets(members)) {
+                    let layout = self[member.ty];
+                    alignment = alignment.max(layout.alignment);
+                    end = end.max(offset + layout.size);
+                }
+                let alignment = self.aggregate_alignment(alignment);
+                TypeLayout {
+                    size: round_up(alignment, end),
+                    alignment,
+                }
+            }
+            Ti::Pointer { .. } | Ti::Image { .. } | Ti::DepthImage { .. } | Ti::Sampler { .. } => {
+                TypeLayout {
+                    size: 0,
+                    alignment: 1,
+                }
+            }
+        }
+    }
+}
+
+impl std::ops::Index<Handle<crate::Type>> for Layouter {
+    type Output = TypeLayout;
+    fn index(&self, handle: Handle<crate::Type>) -> &TypeLayout {
+        &self.layouts[handle.index()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LayoutRules, Layouter, TypeLayout};
+    use crate::{arena::Arena, ArraySize, MemberOrigin, ScalarKind, TypeInner, VectorSize};
+
+    fn layouts(rules: LayoutRules) -> Vec<TypeLayout> {
+        let mut types = Arena::new();
+        let float = types.fetch_or_append(crate::Type {
+            name: None,
+            inner: TypeInner::Scalar {
+                kind: ScalarKind::Float,
+                width: 4,
+            },
+        });
+        let vec3 = types.fetch_or_append(crate::Type {
+            name: None,
+            inner: TypeInner::Vector {
+                size: VectorSize::Tri,
+                kind: ScalarKind::Float,
+                width: 4,
+            },
+        });
+        let mat2 = types.fetch_or_append(crate::Type {
+            name: None,
+            inner: TypeInner::Matrix {
+                columns: VectorSize::Bi,
+                rows: VectorSize::Bi,
+                kind: ScalarKind::Float,
+                width: 4,
+            },
+        });
+        let array = types.fetch_or_append(crate::Type {
+            name: None,
+            inner: TypeInner::Array {
+                base: float,
+                size: ArraySize::Static(3),
+                stride: None,
+            },
+        });
+        let members = [(vec3, 0), (float, 12), (mat2, 16), (array, 48)]
+            .iter()
+            .map(|&(ty, offset)| crate::StructMember {
+                name: None,
+                origin: MemberOrigin::Offset(offset),
+                ty,
+            })
+            .collect();
+        let structure = types.fetch_or_append(crate::Type {
+            name: None,
+            inner: TypeInner::Struct { members },
+        });
+
+        let mut layouter = Layouter::new(rules);
+        layouter.update(&types);
+        vec![
+            layouter[vec3],
+            layouter[mat2],
+            layouter[array],
+            layouter[structure],
+        ]
+    }
+
+    #[test]
+    fn rules() {
+        let layout = |size, alignment| TypeLayout { size, alignment };
+        assert_eq!(
+            layouts(LayoutRules::Std140),
+            [
+                layout(12, 16),
+                layout(32, 16),
+                layout(48, 16),
+                layout(96, 16)
+            ]
+        );
+        assert_eq!(
+            layouts(LayoutRules::Std430),
+            [layout(12, 16), layout(16, 8), layout(12, 4), layout(64, 16)]
+        );
+        assert_eq!(
+            layouts(LayoutRules::Scalar),
+            [layout(12, 4), layout(16, 4), layout(12, 4), layout(60, 4)]
+        );
+        assert_eq!(
+            layouts(LayoutRules::Metal),
+            [layout(16, 16), layout(16, 8), layout(12, 4), layout(64, 16)]
+        );
+    }
+}
@@ -0,0 +1,449 @@
+use super::CallGraph;
+use crate::{
+    arena::{Arena, Handle},
+    FastHashMap,
+};
+
+use thiserror::Error;
+
+#[derive(Clone, Debug, Error)]
+pub enum LinkError {
+    #[error("Global variables bound to {0:?} don't match")]
+    ConflictingBinding(crate::Binding),
+    #[error("Function {name:?} takes {expected} arguments, but is called with {found}")]
+    ArgumentCount {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+    #[error("Entry point {1:?} of the {0:?} stage is defined by both modules")]
+    DuplicateEntryPoint(crate::ShaderStage, String),
+}
+
+/// Check if two global variables are bound to the same slot.
+fn same_slot(
+    binding: &crate::Binding,
+    class: crate::StorageClass,
+    other: &crate::GlobalVariable,
+) -> bool {
+    match other.binding {
+        Some(ref other_binding) => match *binding {
+            crate::Binding::Descriptor { .. } => binding == other_binding,
+            _ => binding == other_binding && class == other.class,
+        },
+        None => false,
+    }
+}
+
+/// Collect the named functions of an arena, the first one winning for each name.
+fn exports(
+    functions: &Arena<crate::Function>,
+    offset: usize,
+) -> FastHashMap<String, (Handle<crate::Function>, usize)> {
+    let mut exports = FastHashMap::default();
+    for (handle, fun) in functions.iter() {
+        if let Some(ref name) = fun.name {
+            exports.entry(name.clone()).or_insert((
+                Handle::from_usize(offset + handle.index()),
+                fun.arguments.len(),
+            ));
+        }
+    }
+    exports
+}
+
+/// Check that the external calls to the exported functions pass the right
+/// number of arguments.
+fn check_calls(
+    expressions: &Arena<crate::Expression>,
+    exports: &FastHashMap<String, (Handle<crate::Function>, usize)>,
+) -> Result<(), LinkError> {
+    for (_, expression) in expressions.iter() {
+        if let crate::Expression::Call {
+            origin: crate::FunctionOrigin::External(ref name),
+            ref arguments,
+        } = *expression
+        {
+            match exports.get(name) {
+                Some(&(_, expected)) if expected != arguments.len() => {
+                    return Err(LinkError::ArgumentCount {
+                        name: name.clone(),
+                        expected,
+                        found: arguments.len(),
+                    })
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Replace the external calls to the exported functions by local calls.
+fn resolve_calls(
+    expressions: &mut Arena<crate::Expression>,
+    exports: &FastHashMap<String, (Handle<crate::Function>, usize)>,
+) {
+    let handles = expressions
+        .iter()
+        .map(|(handle, _)| handle)
+        .collect::<Vec<_>>();
+    for handle in handles {
+        if let crate::Expression::Call { ref mut origin, .. } = *expressions.get_mut(handle) {
+            let resolved = match *origin {
+                crate::FunctionOrigin::External(ref name) => match exports.get(name) {
+                    Some(&(function, _)) => function,
+                    None => continue,
+                },
+                crate::FunctionOrigin::Local(_) => continue,
+            };
+            *origin = crate::FunctionOrigin::Local(resolved);
+        }
+    }
+}
+
+/// Adapt the handles stored in a block copied from another module: the
+/// expressions belong to the new function, and the called functions are shifted.
+fn adopt_block(
+    block: &mut [crate::Statement],
+    expressions: &impl Fn(Handle<crate::Expression>) -> Handle<crate::Expression>,
+    offset: usize,
+) {
+    for statement in block {
+        if let crate::Statement::Call {
+            ref mut function, ..
+        } = *statement
+        {
+            *function = Handle::from_usize(offset + function.index());
+        }
+        statement.for_each_expression_mut(|handle| *handle = expressions(*handle));
+        statement.for_each_block_mut(|block| adopt_block(block, expressions, offset));
+    }
+}
+
+/// Copy a type of the other module, or return `None` if it refers to a type
+/// that has no equivalent.
+fn translate_type(
+    ty: &crate::Type,
+    types: impl Fn(Handle<crate::Type>) -> Option<Handle<crate::Type>>,
+) -> Option<crate::Type> {
+    let mut inner = ty.inner.clone();
+    match inner {
+        crate::TypeInner::Pointer { ref mut base, .. }
+        | crate::TypeInner::Array { ref mut base, .. }
+        | crate::TypeInner::Image { ref mut base, .. } => *base = types(*base)?,
+        crate::TypeInner::Struct { ref mut members } => {
+            for member in members.iter_mut() {
+                member.ty = types(member.ty)?;
+            }
+        }
+        _ => {}
+    }
+    Some(crate::Type {
+        name: ty.name.clone(),
+        inner,
+    })
+}
+
+/// Copy a constant of the other module, see `translate_type`.
+fn translate_constant(
+    constant: &crate::Constant,
+    types: impl Fn(Handle<crate::Type>) -> Option<Handle<crate::Type>>,
+    constants: impl Fn(Handle<crate::Constant>) -> Option<Handle<crate::Constant>>,
+) -> Option<crate::Constant> {
+    let mut inner = constant.inner.clone();
+    if let crate::ConstantInner::Composite(ref mut components) = inner {
+        for component in components.iter_mut() {
+            *component = constants(*component)?;
+        }
+    }
+    Some(crate::Constant {
+        name: constant.name.clone(),
+        specialization: constant.specialization,
+        inner,
+        ty: types(constant.ty)?,
+    })
+}
+
+/// Check that the other module can be merged, without modifying this one.
+fn check(
+    module: &crate::Module,
+    other: &crate::Module,
+    module_exports: &FastHashMap<String, (Handle<crate::Function>, usize)>,
+    other_exports: &FastHashMap<String, (Handle<crate::Function>, usize)>,
+) -> Result<(), LinkError> {
+    // the equivalent types and constants that already exist
+    let mut types = Vec::with_capacity(other.types.len());
+    for (_, ty) in other.types.iter() {
+        let ty = translate_type(ty, |ty| types[ty.index()]);
+        types.push(ty.and_then(|ty| module.types.fetch(&ty)));
+    }
+    let mut constants = Vec::with_capacity(other.constants.len());
+    for (_, constant) in other.constants.iter() {
+        let constant = translate_constant(
+            constant,
+            |ty| types[ty.index()],
+            |constant| constants[constant.index()],
+        );
+        constants.push(constant.and_then(|constant| module.constants.fetch(&constant)));
+    }
+
+    for (_, var) in other.global_variables.iter() {
+        let binding = match var.binding {
+            Some(ref binding) => binding,
+            None => continue,
+        };
+        if let Some((_, existing)) = module
+            .global_variables
+            .iter()
+            .find(|&(_, existing)| same_slot(binding, var.class, existing))
+        {
+            let same = types[var.ty.index()] == Some(existing.ty)
+                && var.init.map(|init| constants[init.index()]) == existing.init.map(Some)
+                && crate::GlobalVariable {
+                    ty: existing.ty,
+                    init: existing.init,
+                    ..var.clone()
+                } == *existing;
+            if !same {
+                return Err(LinkError::ConflictingBinding(
+                    existing.binding.clone().unwrap(),
+                ));
+            }
+        }
+    }
+
+    for (_, fun) in module.functions.iter() {
+        check_calls(&fun.expressions, other_exports)?;
+    }
+    for (_, fun) in other.functions.iter() {
+        check_calls(&fun.expressions, module_exports)?;
+    }
+
+    for entry_point in other.entry_points.iter() {
+        if module
+            .entry_points
+            .iter()
+            .any(|ep| ep.stage == entry_point.stage && ep.name == entry_point.name)
+        {
+            return Err(LinkError::DuplicateEntryPoint(
+                entry_point.stage,
+                entry_point.name.clone(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Merge another module into this one.
+///
+/// Types and constants equal to existing ones are shared. Global variables
+/// bound to the same slot are merged if they are identical, and reported
+/// as a conflict otherwise. Calls to external functions are resolved against
+/// the named functions of the other module, in both directions, and the usage
+/// of global variables is propagated through the new calls.
+///
+/// On error, the module is left unchanged.
+pub fn link(module: &mut crate::Module, other: &crate::Module) -> Result<(), LinkError> {
+    let offset = module.functions.len();
+    let module_exports = exports(&module.functions, 0);
+    let other_exports = exports(&other.functions, offset);
+    check(module, other, &module_exports, &other_exports)?;
+
+    let mut types = Vec::with_capacity(other.types.len());
+    for (_, ty) in other.types.iter() {
+        let ty = translate_type(ty, |ty| Some(types[ty.index()])).unwrap();
+        types.push(module.types.fetch_or_append(ty));
+    }
+
+    let mut constants = Vec::with_capacity(other.constants.len());
+    for (_, constant) in other.constants.iter() {
+        let constant = translate_constant(
+            constant,
+            |ty| Some(types[ty.index()]),
+            |constant| Some(constants[constant.index()]),
+        )
+        .unwrap();
+        constants.push(module.constants.fetch_or_append(constant));
+    }
+
+    let mut globals = Vec::with_capacity(other.global_variables.len());
+    for (_, var) in other.global_variables.iter() {
+        let mut var = var.clone();
+        var.ty = types[var.ty.index()];
+        var.init = var.init.map(|init| constants[init.index()]);
+        let existing = match var.binding {
+            Some(ref binding) => module
+                .global_variables
+                .iter()
+                .find(|&(_, existing)| same_slot(binding, var.class, existing))
+                .map(|(handle, _)| handle),
+            None => None,
+        };
+        globals.push(existing.unwrap_or_else(|| module.global_variables.append(var)));
+    }
+
+    let global_count = module.global_variables.len();
+    let handles = module
+        .functions
+        .iter()
+        .map(|(handle, _)| handle)
+        .collect::<Vec<_>>();
+    for handle in handles {
+        let fun = module.functions.get_mut(handle);
+        if !fun.global_usage.is_empty() {
+            fun.global_usage
+                .resize(global_count, crate::GlobalUse::empty());
+        }
+        resolve_calls(&mut fun.expressions, &other_exports);
+    }
+
+    for (_, fun) in other.functions.iter() {
+        let mut global_usage = Vec::new();
+        if !fun.global_usage.is_empty() {
+            global_usage.resize(global_count, crate::GlobalUse::empty());
+            for (index, &usage) in fun.global_usage.iter().enumerate() {
+                global_usage[globals[index].index()] |= usage;
+            }
+        }
+
+        let mut local_variables = Arena::new();
+        let mut expressions = Arena::new();
+        let adopt_local = local_variables.adopter();
+        let adopt_expression = expressions.adopter();
+
+        for (_, local) in fun.local_variables.iter() {
+            let mut local = local.clone();
+            local.ty = types[local.ty.index()];
+            local.init = local.init.map(&adopt_expression);
+            local_variables.append(local);
+        }
+
+        for (_, expression) in fun.expressions.iter() {
+            let mut expression = expression.clone();
+            match expression {
+                crate::Expression::Constant(ref mut constant) => {
+                    *constant = constants[constant.index()]
+                }
+                crate::Expression::Compose { ref mut ty, .. } => *ty = types[ty.index()],
+                crate::Expression::GlobalVariable(ref mut var) => *var = globals[var.index()],
+                crate::Expression::LocalVariable(ref mut var) => *var = adopt_local(*var),
+                crate::Expression::Call {
+                    origin: crate::FunctionOrigin::Local(ref mut function),
+                    ..
+                } => *function = Handle::from_usize(offset + function.index()),
+                _ => {}
+            }
+            expression.for_each_operand_mut(|handle| *handle = adopt_expression(*handle));
+            expressions.append(expression);
+        }
+        resolve_calls(&mut expressions, &module_exports);
+
+        let mut body = fun.body.clone();
+        adopt_block(&mut body, &adopt_expression, offset);
+
+        module.functions.append(crate::Function {
+            name: fun.name.clone(),
+            arguments: fun
+                .arguments
+                .iter()
+                .map(|argument| crate::FunctionArgument {
+                    ty: types[argument.ty.index()],
+                    ..argument.clone()
+                })
+                .collect(),
+            return_type: fun.return_type.map(|ty| types[ty.index()]),
+            global_usage,
+            local_variables,
+            expressions,
+            body,
+        });
+    }
+
+    for entry_point in other.entry_points.iter() {
+        module.entry_points.push(crate::EntryPoint {
+            stage: entry_point.stage,
+            name: entry_point.name.clone(),
+            function: Handle::from_usize(offset + entry_point.function.index()),
+            modes: entry_point.modes.clone(),
+        });
+    }
+
+    CallGraph::new(&module.functions).propagate_global_usage(&mut module.functions);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{link, LinkError};
+
+    const LIBRARY: &str = "
+        [[location 1]] var<in> a_scale : f32;
+        [[location 0]] var<out> o_color : vec4<f32>;
+        fn scale(value : f32) -> f32 {
+            return value * a_scale;
+        }
+        fn gray(value : f32) -> vec4<f32> {
+            return vec4<f32>(value, value, value, 1.0);
+        }
+    ";
+
+    #[test]
+    fn resolve_calls() {
+        let source = "
+            import \"GLSL.std.450\" as std;
+            [[location 0]] var<in> a_value : f32;
+            [[location 0]] var<out> o_color : vec4<f32>;
+            fn main() -> void {
+                o_color = std::gray(std::scale(a_value));
+                return;
+            }
+            entry_point fragment as \"main\" = main;
+        ";
+        let mut module = crate::front::wgsl::parse_str(source).unwrap();
+        let library = crate::front::wgsl::parse_str(LIBRARY).unwrap();
+        link(&mut module, &library).unwrap();
+        crate::proc::Validator::new().validate(&module).unwrap();
+
+        assert_eq!(module.global_variables.len(), 3);
+        let fun = &module.functions[module.entry_points[0].function];
+        let callees = fun
+            .expressions
+            .iter()
+            .filter_map(|(_, expr)| match *expr {
+                crate::Expression::Call {
+                    origin: crate::FunctionOrigin::Local(function),
+                    ..
+                } => module.functions[function].name.as_deref(),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(callees.len(), 2);
+        assert!(callees.contains(&"scale") && callees.contains(&"gray"));
+        // the scale is read by the library function
+        assert_eq!(fun.global_usage[2], crate::GlobalUse::LOAD);
+    }
+
+    #[test]
+    fn conflicting_binding() {
+        let source = "
+            [[location 0]] var<out> o_value : f32;
+            fn main() -> void {
+                o_value = 1.0;
+                return;
+            }
+            entry_point fragment as \"main\" = main;
+        ";
+        let mut module = crate::front::wgsl::parse_str(source).unwrap();
+        let library = crate::front::wgsl::parse_str(LIBRARY).unwrap();
+        let types = module.types.len();
+        match link(&mut module, &library) {
+            Err(LinkError::ConflictingBinding(crate::Binding::Location(0))) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        // nothing was merged
+        assert_eq!(module.types.len(), types);
+        assert_eq!(module.global_variables.len(), 1);
+        assert_eq!(module.functions.len(), 1);
+    }
+}
@@ -1,8 +1,77 @@
 //! Module processing functionality.
 
-mod interface;
+mod binder;
+mod call_graph;
+mod clip_space;
+mod compactor;
+mod constants;
+mod inliner;
+mod layouter;
+mod linker;
+mod reflection;
+mod remap;
+mod specializer;
 mod typifier;
+mod uniformity;
 mod validator;
+mod walker;
 
+pub use binder::{remap_bindings, BindingError, BindingOptions};
+pub use call_graph::{CallGraph, CallGraphError};
+pub use clip_space::{adjust_clip_space, ClipSpaceAdjustment, DepthConversion};
+pub use compactor::compact;
+pub use constants::{fold_constants, ConstantSolver, ConstantSolvingError};
+pub use inliner::{inline_functions, InliningError};
+pub use layouter::{LayoutRules, Layouter, TypeLayout};
+pub use linker::{link, LinkError};
+pub use reflection::{
+    reflect_entry_point, EntryPointInfo, ReflectionError, ResourceBinding, ResourceKind,
+    StageVariable, VaryingFormat,
+};
+pub use remap::{FunctionMap, ModuleMap};
+pub use specializer::{specialize, SpecializationError};
 pub use typifier::{check_constant_types, ResolveError, Typifier, UnexpectedConstantTypeError};
-pub use validator::{ValidationError, Validator};
+pub use uniformity::{Uniformity, UniformityError};
+pub use validator::{Capabilities, ValidationError, Validator};
+pub use walker::{walk_function, walk_function_mut, Visitor, VisitorMut, Walker, WalkerMut};
+
+impl crate::StorageFormat {
+    /// Returns the kind of scalars that texels of this format are read as.
+    pub fn scalar_kind(self) -> crate::ScalarKind {
+        use crate::{ScalarKind as Sk, StorageFormat as Sf};
+        match self {
+            Sf::R8Unorm
+            | Sf::R8Snorm
+            | Sf::R16Float
+            | Sf::Rg8Unorm
+            | Sf::Rg8Snorm
+            | Sf::R32Float
+            | Sf::Rg16Float
+            | Sf::Rgba8Unorm
+            | Sf::Rgba8Snorm
+            | Sf::Rgb10a2Unorm
+            | Sf::Rg11b10Float
+            | Sf::Rg32Float
+            | Sf::Rgba16Float
+            | Sf::Rgba32Float => Sk::Float,
+            Sf::R8Uint
+            | Sf::R16Uint
+            | Sf::Rg8Uint
+            | Sf::R32Uint
+            | Sf::Rg16Uint
+            | Sf::Rgba8Uint
+            | Sf::Rg32Uint
+            | Sf::Rgba16Uint
+            | Sf::Rgba32Uint => Sk::Uint,
+            Sf::R8Sint
+            | Sf::R16Sint
+            | Sf::Rg8Sint
+            | Sf::R32Sint
+            | Sf::Rg16Sint
+            | Sf::Rgba8Sint
+            | Sf::Rg32Sint
+            | Sf::Rgba16Sint
+            | Sf::Rgba32Sint => Sk::Sint,
+        }
+    }
+}
@@ -0,0 +1,220 @@
+use crate::arena::{Arena, Handle};
+
+use thiserror::Error;
+
+#[derive(Clone, Debug, Error, PartialEq)]
+pub enum ReflectionError {
+    #[error("Entry point {1:?} of stage {0:?} is not found")]
+    EntryPointNotFound(crate::ShaderStage, String),
+    #[error("Global variable {0:?} is not a valid resource")]
+    InvalidResource(Handle<crate::GlobalVariable>),
+}
+
+/// What is bound to a descriptor.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ResourceKind {
+    /// Uniform buffer of the given size in bytes.
+    UniformBuffer { size: u32 },
+    /// Storage buffer of the given size in bytes.
+    ///
+    /// The size doesn't account for elements of a trailing dynamic array.
+    StorageBuffer { size: u32 },
+    /// Sampled or storage image.
+    Image {
+        dim: crate::ImageDimension,
+        flags: crate::ImageFlags,
+        format: Option<crate::StorageFormat>,
+    },
+    /// Depth-comparison image.
+    DepthImage {
+        dim: crate::ImageDimension,
+        arrayed: bool,
+    },
+    /// Sampler, possibly doing depth comparisons.
+    Sampler { comparison: bool },
+}
+
+/// Resource used by an entry point.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResourceBinding {
+    pub variable: Handle<crate::GlobalVariable>,
+    pub name: Option<String>,
+    pub set: u32,
+    pub binding: u32,
+    pub ty: Handle<crate::Type>,
+    pub kind: ResourceKind,
+    /// How the resource is accessed by the entry point.
+    pub usage: crate::GlobalUse,
+}
+
+/// Format of the data passed between the stages.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct VaryingFormat {
+    pub kind: crate::ScalarKind,
+    pub width: crate::Bytes,
+    pub components: u8,
+}
+
+/// Input or output of an entry point.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StageVariable {
+    pub variable: Handle<crate::GlobalVariable>,
+    pub name: Option<String>,
+    /// Either the location or the built-in.
+    pub binding: crate::Binding,
+    pub ty: Handle<crate::Type>,
+    /// Format of the data, if it's a scalar or a vector.
+    pub format: Option<VaryingFormat>,
+}
+
+/// Interface of an entry point.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EntryPointInfo {
+    pub stage: crate::ShaderStage,
+    /// Declared size of a compute workgroup. The `glsl-new` front-end
+    /// doesn't parse `local_size_*` layouts yet, so this is `None` there.
+    pub workgroup_size: Option<[u32; 3]>,
+    /// Resources sorted by the descriptor set and binding.
+    pub resources: Vec<ResourceBinding>,
+    pub inputs: Vec<StageVariable>,
+    pub outputs: Vec<StageVariable>,
+}
+
+fn buffer_size(
+    types: &Arena<crate::Type>,
+    ty: Handle<crate::Type>,
+    rules: super::LayoutRules,
+) -> u32 {
+    let mut layouter = super::Layouter::new(rules);
+    layouter.update(types);
+    layouter[ty].size
+}
+
+fn varying_format(inner: &crate::TypeInner) -> Option<VaryingFormat> {
+    match *inner {
+        crate::TypeInner::Scalar { kind, width } => Some(VaryingFormat {
+            kind,
+            width,
+            components: 1,
+        }),
+        crate::TypeInner::Vector { size, kind, width } => Some(VaryingFormat {
+            kind,
+            width,
+            components: size as u8,
+        }),
+        _ => None,
+    }
+}
+
+/// Collect the usage of globals by a function and all the functions it calls.
+fn collect_usage(
+    module: &crate::Module,
+    function: Handle<crate::Function>,
+) -> Vec<crate::GlobalUse> {
+    let graph = super::CallGraph::new(&module.functions);
+    let mut usage = vec![crate::GlobalUse::empty(); module.global_variables.len()];
+    let mut visited = vec![false; module.functions.len()];
+    let mut stack = vec![function];
+    while let Some(function) = stack.pop() {
+        if visited[function.index()] {
+            continue;
+        }
+        visited[function.index()] = true;
+        for (usage, &function_usage) in usage
+            .iter_mut()
+            .zip(module.functions[function].global_usage.iter())
+        {
+            *usage |= function_usage;
+        }
+        stack.extend_from_slice(graph.callees(function));
+    }
+    usage
+}
+
+/// Describe the interface of an entry point, to help building the pipeline layouts.
+///
+/// Only the global variables used by the entry point and its callees are reported.
+pub fn reflect_entry_point(
+    module: &crate::Module,
+    stage: crate::ShaderStage,
+    name: &str,
+) -> Result<EntryPointInfo, ReflectionError> {
+    let entry_point = module
+        .entry_points
+        .iter()
+        .find(|ep| ep.stage == stage && ep.name == name)
+        .ok_or_else(|| ReflectionError::EntryPointNotFound(stage, name.to_string()))?;
+    let usage = collect_usage(module, entry_point.function);
+
+    let mut info = EntryPointInfo {
+        stage,
+        workgroup_size: entry_point.modes.workgroup_size,
+        resources: Vec::new(),
+        inputs: Vec::new(),
+        outputs: Vec::new(),
+    };
+
+    for ((handle, var), &usage) in module.global_variables.iter().zip(usage.iter()) {
+        if usage.is_empty() {
+            continue;
+        }
+        let inner = &module.types[var.ty].inner;
+        match var.binding {
+            Some(crate::Binding::Descriptor { set, binding }) => {
+                let kind = match (inner, var.class) {
+                    (
+                        &crate::TypeInner::Image {
+                            dim, flags, format, ..
+                        },
+                        _,
+                    ) => ResourceKind::Image { dim, flags, format },
+                    (&crate::TypeInner::DepthImage { dim, arrayed }, _) => {
+                        ResourceKind::DepthImage { dim, arrayed }
+                    }
+                    (&crate::TypeInner::Sampler { comparison }, _) => {
+                        ResourceKind::Sampler { comparison }
+                    }
+                    (&crate::TypeInner::Pointer { .. }, _) => {
+                        return Err(ReflectionError::InvalidResource(handle))
+                    }
+                    // buffers are usually structures, but any data type can be bound
+                    (_, crate::StorageClass::Uniform) => ResourceKind::UniformBuffer {
+                        size: buffer_size(&module.types, var.ty, super::LayoutRules::Std140),
+                    },
+                    (_, crate::StorageClass::StorageBuffer) => ResourceKind::StorageBuffer {
+                        size: buffer_size(&module.types, var.ty, super::LayoutRules::Std430),
+                    },
+                    _ => return Err(ReflectionError::InvalidResource(handle)),
+                };
+                info.resources.push(ResourceBinding {
+                    variable: handle,
+                    name: var.name.clone(),
+                    set,
+                    binding,
+                    ty: var.ty,
+                    kind,
+                    usage,
+                });
+            }
+            Some(ref binding) => {
+                let variable = StageVariable {
+                    variable: handle,
+                    name: var.name.clone(),
+                    binding: binding.clone(),
+                    ty: var.ty,
+                    format: varying_format(inner),
+                };
+                match var.class {
+                    crate::StorageClass::Input => info.inputs.push(variable),
+                    crate::StorageClass::Output => info.outputs.push(variable),
+                    _ => {}
+                }
+            }
+            None => {}
+        }
+    }
+
+    info.resources
+        .sort_by_key(|resource| (resource.set, resource.binding));
+    Ok(info)
+}
@@ -0,0 +1,250 @@
+use crate::arena::{Handle, HandleMap};
+
+/// New handles of the module declarations, after some of them were removed.
+///
+/// Every arena is compacted on its own, e.g. with [`Arena::retain`](crate::Arena::retain),
+/// and the resulting maps are applied to the whole module at once.
+#[derive(Debug)]
+pub struct ModuleMap {
+    pub types: HandleMap<crate::Type>,
+    pub constants: HandleMap<crate::Constant>,
+    pub globals: HandleMap<crate::GlobalVariable>,
+    pub functions: HandleMap<crate::Function>,
+}
+
+impl ModuleMap {
+    /// Create a map keeping all the declarations of a module.
+    pub fn new(module: &crate::Module) -> Self {
+        ModuleMap {
+            types: HandleMap::identity(module.types.len()),
+            constants: HandleMap::identity(module.constants.len()),
+            globals: HandleMap::identity(module.global_variables.len()),
+            functions: HandleMap::identity(module.functions.len()),
+        }
+    }
+
+    fn adjust_expression(&self, expression: &mut crate::Expression) {
+        use crate::Expression as E;
+        match *expression {
+            E::Constant(ref mut constant) => self.constants.adjust(constant),
+            E::Compose { ref mut ty, .. } => self.types.adjust(ty),
+            E::GlobalVariable(ref mut var) => self.globals.adjust(var),
+            E::Call {
+                origin: crate::FunctionOrigin::Local(ref mut function),
+                ..
+            } => self.functions.adjust(function),
+            _ => {}
+        }
+    }
+
+    fn adjust_block(&self, block: &mut [crate::Statement]) {
+        for statement in block {
+            if let crate::Statement::Call {
+                ref mut function, ..
+            } = *statement
+            {
+                self.functions.adjust(function);
+            }
+            statement.for_each_block_mut(|block| self.adjust_block(block));
+        }
+    }
+
+    /// Update the handles stored in a module, after its arenas were compacted.
+    ///
+    /// All the handles have to refer to kept declarations. The global usage of
+    /// the functions is updated to only list the kept global variables.
+    pub fn apply(&self, module: &mut crate::Module) {
+        let handles = module
+            .types
+            .iter()
+            .map(|(handle, _)| handle)
+            .collect::<Vec<_>>();
+        for handle in handles {
+            match module.types.get_mut(handle).inner {
+                crate::TypeInner::Pointer { ref mut base, .. }
+                | crate::TypeInner::Array { ref mut base, .. }
+                | crate::TypeInner::Image { ref mut base, .. } => self.types.adjust(base),
+                crate::TypeInner::Struct { ref mut members } => {
+                    for member in members.iter_mut() {
+                        self.types.adjust(&mut member.ty);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let handles = module
+            .constants
+            .iter()
+            .map(|(handle, _)| handle)
+            .collect::<Vec<_>>();
+        for handle in handles {
+            let constant = module.constants.get_mut(handle);
+            self.types.adjust(&mut constant.ty);
+            if let crate::ConstantInner::Composite(ref mut components) = constant.inner {
+                for component in components.iter_mut() {
+                    self.constants.adjust(component);
+                }
+            }
+        }
+
+        let handles = module
+            .global_variables
+            .iter()
+            .map(|(handle, _)| handle)
+            .collect::<Vec<_>>();
+        for handle in handles {
+            let var = module.global_variables.get_mut(handle);
+            self.types.adjust(&mut var.ty);
+            if let Some(ref mut init) = var.init {
+                self.constants.adjust(init);
+            }
+        }
+
+        let global_count = module.global_variables.len();
+        let handles = module
+            .functions
+            .iter()
+            .map(|(handle, _)| handle)
+            .collect::<Vec<_>>();
+        for handle in handles {
+            let fun = module.functions.get_mut(handle);
+            for argument in fun.arguments.iter_mut() {
+                self.types.adjust(&mut argument.ty);
+            }
+            if let Some(ref mut ty) = fun.return_type {
+                self.types.adjust(ty);
+            }
+            if !fun.global_usage.is_empty() {
+                let mut global_usage = vec![crate::GlobalUse::empty(); global_count];
+                for (index, &usage) in fun.global_usage.iter().enumerate() {
+                    if let Some(var) = self.globals.get(Handle::from_usize(index)) {
+                        global_usage[var.index()] = usage;
+                    }
+                }
+                fun.global_usage = global_usage;
+            }
+            let locals = fun
+                .local_variables
+                .iter()
+                .map(|(handle, _)| handle)
+                .collect::<Vec<_>>();
+            for local in locals {
+                self.types
+                    .adjust(&mut fun.local_variables.get_mut(local).ty);
+            }
+            let expressions = fun
+                .expressions
+                .iter()
+                .map(|(handle, _)| handle)
+                .collect::<Vec<_>>();
+            for expression in expressions {
+                self.adjust_expression(fun.expressions.get_mut(expression));
+            }
+            self.adjust_block(&mut fun.body);
+        }
+
+        for entry_point in module.entry_points.iter_mut() {
+            self.functions.adjust(&mut entry_point.function);
+        }
+    }
+}
+
+/// New handles of the expressions and local variables of a function,
+/// after some of them were removed.
+#[derive(Debug)]
+pub struct FunctionMap {
+    pub expressions: HandleMap<crate::Expression>,
+    pub locals: HandleMap<crate::LocalVariable>,
+}
+
+impl FunctionMap {
+    /// Create a map keeping all the expressions and local variables of a function.
+    pub fn new(fun: &crate::Function) -> Self {
+        FunctionMap {
+            expressions: HandleMap::identity(fun.expressions.len()),
+            locals: HandleMap::identity(fun.local_variables.len()),
+        }
+    }
+
+    fn adjust_expression(&self, expression: &mut crate::Expression) {
+        if let crate::Expression::LocalVariable(ref mut var) = *expression {
+            self.locals.adjust(var);
+        }
+        expression.for_each_operand_mut(|handle| self.expressions.adjust(handle));
+    }
+
+    fn adjust_block(&self, block: &mut [crate::Statement]) {
+        for statement in block {
+            statement.for_each_expression_mut(|handle| self.expressions.adjust(handle));
+            statement.for_each_block_mut(|block| self.adjust_block(block));
+        }
+    }
+
+    /// Update the handles stored in a function, after its expressions and
+    /// local variables were compacted.
+    ///
+    /// All the handles have to refer to kept values.
+    pub fn apply(&self, fun: &mut crate::Function) {
+        let locals = fun
+            .local_variables
+            .iter()
+            .map(|(handle, _)| handle)
+            .collect::<Vec<_>>();
+        for local in locals {
+            if let Some(ref mut init) = fun.local_variables.get_mut(local).init {
+                self.expressions.adjust(init);
+            }
+        }
+        let expressions = fun
+            .expressions
+            .iter()
+            .map(|(handle, _)| handle)
+            .collect::<Vec<_>>();
+        for expression in expressions {
+            self.adjust_expression(fun.expressions.get_mut(expression));
+        }
+        self.adjust_block(&mut fun.body);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FunctionMap, ModuleMap};
+
+    #[test]
+    fn remove_global() {
+        let source = "
+            [[location 0]] var<in> a_unused : f32;
+            [[location 1]] var<in> a_value : f32;
+            [[location 0]] var<out> o_value : f32;
+            fn main() -> void {
+                o_value = a_value;
+                return;
+            }
+            entry_point fragment as \"main\" = main;
+        ";
+        let mut module = crate::front::wgsl::parse_str(source).unwrap();
+        let mut map = ModuleMap::new(&module);
+        map.globals = module
+            .global_variables
+            .retain(|_, var| var.name.as_deref() != Some("a_unused"));
+
+        let handle = module.entry_points[0].function;
+        let fun = module.functions.get_mut(handle);
+        let mut fun_map = FunctionMap::new(fun);
+        fun_map.expressions = fun.expressions.retain(|_, expr| match *expr {
+            crate::Expression::GlobalVariable(var) => map.globals.get(var).is_some(),
+            _ => true,
+        });
+        fun_map.apply(fun);
+        map.apply(&mut module);
+        crate::proc::Validator::new().validate(&module).unwrap();
+
+        let fun = &module.functions[module.entry_points[0].function];
+        assert_eq!(
+            fun.global_usage,
+            [crate::GlobalUse::LOAD, crate::GlobalUse::STORE]
+        );
+    }
+}
@@ -0,0 +1,85 @@
+use super::typifier::{check_constant_types, UnexpectedConstantTypeError};
+use crate::FastHashMap;
+
+use thiserror::Error;
+
+#[derive(Clone, Debug, Error)]
+pub enum SpecializationError {
+    #[error("Specialization constant {id} can't be overridden with a composite value")]
+    CompositeValue { id: u32 },
+    #[error("Value for specialization constant {id} has the wrong type: {source}")]
+    InvalidValue {
+        id: u32,
+        source: UnexpectedConstantTypeError,
+    },
+}
+
+/// Bake the given values into the specialization constants of a module.
+///
+/// Each constant with a specialization ID present in `values` gets its value
+/// replaced and stops being a specialization constant. Other constants keep
+/// their default values, and IDs that don't match any constant are ignored.
+pub fn specialize(
+    module: &mut crate::Module,
+    values: &FastHashMap<u32, crate::ConstantInner>,
+) -> Result<(), SpecializationError> {
+    let overridden = module
+        .constants
+        .iter()
+        .filter_map(|(handle, constant)| {
+            let id = constant.specialization?;
+            values.get(&id).map(|value| (handle, id, value))
+        })
+        .collect::<Vec<_>>();
+
+    for (handle, id, value) in overridden {
+        if let crate::ConstantInner::Composite(_) = *value {
+            return Err(SpecializationError::CompositeValue { id });
+        }
+        let ty = module.constants[handle].ty;
+        check_constant_types(value, &module.types[ty].inner)
+            .map_err(|source| SpecializationError::InvalidValue { id, source })?;
+
+        let constant = module.constants.get_mut(handle);
+        constant.inner = value.clone();
+        constant.specialization = None;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{specialize, SpecializationError};
+    use crate::{ConstantInner, FastHashMap};
+
+    fn spec_module() -> crate::Module {
+        let mut builder = crate::builder::ModuleBuilder::new();
+        let ty = builder.scalar(crate::ScalarKind::Float, 4);
+        builder.named_constant("scale", Some(3), ConstantInner::Float(1.0), ty);
+        builder.finish()
+    }
+
+    #[test]
+    fn override_value() {
+        let mut module = spec_module();
+        let mut values = FastHashMap::default();
+        values.insert(3, ConstantInner::Float(2.5));
+        specialize(&mut module, &values).unwrap();
+
+        let (_, constant) = module.constants.iter().next().unwrap();
+        assert_eq!(constant.inner, ConstantInner::Float(2.5));
+        assert_eq!(constant.specialization, None);
+    }
+
+    #[test]
+    fn wrong_type() {
+        let mut module = spec_module();
+        let mut values = FastHashMap::default();
+        values.insert(3, ConstantInner::Bool(true));
+        match specialize(&mut module, &values) {
+            Err(SpecializationError::InvalidValue { id: 3, .. }) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+}
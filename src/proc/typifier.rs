@@ -15,6 +15,8 @@ pub enum ResolveError {
     InvalidAccessIndex,
     #[error("Function {name} not defined")]
     FunctionNotDefined { name: String },
+    #[error("Function argument {0} doesn't exist")]
+    InvalidArgumentIndex(u32),
     #[error("Function without return type")]
     FunctionReturnsVoid,
 }
@@ -33,6 +35,7 @@ impl Typifier {
         constants: &Arena<crate::Constant>,
         global_vars: &Arena<crate::GlobalVariable>,
         local_vars: &Arena<crate::LocalVariable>,
+        arguments: &[crate::FunctionArgument],
         functions: &Arena<crate::Function>,
     ) -> Result<Handle<crate::Type>, ResolveError> {
         if self.types.len() <= expr_handle.index() {
@@ -81,10 +84,21 @@ impl Typifier {
                     }
                     crate::Expression::Constant(h) => constants[h].ty,
                     crate::Expression::Compose { ty, .. } => ty,
-                    crate::Expression::FunctionParameter(_) => unimplemented!(),
+                    crate::Expression::FunctionParameter(index) => {
+                        arguments
+                            .get(index as usize)
+                            .ok_or(ResolveError::InvalidArgumentIndex(index))?
+                            .ty
+                    }
                     crate::Expression::GlobalVariable(h) => global_vars[h].ty,
                     crate::Expression::LocalVariable(h) => local_vars[h].ty,
-                    crate::Expression::Load { .. } => unimplemented!(),
+                    crate::Expression::Load { pointer } => {
+                        let pointer_ty = self.types[pointer.index()];
+                        match types[pointer_ty].inner {
+                            crate::TypeInner::Pointer { base, .. } => base,
+                            _ => pointer_ty,
+                        }
+                    }
                     crate::Expression::ImageSample { image, .. } => {
                         let image = self.resolve(
                             image,
@@ -93,6 +107,7 @@ impl Typifier {
                             constants,
                             global_vars,
                             local_vars,
+                            arguments,
                             functions,
                         )?;
 
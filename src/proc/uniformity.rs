@@ -0,0 +1,409 @@
+use crate::arena::{Arena, Handle};
+
+use thiserror::Error;
+
+#[derive(Clone, Debug, Error, PartialEq)]
+pub enum UniformityError {
+    #[error("Expression {1:?} of function {0:?} needs derivatives, but is evaluated in non-uniform control flow")]
+    NonUniformDerivative(Handle<crate::Function>, Handle<crate::Expression>),
+    #[error("Function {0:?} calls {1:?}, which needs derivatives, in non-uniform control flow")]
+    NonUniformCall(Handle<crate::Function>, Handle<crate::Function>),
+}
+
+/// Uniformity of the expressions of a function.
+#[derive(Debug)]
+struct FunctionUniformity {
+    non_uniform: Vec<bool>,
+    requires_uniform_control: bool,
+}
+
+/// Ways for the control flow to leave a construct in only some of the invocations.
+#[derive(Clone, Copy, Default)]
+struct Divergence {
+    /// Some invocations break or continue the enclosing loop.
+    breaks: bool,
+    /// Some invocations return from the function, or are killed.
+    returns: bool,
+}
+
+impl Divergence {
+    fn any(self) -> bool {
+        self.breaks || self.returns
+    }
+}
+
+struct Analyzer<'a> {
+    module: &'a crate::Module,
+    function: Handle<crate::Function>,
+    expressions: &'a Arena<crate::Expression>,
+    functions: &'a [Option<FunctionUniformity>],
+    /// Memoized uniformity of the expressions: `None` if not yet known.
+    non_uniform: Vec<Option<bool>>,
+    locals: Vec<bool>,
+    changed: bool,
+    /// Whether an evaluated expression needs derivatives.
+    requires_uniform_control: bool,
+    report: bool,
+    error: Option<UniformityError>,
+}
+
+impl Analyzer<'_> {
+    fn is_global_uniform(&self, handle: Handle<crate::GlobalVariable>) -> bool {
+        let var = &self.module.global_variables[handle];
+        match var.class {
+            crate::StorageClass::Constant | crate::StorageClass::Uniform => true,
+            crate::StorageClass::Input => matches!(
+                var.binding,
+                Some(crate::Binding::BuiltIn(crate::BuiltIn::BaseInstance))
+                    | Some(crate::Binding::BuiltIn(crate::BuiltIn::BaseVertex))
+                    | Some(crate::Binding::BuiltIn(crate::BuiltIn::WorkGroupId))
+            ),
+            // storage may be written differently by each invocation
+            crate::StorageClass::Function
+            | crate::StorageClass::Output
+            | crate::StorageClass::Private
+            | crate::StorageClass::StorageBuffer
+            | crate::StorageClass::WorkGroup => false,
+        }
+    }
+
+    fn is_non_uniform(&mut self, handle: Handle<crate::Expression>) -> bool {
+        if let Some(non_uniform) = self.non_uniform[handle.index()] {
+            return non_uniform;
+        }
+        use crate::Expression as E;
+        let expression = &self.expressions[handle];
+        let mut operands = false;
+        expression.for_each_operand(|operand| operands |= self.is_non_uniform(operand));
+        let non_uniform = match *expression {
+            E::FunctionParameter(_) => true,
+            E::GlobalVariable(var) => !self.is_global_uniform(var),
+            E::LocalVariable(var) => self.locals[var.index()],
+            // local functions may read anything
+            E::Call {
+                origin: crate::FunctionOrigin::Local(_),
+                ..
+            } => true,
+            _ => operands,
+        };
+        self.non_uniform[handle.index()] = Some(non_uniform);
+        non_uniform
+    }
+
+    /// Check the expressions evaluated by a statement.
+    fn evaluate(&mut self, handle: Handle<crate::Expression>, uniform: bool) {
+        use crate::Expression as E;
+        let expression = &self.expressions[handle];
+        expression.for_each_operand(|operand| self.evaluate(operand, uniform));
+        let requires_derivatives = match *expression {
+            // the level of detail is computed from the derivatives
+            E::ImageSample { .. } | E::Derivative { .. } => true,
+            E::Call {
+                origin: crate::FunctionOrigin::Local(function),
+                ..
+            } => matches!(
+                self.functions[function.index()],
+                Some(ref info) if info.requires_uniform_control
+            ),
+            _ => false,
+        };
+        if requires_derivatives {
+            self.requires_uniform_control = true;
+            if !uniform && self.report && self.error.is_none() {
+                self.error = Some(UniformityError::NonUniformDerivative(self.function, handle));
+            }
+        }
+    }
+
+    fn store(
+        &mut self,
+        pointer: Handle<crate::Expression>,
+        value: Handle<crate::Expression>,
+        uniform: bool,
+    ) {
+        let mut var = pointer;
+        let mut non_uniform = !uniform | self.is_non_uniform(value);
+        loop {
+            match self.expressions[var] {
+                crate::Expression::Access { base, index } => {
+                    non_uniform |= self.is_non_uniform(index);
+                    var = base;
+                }
+                crate::Expression::AccessIndex { base, .. } => var = base,
+                crate::Expression::LocalVariable(local) => {
+                    if non_uniform && !self.locals[local.index()] {
+                        self.locals[local.index()] = true;
+                        self.changed = true;
+                    }
+                    break;
+                }
+                _ => break,
+            }
+        }
+    }
+
+    fn block(&mut self, block: &[crate::Statement], mut uniform: bool) -> Divergence {
+        use crate::Statement as S;
+        let mut divergence = Divergence::default();
+        for statement in block {
+            let inner = match *statement {
+                S::Empty | S::EmitVertex | S::EndPrimitive => Divergence::default(),
+                S::Block(ref block) => self.block(block, uniform),
+                S::If {
+                    condition,
+                    ref accept,
+                    ref reject,
+                } => {
+                    self.evaluate(condition, uniform);
+                    let uniform = uniform && !self.is_non_uniform(condition);
+                    let accept = self.block(accept, uniform);
+                    let reject = self.block(reject, uniform);
+                    Divergence {
+                        breaks: accept.breaks | reject.breaks,
+                        returns: accept.returns | reject.returns,
+                    }
+                }
+                S::Switch {
+                    selector,
+                    ref cases,
+                    ref default,
+                } => {
+                    self.evaluate(selector, uniform);
+                    let uniform = uniform && !self.is_non_uniform(selector);
+                    let mut divergence = self.block(default, uniform);
+                    for (case, _) in cases.values() {
+                        let case = self.block(case, uniform);
+                        divergence.breaks |= case.breaks;
+                        divergence.returns |= case.returns;
+                    }
+                    divergence
+                }
+                S::Loop {
+                    ref body,
+                    ref continuing,
+                } => {
+                    // find out if the next iterations start in non-uniform control flow
+                    let report = std::mem::replace(&mut self.report, false);
+                    let first = self.block(body, uniform);
+                    let uniform = uniform && !first.any();
+                    self.report = report;
+                    let body = self.block(body, uniform);
+                    let continuing = self.block(continuing, uniform && !body.any());
+                    Divergence {
+                        breaks: false,
+                        returns: body.returns | continuing.returns,
+                    }
+                }
+                S::Break | S::Continue => Divergence {
+                    breaks: !uniform,
+                    returns: false,
+                },
+                S::Return { value } => {
+                    if let Some(value) = value {
+                        self.evaluate(value, uniform);
+                    }
+                    Divergence {
+                        breaks: false,
+                        returns: !uniform,
+                    }
+                }
+                S::Kill => Divergence {
+                    breaks: false,
+                    returns: !uniform,
+                },
+                S::Store { pointer, value } => {
+                    self.evaluate(pointer, uniform);
+                    self.evaluate(value, uniform);
+                    self.store(pointer, value, uniform);
+                    Divergence::default()
+                }
+                S::Call {
+                    function,
+                    ref arguments,
+                } => {
+                    let callee = &self.module.functions[function];
+                    for (argument, &value) in callee.arguments.iter().zip(arguments) {
+                        self.evaluate(value, uniform);
+                        // the callee may write anything through pointers
+                        if let crate::TypeInner::Pointer { .. } =
+                            self.module.types[argument.ty].inner
+                        {
+                            self.store(value, value, false);
+                        }
+                    }
+                    if matches!(
+                        self.functions[function.index()],
+                        Some(ref info) if info.requires_uniform_control
+                    ) {
+                        self.requires_uniform_control = true;
+                        if !uniform && self.report && self.error.is_none() {
+                            self.error =
+                                Some(UniformityError::NonUniformCall(self.function, function));
+                        }
+                    }
+                    Divergence::default()
+                }
+            };
+            if inner.any() {
+                uniform = false;
+            }
+            divergence.breaks |= inner.breaks;
+            divergence.returns |= inner.returns;
+        }
+        divergence
+    }
+}
+
+/// Uniformity of the values and the control flow across invocations.
+///
+/// Values are considered non-uniform if they may differ between the
+/// invocations of a draw or dispatch, such as stage inputs, storage contents
+/// and function parameters. Operations computing derivatives, including
+/// sampling with an implicit level of detail, need uniform control flow.
+///
+/// The IR has no barrier statement yet, so barriers are not checked.
+///
+/// `Validator` only reports these errors when asked to, otherwise they can
+/// be treated as warnings.
+#[derive(Debug)]
+pub struct Uniformity {
+    functions: Vec<Option<FunctionUniformity>>,
+    errors: Vec<UniformityError>,
+}
+
+impl Uniformity {
+    /// Analyze all the functions of a module.
+    ///
+    /// Functions are assumed to be called in uniform control flow.
+    pub fn new(module: &crate::Module) -> Self {
+        let order = super::CallGraph::new(&module.functions)
+            .reverse_topological_order()
+            .unwrap_or_else(|_| module.functions.iter().map(|(handle, _)| handle).collect());
+        let mut functions = Vec::with_capacity(module.functions.len());
+        functions.resize_with(module.functions.len(), || None);
+        let mut errors = Vec::new();
+
+        for handle in order {
+            let fun = &module.functions[handle];
+            let mut analyzer = Analyzer {
+                module,
+                function: handle,
+                expressions: &fun.expressions,
+                functions: &functions,
+                non_uniform: vec![None; fun.expressions.len()],
+                locals: vec![false; fun.local_variables.len()],
+                changed: true,
+                requires_uniform_control: false,
+                report: false,
+                error: None,
+            };
+            // locals become non-uniform when they are assigned a non-uniform value,
+            // which in turn affects the other values and the control flow
+            while analyzer.changed {
+                analyzer.changed = false;
+                for (local, var) in fun.local_variables.iter() {
+                    if let Some(init) = var.init {
+                        if !analyzer.locals[local.index()] && analyzer.is_non_uniform(init) {
+                            analyzer.locals[local.index()] = true;
+                            analyzer.changed = true;
+                        }
+                    }
+                }
+                analyzer.block(&fun.body, true);
+                for value in analyzer.non_uniform.iter_mut() {
+                    *value = None;
+                }
+            }
+            analyzer.report = true;
+            for (_, var) in fun.local_variables.iter() {
+                if let Some(init) = var.init {
+                    analyzer.evaluate(init, true);
+                }
+            }
+            analyzer.block(&fun.body, true);
+
+            let non_uniform = (0..fun.expressions.len())
+                .map(|index| analyzer.is_non_uniform(Handle::from_usize(index)))
+                .collect();
+            let requires_uniform_control = analyzer.requires_uniform_control;
+            errors.extend(analyzer.error);
+            functions[handle.index()] = Some(FunctionUniformity {
+                non_uniform,
+                requires_uniform_control,
+            });
+        }
+
+        Uniformity { functions, errors }
+    }
+
+    /// Check if an expression of the function has the same value in all invocations.
+    pub fn is_uniform(
+        &self,
+        function: Handle<crate::Function>,
+        expr: Handle<crate::Expression>,
+    ) -> bool {
+        matches!(
+            self.functions[function.index()],
+            Some(ref info) if !info.non_uniform[expr.index()]
+        )
+    }
+
+    /// Check if the function has to be called in uniform control flow.
+    pub fn requires_uniform_control(&self, function: Handle<crate::Function>) -> bool {
+        matches!(
+            self.functions[function.index()],
+            Some(ref info) if info.requires_uniform_control
+        )
+    }
+
+    /// Return the operations evaluated in non-uniform control flow
+    /// that need it to be uniform.
+    pub fn errors(&self) -> &[UniformityError] {
+        &self.errors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Uniformity, UniformityError};
+
+    #[test]
+    fn derivatives() {
+        let source = "
+            [[location 0]] var<in> v_value : f32;
+            [[location 0]] var<out> o_color : f32;
+            fn uniform_derivative() -> void {
+                o_color = dpdx(v_value);
+                return;
+            }
+            fn non_uniform_derivative() -> void {
+                if (v_value > 0.0) {
+                    o_color = dpdx(v_value);
+                }
+                return;
+            }
+            entry_point fragment as \"a\" = uniform_derivative;
+            entry_point fragment as \"b\" = non_uniform_derivative;
+        ";
+        let module = crate::front::wgsl::parse_str(source).unwrap();
+        let uniformity = Uniformity::new(&module);
+        let bad = module.entry_points[1].function;
+        match *uniformity.errors() {
+            [UniformityError::NonUniformDerivative(function, expr)] => {
+                assert_eq!(function, bad);
+                assert!(!uniformity.is_uniform(function, expr));
+            }
+            ref other => panic!("Unexpected errors {:?}", other),
+        }
+        assert!(uniformity.requires_uniform_control(module.entry_points[0].function));
+
+        crate::proc::Validator::new().validate(&module).unwrap();
+        match crate::proc::Validator::new()
+            .check_uniformity(true)
+            .validate(&module)
+        {
+            Err(crate::proc::ValidationError::NonUniformControlFlow(_)) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+}
@@ -1,7 +1,24 @@
 use crate::arena::Handle;
 
+bitflags::bitflags! {
+    /// Optional features that a module is allowed to use.
+    pub struct Capabilities: u8 {
+        /// 16-bit floating-point scalars.
+        const FLOAT16 = 0x1;
+        /// 64-bit floating-point scalars.
+        const FLOAT64 = 0x2;
+        /// 16-bit integer scalars.
+        const INT16 = 0x4;
+        /// 64-bit integer scalars.
+        const INT64 = 0x8;
+    }
+}
+
 #[derive(Debug)]
-pub struct Validator {}
+pub struct Validator {
+    capabilities: Capabilities,
+    check_uniformity: bool,
+}
 
 #[derive(Clone, Debug, thiserror::Error)]
 pub enum ValidationError {
@@ -9,14 +26,74 @@ pub enum ValidationError {
     InvalidTypeWidth(crate::ScalarKind, crate::Bytes),
     #[error("The type handle can not be resolved")]
     UnresolvedType(Handle<crate::Type>),
+    #[error("The storage format {0:?} doesn't match the image type")]
+    InvalidStorageFormat(crate::StorageFormat),
+    #[error("The initializer of global variable {0:?} doesn't match its type")]
+    InvalidGlobalInitializer(Handle<crate::GlobalVariable>),
     #[error("There are instructions after `return`/`break`/`continue`")]
     InvalidControlFlowExitTail,
+    #[error("Member {1} of structure {0:?} overlaps with the previous one")]
+    OverlappingMember(Handle<crate::Type>, u32),
+    #[error("Member {1} of structure {0:?} is not aligned")]
+    MisalignedMember(Handle<crate::Type>, u32),
+    #[error("The stride of array {0:?} is smaller than its element")]
+    InvalidArrayStride(Handle<crate::Type>),
+    #[error("Function {0:?} returns nothing, so it can only be called by a statement")]
+    InvalidVoidCall(Handle<crate::Function>),
+    #[error("Function {0:?} is called recursively")]
+    Recursion(Handle<crate::Function>),
+    #[error("Primitives can only be emitted by geometry shaders, not in the {0:?} stage")]
+    InvalidPrimitiveEmission(crate::ShaderStage),
+    #[error(transparent)]
+    NonUniformControlFlow(#[from] super::UniformityError),
+}
+
+/// Check if a block emits vertices or ends primitives.
+fn emits_primitives(block: &[crate::Statement]) -> bool {
+    block.iter().any(|statement| match *statement {
+        crate::Statement::EmitVertex | crate::Statement::EndPrimitive => true,
+        _ => {
+            let mut found = false;
+            statement.for_each_block(|block| found |= emits_primitives(block));
+            found
+        }
+    })
 }
 
 impl Validator {
     /// Construct a new validator instance.
     pub fn new() -> Self {
-        Validator {}
+        Self::with_capabilities(Capabilities::empty())
+    }
+
+    /// Construct a new validator instance, allowing the given capabilities.
+    pub fn with_capabilities(capabilities: Capabilities) -> Self {
+        Validator {
+            capabilities,
+            check_uniformity: false,
+        }
+    }
+
+    /// Also reject derivatives evaluated in non-uniform control flow.
+    ///
+    /// This is off by default, since the analysis is conservative and rejects
+    /// some valid shaders. See `Uniformity` for reporting these as warnings.
+    pub fn check_uniformity(&mut self, check: bool) -> &mut Self {
+        self.check_uniformity = check;
+        self
+    }
+
+    fn check_width(&self, kind: crate::ScalarKind, width: crate::Bytes) -> bool {
+        use crate::ScalarKind as Sk;
+        match (kind, width) {
+            (Sk::Bool, width) => width == 1,
+            (_, 4) => true,
+            (Sk::Float, 2) => self.capabilities.contains(Capabilities::FLOAT16),
+            (Sk::Float, 8) => self.capabilities.contains(Capabilities::FLOAT64),
+            (Sk::Sint, 2) | (Sk::Uint, 2) => self.capabilities.contains(Capabilities::INT16),
+            (Sk::Sint, 8) | (Sk::Uint, 8) => self.capabilities.contains(Capabilities::INT64),
+            _ => false,
+        }
     }
 
     /// Check the given module to be valid.
@@ -27,11 +104,7 @@ impl Validator {
                 Ti::Scalar { kind, width }
                 | Ti::Vector { kind, width, .. }
                 | Ti::Matrix { kind, width, .. } => {
-                    let expected = match kind {
-                        crate::ScalarKind::Bool => 1,
-                        _ => 4,
-                    };
-                    if width != expected {
+                    if !self.check_width(kind, width) {
                         return Err(ValidationError::InvalidTypeWidth(kind, width));
                     }
                 }
@@ -46,18 +119,215 @@ impl Validator {
                     }
                 }
                 Ti::Struct { ref members } => {
-                    //TODO: check that offsets are not intersecting?
                     for member in members {
                         if member.ty >= handle {
                             return Err(ValidationError::UnresolvedType(member.ty));
                         }
                     }
                 }
-                Ti::Image { .. } | Ti::DepthImage { .. } => {}
+                Ti::Image {
+                    base,
+                    dim: _,
+                    flags,
+                    format,
+                } => {
+                    if base >= handle {
+                        return Err(ValidationError::UnresolvedType(base));
+                    }
+                    if let Some(format) = format {
+                        let kind_matches = match module.types[base].inner {
+                            Ti::Scalar { kind, .. } => kind == format.scalar_kind(),
+                            _ => false,
+                        };
+                        if flags.contains(crate::ImageFlags::SAMPLED) || !kind_matches {
+                            return Err(ValidationError::InvalidStorageFormat(format));
+                        }
+                    }
+                }
+                Ti::DepthImage { .. } => {}
                 Ti::Sampler { comparison: _ } => {}
             }
         }
 
+        // the types are only checked against the loosest layout rules,
+        // since they don't know which storage they are used with
+        let mut layouter = super::Layouter::new(super::LayoutRules::Scalar);
+        layouter.update(&module.types);
+        for (handle, ty) in module.types.iter() {
+            match ty.inner {
+                crate::TypeInner::Array {
+                    base,
+                    stride: Some(stride),
+                    ..
+                } if stride.get() < layouter[base].size => {
+                    return Err(ValidationError::InvalidArrayStride(handle));
+                }
+                crate::TypeInner::Struct { ref members } => {
+                    let mut end = 0;
+                    for (index, member) in members.iter().enumerate() {
+                        let offset = match member.origin {
+                            crate::MemberOrigin::Offset(offset) => offset,
+                            crate::MemberOrigin::BuiltIn(_) => continue,
+                        };
+                        let layout = layouter[member.ty];
+                        if offset < end {
+                            return Err(ValidationError::OverlappingMember(handle, index as u32));
+                        }
+                        if layout.align_offset(offset) != offset {
+                            return Err(ValidationError::MisalignedMember(handle, index as u32));
+                        }
+                        end = offset + layout.size;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        for (handle, var) in module.global_variables.iter() {
+            if let Some(init) = var.init {
+                if module.constants[init].ty != var.ty {
+                    return Err(ValidationError::InvalidGlobalInitializer(handle));
+                }
+            }
+        }
+
+        for (_, fun) in module.functions.iter() {
+            for (_, expr) in fun.expressions.iter() {
+                if let crate::Expression::Call {
+                    origin: crate::FunctionOrigin::Local(callee),
+                    ..
+                } = *expr
+                {
+                    if module.functions[callee].return_type.is_none() {
+                        return Err(ValidationError::InvalidVoidCall(callee));
+                    }
+                }
+            }
+        }
+
+        let call_graph = super::CallGraph::new(&module.functions);
+        if let Err(super::CallGraphError::Recursion(handle)) =
+            call_graph.reverse_topological_order()
+        {
+            return Err(ValidationError::Recursion(handle));
+        }
+
+        for entry_point in module.entry_points.iter() {
+            if entry_point.stage == crate::ShaderStage::Geometry {
+                continue;
+            }
+            let mut reached = vec![entry_point.function];
+            let mut index = 0;
+            while let Some(&function) = reached.get(index) {
+                if emits_primitives(&module.functions[function].body) {
+                    return Err(ValidationError::InvalidPrimitiveEmission(entry_point.stage));
+                }
+                for &callee in call_graph.callees(function) {
+                    if !reached.contains(&callee) {
+                        reached.push(callee);
+                    }
+                }
+                index += 1;
+            }
+        }
+
+        if self.check_uniformity {
+            if let Some(error) = super::Uniformity::new(module).errors().first() {
+                return Err(error.clone().into());
+            }
+        }
+
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Capabilities, ValidationError, Validator};
+
+    fn scalar_module(kind: crate::ScalarKind, width: crate::Bytes) -> crate::Module {
+        let mut builder = crate::builder::ModuleBuilder::new();
+        builder.scalar(kind, width);
+        builder.finish()
+    }
+
+    #[test]
+    fn scalar_widths() {
+        let half = scalar_module(crate::ScalarKind::Float, 2);
+        match Validator::new().validate(&half) {
+            Err(ValidationError::InvalidTypeWidth(crate::ScalarKind::Float, 2)) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+        Validator::with_capabilities(Capabilities::FLOAT16)
+            .validate(&half)
+            .unwrap();
+
+        let long = scalar_module(crate::ScalarKind::Sint, 8);
+        assert!(Validator::with_capabilities(Capabilities::FLOAT64)
+            .validate(&long)
+            .is_err());
+        Validator::with_capabilities(Capabilities::INT64)
+            .validate(&long)
+            .unwrap();
+    }
+
+    #[test]
+    fn member_layout() {
+        let overlapping = crate::front::wgsl::parse_str(
+            "type Foo = struct { [[offset 0]] a : vec2<f32>; [[offset 4]] b : f32; };",
+        )
+        .unwrap();
+        match Validator::new().validate(&overlapping) {
+            Err(ValidationError::OverlappingMember(_, 1)) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+
+        let misaligned =
+            crate::front::wgsl::parse_str("type Foo = struct { [[offset 2]] a : f32; };").unwrap();
+        match Validator::new().validate(&misaligned) {
+            Err(ValidationError::MisalignedMember(_, 0)) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn primitive_emission() {
+        let emitting_module = |stage| {
+            let mut builder = crate::builder::ModuleBuilder::new();
+            let emit = {
+                let mut fun = builder.function("emit", &[], None);
+                fun.statement(crate::Statement::EmitVertex);
+                fun.statement(crate::Statement::EndPrimitive);
+                fun.finish()
+            };
+            let main = {
+                let mut fun = builder.function("main", &[], None);
+                fun.call_statement(emit, Vec::new());
+                fun.finish()
+            };
+            builder.entry_point(stage, "main", main, Default::default());
+            builder.finish()
+        };
+
+        Validator::new()
+            .validate(&emitting_module(crate::ShaderStage::Geometry))
+            .unwrap();
+        match Validator::new().validate(&emitting_module(crate::ShaderStage::Vertex)) {
+            Err(ValidationError::InvalidPrimitiveEmission(crate::ShaderStage::Vertex)) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn recursion() {
+        let mut builder = crate::builder::ModuleBuilder::new();
+        let forever = crate::arena::Handle::from_usize(builder.module().functions.len());
+        let mut fun = builder.function("forever", &[], None);
+        fun.call_statement(forever, Vec::new());
+        fun.finish();
+        match Validator::new().validate(&builder.finish()) {
+            Err(ValidationError::Recursion(handle)) => assert_eq!(handle, forever),
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,514 @@
+use crate::arena::{Arena, Handle};
+
+impl crate::Expression {
+    /// Calls `f` on each expression this one depends on, in evaluation order.
+    pub fn for_each_operand(&self, mut f: impl FnMut(Handle<crate::Expression>)) {
+        use crate::Expression as E;
+        match *self {
+            E::Access { base, index } => {
+                f(base);
+                f(index);
+            }
+            E::AccessIndex { base, .. } => f(base),
+            E::Constant(_)
+            | E::FunctionParameter(_)
+            | E::GlobalVariable(_)
+            | E::LocalVariable(_) => {}
+            E::Compose { ref components, .. } => components.iter().cloned().for_each(f),
+            E::Load { pointer } => f(pointer),
+            E::ImageSample {
+                image,
+                sampler,
+                coordinate,
+                depth_ref,
+            } => {
+                f(image);
+                f(sampler);
+                f(coordinate);
+                depth_ref.into_iter().for_each(f);
+            }
+            E::Unary { expr, .. } | E::Derivative { expr, .. } => f(expr),
+            E::Binary { left, right, .. }
+            | E::DotProduct(left, right)
+            | E::CrossProduct(left, right) => {
+                f(left);
+                f(right);
+            }
+            E::Intrinsic { argument, .. } => f(argument),
+            E::Call { ref arguments, .. } => arguments.iter().cloned().for_each(f),
+        }
+    }
+
+    /// Calls `f` on the handle of each expression this one depends on,
+    /// in evaluation order.
+    pub fn for_each_operand_mut(&mut self, mut f: impl FnMut(&mut Handle<crate::Expression>)) {
+        use crate::Expression as E;
+        match *self {
+            E::Access {
+                ref mut base,
+                ref mut index,
+            } => {
+                f(base);
+                f(index);
+            }
+            E::AccessIndex { ref mut base, .. } => f(base),
+            E::Constant(_)
+            | E::FunctionParameter(_)
+            | E::GlobalVariable(_)
+            | E::LocalVariable(_) => {}
+            E::Compose {
+                ref mut components, ..
+            } => components.iter_mut().for_each(f),
+            E::Load { ref mut pointer } => f(pointer),
+            E::ImageSample {
+                ref mut image,
+                ref mut sampler,
+                ref mut coordinate,
+                ref mut depth_ref,
+            } => {
+                f(image);
+                f(sampler);
+                f(coordinate);
+                depth_ref.iter_mut().for_each(f);
+            }
+            E::Unary { ref mut expr, .. } | E::Derivative { ref mut expr, .. } => f(expr),
+            E::Binary {
+                ref mut left,
+                ref mut right,
+                ..
+            }
+            | E::DotProduct(ref mut left, ref mut right)
+            | E::CrossProduct(ref mut left, ref mut right) => {
+                f(left);
+                f(right);
+            }
+            E::Intrinsic {
+                ref mut argument, ..
+            } => f(argument),
+            E::Call {
+                ref mut arguments, ..
+            } => arguments.iter_mut().for_each(f),
+        }
+    }
+}
+
+impl crate::Statement {
+    /// Calls `f` on each expression evaluated by this statement itself,
+    /// not including the ones of the nested blocks.
+    pub fn for_each_expression(&self, mut f: impl FnMut(Handle<crate::Expression>)) {
+        use crate::Statement as S;
+        match *self {
+            S::Empty
+            | S::Block(_)
+            | S::Loop { .. }
+            | S::Break
+            | S::Continue
+            | S::Kill
+            | S::EmitVertex
+            | S::EndPrimitive => {}
+            S::If { condition, .. } => f(condition),
+            S::Switch { selector, .. } => f(selector),
+            S::Return { value } => value.into_iter().for_each(f),
+            S::Store { pointer, value } => {
+                f(pointer);
+                f(value);
+            }
+            S::Call { ref arguments, .. } => arguments.iter().cloned().for_each(f),
+        }
+    }
+
+    /// Calls `f` on the handle of each expression evaluated by this statement
+    /// itself, not including the ones of the nested blocks.
+    pub fn for_each_expression_mut(&mut self, mut f: impl FnMut(&mut Handle<crate::Expression>)) {
+        use crate::Statement as S;
+        match *self {
+            S::Empty
+            | S::Block(_)
+            | S::Loop { .. }
+            | S::Break
+            | S::Continue
+            | S::Kill
+            | S::EmitVertex
+            | S::EndPrimitive => {}
+            S::If {
+                ref mut condition, ..
+            } => f(condition),
+            S::Switch {
+                ref mut selector, ..
+            } => f(selector),
+            S::Return { ref mut value } => value.iter_mut().for_each(f),
+            S::Store {
+                ref mut pointer,
+                ref mut value,
+            } => {
+                f(pointer);
+                f(value);
+            }
+            S::Call {
+                ref mut arguments, ..
+            } => arguments.iter_mut().for_each(f),
+        }
+    }
+
+    /// Calls `f` on each block nested in this statement, in execution order.
+    pub fn for_each_block(&self, mut f: impl FnMut(&crate::Block)) {
+        use crate::Statement as S;
+        match *self {
+            S::Block(ref block) => f(block),
+            S::If {
+                ref accept,
+                ref reject,
+                ..
+            } => {
+                f(accept);
+                f(reject);
+            }
+            S::Switch {
+                ref cases,
+                ref default,
+                ..
+            } => {
+                for (case, _) in cases.values() {
+                    f(case);
+                }
+                f(default);
+            }
+            S::Loop {
+                ref body,
+                ref continuing,
+            } => {
+                f(body);
+                f(continuing);
+            }
+            S::Empty
+            | S::Break
+            | S::Continue
+            | S::Return { .. }
+            | S::Kill
+            | S::Store { .. }
+            | S::Call { .. }
+            | S::EmitVertex
+            | S::EndPrimitive => {}
+        }
+    }
+
+    /// Calls `f` on each block nested in this statement, in execution order.
+    pub fn for_each_block_mut(&mut self, mut f: impl FnMut(&mut crate::Block)) {
+        use crate::Statement as S;
+        match *self {
+            S::Block(ref mut block) => f(block),
+            S::If {
+                ref mut accept,
+                ref mut reject,
+                ..
+            } => {
+                f(accept);
+                f(reject);
+            }
+            S::Switch {
+                ref mut cases,
+                ref mut default,
+                ..
+            } => {
+                for (case, _) in cases.values_mut() {
+                    f(case);
+                }
+                f(default);
+            }
+            S::Loop {
+                ref mut body,
+                ref mut continuing,
+            } => {
+                f(body);
+                f(continuing);
+            }
+            S::Empty
+            | S::Break
+            | S::Continue
+            | S::Return { .. }
+            | S::Kill
+            | S::Store { .. }
+            | S::Call { .. }
+            | S::EmitVertex
+            | S::EndPrimitive => {}
+        }
+    }
+}
+
+/// Hooks called while walking the body of a function.
+///
+/// Expressions are visited after all the expressions they depend on.
+/// An expression used in several places is visited every time.
+pub trait Visitor {
+    /// Called for each expression producing a value.
+    fn visit_expr(&mut self, _: Handle<crate::Expression>, _: &crate::Expression) {}
+    /// Called for each expression on the left hand side of a `Store`,
+    /// i.e. the pointer and the bases it is accessed from.
+    fn visit_lhs_expr(&mut self, _: Handle<crate::Expression>, _: &crate::Expression) {}
+    /// Called for each statement, before walking its contents.
+    fn visit_statement(&mut self, _: &crate::Statement) {}
+}
+
+/// Walks the expressions and statements of a function, calling the visitor hooks.
+pub struct Walker<'a, T> {
+    expressions: &'a Arena<crate::Expression>,
+    visitor: T,
+}
+
+impl<'a, T: Visitor> Walker<'a, T> {
+    /// Create a walker over the given expressions.
+    pub fn new(expressions: &'a Arena<crate::Expression>, visitor: T) -> Self {
+        Walker {
+            expressions,
+            visitor,
+        }
+    }
+
+    /// Return the visitor, with the state it gathered.
+    pub fn into_visitor(self) -> T {
+        self.visitor
+    }
+
+    /// Walk an expression, after the ones it depends on.
+    pub fn walk_expr(&mut self, handle: Handle<crate::Expression>) {
+        let expr = &self.expressions[handle];
+        expr.for_each_operand(|operand| self.walk_expr(operand));
+        self.visitor.visit_expr(handle, expr);
+    }
+
+    fn walk_lhs_expr(&mut self, handle: Handle<crate::Expression>) {
+        let expr = &self.expressions[handle];
+        match *expr {
+            crate::Expression::Access { base, index } => {
+                self.walk_expr(index);
+                self.walk_lhs_expr(base);
+            }
+            crate::Expression::AccessIndex { base, .. } => {
+                self.walk_lhs_expr(base);
+            }
+            _ => {}
+        }
+        self.visitor.visit_lhs_expr(handle, expr);
+    }
+
+    /// Walk the statements of a block, and the expressions they evaluate.
+    pub fn walk_block(&mut self, block: &[crate::Statement]) {
+        for statement in block {
+            self.visitor.visit_statement(statement);
+            match *statement {
+                crate::Statement::Store { pointer, value } => {
+                    self.walk_lhs_expr(pointer);
+                    self.walk_expr(value);
+                }
+                _ => statement.for_each_expression(|expr| self.walk_expr(expr)),
+            }
+            statement.for_each_block(|block| self.walk_block(block));
+        }
+    }
+}
+
+/// Walk the initializers of the local variables of a function, then its body.
+pub fn walk_function<T: Visitor>(fun: &crate::Function, visitor: T) -> T {
+    let mut walker = Walker::new(&fun.expressions, visitor);
+    for (_, local) in fun.local_variables.iter() {
+        if let Some(init) = local.init {
+            walker.walk_expr(init);
+        }
+    }
+    walker.walk_block(&fun.body);
+    walker.into_visitor()
+}
+
+/// Hooks called while walking the body of a function, allowing to modify it.
+///
+/// The hooks get the expression arena, so they can change the visited
+/// expressions and append new ones. The contents of statements are walked
+/// after the hook is called on them, so a replaced statement is walked too.
+pub trait VisitorMut {
+    /// Called for each expression, after the expressions it depends on.
+    fn visit_expr(&mut self, _: Handle<crate::Expression>, _: &mut Arena<crate::Expression>) {}
+    /// Called for each statement, before walking its contents.
+    fn visit_statement(&mut self, _: &mut crate::Statement, _: &mut Arena<crate::Expression>) {}
+}
+
+/// Walks the expressions and statements of a function, calling the mutable visitor hooks.
+pub struct WalkerMut<'a, T> {
+    expressions: &'a mut Arena<crate::Expression>,
+    visitor: T,
+}
+
+impl<'a, T: VisitorMut> WalkerMut<'a, T> {
+    /// Create a walker over the given expressions.
+    pub fn new(expressions: &'a mut Arena<crate::Expression>, visitor: T) -> Self {
+        WalkerMut {
+            expressions,
+            visitor,
+        }
+    }
+
+    /// Return the visitor, with the state it gathered.
+    pub fn into_visitor(self) -> T {
+        self.visitor
+    }
+
+    /// Walk an expression, after the ones it depends on.
+    pub fn walk_expr(&mut self, handle: Handle<crate::Expression>) {
+        let mut operands = Vec::new();
+        self.expressions[handle].for_each_operand(|operand| operands.push(operand));
+        for operand in operands {
+            self.walk_expr(operand);
+        }
+        self.visitor.visit_expr(handle, self.expressions);
+    }
+
+    /// Walk the statements of a block, and the expressions they evaluate.
+    pub fn walk_block(&mut self, block: &mut [crate::Statement]) {
+        for statement in block {
+            self.visitor.visit_statement(statement, self.expressions);
+            statement.for_each_expression(|expr| self.walk_expr(expr));
+            statement.for_each_block_mut(|block| self.walk_block(block));
+        }
+    }
+}
+
+/// Walk the initializers of the local variables of a function, then its body,
+/// allowing the visitor to modify them.
+pub fn walk_function_mut<T: VisitorMut>(fun: &mut crate::Function, visitor: T) -> T {
+    let mut walker = WalkerMut::new(&mut fun.expressions, visitor);
+    for (_, local) in fun.local_variables.iter() {
+        if let Some(init) = local.init {
+            walker.walk_expr(init);
+        }
+    }
+    walker.walk_block(&mut fun.body);
+    walker.into_visitor()
+}
+
+struct GlobalUseVisitor(Vec<crate::GlobalUse>);
+
+impl Visitor for GlobalUseVisitor {
+    fn visit_expr(&mut self, _: Handle<crate::Expression>, expr: &crate::Expression) {
+        if let crate::Expression::GlobalVariable(handle) = *expr {
+            self.0[handle.index()] |= crate::GlobalUse::LOAD;
+        }
+    }
+
+    fn visit_lhs_expr(&mut self, _: Handle<crate::Expression>, expr: &crate::Expression) {
+        if let crate::Expression::GlobalVariable(handle) = *expr {
+            self.0[handle.index()] |= crate::GlobalUse::STORE;
+        }
+    }
+}
+
+impl crate::GlobalUse {
+    pub fn scan(
+        expressions: &Arena<crate::Expression>,
+        body: &[crate::Statement],
+        globals: &Arena<crate::GlobalVariable>,
+    ) -> Vec<Self> {
+        let mut walker = Walker::new(
+            expressions,
+            GlobalUseVisitor(vec![crate::GlobalUse::empty(); globals.len()]),
+        );
+        walker.walk_block(body);
+        walker.into_visitor().0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{walk_function, walk_function_mut, Visitor, VisitorMut};
+    use crate::{
+        arena::{Arena, Handle},
+        Expression, Statement,
+    };
+
+    #[derive(Default)]
+    struct Recorder {
+        expressions: Vec<Handle<Expression>>,
+        statements: usize,
+    }
+
+    impl Visitor for Recorder {
+        fn visit_expr(&mut self, handle: Handle<Expression>, _: &Expression) {
+            self.expressions.push(handle);
+        }
+        fn visit_statement(&mut self, _: &Statement) {
+            self.statements += 1;
+        }
+    }
+
+    /// Replaces `a + b` by `a * b * 2.0`.
+    struct Doubler {
+        two: Handle<crate::Constant>,
+    }
+
+    impl VisitorMut for Doubler {
+        fn visit_expr(&mut self, handle: Handle<Expression>, expressions: &mut Arena<Expression>) {
+            if let Expression::Binary {
+                op: crate::BinaryOperator::Add,
+                left,
+                right,
+            } = expressions[handle]
+            {
+                let product = expressions.append(Expression::Binary {
+                    op: crate::BinaryOperator::Multiply,
+                    left,
+                    right,
+                });
+                let two = expressions.append(Expression::Constant(self.two));
+                *expressions.get_mut(handle) = Expression::Binary {
+                    op: crate::BinaryOperator::Multiply,
+                    left: product,
+                    right: two,
+                };
+            }
+        }
+    }
+
+    #[test]
+    fn traversal() {
+        let source = "
+            [[location 0]] var<in> a_value : f32;
+            [[location 0]] var<out> o_value : f32;
+            fn main() -> void {
+                var scale : f32 = a_value;
+                if (a_value > 0.0) {
+                    o_value = a_value + scale;
+                }
+                return;
+            }
+            entry_point fragment as \"main\" = main;
+        ";
+        let mut module = crate::front::wgsl::parse_str(source).unwrap();
+        let handle = module.entry_points[0].function;
+
+        let fun = &module.functions[handle];
+        let recorder = walk_function(fun, Recorder::default());
+        // the variable is initialized with a store, since its value isn't constant
+        assert_eq!(recorder.statements, 4);
+        for (index, &expr) in recorder.expressions.iter().enumerate() {
+            // dependencies come first
+            if let Expression::Binary { left, right, .. } = fun.expressions[expr] {
+                assert!(recorder.expressions[..index].contains(&left));
+                assert!(recorder.expressions[..index].contains(&right));
+            }
+        }
+
+        let two = module.constants.fetch_or_append(crate::Constant {
+            name: None,
+            specialization: None,
+            inner: crate::ConstantInner::Float(2.0),
+            ty: module.global_variables.iter().next().unwrap().1.ty,
+        });
+        let fun = module.functions.get_mut(handle);
+        let count = fun.expressions.len();
+        walk_function_mut(fun, Doubler { two });
+        assert_eq!(fun.expressions.len(), count + 2);
+        assert!(fun.expressions.iter().all(|(_, expr)| match *expr {
+            Expression::Binary { op, .. } => op != crate::BinaryOperator::Add,
+            _ => true,
+        }));
+        crate::proc::Validator::new().validate(&module).unwrap();
+    }
+}
@@ -148,5 +148,6 @@ fn compute_main() -> void {
 
   return;
 }
+[[workgroup_size 64]]
 entry_point compute as "main" = compute_main;
 
@@ -48,8 +48,14 @@ fn convert_quad() {
         );
         let options = msl::Options {
             binding_map: &binding_map,
+            clip_space: naga::proc::ClipSpaceAdjustment {
+                flip_y: true,
+                depth: Some(naga::proc::DepthConversion::UnitToSigned),
+            },
         };
-        msl::write_string(&module, options).unwrap();
+        let output = msl::write_string(&module, options).unwrap();
+        assert!(output.contains("-output.o_position.y"));
+        assert!(output.contains("output.o_position.z * 2.0 - output.o_position.w"));
     }
 }
 
@@ -89,11 +95,77 @@ fn convert_boids() {
         );
         let options = msl::Options {
             binding_map: &binding_map,
+            clip_space: Default::default(),
         };
         msl::write_string(&module, options).unwrap();
     }
 }
 
+#[cfg(feature = "spirv")]
+#[test]
+fn convert_simple() {
+    let module = load_wgsl("simple.wgsl");
+    naga::proc::Validator::new().validate(&module).unwrap();
+    let mut writer =
+        naga::back::spv::Writer::new(&module.header, naga::back::spv::WriterFlags::empty());
+    writer.write(&module).unwrap();
+}
+
+#[cfg(feature = "spirv")]
+#[test]
+fn convert_spv_clip_space() {
+    let module = naga::front::wgsl::parse_str(
+        "
+        [[location 0]] var<in> a_pos : vec4<f32>;
+        [[builtin position]] var<out> o_position : vec4<f32>;
+        fn main() -> void {
+            o_position = a_pos;
+            return;
+        }
+        entry_point vertex as \"main\" = main;
+        ",
+    )
+    .unwrap();
+    let flags =
+        naga::back::spv::WriterFlags::FLIP_Y | naga::back::spv::WriterFlags::DEPTH_UNIT_TO_SIGNED;
+    let words = naga::back::spv::Writer::new(&module.header, flags)
+        .write(&module)
+        .unwrap();
+    let module = naga::front::spv::Parser::new(words.into_iter())
+        .parse()
+        .unwrap();
+
+    let fun = &module.functions[module.entry_points[0].function];
+    let stores = fun
+        .body
+        .iter()
+        .filter_map(|statement| match *statement {
+            naga::Statement::Store { value, .. } => Some(&fun.expressions[value]),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(stores.len(), 2);
+    match *stores[1] {
+        naga::Expression::Compose { ref components, .. } => {
+            assert!(matches!(
+                fun.expressions[components[1]],
+                naga::Expression::Unary {
+                    op: naga::UnaryOperator::Negate,
+                    ..
+                }
+            ));
+            assert!(matches!(
+                fun.expressions[components[2]],
+                naga::Expression::Binary {
+                    op: naga::BinaryOperator::Subtract,
+                    ..
+                }
+            ));
+        }
+        ref other => panic!("Unexpected position: {:?}", other),
+    }
+}
+
 #[cfg(feature = "spirv")]
 #[test]
 fn convert_cube() {
@@ -104,6 +176,56 @@ fn convert_cube() {
     validator.validate(&fs).unwrap();
 }
 
+#[cfg(feature = "spirv")]
+#[test]
+fn convert_cube_clip_space() {
+    let mut module = load_spv("cube.vert.spv");
+    let adjustment = naga::proc::ClipSpaceAdjustment {
+        flip_y: true,
+        depth: None,
+    };
+    naga::proc::adjust_clip_space(&mut module, &adjustment);
+    naga::proc::Validator::new().validate(&module).unwrap();
+
+    // the position is a member of the `gl_PerVertex` block
+    let fun = &module.functions[module.entry_points[0].function];
+    let value = fun
+        .body
+        .iter()
+        .filter_map(|statement| match *statement {
+            naga::Statement::Store { pointer, value } => match fun.expressions[pointer] {
+                naga::Expression::AccessIndex { index: 0, .. } => Some(value),
+                _ => None,
+            },
+            _ => None,
+        })
+        .last()
+        .unwrap();
+    match fun.expressions[value] {
+        naga::Expression::Compose { ref components, .. } => match fun.expressions[components[1]] {
+            naga::Expression::Unary {
+                op: naga::UnaryOperator::Negate,
+                ..
+            } => {}
+            ref other => panic!("Unexpected Y: {:?}", other),
+        },
+        ref other => panic!("Unexpected value: {:?}", other),
+    }
+}
+
+#[cfg(feature = "spirv")]
+#[test]
+fn convert_cube_compacted() {
+    let mut validator = naga::proc::Validator::new();
+    for &name in &["cube.vert.spv", "cube.frag.spv"] {
+        let mut module = load_spv(name);
+        let types = module.types.len();
+        naga::proc::compact(&mut module);
+        assert!(module.types.len() <= types);
+        validator.validate(&module).unwrap();
+    }
+}
+
 #[cfg(feature = "glsl")]
 #[test]
 #[ignore]
@@ -121,7 +243,7 @@ fn convert_phong_lighting() {
     };
     let writer_flags = naga::back::spv::WriterFlags::empty();
     let mut w = naga::back::spv::Writer::new(&header, writer_flags);
-    w.write(&module);
+    w.write(&module).unwrap();
 }
 
 #[cfg(feature = "glsl")]
@@ -134,3 +256,292 @@ fn constant_expressions() {
     );
     naga::proc::Validator::new().validate(&module).unwrap();
 }
+
+#[cfg(feature = "glsl-out")]
+#[test]
+fn convert_storage_image() {
+    let module = naga::front::wgsl::parse_str(
+        "[[set 0, binding 0]] var<uniform> u_output : texture_wo_2d<rgba8unorm>;",
+    )
+    .unwrap();
+    naga::proc::Validator::new().validate(&module).unwrap();
+
+    let mut output = Vec::new();
+    naga::back::glsl::write(&module, &mut output, Default::default()).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.contains("layout(rgba8,set=0,binding=0) uniform writeonly image2D"));
+}
+
+#[cfg(all(feature = "glsl", feature = "glsl-out"))]
+#[test]
+fn convert_inout_parameter() {
+    let module = naga::front::glsl::parse_str(
+        "#version 450 core
+        layout(location=0) in vec4 v_color;
+        layout(location=1) in float v_factor;
+        layout(location=0) out vec4 o_color;
+        void scale(inout vec4 value, float factor) {
+            value = value * factor;
+        }
+        void main() {
+            vec4 color = v_color;
+            scale(color, v_factor);
+            o_color = color;
+        }",
+        String::from("main"),
+        naga::ShaderStage::Fragment,
+    )
+    .unwrap();
+    naga::proc::Validator::new().validate(&module).unwrap();
+
+    let mut output = Vec::new();
+    naga::back::glsl::write(&module, &mut output, Default::default()).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.contains("(inout vec4 value,float factor)"));
+    assert!(output.contains("scale(color,v_factor);"));
+
+    let binding_map = naga::back::msl::BindingMap::default();
+    let options = naga::back::msl::Options {
+        binding_map: &binding_map,
+        clip_space: Default::default(),
+    };
+    let msl = naga::back::msl::write_string(&module, options).unwrap();
+    assert!(msl.contains("thread Type0 &value"));
+    assert!(msl.contains("scale(color, input.v_factor);"));
+
+    #[cfg(feature = "spirv")]
+    {
+        let mut writer =
+            naga::back::spv::Writer::new(&module.header, naga::back::spv::WriterFlags::empty());
+        let words = writer.write(&module).unwrap();
+        let module = naga::front::spv::Parser::new(words.into_iter())
+            .parse()
+            .unwrap();
+        let (_, main) = module
+            .functions
+            .iter()
+            .find(|&(_, fun)| {
+                fun.body
+                    .iter()
+                    .any(|s| matches!(*s, naga::Statement::Call { .. }))
+            })
+            .unwrap();
+        // the variable passed to the helper is the one stored to the output
+        let variable = match main.body[..] {
+            [.., naga::Statement::Call { ref arguments, .. }, naga::Statement::Store { pointer, value }, _] =>
+            {
+                match main.expressions[pointer] {
+                    naga::Expression::GlobalVariable(var) => {
+                        assert_eq!(
+                            module.global_variables[var].class,
+                            naga::StorageClass::Output
+                        )
+                    }
+                    ref other => panic!("Unexpected store pointer {:?}", other),
+                }
+                match main.expressions[value] {
+                    naga::Expression::Load { pointer } => assert_eq!(pointer, arguments[0]),
+                    ref other => panic!("Unexpected stored value {:?}", other),
+                }
+                arguments[0]
+            }
+            ref other => panic!("Unexpected body {:?}", other),
+        };
+        assert!(matches!(
+            main.expressions[variable],
+            naga::Expression::LocalVariable(_)
+        ));
+    }
+}
+
+#[cfg(feature = "glsl-out")]
+#[test]
+fn convert_execution_modes() {
+    let mut builder = naga::builder::ModuleBuilder::new();
+    let main = {
+        let mut fun = builder.function("main", &[], None);
+        fun.statement(naga::Statement::EmitVertex);
+        fun.finish()
+    };
+    for &(name, topology) in &[
+        ("points", naga::PrimitiveTopology::Points),
+        ("lines", naga::PrimitiveTopology::Lines),
+    ] {
+        let modes = naga::ExecutionModes {
+            input_topology: Some(topology),
+            output_topology: Some(naga::PrimitiveTopology::Points),
+            output_vertices: Some(1),
+            ..Default::default()
+        };
+        builder.entry_point(naga::ShaderStage::Geometry, name, main, modes);
+    }
+    let module = builder.finish();
+    naga::proc::Validator::new().validate(&module).unwrap();
+
+    let mut output = Vec::new();
+    assert!(naga::back::glsl::write(&module, &mut output, Default::default()).is_err());
+
+    let points = module
+        .extract_entry_point("points", naga::ShaderStage::Geometry)
+        .unwrap();
+    let mut output = Vec::new();
+    naga::back::glsl::write(&points, &mut output, Default::default()).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.contains("layout(points) in;"));
+    assert!(output.contains("layout(points,max_vertices=1) out;"));
+}
+
+#[cfg(feature = "glsl-out")]
+#[test]
+fn convert_global_initializer() {
+    let module = naga::front::wgsl::parse_str("var<private> scale : f32 = 2.0;").unwrap();
+    naga::proc::Validator::new().validate(&module).unwrap();
+
+    let mut output = Vec::new();
+    naga::back::glsl::write(&module, &mut output, Default::default()).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.contains("float scale = 2.0;"));
+}
+
+#[cfg(feature = "glsl")]
+#[test]
+fn convert_private_initializer_in_helper() {
+    let module = naga::front::glsl::parse_str(
+        "#version 450 core
+        layout(location=0) out int o_count;
+        int counter = 3;
+        void bump() {
+            counter = counter + 1;
+        }
+        void main() {
+            bump();
+            o_count = counter;
+        }",
+        String::from("main"),
+        naga::ShaderStage::Fragment,
+    )
+    .unwrap();
+    naga::proc::Validator::new().validate(&module).unwrap();
+
+    let binding_map = naga::back::msl::BindingMap::default();
+    let options = naga::back::msl::Options {
+        binding_map: &binding_map,
+        clip_space: Default::default(),
+    };
+    let msl = naga::back::msl::write_string(&module, options).unwrap();
+    assert!(msl.contains("thread Type0 &counter"));
+    assert!(msl.contains("bump(counter);"));
+    assert!(msl.contains("Type0 counter = 3;"));
+}
+
+#[cfg(feature = "glsl-out")]
+#[test]
+fn convert_spec_constant() {
+    let mut module = naga::front::wgsl::parse_str("const scale : f32 = 2.0;").unwrap();
+    assert_eq!(module.constants.len(), 1);
+    let (handle, _) = module.constants.iter().next().unwrap();
+    module.constants.get_mut(handle).specialization = Some(3);
+
+    let mut output = Vec::new();
+    naga::back::glsl::write(&module, &mut output, Default::default()).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.contains("layout(constant_id=3) const float scale = 2.0;"));
+
+    let mut values = naga::FastHashMap::default();
+    values.insert(3, naga::ConstantInner::Float(4.0));
+    naga::proc::specialize(&mut module, &values).unwrap();
+
+    let mut output = Vec::new();
+    naga::back::glsl::write(&module, &mut output, Default::default()).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert!(!output.contains("constant_id"));
+}
+
+#[test]
+fn reflect_boids() {
+    use naga::proc::{reflect_entry_point, ResourceKind};
+
+    let module = load_wgsl("boids.wgsl");
+    let info = reflect_entry_point(&module, naga::ShaderStage::Compute, "main").unwrap();
+    assert_eq!(info.workgroup_size, Some([64, 1, 1]));
+    let resources = info
+        .resources
+        .iter()
+        .map(|res| (res.name.as_deref().unwrap(), res.binding, res.kind.clone()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        resources,
+        [
+            ("params", 0, ResourceKind::UniformBuffer { size: 32 }),
+            ("particlesA", 1, ResourceKind::StorageBuffer { size: 80 }),
+            ("particlesB", 2, ResourceKind::StorageBuffer { size: 80 }),
+        ]
+    );
+    assert!(info.resources[2].usage.contains(naga::GlobalUse::STORE));
+    assert!(info.outputs.is_empty());
+
+    let info = reflect_entry_point(&module, naga::ShaderStage::Vertex, "main").unwrap();
+    assert!(info.resources.is_empty());
+    let locations = info
+        .inputs
+        .iter()
+        .filter_map(|input| match input.binding {
+            naga::Binding::Location(location) => Some(location),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(locations, [0, 1, 2]);
+    assert_eq!(info.inputs[0].format.unwrap().components, 2);
+}
+
+#[test]
+fn reflect_plain_buffers() {
+    use naga::proc::{reflect_entry_point, ResourceKind};
+
+    let mut builder = naga::builder::ModuleBuilder::new();
+    let float = builder.scalar(naga::ScalarKind::Float, 4);
+    let vector = builder.vector(naga::VectorSize::Quad, naga::ScalarKind::Float, 4);
+    let array = builder.ty(naga::TypeInner::Array {
+        base: float,
+        size: naga::ArraySize::Static(4),
+        stride: None,
+    });
+    let uniform = builder.global_variable(
+        "uniform",
+        naga::StorageClass::Uniform,
+        Some(naga::Binding::Descriptor { set: 0, binding: 0 }),
+        vector,
+    );
+    let storage = builder.global_variable(
+        "storage",
+        naga::StorageClass::StorageBuffer,
+        Some(naga::Binding::Descriptor { set: 0, binding: 1 }),
+        array,
+    );
+    let main = {
+        let mut fun = builder.function("main", &[], None);
+        let uniform = fun.global(uniform);
+        let value = fun.load(uniform);
+        let storage = fun.global(storage);
+        let element = fun.access_index(storage, 0);
+        let component = fun.access_index(value, 0);
+        fun.store(element, component);
+        fun.finish()
+    };
+    builder.entry_point(naga::ShaderStage::Compute, "main", main, Default::default());
+    let module = builder.finish();
+
+    let info = reflect_entry_point(&module, naga::ShaderStage::Compute, "main").unwrap();
+    let kinds = info
+        .resources
+        .iter()
+        .map(|res| res.kind.clone())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        kinds,
+        [
+            ResourceKind::UniformBuffer { size: 16 },
+            ResourceKind::StorageBuffer { size: 16 },
+        ]
+    );
+}